@@ -16,6 +16,7 @@
 )]
 #![allow(clippy::too_many_arguments, clippy::blocks_in_if_conditions)]
 
+use std::collections::HashSet;
 use std::fmt::Write;
 use std::fs::File;
 use std::io::{stdin, BufRead};
@@ -25,16 +26,22 @@ use eyre::Context;
 use git_branchless_invoke::CommandContext;
 use git_branchless_opts::{HookArgs, HookSubcommand};
 use itertools::Itertools;
+use lib::core::config::get_ref_namespace;
 use lib::core::dag::Dag;
 use lib::core::repo_ext::RepoExt;
 use lib::core::rewrite::rewrite_hooks::get_deferred_commits_path;
+use lib::try_exit_code;
 use lib::util::EyreExitOr;
 use tracing::{error, instrument, warn};
 
 use lib::core::eventlog::{should_ignore_ref_updates, Event, EventLogDb, EventReplayer};
 use lib::core::formatting::{Glyphs, Pluralize};
-use lib::core::gc::{gc, mark_commit_reachable};
-use lib::git::{CategorizedReferenceName, MaybeZeroOid, NonZeroOid, ReferenceName, Repo};
+use lib::core::gc::{
+    gc, mark_active_commits_reachable, mark_commit_reachable, maybe_auto_gc, GcOptions,
+};
+use lib::git::{
+    CategorizedReferenceName, GitRunInfo, MaybeZeroOid, NonZeroOid, ReferenceName, Repo,
+};
 
 use lib::core::effects::Effects;
 pub use lib::core::rewrite::rewrite_hooks::{
@@ -309,6 +316,7 @@ mod reference_transaction {
     #[cfg(test)]
     #[test]
     fn test_parse_reference_transaction_line() -> eyre::Result<()> {
+        use lib::core::config::DEFAULT_REF_NAMESPACE;
         use lib::core::eventlog::should_ignore_ref_updates;
 
         let line = "123abc 456def refs/heads/mybranch";
@@ -335,7 +343,10 @@ mod reference_transaction {
                     ref_name: ReferenceName::from("ORIG_HEAD"),
                 }
             );
-            assert!(should_ignore_ref_updates(&parsed_line.ref_name));
+            assert!(should_ignore_ref_updates(
+                &parsed_line.ref_name,
+                DEFAULT_REF_NAMESPACE
+            ));
         }
 
         let line = "there are not three fields here";
@@ -410,6 +421,105 @@ mod reference_transaction {
     }
 }
 
+/// Determine whether the reference transaction consists of exactly the deletion
+/// of one local branch and the creation of another local branch at the same
+/// commit, which is how Git reports `git branch -m`.
+///
+/// Returns: The old reference name, the new reference name, and the OID which
+/// the reference pointed to, if the transaction was a rename.
+fn detect_reference_rename(
+    parsed_lines: &[reference_transaction::ParsedReferenceTransactionLine],
+) -> Option<(ReferenceName, ReferenceName, NonZeroOid)> {
+    use reference_transaction::ParsedReferenceTransactionLine;
+
+    let is_local_branch = |ref_name: &ReferenceName| {
+        matches!(
+            CategorizedReferenceName::new(ref_name),
+            CategorizedReferenceName::LocalBranch { .. }
+        )
+    };
+    match parsed_lines {
+        [ParsedReferenceTransactionLine {
+            ref_name: deleted_ref_name,
+            old_oid: MaybeZeroOid::NonZero(deleted_oid),
+            new_oid: MaybeZeroOid::Zero,
+        }, ParsedReferenceTransactionLine {
+            ref_name: created_ref_name,
+            old_oid: MaybeZeroOid::Zero,
+            new_oid: MaybeZeroOid::NonZero(created_oid),
+        }]
+        | [ParsedReferenceTransactionLine {
+            ref_name: created_ref_name,
+            old_oid: MaybeZeroOid::Zero,
+            new_oid: MaybeZeroOid::NonZero(created_oid),
+        }, ParsedReferenceTransactionLine {
+            ref_name: deleted_ref_name,
+            old_oid: MaybeZeroOid::NonZero(deleted_oid),
+            new_oid: MaybeZeroOid::Zero,
+        }] if deleted_oid == created_oid
+            && is_local_branch(deleted_ref_name)
+            && is_local_branch(created_ref_name) =>
+        {
+            Some((
+                deleted_ref_name.clone(),
+                created_ref_name.clone(),
+                *deleted_oid,
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Determine whether any of the given events moved a main branch (or the
+/// remote-tracking branch it follows) to a commit which isn't a descendant of
+/// its previous location, such as when pulling after the upstream main branch
+/// was force-pushed.
+fn is_main_branch_rewound(repo: &Repo, events: &[Event]) -> eyre::Result<bool> {
+    let updates = events
+        .iter()
+        .filter_map(|event| match event {
+            Event::RefUpdateEvent {
+                ref_name,
+                old_oid: MaybeZeroOid::NonZero(old_oid),
+                new_oid: MaybeZeroOid::NonZero(new_oid),
+                ..
+            }
+            | Event::RemoteRefUpdateEvent {
+                ref_name,
+                old_oid: MaybeZeroOid::NonZero(old_oid),
+                new_oid: MaybeZeroOid::NonZero(new_oid),
+                ..
+            } => Some((ref_name, *old_oid, *new_oid)),
+            _ => None,
+        })
+        .collect_vec();
+    if updates.is_empty() {
+        return Ok(false);
+    }
+
+    let mut main_branch_reference_names: HashSet<ReferenceName> = HashSet::new();
+    for branch in
+        std::iter::once(repo.get_main_branch()?).chain(repo.get_additional_main_branches()?)
+    {
+        main_branch_reference_names.insert(branch.get_reference_name()?);
+        if let Some(upstream_branch) = branch.get_upstream_branch()? {
+            main_branch_reference_names.insert(upstream_branch.get_reference_name()?);
+        }
+    }
+
+    for (ref_name, old_oid, new_oid) in updates {
+        if !main_branch_reference_names.contains(ref_name) {
+            continue;
+        }
+        // If the previous commit has since been garbage-collected, then it
+        // can't be an ancestor of the new one.
+        if repo.find_commit(old_oid)?.is_none() || !repo.is_ancestor(old_oid, new_oid)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
 /// Handle Git's `reference-transaction` hook.
 ///
 /// See the man-page for `githooks(5)`.
@@ -431,6 +541,7 @@ fn hook_reference_transaction(effects: &Effects, transaction_state: &str) -> eyr
     let event_tx_id = event_log_db.make_transaction_id(now, "reference-transaction")?;
 
     let packed_references = read_packed_refs_file(&repo)?;
+    let ref_namespace = get_ref_namespace(&repo)?;
 
     let parsed_lines: Vec<ParsedReferenceTransactionLine> = stdin()
         .lock()
@@ -460,7 +571,7 @@ fn hook_reference_transaction(effects: &Effects, transaction_state: &str) -> eyr
                  ref_name,
                  old_oid: _,
                  new_oid: _,
-             }| !should_ignore_ref_updates(ref_name),
+             }| !should_ignore_ref_updates(ref_name, &ref_namespace),
         )
         .map(|parsed_line| fix_packed_reference_oid(&repo, &packed_references, parsed_line))
         .collect();
@@ -496,30 +607,76 @@ fn hook_reference_transaction(effects: &Effects, transaction_state: &str) -> eyr
         .duration_since(SystemTime::UNIX_EPOCH)
         .wrap_err("Calculating timestamp")?
         .as_secs_f64();
-    let events = parsed_lines
-        .into_iter()
-        .map(
-            |ParsedReferenceTransactionLine {
-                 ref_name,
-                 old_oid,
-                 new_oid,
-             }| {
-                Event::RefUpdateEvent {
-                    timestamp,
-                    event_tx_id,
-                    ref_name,
-                    old_oid,
-                    new_oid,
-                    message: None,
-                }
-            },
-        )
-        .collect::<Vec<Event>>();
+    let events = match detect_reference_rename(&parsed_lines) {
+        Some((old_ref_name, new_ref_name, oid)) => vec![Event::RefRenameEvent {
+            timestamp,
+            event_tx_id,
+            old_ref_name,
+            new_ref_name,
+            oid,
+        }],
+        None => parsed_lines
+            .into_iter()
+            .map(
+                |ParsedReferenceTransactionLine {
+                     ref_name,
+                     old_oid,
+                     new_oid,
+                 }| match CategorizedReferenceName::new(&ref_name) {
+                    CategorizedReferenceName::RemoteBranch { .. } => Event::RemoteRefUpdateEvent {
+                        timestamp,
+                        event_tx_id,
+                        ref_name,
+                        old_oid,
+                        new_oid,
+                    },
+                    CategorizedReferenceName::LocalBranch { .. }
+                    | CategorizedReferenceName::OtherRef { .. } => Event::RefUpdateEvent {
+                        timestamp,
+                        event_tx_id,
+                        ref_name,
+                        old_oid,
+                        new_oid,
+                        message: None,
+                    },
+                },
+            )
+            .collect::<Vec<Event>>(),
+    };
+    // The previous history of a rewound main branch would otherwise stay in
+    // the commit graph cache indefinitely.
+    let should_clear_dag_cache = is_main_branch_rewound(&repo, &events)?;
     event_log_db.add_events(events)?;
+    if should_clear_dag_cache {
+        Dag::clear_cache(&repo)?;
+    }
 
     Ok(())
 }
 
+/// Handle Git's `pre-auto-gc` hook.
+///
+/// Git is about to collect unreachable objects, so make sure that every commit
+/// which should still be visible is kept alive first, and then release any
+/// commits which are no longer visible.
+#[instrument]
+fn hook_pre_auto_gc(effects: &Effects, git_run_info: &GitRunInfo) -> EyreExitOr<()> {
+    let repo = Repo::from_current_dir()?;
+    let num_marked = mark_active_commits_reachable(effects, &repo)?;
+    if num_marked > 0 {
+        writeln!(
+            effects.get_output_stream(),
+            "branchless: kept alive {} which had not been marked as reachable",
+            Pluralize {
+                determiner: None,
+                amount: num_marked,
+                unit: ("commit", "commits"),
+            },
+        )?;
+    }
+    gc(effects, git_run_info, &GcOptions::default())
+}
+
 /// `hook` subcommand.
 #[instrument]
 pub fn command_main(ctx: CommandContext, args: HookArgs) -> EyreExitOr<()> {
@@ -536,11 +693,12 @@ pub fn command_main(ctx: CommandContext, args: HookArgs) -> EyreExitOr<()> {
         }
 
         HookSubcommand::PreAutoGc => {
-            gc(&effects)?;
+            try_exit_code!(hook_pre_auto_gc(&effects, &git_run_info)?);
         }
 
         HookSubcommand::PostApplypatch => {
             hook_post_applypatch(&effects)?;
+            try_exit_code!(maybe_auto_gc(&effects, &git_run_info)?);
         }
 
         HookSubcommand::PostCheckout {
@@ -554,18 +712,22 @@ pub fn command_main(ctx: CommandContext, args: HookArgs) -> EyreExitOr<()> {
                 &current_commit,
                 is_branch_checkout,
             )?;
+            try_exit_code!(maybe_auto_gc(&effects, &git_run_info)?);
         }
 
         HookSubcommand::PostCommit => {
             hook_post_commit(&effects)?;
+            try_exit_code!(maybe_auto_gc(&effects, &git_run_info)?);
         }
 
         HookSubcommand::PostMerge { is_squash_merge } => {
             hook_post_merge(&effects, is_squash_merge)?;
+            try_exit_code!(maybe_auto_gc(&effects, &git_run_info)?);
         }
 
         HookSubcommand::PostRewrite { rewrite_type } => {
             hook_post_rewrite(&effects, &git_run_info, &rewrite_type)?;
+            try_exit_code!(maybe_auto_gc(&effects, &git_run_info)?);
         }
 
         HookSubcommand::ReferenceTransaction { transaction_state } => {
@@ -1,3 +1,11 @@
+use std::str::FromStr;
+
+use lib::core::dag::Dag;
+use lib::core::effects::Effects;
+use lib::core::eventlog::{CommitActivityStatus, Event, EventLogDb, EventReplayer};
+use lib::core::formatting::Glyphs;
+use lib::core::repo_ext::RepoExt;
+use lib::git::{MaybeZeroOid, NonZeroOid, ReferenceName};
 use lib::testing::{make_git, make_git_worktree, GitRunOptions, GitWorktreeWrapper};
 
 #[test]
@@ -231,3 +239,204 @@ fn test_hooks_in_worktree() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_reference_transaction_rename() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "hook", "reference-transaction", "committed"],
+            &GitRunOptions {
+                input: Some(format!(
+                    "{test1_oid} {zero} refs/heads/foo\n{zero} {test1_oid} refs/heads/bar\n",
+                    zero = MaybeZeroOid::Zero,
+                )),
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: processing 2 updates: branch bar, branch foo
+        "###);
+    }
+
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let events = event_log_db.get_events()?;
+    match events.last() {
+        Some(Event::RefRenameEvent {
+            timestamp: _,
+            event_tx_id: _,
+            old_ref_name,
+            new_ref_name,
+            oid,
+        }) => {
+            assert_eq!(old_ref_name, &ReferenceName::from("refs/heads/foo"));
+            assert_eq!(new_ref_name, &ReferenceName::from("refs/heads/bar"));
+            assert_eq!(oid, &test1_oid);
+        }
+        other => panic!("Expected a rename event, got: {other:?}"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_reference_transaction_remote_ref() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    let master_oid = git.get_repo()?.get_main_branch_oid()?;
+    git.run(&["remote", "add", "origin", "file:///some-remote"])?;
+    git.run(&[
+        "update-ref",
+        "refs/remotes/origin/master",
+        &master_oid.to_string(),
+    ])?;
+    git.run(&["branch", "-u", "origin/master"])?;
+
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let repo = git.get_repo()?;
+    let start_cursor = {
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+        event_replayer.make_default_cursor()
+    };
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "hook", "reference-transaction", "committed"],
+            &GitRunOptions {
+                input: Some(format!(
+                    "{master_oid} {test1_oid} refs/remotes/origin/master\n"
+                )),
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: processing 1 update: remote branch origin/master
+        "###);
+    }
+
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    match event_log_db.get_events()?.last() {
+        Some(Event::RemoteRefUpdateEvent {
+            timestamp: _,
+            event_tx_id: _,
+            ref_name,
+            old_oid,
+            new_oid,
+        }) => {
+            assert_eq!(ref_name, &ReferenceName::from("refs/remotes/origin/master"));
+            assert_eq!(old_oid, &MaybeZeroOid::NonZero(master_oid));
+            assert_eq!(new_oid, &MaybeZeroOid::NonZero(test1_oid));
+        }
+        other => panic!("Expected a remote reference update event, got: {other:?}"),
+    }
+
+    let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+    let end_cursor = event_replayer.make_default_cursor();
+    let references_snapshot = repo.get_references_snapshot()?;
+    let mut dag = Dag::open_and_sync(
+        &effects,
+        &repo,
+        &event_replayer,
+        end_cursor,
+        &references_snapshot,
+    )?;
+    let commits_made_public = event_replayer.get_commits_made_public(
+        &effects,
+        &repo,
+        &mut dag,
+        start_cursor,
+        end_cursor,
+    )?;
+    assert_eq!(
+        dag.commit_set_to_vec(&commits_made_public)?,
+        vec![test1_oid]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_reference_transaction_commit_observed() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    let master_oid = git.get_repo()?.get_main_branch_oid()?;
+    git.run(&["remote", "add", "origin", "file:///some-remote"])?;
+
+    // Simulate a commit brought in by `git fetch`, which doesn't run any hooks
+    // other than `reference-transaction`.
+    let (stdout, _stderr) =
+        git.run(&["commit-tree", "HEAD^{tree}", "-p", "HEAD", "-m", "fetched"])?;
+    let fetched_oid = NonZeroOid::from_str(stdout.trim())?;
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "hook", "reference-transaction", "committed"],
+            &GitRunOptions {
+                input: Some(format!(
+                    "0000000000000000000000000000000000000000 {fetched_oid} refs/remotes/origin/feature\n"
+                )),
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: processing 1 update: remote branch origin/feature
+        "###);
+    }
+
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+
+    // The hook only records the reference update. The observed commits are
+    // derived the next time that the DAG is synced.
+    match event_log_db.get_events()?.last() {
+        Some(Event::RemoteRefUpdateEvent { new_oid, .. }) => {
+            assert_eq!(new_oid, &MaybeZeroOid::NonZero(fetched_oid));
+        }
+        other => panic!("Expected a remote ref update event, got: {other:?}"),
+    }
+
+    git.branchless("smartlog", &[])?;
+    let num_events = event_log_db.get_events()?.len();
+    match event_log_db.get_events()?.last() {
+        Some(Event::CommitObservedEvent {
+            timestamp: _,
+            event_tx_id: _,
+            commit_oid,
+        }) => {
+            assert_eq!(commit_oid, &fetched_oid);
+        }
+        other => panic!("Expected a commit-observed event, got: {other:?}"),
+    }
+
+    let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    assert!(event_replayer
+        .get_cursor_commit_observed_time(event_cursor, fetched_oid)
+        .is_some());
+    assert!(event_replayer
+        .get_cursor_commit_observed_time(event_cursor, master_oid)
+        .is_none());
+    assert_eq!(
+        event_replayer.get_cursor_commit_activity_status(event_cursor, fetched_oid),
+        CommitActivityStatus::Inactive
+    );
+
+    // The same reference update isn't considered again.
+    git.branchless("smartlog", &[])?;
+    assert_eq!(event_log_db.get_events()?.len(), num_events);
+
+    Ok(())
+}
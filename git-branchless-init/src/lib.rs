@@ -24,7 +24,7 @@ use tracing::{instrument, warn};
 
 use git_branchless_opts::{write_man_pages, InitArgs, InstallManPagesArgs};
 use lib::core::config::{
-    get_default_branch_name, get_default_hooks_dir, get_main_worktree_hooks_dir,
+    get_default_branch_name, get_default_hooks_dir, get_main_worktree_hooks_dir, get_ref_namespace,
 };
 use lib::core::dag::Dag;
 use lib::core::effects::Effects;
@@ -519,7 +519,10 @@ fn set_configs(
 
     config.set("branchless.core.mainBranch", main_branch_name)?;
     config.set("advice.detachedHead", false)?;
-    config.set("log.excludeDecoration", "refs/branchless/*")?;
+    config.set(
+        "log.excludeDecoration",
+        format!("{}*", get_ref_namespace(repo)?),
+    )?;
 
     Ok(())
 }
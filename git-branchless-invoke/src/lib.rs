@@ -20,15 +20,15 @@ use std::fmt::Write;
 use std::path::PathBuf;
 use std::time::SystemTime;
 
-use clap::{CommandFactory, FromArgMatches, Parser};
+use clap::{CommandFactory, FromArgMatches, Parser, ValueEnum};
 use cursive_core::theme::BaseColor;
 use cursive_core::utils::markup::StyledString;
 use eyre::Context;
-use git_branchless_opts::{ColorSetting, GlobalArgs};
+use git_branchless_opts::{ColorSetting, GlobalArgs, GlyphsSetting};
 use lib::core::config::env_vars::{get_git_exec_path, get_path_to_git};
 use lib::core::effects::Effects;
 use lib::core::formatting::Glyphs;
-use lib::git::GitRunInfo;
+use lib::git::{ConfigRead, GitRunInfo};
 use lib::git::{Repo, RepoError};
 use lib::util::{ExitCode, EyreExitOr};
 use tracing::level_filters::LevelFilter;
@@ -126,6 +126,24 @@ fn install_libgit2_tracing() {
     }
 }
 
+/// Get the glyphs setting from the `branchless.glyphs` config setting of the
+/// current repository, if any.
+#[instrument]
+fn get_glyphs_setting() -> GlyphsSetting {
+    let value: Option<String> = Repo::from_current_dir()
+        .ok()
+        .and_then(|repo| repo.get_readonly_config().ok())
+        .and_then(|config| config.get("branchless.glyphs").ok())
+        .flatten();
+    match value {
+        Some(value) => GlyphsSetting::from_str(&value, true).unwrap_or_else(|err| {
+            warn!(?value, ?err, "Invalid value for branchless.glyphs");
+            GlyphsSetting::Auto
+        }),
+        None => GlyphsSetting::Auto,
+    }
+}
+
 #[instrument]
 fn check_unsupported_config_options(effects: &Effects) -> eyre::Result<Option<ExitCode>> {
     let _repo = match Repo::from_current_dir() {
@@ -175,6 +193,7 @@ pub fn do_main_and_drop_locals<T: Parser>(
     let GlobalArgs {
         working_directory,
         color,
+        glyphs,
     } = GlobalArgs::from_arg_matches(&matches)
         .map_err(|err| eyre::eyre!("Could not parse global arguments: {err}"))?;
 
@@ -207,6 +226,17 @@ pub fn do_main_and_drop_locals<T: Parser>(
         Some(ColorSetting::Never) => Glyphs::text(),
         Some(ColorSetting::Auto) | None => Glyphs::detect(),
     };
+    let color = match glyphs.unwrap_or_else(get_glyphs_setting) {
+        GlyphsSetting::Unicode => color,
+        GlyphsSetting::Ascii => color.ascii(),
+        GlyphsSetting::Auto => {
+            if Glyphs::terminal_supports_unicode() {
+                color
+            } else {
+                color.ascii()
+            }
+        }
+    };
     let effects = Effects::new(color);
 
     let _tracing_guard = install_tracing(effects.clone());
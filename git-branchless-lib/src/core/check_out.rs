@@ -41,7 +41,8 @@ pub enum CheckoutTarget {
 /// Options for checking out a commit.
 #[derive(Clone, Debug)]
 pub struct CheckOutCommitOptions {
-    /// Additional arguments to pass to `git checkout`.
+    /// Additional arguments to pass to `git checkout` (or `git reset`, if
+    /// `reset` is set).
     pub additional_args: Vec<OsString>,
 
     /// Use `git reset` rather than `git checkout`; that is, leave the index and
@@ -142,7 +143,13 @@ pub fn check_out_commit(
 
     if *reset {
         if let Some(target) = &target {
-            try_exit_code!(git_run_info.run(effects, Some(event_tx_id), &["reset", target])?);
+            let reset_args = {
+                let mut args = vec![OsStr::new("reset")];
+                args.extend(additional_args.iter().map(OsStr::new));
+                args.push(OsStr::new(target.as_str()));
+                args
+            };
+            try_exit_code!(git_run_info.run(effects, Some(event_tx_id), reset_args.as_slice())?);
         }
     } else {
         let checkout_args = {
@@ -4,7 +4,7 @@ use std::ffi::OsString;
 use std::fmt::Write;
 use std::path::PathBuf;
 
-use cursive::theme::{BaseColor, Effect, Style};
+use cursive::theme::{BaseColor, Color, Effect, Style};
 use cursive::utils::markup::StyledString;
 use eyre::Context;
 use tracing::{instrument, warn};
@@ -87,6 +87,24 @@ pub fn get_main_branch_name(repo: &Repo) -> eyre::Result<String> {
     Ok("master".to_string())
 }
 
+/// Get the names of any additional long-lived branches (such as release
+/// branches) which should be treated like the main branch. Commits reachable
+/// from any of them are considered public.
+///
+/// The names are read from `branchless.core.additionalMainBranches` and may be
+/// separated by whitespace or commas.
+#[instrument]
+pub fn get_additional_main_branch_names(repo: &Repo) -> eyre::Result<Vec<String>> {
+    let branch_names: String = repo
+        .get_readonly_config()?
+        .get_or("branchless.core.additionalMainBranches", String::new())?;
+    Ok(branch_names
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_owned())
+        .collect())
+}
+
 /// If `true`, switch to the branch associated with a target commit instead of
 /// the commit directly.
 ///
@@ -108,6 +126,116 @@ pub fn get_smartlog_default_revset(repo: &Repo) -> eyre::Result<String> {
         })
 }
 
+/// The template used to render each commit in the smartlog. If unset, the
+/// commit descriptors are rendered in their default layout. See
+/// `TemplateDescriptor` for the supported placeholders.
+#[instrument]
+pub fn get_smartlog_commit_template(repo: &Repo) -> eyre::Result<Option<String>> {
+    repo.get_readonly_config()?
+        .get("branchless.smartlog.commitTemplate")
+}
+
+/// A regular expression used to extract a ticket ID from each commit message,
+/// for display with the `%t` placeholder in the smartlog commit template.
+#[instrument]
+pub fn get_smartlog_ticket_pattern(repo: &Repo) -> eyre::Result<Option<String>> {
+    repo.get_readonly_config()?
+        .get("branchless.smartlog.ticketPattern")
+}
+
+/// The number of commits in a linear run of draft commits above which the run
+/// is collapsed in the smartlog. A value of `0` disables collapsing.
+#[instrument]
+pub fn get_smartlog_collapse_threshold(repo: &Repo) -> eyre::Result<usize> {
+    let threshold: i32 = repo
+        .get_readonly_config()?
+        .get_or("branchless.smartlog.collapseThreshold", 20)?;
+    Ok(usize::try_from(threshold).unwrap_or(0))
+}
+
+/// The maximum number of stacks to show in the smartlog, keeping the stacks
+/// with the most recent activity. A value of `0` shows all stacks.
+#[instrument]
+pub fn get_smartlog_stack_limit(repo: &Repo) -> eyre::Result<usize> {
+    let limit: i32 = repo
+        .get_readonly_config()?
+        .get_or("branchless.smartlog.stackLimit", 0)?;
+    Ok(usize::try_from(limit).unwrap_or(0))
+}
+
+/// If `true`, record the commits in every rendered smartlog for use with
+/// `git smartlog --diff`, rather than only when `--diff` is passed.
+#[instrument]
+pub fn get_smartlog_track_changes(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.smartlog.trackChanges", false)
+}
+
+/// If `true`, render the smartlog with the most recent commits at the top
+/// rather than at the bottom. Passing `--reverse` to the smartlog inverts
+/// this setting.
+#[instrument]
+pub fn get_smartlog_reverse(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.smartlog.reverse", false)
+}
+
+/// Colors used to render the smartlog. Each color can be overridden with the
+/// `branchless.smartlog.color.<name>` config key, whose value is a color name
+/// such as `red`, `light blue`, or `default`.
+#[derive(Clone, Debug)]
+pub struct SmartlogColors {
+    /// The color of the node for the commit at `HEAD`.
+    pub head: Option<Color>,
+
+    /// The color of the nodes for main branch commits.
+    pub main: Option<Color>,
+
+    /// The color of branch labels.
+    pub branch: Color,
+
+    /// The color of remote-tracking branch labels.
+    pub remote_branch: Color,
+
+    /// The color of the lines for hidden (obsolete) commits.
+    pub hidden: Option<Color>,
+
+    /// The color of the lines connecting commits in the graph.
+    pub graph: Option<Color>,
+}
+
+/// Get the colors to use when rendering the smartlog. Invalid colors are
+/// ignored in favor of the default color.
+#[instrument]
+pub fn get_smartlog_colors(repo: &Repo) -> eyre::Result<SmartlogColors> {
+    let config = repo.get_readonly_config()?;
+    let get_color = |name: &str| -> eyre::Result<Option<Color>> {
+        let key = format!("branchless.smartlog.color.{name}");
+        let value: Option<String> = config.get(&key)?;
+        let value = match value {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        match Color::parse(value.trim()) {
+            Some(color @ (Color::Dark(_) | Color::Light(_) | Color::TerminalDefault)) => {
+                Ok(Some(color))
+            }
+            Some(Color::Rgb(..) | Color::RgbLowRes(..)) | None => {
+                warn!(?key, ?value, "Ignoring unsupported color in config");
+                Ok(None)
+            }
+        }
+    };
+    Ok(SmartlogColors {
+        head: get_color("head")?,
+        main: get_color("main")?,
+        branch: get_color("branch")?.unwrap_or(BaseColor::Green.light()),
+        remote_branch: get_color("remoteBranch")?.unwrap_or(BaseColor::Cyan.light()),
+        hidden: get_color("hidden")?,
+        graph: get_color("graph")?,
+    })
+}
+
 /// Get the default comment character.
 #[instrument]
 pub fn get_comment_char(repo: &Repo) -> eyre::Result<char> {
@@ -227,6 +355,38 @@ pub fn get_commit_descriptors_branches(repo: &Repo) -> eyre::Result<bool> {
         .get_or("branchless.commitDescriptors.branches", true)
 }
 
+/// If `true`, show remote-tracking branches pointing to each commit in the
+/// smartlog, such as `origin/feature`.
+#[instrument]
+pub fn get_commit_descriptors_remote_branches(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.commitDescriptors.remoteBranches", false)
+}
+
+/// If `true`, show how many commits each local branch is ahead of and behind
+/// its upstream branch in the smartlog.
+#[instrument]
+pub fn get_commit_descriptors_upstream_divergence(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.commitDescriptors.upstreamDivergence", false)
+}
+
+/// If `true`, show the number of files with uncommitted changes next to the
+/// `HEAD` commit in the smartlog.
+#[instrument]
+pub fn get_commit_descriptors_working_copy_status(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.commitDescriptors.workingCopyStatus", false)
+}
+
+/// If `true`, show the number of inserted and deleted lines next to each draft
+/// commit in the smartlog.
+#[instrument]
+pub fn get_commit_descriptors_diff_stat(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.commitDescriptors.diffStat", false)
+}
+
 /// If `true`, show associated Phabricator commits in the smartlog.
 #[instrument]
 pub fn get_commit_descriptors_differential_revision(repo: &Repo) -> eyre::Result<bool> {
@@ -234,6 +394,41 @@ pub fn get_commit_descriptors_differential_revision(repo: &Repo) -> eyre::Result
         .get_or("branchless.commitDescriptors.differentialRevision", true)
 }
 
+/// If `true`, show whether each commit is signed, and whether its signature is
+/// valid, in the smartlog.
+#[instrument]
+pub fn get_commit_descriptors_signature(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.commitDescriptors.signature", false)
+}
+
+/// If `true`, show how many commits each stack is ahead of and behind the main
+/// branch in the smartlog.
+#[instrument]
+pub fn get_commit_descriptors_ahead_behind(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.commitDescriptors.aheadBehind", false)
+}
+
+/// A shell command to run for each commit in the smartlog, whose output is
+/// displayed next to the commit. See `StatusCommandDescriptor`.
+#[instrument]
+pub fn get_commit_descriptors_status_command(repo: &Repo) -> eyre::Result<Option<String>> {
+    repo.get_readonly_config()?
+        .get("branchless.commitDescriptors.statusCommand")
+}
+
+/// The number of seconds for which the output of
+/// `branchless.commitDescriptors.statusCommand` is cached for each commit.
+#[instrument]
+pub fn get_commit_descriptors_status_command_cache_seconds(repo: &Repo) -> eyre::Result<u64> {
+    let cache_seconds: i32 = repo.get_readonly_config()?.get_or(
+        "branchless.commitDescriptors.statusCommandCacheSeconds",
+        300,
+    )?;
+    Ok(u64::try_from(cache_seconds).unwrap_or(0))
+}
+
 /// If `true`, show the age of each commit in the smartlog.
 #[instrument]
 pub fn get_commit_descriptors_relative_time(repo: &Repo) -> eyre::Result<bool> {
@@ -241,6 +436,80 @@ pub fn get_commit_descriptors_relative_time(repo: &Repo) -> eyre::Result<bool> {
         .get_or("branchless.commitDescriptors.relativeTime", true)
 }
 
+/// The number of days that a commit must have been hidden before `git
+/// branchless gc` will delete its keep-alive reference. If unset, references
+/// are deleted as soon as their commits are hidden.
+#[instrument]
+pub fn get_gc_retention_days(repo: &Repo) -> eyre::Result<Option<i32>> {
+    repo.get_readonly_config()?
+        .get("branchless.gc.retentionDays")
+}
+
+/// The number of events which can be recorded since the last garbage
+/// collection before `gc` is run automatically by the hooks. If unset or `0`,
+/// garbage collection is never run automatically.
+#[instrument]
+pub fn get_gc_auto_events(repo: &Repo) -> eyre::Result<usize> {
+    let auto_events: i32 = repo
+        .get_readonly_config()?
+        .get_or("branchless.gc.autoEvents", 0)?;
+    Ok(usize::try_from(auto_events).unwrap_or(0))
+}
+
+/// The number of days of history to keep in the event log when running `git
+/// branchless gc --aggressive`.
+#[instrument]
+pub fn get_gc_event_log_retention_days(repo: &Repo) -> eyre::Result<u32> {
+    let retention_days: i32 = repo
+        .get_readonly_config()?
+        .get_or("branchless.gc.eventLogRetentionDays", 90)?;
+    Ok(u32::try_from(retention_days).unwrap_or(0))
+}
+
+/// The default value of `get_ref_namespace`.
+pub const DEFAULT_REF_NAMESPACE: &str = "refs/branchless/";
+
+/// The namespace under which git-branchless creates references for its own
+/// use, such as to keep commits alive. Always ends with a `/`.
+#[instrument]
+pub fn get_ref_namespace(repo: &Repo) -> eyre::Result<String> {
+    let ref_namespace: Option<String> = repo
+        .get_readonly_config()?
+        .get("branchless.core.refNamespace")?;
+    let ref_namespace = match ref_namespace {
+        Some(ref_namespace) if !ref_namespace.is_empty() => ref_namespace,
+        Some(_) | None => return Ok(DEFAULT_REF_NAMESPACE.to_string()),
+    };
+    if ref_namespace.ends_with('/') {
+        Ok(ref_namespace)
+    } else {
+        Ok(format!("{ref_namespace}/"))
+    }
+}
+
+/// The number of most recent event transactions which are left untouched when
+/// compacting the event log with `git branchless gc --aggressive`. Operations
+/// older than this can't be undone precisely.
+#[instrument]
+pub fn get_gc_event_log_compaction_depth(repo: &Repo) -> eyre::Result<usize> {
+    let compaction_depth: i32 = repo
+        .get_readonly_config()?
+        .get_or("branchless.gc.eventLogCompactionDepth", 1000)?;
+    Ok(usize::try_from(compaction_depth).unwrap_or(0))
+}
+
+/// The number of events which can be recorded after the last persisted event
+/// replayer snapshot before a new snapshot is saved. Commands which support
+/// snapshots then only need to replay the events recorded after the latest
+/// snapshot. If `0`, snapshots are not saved.
+#[instrument]
+pub fn get_event_replayer_snapshot_interval(repo: &Repo) -> eyre::Result<usize> {
+    let interval: i32 = repo
+        .get_readonly_config()?
+        .get_or("branchless.core.replayerSnapshotInterval", 1000)?;
+    Ok(usize::try_from(interval).unwrap_or(0))
+}
+
 /// Config key for `get_restack_warn_abandoned`.
 pub const RESTACK_WARN_ABANDONED_CONFIG_KEY: &str = "branchless.restack.warnAbandoned";
 
@@ -259,6 +528,10 @@ pub enum Hint {
     /// Suggest running `git restack` when the smartlog prints an abandoned commit.
     SmartlogFixAbandoned,
 
+    /// Suggest running `git fetch --unshallow` when the smartlog can't connect
+    /// commits to the main branch because of a shallow clone.
+    SmartlogUnshallow,
+
     /// Suggest showing more output with `git test show` using `--verbose`.
     TestShowVerbose,
 }
@@ -270,6 +543,7 @@ impl Hint {
             Hint::MoveImplicitHeadArgument => "branchless.hint.moveImplicitHeadArgument",
             Hint::RestackWarnAbandoned => "branchless.hint.restackWarnAbandoned",
             Hint::SmartlogFixAbandoned => "branchless.hint.smartlogFixAbandoned",
+            Hint::SmartlogUnshallow => "branchless.hint.smartlogUnshallow",
             Hint::TestShowVerbose => "branchless.hint.testShowVerbose",
         }
     }
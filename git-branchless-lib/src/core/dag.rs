@@ -2,10 +2,12 @@
 //! allows for efficient graph queries.
 
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Debug;
 use std::future::Future;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 
 use async_trait::async_trait;
 use eden_dag::namedag::MemNameDag;
@@ -19,8 +21,10 @@ use once_cell::sync::OnceCell;
 use tracing::{instrument, trace, warn};
 
 use crate::core::effects::{Effects, OperationType};
-use crate::core::eventlog::{CommitActivityStatus, EventCursor, EventReplayer};
-use crate::git::{Commit, MaybeZeroOid, NonZeroOid, Repo, Time};
+use crate::core::eventlog::{
+    CommitActivityStatus, Event, EventCursor, EventLogDb, EventReplayer, EventTransactionId,
+};
+use crate::git::{Commit, CommitGraph, ConfigRead, MaybeZeroOid, NonZeroOid, Repo, Time};
 
 use super::repo_ext::RepoReferencesSnapshot;
 
@@ -81,8 +85,69 @@ pub fn union_all(commits: &[CommitSet]) -> CommitSet {
         .fold(CommitSet::empty(), |acc, elem| acc.union(elem))
 }
 
+/// Get the commits whose parents have been overridden, either by replacing
+/// the commit with `git replace` or by listing it in the `info/grafts` file,
+/// along with the parents that Git uses for them instead.
+#[instrument]
+pub fn get_parent_overrides(repo: &Repo) -> eyre::Result<BTreeMap<NonZeroOid, Vec<NonZeroOid>>> {
+    let mut result = BTreeMap::new();
+
+    let use_replace_refs = std::env::var_os("GIT_NO_REPLACE_OBJECTS").is_none()
+        && repo
+            .get_readonly_config()?
+            .get_or("core.useReplaceRefs", true)?;
+    if use_replace_refs {
+        for reference in repo.get_replace_references()? {
+            let reference_name = reference.get_name()?;
+            let replaced_oid = match reference_name
+                .as_str()
+                .strip_prefix("refs/replace/")
+                .and_then(|oid| oid.parse::<NonZeroOid>().ok())
+            {
+                Some(replaced_oid) => replaced_oid,
+                None => continue,
+            };
+            if let Some(replacement_commit) = reference.peel_to_commit()? {
+                result.insert(replaced_oid, replacement_commit.get_parent_oids());
+            }
+        }
+    }
+
+    // Grafts take precedence over replacements, as in Git.
+    let grafts_path = repo.get_grafts_path();
+    let grafts = match std::fs::read_to_string(&grafts_path) {
+        Ok(grafts) => grafts,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err).wrap_err_with(|| format!("Reading {grafts_path:?}")),
+    };
+    for line in grafts.lines() {
+        if line.starts_with('#') {
+            continue;
+        }
+        let mut oids = line.split_whitespace().map(|oid| oid.parse::<NonZeroOid>());
+        if let Some(Ok(commit_oid)) = oids.next() {
+            let parent_oids: Result<Vec<_>, _> = oids.collect();
+            match parent_oids {
+                Ok(parent_oids) => {
+                    result.insert(commit_oid, parent_oids);
+                }
+                Err(err) => warn!(?line, ?err, "Ignoring malformed graft"),
+            }
+        }
+    }
+
+    Ok(result)
+}
+
 struct GitParentsBlocking {
     repo: Arc<Mutex<Repo>>,
+
+    /// Commits whose parents have been overridden; see `get_parent_overrides`.
+    parent_overrides: BTreeMap<NonZeroOid, Vec<NonZeroOid>>,
+
+    /// Git's on-disk `commit-graph` file, if any. Parents are read from here
+    /// when possible, since it's much faster than loading commit objects.
+    commit_graph: Option<CommitGraph>,
 }
 
 #[async_trait]
@@ -99,6 +164,22 @@ impl Parents for GitParentsBlocking {
             MaybeZeroOid::Zero => return Ok(Vec::new()),
         };
 
+        if let Some(parent_oids) = self.parent_overrides.get(&oid) {
+            return Ok(parent_oids
+                .iter()
+                .copied()
+                .map(CommitVertex::from)
+                .collect());
+        }
+
+        if let Some(parent_oids) = self
+            .commit_graph
+            .as_ref()
+            .and_then(|commit_graph| commit_graph.get_parent_oids(oid))
+        {
+            return Ok(parent_oids.into_iter().map(CommitVertex::from).collect());
+        }
+
         let repo = self.repo.lock().unwrap();
         let commit = repo
             .find_commit(oid)
@@ -143,6 +224,9 @@ pub struct Dag {
     /// A set containing all commits currently pointed to by local branches.
     pub branch_commits: CommitSet,
 
+    /// A set containing all commits currently pointed to by tags.
+    pub tag_commits: CommitSet,
+
     /// A set containing all commits that have been observed by the
     /// `EventReplayer`.
     observed_commits: CommitSet,
@@ -166,6 +250,7 @@ impl Dag {
             head_commit: self.head_commit.clone(),
             main_branch_commit: self.main_branch_commit.clone(),
             branch_commits: self.branch_commits.clone(),
+            tag_commits: self.tag_commits.clone(),
             observed_commits: self.observed_commits.clone(),
             obsolete_commits: self.obsolete_commits.clone(),
             public_commits: OnceCell::new(),
@@ -185,6 +270,7 @@ impl Dag {
         event_cursor: EventCursor,
         references_snapshot: &RepoReferencesSnapshot,
     ) -> eyre::Result<Self> {
+        Self::clear_cache_if_parents_changed(repo)?;
         let mut dag = Self::open_without_syncing(
             effects,
             repo,
@@ -196,6 +282,50 @@ impl Dag {
         Ok(dag)
     }
 
+    /// The parents recorded in the DAG depend on more than the commit objects
+    /// themselves:
+    ///
+    /// - In a shallow clone, commits at the shallow boundary are recorded
+    ///   without their parents. If the boundary moves (such as after `git
+    ///   fetch --deepen` or `git fetch --unshallow`), those commits would
+    ///   incorrectly remain parentless.
+    /// - `git replace` and `info/grafts` override the parents of commits.
+    ///
+    /// If any of these have changed since the DAG was built, discard the DAG
+    /// and let it be rebuilt.
+    #[instrument]
+    fn clear_cache_if_parents_changed(repo: &Repo) -> eyre::Result<()> {
+        use std::io::Write;
+
+        let read_if_exists = |path: &Path| -> eyre::Result<Vec<u8>> {
+            match std::fs::read(path) {
+                Ok(contents) => Ok(contents),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+                Err(err) => Err(err).wrap_err_with(|| format!("Reading {path:?}")),
+            }
+        };
+
+        let mut fingerprint = read_if_exists(&repo.get_shallow_path())?;
+        for (oid, parent_oids) in get_parent_overrides(repo)? {
+            writeln!(fingerprint, "{oid} {}", parent_oids.iter().join(" "))?;
+        }
+
+        let fingerprint_path = repo.get_branchless_dir()?.join("dag-parents");
+        if read_if_exists(&fingerprint_path)? == fingerprint {
+            return Ok(());
+        }
+
+        Self::clear_cache(repo)?;
+        if fingerprint.is_empty() {
+            std::fs::remove_file(&fingerprint_path)
+                .wrap_err_with(|| format!("Deleting {fingerprint_path:?}"))?;
+        } else {
+            std::fs::write(&fingerprint_path, fingerprint)
+                .wrap_err_with(|| format!("Writing {fingerprint_path:?}"))?;
+        }
+        Ok(())
+    }
+
     /// Initialize a DAG for the given repository, without updating it with new
     /// commits that may have appeared.
     ///
@@ -214,7 +344,9 @@ impl Dag {
         let RepoReferencesSnapshot {
             head_oid,
             main_branch_oid,
+            additional_main_branch_oids,
             branch_oid_to_names,
+            tag_oids,
         } = references_snapshot;
 
         let obsolete_commits: CommitSet = observed_commits
@@ -235,14 +367,21 @@ impl Dag {
             Some(head_oid) => CommitSet::from(*head_oid),
             None => CommitSet::empty(),
         };
-        let main_branch_commit = CommitSet::from(*main_branch_oid);
+        let main_branch_commit = CommitSet::from(*main_branch_oid).union(
+            &additional_main_branch_oids
+                .iter()
+                .copied()
+                .collect::<CommitSet>(),
+        );
         let branch_commits: CommitSet = branch_oid_to_names.keys().copied().collect();
+        let tag_commits: CommitSet = tag_oids.iter().copied().collect();
 
         Ok(Self {
             inner: dag,
             head_commit,
             main_branch_commit,
             branch_commits,
+            tag_commits,
             observed_commits,
             obsolete_commits,
             public_commits: Default::default(),
@@ -252,6 +391,24 @@ impl Dag {
         })
     }
 
+    /// Delete the on-disk DAG for the repository, so that it's rebuilt from
+    /// the current references the next time that it's opened.
+    ///
+    /// Commits are never removed from the DAG once added, so this discards
+    /// commits which are no longer referenced, such as the previous history
+    /// of a main branch which was rewound.
+    #[instrument]
+    pub fn clear_cache(repo: &Repo) -> eyre::Result<()> {
+        let dag_dir = repo.get_dag_dir()?;
+        match std::fs::remove_dir_all(&dag_dir) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => {
+                Err(err).wrap_err_with(|| format!("Deleting DAG directory at: {:?}", &dag_dir))
+            }
+        }
+    }
+
     #[instrument]
     fn open_inner_dag(repo: &Repo) -> eyre::Result<eden_dag::Dag> {
         let dag_dir = repo.get_dag_dir()?;
@@ -272,7 +429,8 @@ impl Dag {
         let non_master_heads = self
             .observed_commits
             .union(&self.head_commit)
-            .union(&self.branch_commits);
+            .union(&self.branch_commits)
+            .union(&self.tag_commits);
         self.sync_from_oids(effects, repo, master_heads, non_master_heads)
     }
 
@@ -305,10 +463,14 @@ impl Dag {
             .collect_vec();
         let heads = [master_heads, non_master_heads].concat();
 
+        let parent_overrides = get_parent_overrides(repo)?;
+        let commit_graph = CommitGraph::open_for_repo(repo)?;
         let repo = repo.try_clone()?;
         futures::executor::block_on(self.inner.add_heads_and_flush(
             &GitParentsBlocking {
                 repo: Arc::new(Mutex::new(repo)),
+                parent_overrides,
+                commit_graph,
             },
             &VertexListWithOptions::from(heads),
         ))?;
@@ -343,6 +505,7 @@ impl Dag {
             inner,
             head_commit: self.head_commit.clone(),
             branch_commits: self.branch_commits.clone(),
+            tag_commits: self.tag_commits.clone(),
             main_branch_commit: self.main_branch_commit.clone(),
             observed_commits: self.observed_commits.clone(),
             obsolete_commits: CommitSet::empty(),
@@ -473,7 +636,7 @@ impl Dag {
 
     /// Determine the set of commits which are considered to be "visible". A
     /// commit is "visible" if it is not obsolete or has a non-obsolete
-    /// descendant.
+    /// descendant. Commits pointed to by branches or tags are always visible.
     #[instrument]
     pub fn query_visible_heads(&self) -> eyre::Result<&CommitSet> {
         self.visible_heads.get_or_try_init(|| {
@@ -481,7 +644,8 @@ impl Dag {
                 .union(&self.observed_commits.difference(&self.obsolete_commits))
                 .union(&self.head_commit)
                 .union(&self.main_branch_commit)
-                .union(&self.branch_commits);
+                .union(&self.branch_commits)
+                .union(&self.tag_commits);
             let visible_heads = self.run_blocking(self.inner.heads(visible_heads))?;
             Ok(visible_heads)
         })
@@ -670,6 +834,43 @@ impl Dag {
         Ok(result)
     }
 
+    /// For each of the given commits, find its merge-bases with the main
+    /// branch, i.e. the nearest public ancestors of the commit. A public
+    /// commit is its own merge-base.
+    ///
+    /// This is equivalent to calling `query_heads_ancestors` on the public
+    /// ancestors of each commit individually, but the set of candidate
+    /// merge-bases is computed once for all commits, so each individual
+    /// query only has to consider a handful of public commits rather than the
+    /// entire main branch history.
+    #[instrument]
+    pub fn query_main_branch_merge_bases_many(
+        &self,
+        oids: &[NonZeroOid],
+    ) -> eyre::Result<HashMap<NonZeroOid, CommitSet>> {
+        let public_commits = self.query_public_commits_slow()?;
+        let commits: CommitSet = oids.iter().copied().collect();
+        let draft_ancestors = self.query_ancestors(commits)?.difference(public_commits);
+        let candidates = self
+            .query_parents(draft_ancestors)?
+            .intersection(public_commits);
+
+        let mut result = HashMap::new();
+        for oid in oids {
+            if result.contains_key(oid) {
+                continue;
+            }
+            let merge_bases = if self.set_contains(public_commits, *oid)? {
+                CommitSet::from(*oid)
+            } else {
+                let commit_ancestors = self.query_ancestors(CommitSet::from(*oid))?;
+                self.query_heads_ancestors(commit_ancestors.intersection(&candidates))?
+            };
+            result.insert(*oid, merge_bases);
+        }
+        Ok(result)
+    }
+
     /// Given a CommitSet, return a list of CommitSets, each representing a
     /// connected component of the set.
     ///
@@ -797,3 +998,111 @@ pub fn sorted_commit_set<'repo>(
 
     Ok(commits)
 }
+
+/// Record `CommitObservedEvent`s for the commits which were brought into the
+/// repository by remote-tracking reference updates (such as by `git fetch`)
+/// since the last time this was called.
+///
+/// This is done when the DAG is synced rather than in the
+/// `reference-transaction` hook, so that fetches don't pay for a DAG sync.
+/// Commits which were already observed, or which the user is already tracking,
+/// are skipped. Each commit is recorded with the time of the reference update
+/// which brought it in.
+///
+/// Returns the number of commits which were recorded as observed.
+#[instrument]
+pub fn record_observed_commits(
+    effects: &Effects,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    event_replayer: &EventReplayer,
+    dag: &mut Dag,
+) -> eyre::Result<usize> {
+    let event_cursor = event_replayer.make_default_cursor();
+    let start_cursor = match event_log_db.get_observed_cursor()? {
+        Some(observed_cursor) => event_replayer.advance_cursor(observed_cursor, 0),
+        None => event_replayer.make_cursor(0),
+    };
+
+    // Group the updates by transaction, since a single fetch may update
+    // several remote-tracking references at once.
+    let mut updates: Vec<(EventTransactionId, f64, CommitSet, CommitSet)> = Vec::new();
+    for event in event_replayer.get_events_since_cursor(start_cursor) {
+        let (timestamp, event_tx_id, old_oid, new_oid) = match event {
+            Event::RemoteRefUpdateEvent {
+                timestamp,
+                event_tx_id,
+                ref_name: _,
+                old_oid,
+                new_oid: MaybeZeroOid::NonZero(new_oid),
+            } => (*timestamp, *event_tx_id, *old_oid, *new_oid),
+            _ => continue,
+        };
+        // Either commit may have since been garbage-collected.
+        if repo.find_commit(new_oid)?.is_none() {
+            continue;
+        }
+        let old_heads = match old_oid {
+            MaybeZeroOid::NonZero(old_oid) if repo.find_commit(old_oid)?.is_some() => {
+                CommitSet::from(old_oid)
+            }
+            MaybeZeroOid::NonZero(_) | MaybeZeroOid::Zero => CommitSet::empty(),
+        };
+        let new_heads = CommitSet::from(new_oid);
+        match updates.last_mut() {
+            Some((last_event_tx_id, _, last_old_heads, last_new_heads))
+                if *last_event_tx_id == event_tx_id =>
+            {
+                *last_old_heads = last_old_heads.union(&old_heads);
+                *last_new_heads = last_new_heads.union(&new_heads);
+            }
+            _ => updates.push((event_tx_id, timestamp, old_heads, new_heads)),
+        }
+    }
+
+    let mut observed_oids: HashSet<NonZeroOid> = HashSet::new();
+    let mut observed_events = Vec::new();
+    if !updates.is_empty() {
+        let main_branch_commit = dag.main_branch_commit.clone();
+        let all_heads = union_all(
+            &updates
+                .iter()
+                .flat_map(|(_, _, old_heads, new_heads)| [old_heads.clone(), new_heads.clone()])
+                .collect_vec(),
+        );
+        dag.sync_from_oids(effects, repo, main_branch_commit.clone(), all_heads)?;
+
+        for (_event_tx_id, timestamp, old_heads, new_heads) in updates {
+            let old_heads = old_heads.union(&main_branch_commit);
+            let commit_oids = dag.commit_set_to_vec(&dag.query_only(new_heads, old_heads)?)?;
+            for commit_oid in commit_oids {
+                if event_replayer.get_cursor_commit_activity_status(event_cursor, commit_oid)
+                    == CommitActivityStatus::Inactive
+                    && event_replayer
+                        .get_cursor_commit_observed_time(event_cursor, commit_oid)
+                        .is_none()
+                    && observed_oids.insert(commit_oid)
+                {
+                    observed_events.push((timestamp, commit_oid));
+                }
+            }
+        }
+    }
+
+    let num_observed = observed_events.len();
+    if !observed_events.is_empty() {
+        let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "observe commits")?;
+        event_log_db.add_events(
+            observed_events
+                .into_iter()
+                .map(|(timestamp, commit_oid)| Event::CommitObservedEvent {
+                    timestamp,
+                    event_tx_id,
+                    commit_oid,
+                })
+                .collect(),
+        )?;
+    }
+    event_log_db.set_observed_cursor(event_cursor)?;
+    Ok(num_observed)
+}
@@ -9,22 +9,42 @@ use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
 
 use std::fmt::Display;
+use std::ops::ControlFlow;
 use std::str::FromStr;
 use std::time::{Duration, SystemTime};
 
 use eyre::Context;
-use tracing::{error, instrument};
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use tracing::{error, instrument, warn};
 
+use crate::core::config::{
+    get_event_replayer_snapshot_interval, get_ref_namespace, DEFAULT_REF_NAMESPACE,
+};
 use crate::core::effects::{Effects, OperationType};
 use crate::core::repo_ext::RepoExt;
-use crate::git::{CategorizedReferenceName, MaybeZeroOid, NonZeroOid, ReferenceName, Repo};
+use crate::git::{
+    CategorizedReferenceName, DiffStat, MaybeZeroOid, NonZeroOid, ReferenceName, Repo,
+};
 
+use super::dag::{CommitSet, Dag};
 use super::repo_ext::RepoReferencesSnapshot;
 
 /// When this environment variable is set, we reuse the ID for the transaction
 /// which the caller has already started.
 pub const BRANCHLESS_TRANSACTION_ID_ENV_VAR: &str = "BRANCHLESS_TRANSACTION_ID";
 
+/// Git sets this environment variable to the name of the command being run
+/// (such as `rebase` or `pull`) when that command updates references, so that
+/// its hooks and subprocesses can identify it.
+pub const GIT_REFLOG_ACTION_ENV_VAR: &str = "GIT_REFLOG_ACTION";
+
+/// Reference updates which are identical to the most recent update of the same
+/// reference and which occur within this many seconds of it are not recorded.
+/// Some tools (such as IDEs) invoke hooks many times in quick succession, which
+/// would otherwise flood the event log with duplicate events.
+pub const EVENT_COALESCE_WINDOW_SECS: f64 = 1.0;
+
 // Wrapper around the row stored directly in the database.
 #[derive(Clone, Debug)]
 struct Row {
@@ -53,7 +73,7 @@ struct Row {
 ///
 /// Unlike in a database, there is no specific guarantee that an event
 /// transaction is an atomic unit of work.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum EventTransactionId {
     /// A normal transaction ID.
     Id(isize),
@@ -134,6 +154,71 @@ pub enum Event {
         message: Option<ReferenceName>,
     },
 
+    /// Indicates that a reference was renamed, such as with `git branch -m`.
+    ///
+    /// Git reports a rename as the deletion of the old reference and the
+    /// creation of the new reference. Recording it as a single event lets us
+    /// track the reference across the rename, rather than treating the new
+    /// reference as unrelated to the old one.
+    RefRenameEvent {
+        /// The timestamp of the event.
+        timestamp: f64,
+
+        /// The transaction ID of the event.
+        event_tx_id: EventTransactionId,
+
+        /// The full name of the reference before the rename.
+        old_ref_name: ReferenceName,
+
+        /// The full name of the reference after the rename.
+        new_ref_name: ReferenceName,
+
+        /// The OID that the reference pointed to when it was renamed.
+        oid: NonZeroOid,
+    },
+
+    /// Indicates that a remote-tracking reference was updated, such as by
+    /// `git fetch` or `git push`.
+    ///
+    /// These are recorded separately from `RefUpdateEvent`s, since they
+    /// reflect the state of a remote rather than actions that the user took in
+    /// this repository. In particular, the upstream of the main branch moving
+    /// means that commits have become public.
+    RemoteRefUpdateEvent {
+        /// The timestamp of the event.
+        timestamp: f64,
+
+        /// The transaction ID of the event.
+        event_tx_id: EventTransactionId,
+
+        /// The full name of the remote-tracking reference that was updated.
+        ///
+        /// For example, `refs/remotes/origin/master`.
+        ref_name: ReferenceName,
+
+        /// The old referent OID.
+        old_oid: MaybeZeroOid,
+
+        /// The updated referent OID.
+        new_oid: MaybeZeroOid,
+    },
+
+    /// Indicates that a commit was first observed in this repository, such as
+    /// when it was brought in by `git fetch`.
+    ///
+    /// Unlike `CommitEvent`, this doesn't mark the commit as active. It only
+    /// records when the user learned about the commit.
+    CommitObservedEvent {
+        /// The timestamp of the event.
+        timestamp: f64,
+
+        /// The transaction ID of the event.
+        event_tx_id: EventTransactionId,
+
+        /// The OID of the commit that was observed.
+        commit_oid: NonZeroOid,
+    },
+
     /// Indicate that the user made a commit.
     ///
     /// User commits should be marked as active.
@@ -178,6 +263,34 @@ pub enum Event {
         commit_oid: NonZeroOid,
     },
 
+    /// Indicates that a commit was pinned by the user, so that it is never
+    /// garbage-collected, even if it is hidden.
+    PinEvent {
+        /// The timestamp of the event.
+        timestamp: f64,
+
+        /// The transaction ID of the event.
+        event_tx_id: EventTransactionId,
+
+        /// The OID of the commit that was pinned.
+        commit_oid: NonZeroOid,
+    },
+
+    /// Indicates that a commit was unpinned by the user.
+    ///
+    /// If the commit in question was not already pinned, then this has no
+    /// practical effect.
+    UnpinEvent {
+        /// The timestamp of the event.
+        timestamp: f64,
+
+        /// The transaction ID of the event.
+        event_tx_id: EventTransactionId,
+
+        /// The OID of the commit that was unpinned.
+        commit_oid: NonZeroOid,
+    },
+
     /// Represents a snapshot of the working copy made at a certain time,
     /// typically before a potentially-destructive operation.
     WorkingCopySnapshot {
@@ -201,14 +314,77 @@ pub enum Event {
 }
 
 impl Event {
+    /// Get the OIDs of the commits which this event refers to.
+    ///
+    /// References may point to objects other than commits, so the OIDs of
+    /// reference updates are not included.
+    pub fn get_commit_oids(&self) -> Vec<NonZeroOid> {
+        match self {
+            Event::RewriteEvent {
+                old_commit_oid,
+                new_commit_oid,
+                ..
+            } => [*old_commit_oid, *new_commit_oid]
+                .into_iter()
+                .filter_map(|oid| match oid {
+                    MaybeZeroOid::NonZero(oid) => Some(oid),
+                    MaybeZeroOid::Zero => None,
+                })
+                .collect(),
+            Event::RefUpdateEvent { .. }
+            | Event::RefRenameEvent { .. }
+            | Event::RemoteRefUpdateEvent { .. } => Vec::new(),
+            Event::CommitEvent { commit_oid, .. }
+            | Event::CommitObservedEvent { commit_oid, .. }
+            | Event::ObsoleteEvent { commit_oid, .. }
+            | Event::UnobsoleteEvent { commit_oid, .. }
+            | Event::PinEvent { commit_oid, .. }
+            | Event::UnpinEvent { commit_oid, .. } => vec![*commit_oid],
+            Event::WorkingCopySnapshot {
+                head_oid,
+                commit_oid,
+                ..
+            } => match head_oid {
+                MaybeZeroOid::NonZero(head_oid) => vec![*head_oid, *commit_oid],
+                MaybeZeroOid::Zero => vec![*commit_oid],
+            },
+        }
+    }
+
+    /// Get the names of the references which this event updates.
+    pub fn get_ref_names(&self) -> Vec<&ReferenceName> {
+        match self {
+            Event::RefUpdateEvent { ref_name, .. }
+            | Event::RemoteRefUpdateEvent { ref_name, .. } => vec![ref_name],
+            Event::RefRenameEvent {
+                old_ref_name,
+                new_ref_name,
+                ..
+            } => vec![old_ref_name, new_ref_name],
+            Event::RewriteEvent { .. }
+            | Event::CommitEvent { .. }
+            | Event::CommitObservedEvent { .. }
+            | Event::ObsoleteEvent { .. }
+            | Event::UnobsoleteEvent { .. }
+            | Event::PinEvent { .. }
+            | Event::UnpinEvent { .. }
+            | Event::WorkingCopySnapshot { .. } => Vec::new(),
+        }
+    }
+
     /// Get the timestamp associated with this event.
     pub fn get_timestamp(&self) -> SystemTime {
         let timestamp = match self {
             Event::RewriteEvent { timestamp, .. } => timestamp,
             Event::RefUpdateEvent { timestamp, .. } => timestamp,
+            Event::RefRenameEvent { timestamp, .. } => timestamp,
+            Event::RemoteRefUpdateEvent { timestamp, .. } => timestamp,
+            Event::CommitObservedEvent { timestamp, .. } => timestamp,
             Event::CommitEvent { timestamp, .. } => timestamp,
             Event::ObsoleteEvent { timestamp, .. } => timestamp,
             Event::UnobsoleteEvent { timestamp, .. } => timestamp,
+            Event::PinEvent { timestamp, .. } => timestamp,
+            Event::UnpinEvent { timestamp, .. } => timestamp,
             Event::WorkingCopySnapshot { timestamp, .. } => timestamp,
         };
         SystemTime::UNIX_EPOCH + Duration::from_secs_f64(*timestamp)
@@ -219,9 +395,14 @@ impl Event {
         match self {
             Event::RewriteEvent { event_tx_id, .. } => *event_tx_id,
             Event::RefUpdateEvent { event_tx_id, .. } => *event_tx_id,
+            Event::RefRenameEvent { event_tx_id, .. } => *event_tx_id,
+            Event::RemoteRefUpdateEvent { event_tx_id, .. } => *event_tx_id,
+            Event::CommitObservedEvent { event_tx_id, .. } => *event_tx_id,
             Event::CommitEvent { event_tx_id, .. } => *event_tx_id,
             Event::ObsoleteEvent { event_tx_id, .. } => *event_tx_id,
             Event::UnobsoleteEvent { event_tx_id, .. } => *event_tx_id,
+            Event::PinEvent { event_tx_id, .. } => *event_tx_id,
+            Event::UnpinEvent { event_tx_id, .. } => *event_tx_id,
             Event::WorkingCopySnapshot { event_tx_id, .. } => *event_tx_id,
         }
     }
@@ -240,6 +421,18 @@ impl TryFrom<Event> for Row {
                 event_tx_id: EventTransactionId::Suppressed,
                 ..
             }
+            | Event::RefRenameEvent {
+                event_tx_id: EventTransactionId::Suppressed,
+                ..
+            }
+            | Event::RemoteRefUpdateEvent {
+                event_tx_id: EventTransactionId::Suppressed,
+                ..
+            }
+            | Event::CommitObservedEvent {
+                event_tx_id: EventTransactionId::Suppressed,
+                ..
+            }
             | Event::CommitEvent {
                 event_tx_id: EventTransactionId::Suppressed,
                 ..
@@ -252,6 +445,14 @@ impl TryFrom<Event> for Row {
                 event_tx_id: EventTransactionId::Suppressed,
                 ..
             }
+            | Event::PinEvent {
+                event_tx_id: EventTransactionId::Suppressed,
+                ..
+            }
+            | Event::UnpinEvent {
+                event_tx_id: EventTransactionId::Suppressed,
+                ..
+            }
             | Event::WorkingCopySnapshot {
                 event_tx_id: EventTransactionId::Suppressed,
                 ..
@@ -289,6 +490,52 @@ impl TryFrom<Event> for Row {
                 message,
             },
 
+            Event::RefRenameEvent {
+                timestamp,
+                event_tx_id: EventTransactionId::Id(event_tx_id),
+                old_ref_name,
+                new_ref_name,
+                oid,
+            } => Row {
+                timestamp,
+                event_tx_id,
+                type_: String::from("ref-rename"),
+                ref1: Some(oid.into()),
+                ref2: Some(new_ref_name),
+                ref_name: Some(old_ref_name),
+                message: None,
+            },
+
+            Event::RemoteRefUpdateEvent {
+                timestamp,
+                event_tx_id: EventTransactionId::Id(event_tx_id),
+                ref_name,
+                old_oid,
+                new_oid,
+            } => Row {
+                timestamp,
+                event_tx_id,
+                type_: String::from("remote-ref-move"),
+                ref1: Some(old_oid.into()),
+                ref2: Some(new_oid.into()),
+                ref_name: Some(ref_name),
+                message: None,
+            },
+
+            Event::CommitObservedEvent {
+                timestamp,
+                event_tx_id: EventTransactionId::Id(event_tx_id),
+                commit_oid,
+            } => Row {
+                timestamp,
+                event_tx_id,
+                type_: String::from("commit-observed"),
+                ref1: Some(commit_oid.into()),
+                ref2: None,
+                ref_name: None,
+                message: None,
+            },
+
             Event::CommitEvent {
                 timestamp,
                 event_tx_id: EventTransactionId::Id(event_tx_id),
@@ -331,6 +578,34 @@ impl TryFrom<Event> for Row {
                 message: None,
             },
 
+            Event::PinEvent {
+                timestamp,
+                event_tx_id: EventTransactionId::Id(event_tx_id),
+                commit_oid,
+            } => Row {
+                timestamp,
+                event_tx_id,
+                type_: String::from("pin"),
+                ref1: Some(commit_oid.into()),
+                ref2: None,
+                ref_name: None,
+                message: None,
+            },
+
+            Event::UnpinEvent {
+                timestamp,
+                event_tx_id: EventTransactionId::Id(event_tx_id),
+                commit_oid,
+            } => Row {
+                timestamp,
+                event_tx_id,
+                type_: String::from("unpin"),
+                ref1: Some(commit_oid.into()),
+                ref2: None,
+                ref_name: None,
+                message: None,
+            },
+
             Event::WorkingCopySnapshot {
                 timestamp,
                 event_tx_id: EventTransactionId::Id(event_tx_id),
@@ -406,6 +681,44 @@ fn try_from_row_helper(row: &Row) -> Result<Event, eyre::Error> {
             }
         }
 
+        "ref-rename" => {
+            let oid: NonZeroOid = get_oid(&ref1, "reference OID")?.try_into()?;
+            let old_ref_name =
+                ref_name.ok_or_else(|| eyre::eyre!("ref-rename event missing old ref name"))?;
+            let new_ref_name =
+                ref2.ok_or_else(|| eyre::eyre!("ref-rename event missing new ref name"))?;
+            Event::RefRenameEvent {
+                timestamp,
+                event_tx_id,
+                old_ref_name,
+                new_ref_name,
+                oid,
+            }
+        }
+
+        "remote-ref-move" => {
+            let ref_name =
+                ref_name.ok_or_else(|| eyre::eyre!("remote-ref-move event missing ref name"))?;
+            let old_oid = get_oid(&ref1, "old OID")?;
+            let new_oid = get_oid(&ref2, "new OID")?;
+            Event::RemoteRefUpdateEvent {
+                timestamp,
+                event_tx_id,
+                ref_name,
+                old_oid,
+                new_oid,
+            }
+        }
+
+        "commit-observed" => {
+            let commit_oid: NonZeroOid = get_oid(&ref1, "commit OID")?.try_into()?;
+            Event::CommitObservedEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid,
+            }
+        }
+
         "commit" => {
             let commit_oid: NonZeroOid = get_oid(&ref1, "commit OID")?.try_into()?;
             Event::CommitEvent {
@@ -433,6 +746,24 @@ fn try_from_row_helper(row: &Row) -> Result<Event, eyre::Error> {
             }
         }
 
+        "pin" => {
+            let commit_oid: NonZeroOid = get_oid(&ref1, "commit OID")?.try_into()?;
+            Event::PinEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid,
+            }
+        }
+
+        "unpin" => {
+            let commit_oid: NonZeroOid = get_oid(&ref1, "commit OID")?.try_into()?;
+            Event::UnpinEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid,
+            }
+        }
+
         "snapshot" => {
             let head_oid: MaybeZeroOid = get_oid(&ref1, "head OID")?;
             let commit_oid: NonZeroOid = get_oid(&ref2, "commit OID")?.try_into()?;
@@ -464,9 +795,117 @@ impl TryFrom<Row> for Event {
     }
 }
 
+/// The machine and user which created an event transaction, and therefore all
+/// of the events in it. This distinguishes events made on different machines
+/// when the repository is on a shared network filesystem.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransactionOrigin {
+    /// The name of the machine, if known.
+    pub hostname: Option<String>,
+
+    /// The name of the user, if known.
+    pub username: Option<String>,
+}
+
+impl TransactionOrigin {
+    /// Determine the origin of transactions created by the current process.
+    ///
+    /// The hostname is read with `gethostname(2)` on Unix, and from the
+    /// `COMPUTERNAME` environment variable elsewhere. The username is read from
+    /// the `USER` or `USERNAME` environment variables.
+    pub fn current() -> Self {
+        let non_empty = |value: String| {
+            let value = value.trim();
+            if value.is_empty() {
+                None
+            } else {
+                Some(value.to_owned())
+            }
+        };
+        let get_env = |names: &[&str]| {
+            names
+                .iter()
+                .filter_map(|name| std::env::var(name).ok())
+                .find_map(non_empty)
+        };
+        let hostname = Self::get_hostname().and_then(non_empty);
+        let username = get_env(&["USER", "USERNAME"]);
+        Self { hostname, username }
+    }
+
+    #[cfg(unix)]
+    fn get_hostname() -> Option<String> {
+        let mut buf = [0_u8; 256];
+        // SAFETY: `buf` is valid for writes of `buf.len()` bytes.
+        let result = unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) };
+        if result != 0 {
+            return None;
+        }
+        // The name may be truncated without a terminating NUL byte.
+        let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+        String::from_utf8(buf[..len].to_vec()).ok()
+    }
+
+    #[cfg(not(unix))]
+    fn get_hostname() -> Option<String> {
+        std::env::var("COMPUTERNAME").ok()
+    }
+
+    /// Whether this origin is known to be a different machine or user than
+    /// `other`. Fields which are unknown for either origin are not compared,
+    /// since hooks may run with a reduced environment.
+    pub fn differs_from(&self, other: &Self) -> bool {
+        let differs = |lhs: &Option<String>, rhs: &Option<String>| match (lhs, rhs) {
+            (Some(lhs), Some(rhs)) => lhs != rhs,
+            _ => false,
+        };
+        differs(&self.hostname, &other.hostname) || differs(&self.username, &other.username)
+    }
+}
+
+impl Display for TransactionOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.username, &self.hostname) {
+            (Some(username), Some(hostname)) => write!(f, "{username}@{hostname}"),
+            (Some(username), None) => write!(f, "{username}"),
+            (None, Some(hostname)) => write!(f, "{hostname}"),
+            (None, None) => write!(f, "unknown"),
+        }
+    }
+}
+
+/// Criteria for selecting events with `EventLogDb::query`. An event must match
+/// all of the criteria which are set.
+#[derive(Clone, Debug, Default)]
+pub struct EventFilter {
+    /// If non-empty, only include events whose type is one of these, as stored
+    /// in the database (such as `commit`, `ref-move`, or `rewrite`).
+    pub event_types: Vec<String>,
+
+    /// Only include events which affect the reference with this full name.
+    pub ref_name: Option<ReferenceName>,
+
+    /// Only include events which refer to this commit.
+    pub commit_oid: Option<NonZeroOid>,
+
+    /// Only include events belonging to this transaction.
+    pub event_tx_id: Option<EventTransactionId>,
+
+    /// Only include events which happened at or after this time.
+    pub start_time: Option<SystemTime>,
+
+    /// Only include events which happened before this time.
+    pub end_time: Option<SystemTime>,
+}
+
 /// Stores `Event`s on disk.
 pub struct EventLogDb<'conn> {
     conn: &'conn rusqlite::Connection,
+
+    /// The ID of the repository whose events this handle operates on. This is
+    /// empty unless the database is shared between multiple clones. See
+    /// `Repo::get_db_conn`.
+    repo_id: String,
 }
 
 impl std::fmt::Debug for EventLogDb<'_> {
@@ -475,143 +914,1383 @@ impl std::fmt::Debug for EventLogDb<'_> {
     }
 }
 
-#[instrument]
-fn init_tables(conn: &rusqlite::Connection) -> eyre::Result<()> {
-    conn.execute(
-        "
-CREATE TABLE IF NOT EXISTS event_log (
-    timestamp REAL NOT NULL,
-    type TEXT NOT NULL,
-    event_tx_id INTEGER NOT NULL,
-    old_ref TEXT,
-    new_ref TEXT,
-    ref_name TEXT,
-    message TEXT
-)
-",
-        rusqlite::params![],
-    )
-    .wrap_err("Creating `event_log` table")?;
+fn insert_row(tx: &rusqlite::Transaction, row: &Row, position: Option<f64>) -> eyre::Result<()> {
+    let Row {
+        timestamp,
+        type_,
+        event_tx_id,
+        ref1,
+        ref2,
+        ref_name,
+        message,
+    } = row;
 
-    conn.execute(
+    let ref1 = ref1.as_ref().map(|x| x.as_str());
+    let ref2 = ref2.as_ref().map(|x| x.as_str());
+    let ref_name = ref_name.as_ref().map(|x| x.as_str());
+    let message = message.as_ref().map(|x| x.as_str());
+
+    tx.execute(
         "
-CREATE TABLE IF NOT EXISTS event_transactions (
-    timestamp REAL NOT NULL,
+INSERT INTO event_log
+(timestamp, type, event_tx_id, old_ref, new_ref, ref_name, message, position)
+VALUES
+(:timestamp, :type, :event_tx_id, :old_ref, :new_ref, :ref_name, :message, :position)
+    ",
+        rusqlite::named_params! {
+            ":timestamp": timestamp,
+            ":type": &type_,
+            ":event_tx_id": event_tx_id,
+            ":old_ref": &ref1,
+            ":new_ref": &ref2,
+            ":ref_name": &ref_name,
+            ":message": &message,
+            ":position": position,
+        },
+    )?;
+    Ok(())
+}
 
-    -- Set as `PRIMARY KEY` to have SQLite select a value automatically. Set as
-    -- `AUTOINCREMENT` to ensure that SQLite doesn't reuse the value later if a
-    -- row is deleted. (We don't plan to delete rows right now, but maybe
-    -- later?)
-    event_tx_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+fn read_row(row: &rusqlite::Row) -> rusqlite::Result<Row> {
+    let timestamp: f64 = row.get("timestamp")?;
+    let event_tx_id: isize = row.get("event_tx_id")?;
+    let type_: String = row.get("type")?;
+    let ref_name: Option<String> = row.get("ref_name")?;
+    let old_ref: Option<String> = row.get("old_ref")?;
+    let new_ref: Option<String> = row.get("new_ref")?;
+    let message: Option<String> = row.get("message")?;
+
+    Ok(Row {
+        timestamp,
+        event_tx_id,
+        type_,
+        ref_name: ref_name.map(ReferenceName::from),
+        ref1: old_ref.map(ReferenceName::from),
+        ref2: new_ref.map(ReferenceName::from),
+        message: message.map(ReferenceName::from),
+    })
+}
+
+/// A serializable copy of the contents of the event log, which can be used to
+/// back up the event log or move it to another repository.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EventLogExport {
+    /// The event transactions, in order of creation.
+    pub transactions: Vec<ExportedTransaction>,
+
+    /// The events, from oldest to newest.
+    pub events: Vec<ExportedEvent>,
+}
+
+/// An event transaction in an [`EventLogExport`].
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ExportedTransaction {
+    /// The ID of the transaction in the exported event log. Transactions are
+    /// assigned new IDs when imported.
+    pub event_tx_id: isize,
+
+    /// The time at which the transaction was created.
+    pub timestamp: f64,
+
+    /// The message describing the transaction.
+    pub message: Option<String>,
+
+    /// The Git command which caused the transaction to be created, if known.
+    #[serde(default)]
+    pub command: Option<String>,
+
+    /// The name of the machine on which the transaction was created, if known.
+    #[serde(default)]
+    pub hostname: Option<String>,
+
+    /// The name of the user who created the transaction, if known.
+    #[serde(default)]
+    pub username: Option<String>,
+
+    /// The reason which the user gave for the transaction, if any.
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// An event in an [`EventLogExport`]. The fields correspond to the columns of
+/// the `event_log` table.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ExportedEvent {
+    /// The time at which the event happened.
+    pub timestamp: f64,
+
+    /// The type of the event.
+    #[serde(rename = "type")]
+    pub type_: String,
+
+    /// The ID of the transaction that the event belongs to.
+    pub event_tx_id: isize,
+
+    /// The first reference or OID associated with the event, if any.
+    pub old_ref: Option<String>,
+
+    /// The second reference or OID associated with the event, if any.
+    pub new_ref: Option<String>,
+
+    /// The name of the reference associated with the event, if any.
+    pub ref_name: Option<String>,
+
+    /// The message associated with the event, if any.
+    pub message: Option<String>,
+}
+
+impl TryFrom<Event> for ExportedEvent {
+    type Error = ();
+
+    fn try_from(event: Event) -> Result<Self, Self::Error> {
+        let Row {
+            timestamp,
+            type_,
+            event_tx_id,
+            ref1,
+            ref2,
+            ref_name,
+            message,
+        } = Row::try_from(event)?;
+        Ok(ExportedEvent {
+            timestamp,
+            type_,
+            event_tx_id,
+            old_ref: ref1.map(|ref1| ref1.as_str().to_owned()),
+            new_ref: ref2.map(|ref2| ref2.as_str().to_owned()),
+            ref_name: ref_name.map(|ref_name| ref_name.as_str().to_owned()),
+            message: message.map(|message| message.as_str().to_owned()),
+        })
+    }
+}
+
+impl TryFrom<ExportedEvent> for Event {
+    type Error = eyre::Error;
+
+    fn try_from(event: ExportedEvent) -> Result<Self, Self::Error> {
+        let ExportedEvent {
+            timestamp,
+            type_,
+            event_tx_id,
+            old_ref,
+            new_ref,
+            ref_name,
+            message,
+        } = event;
+        Event::try_from(Row {
+            timestamp,
+            type_,
+            event_tx_id,
+            ref1: old_ref.map(ReferenceName::from),
+            ref2: new_ref.map(ReferenceName::from),
+            ref_name: ref_name.map(ReferenceName::from),
+            message: message.map(ReferenceName::from),
+        })
+    }
+}
+
+/// A migration which brings the database schema from the previous version to
+/// the next version.
+struct Migration {
+    /// A description of the migration, used for error messages.
+    description: &'static str,
+
+    /// The SQL statements to execute to apply the migration.
+    sql: &'static str,
+}
+
+/// The ordered list of migrations to apply to the database. The schema version
+/// of the database is the number of migrations which have been applied to it.
+///
+/// Migrations must never be modified or reordered once released, since
+/// existing databases will have already applied them. To change the schema,
+/// append a new migration.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        description: "Create initial tables",
+        // These use `IF NOT EXISTS` because databases created before the
+        // introduction of schema versioning already have these tables, but
+        // have no recorded schema version.
+        sql: "
+CREATE TABLE IF NOT EXISTS event_log (
+    timestamp REAL NOT NULL,
+    type TEXT NOT NULL,
+    event_tx_id INTEGER NOT NULL,
+    old_ref TEXT,
+    new_ref TEXT,
+    ref_name TEXT,
+    message TEXT
+);
+
+CREATE TABLE IF NOT EXISTS event_transactions (
+    timestamp REAL NOT NULL,
+
+    -- Set as `PRIMARY KEY` to have SQLite select a value automatically. Set as
+    -- `AUTOINCREMENT` to ensure that SQLite doesn't reuse the value later if a
+    -- row is deleted. (We don't plan to delete rows right now, but maybe
+    -- later?)
+    event_tx_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
 
     message TEXT
+);
+
+CREATE TABLE IF NOT EXISTS gc_cursor (
+    -- The ID of the first event which has not yet been considered by `gc`.
+    -- This table holds at most one row.
+    event_id INTEGER NOT NULL
+);
+",
+    },
+    Migration {
+        description: "Record the invoking command for each transaction",
+        sql: "
+-- The Git command which caused the transaction to be created, if known.
+ALTER TABLE event_transactions ADD COLUMN command TEXT;
+",
+    },
+    Migration {
+        description: "Associate transactions with a repository ID",
+        sql: "
+-- The ID of the repository which the transaction belongs to, for databases
+-- which are shared between multiple clones. Empty for unshared databases.
+ALTER TABLE event_transactions ADD COLUMN repo_id TEXT NOT NULL DEFAULT '';
+ALTER TABLE gc_cursor ADD COLUMN repo_id TEXT NOT NULL DEFAULT '';
+
+CREATE INDEX IF NOT EXISTS event_transactions_repo_id
+ON event_transactions (repo_id);
+CREATE INDEX IF NOT EXISTS event_log_event_tx_id
+ON event_log (event_tx_id);
+",
+    },
+    Migration {
+        description: "Create table for event replayer snapshots",
+        sql: "
+CREATE TABLE replayer_snapshots (
+    -- The row ID of the last event in `event_log` reflected in the snapshot.
+    event_rowid INTEGER NOT NULL,
+
+    -- The ID of the repository which the snapshot belongs to. See
+    -- `event_transactions.repo_id`.
+    repo_id TEXT NOT NULL DEFAULT '',
+
+    -- The serialized state of the event replayer.
+    state TEXT NOT NULL
+);
+",
+    },
+    Migration {
+        description: "Index events by reference name",
+        sql: "
+-- Used to find the most recent update of a reference when coalescing
+-- duplicate reference updates.
+CREATE INDEX IF NOT EXISTS event_log_ref_name
+ON event_log (ref_name);
+",
+    },
+    Migration {
+        description: "Record the machine and user for each transaction",
+        sql: "
+-- The name of the machine and user which created the transaction, if known.
+-- These distinguish events made on different machines when the repository is
+-- on a shared network filesystem.
+ALTER TABLE event_transactions ADD COLUMN hostname TEXT;
+ALTER TABLE event_transactions ADD COLUMN username TEXT;
+",
+    },
+    Migration {
+        description: "Create table for cached commit diff stats",
+        sql: "
+-- Commits are immutable, so their diff stats never need to be invalidated and
+-- can be shared between repositories.
+CREATE TABLE diff_stats (
+    commit_oid TEXT NOT NULL PRIMARY KEY,
+    insertions INTEGER NOT NULL,
+    deletions INTEGER NOT NULL
+);
+",
+    },
+    Migration {
+        description: "Record a reason for each transaction",
+        sql: "
+-- The reason which the user gave for the transaction, such as why they hid
+-- some commits, if any.
+ALTER TABLE event_transactions ADD COLUMN reason TEXT;
+",
+    },
+    Migration {
+        description: "Record positions for backfilled events",
+        sql: "
+-- The position of the event in the event log, for events which were added
+-- after events that happened later than them, such as by `event-log backfill`.
+-- Events are ordered by this, or by their row ID if it's `NULL`.
+ALTER TABLE event_log ADD COLUMN position REAL;
+",
+    },
+    Migration {
+        description: "Create table for the observed-commits cursor",
+        sql: "
+CREATE TABLE observed_cursor (
+    -- The ID of the first event which has not yet been scanned for newly
+    -- observed commits. This table holds at most one row per repository.
+    event_id INTEGER NOT NULL,
+
+    -- The ID of the repository which the cursor belongs to. See
+    -- `event_transactions.repo_id`.
+    repo_id TEXT NOT NULL DEFAULT ''
+);
+",
+    },
+];
+
+/// The schema version which this version of git-branchless expects the
+/// database to have.
+pub const EVENT_LOG_SCHEMA_VERSION: usize = MIGRATIONS.len();
+
+/// Get the schema version of the database, which is the number of migrations
+/// that have been applied to it. This doesn't write to the database.
+#[instrument]
+pub fn get_schema_version(conn: &rusqlite::Connection) -> eyre::Result<usize> {
+    let has_schema_version_table: bool = conn
+        .query_row(
+            "
+SELECT COUNT(*) > 0
+FROM sqlite_master
+WHERE type = 'table' AND name = 'schema_version'
+",
+            rusqlite::params![],
+            |row| row.get(0),
+        )
+        .wrap_err("Checking for `schema_version` table")?;
+    if !has_schema_version_table {
+        return Ok(0);
+    }
+
+    let version: Option<isize> = conn
+        .query_row("SELECT version FROM schema_version", [], |row| row.get(0))
+        .optional()
+        .wrap_err("Querying schema version")?;
+    let version = match version {
+        Some(version) => usize::try_from(version)
+            .wrap_err_with(|| format!("Invalid schema version: {version}"))?,
+        None => 0,
+    };
+    Ok(version)
+}
+
+fn ensure_schema_version_supported(version: usize) -> eyre::Result<()> {
+    if version > EVENT_LOG_SCHEMA_VERSION {
+        eyre::bail!(
+            "The event log database has schema version {version}, \
+            but this version of git-branchless only supports up to schema version {EVENT_LOG_SCHEMA_VERSION}. \
+            Please upgrade git-branchless."
+        );
+    }
+    Ok(())
+}
+
+/// Bring the database schema up to date by applying any migrations which
+/// haven't been applied yet.
+#[instrument]
+fn migrate(conn: &rusqlite::Connection) -> eyre::Result<()> {
+    // The schema is usually already up to date, in which case there's no need
+    // to take the write lock.
+    let current_version = get_schema_version(conn)?;
+    ensure_schema_version_supported(current_version)?;
+    if current_version == EVENT_LOG_SCHEMA_VERSION {
+        return Ok(());
+    }
+
+    // Take the write lock, so that concurrent processes don't attempt to apply
+    // the same migrations. Another process may have applied some of them in the
+    // meantime, so read the schema version again.
+    let tx = rusqlite::Transaction::new_unchecked(conn, rusqlite::TransactionBehavior::Immediate)
+        .wrap_err("Starting migration transaction")?;
+    tx.execute(
+        "
+CREATE TABLE IF NOT EXISTS schema_version (
+    -- The number of migrations which have been applied to the database.
+    -- This table holds at most one row.
+    version INTEGER NOT NULL
 )
 ",
-        rusqlite::params![],
-    )
-    .wrap_err("Creating `event_transactions` table")?;
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `schema_version` table")?;
+    let current_version = get_schema_version(&tx)?;
+    ensure_schema_version_supported(current_version)?;
+
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(current_version) {
+        let Migration { description, sql } = migration;
+        tx.execute_batch(sql).wrap_err_with(|| {
+            format!(
+                "Applying migration {} of {}: {description}",
+                version + 1,
+                EVENT_LOG_SCHEMA_VERSION
+            )
+        })?;
+    }
+
+    if current_version < EVENT_LOG_SCHEMA_VERSION {
+        tx.execute("DELETE FROM schema_version", rusqlite::params![])?;
+        tx.execute(
+            "INSERT INTO schema_version VALUES (:version)",
+            rusqlite::named_params! {
+                ":version": EVENT_LOG_SCHEMA_VERSION,
+            },
+        )?;
+    }
+
+    tx.commit().wrap_err("Committing migration transaction")?;
+    Ok(())
+}
+
+/// Get the repository ID which the connection was tagged with by
+/// `Repo::get_db_conn`, or the empty string if the database isn't shared.
+#[instrument]
+fn get_connection_repo_id(conn: &rusqlite::Connection) -> eyre::Result<String> {
+    let has_repo_id_table: bool = conn
+        .query_row(
+            "
+SELECT COUNT(*) > 0
+FROM sqlite_temp_master
+WHERE type = 'table' AND name = 'repo_id'
+",
+            rusqlite::params![],
+            |row| row.get(0),
+        )
+        .wrap_err("Checking for `repo_id` table")?;
+    if !has_repo_id_table {
+        return Ok(String::new());
+    }
+
+    let repo_id: Option<String> = conn
+        .query_row("SELECT repo_id FROM temp.repo_id", [], |row| row.get(0))
+        .optional()
+        .wrap_err("Querying repository ID")?;
+    Ok(repo_id.unwrap_or_default())
+}
+
+/// Assign the transactions which were recorded before the database was shared,
+/// and so don't belong to any repository, to the repository which the
+/// connection was tagged with. Otherwise, they would disappear from the event
+/// log once `branchless.core.sharedDatabasePath` points to the database.
+///
+/// The unowned cursors and event replayer snapshots are deleted rather than
+/// reassigned, since they're only caches and might conflict with the
+/// repository's own.
+#[instrument]
+fn claim_unowned_transactions(conn: &rusqlite::Connection, repo_id: &str) -> eyre::Result<()> {
+    if repo_id.is_empty() {
+        return Ok(());
+    }
+
+    // Check first, so that the write lock is only taken when there's something
+    // to claim.
+    let has_unowned_rows: bool = conn
+        .query_row(
+            "
+SELECT EXISTS (SELECT 1 FROM event_transactions WHERE repo_id = '')
+    OR EXISTS (SELECT 1 FROM gc_cursor WHERE repo_id = '')
+    OR EXISTS (SELECT 1 FROM observed_cursor WHERE repo_id = '')
+    OR EXISTS (SELECT 1 FROM replayer_snapshots WHERE repo_id = '')
+",
+            rusqlite::params![],
+            |row| row.get(0),
+        )
+        .wrap_err("Checking for unowned transactions")?;
+    if !has_unowned_rows {
+        return Ok(());
+    }
+
+    let tx = rusqlite::Transaction::new_unchecked(conn, rusqlite::TransactionBehavior::Immediate)
+        .wrap_err("Starting transaction to claim unowned transactions")?;
+    tx.execute(
+        "UPDATE event_transactions SET repo_id = :repo_id WHERE repo_id = ''",
+        rusqlite::named_params! { ":repo_id": repo_id },
+    )
+    .wrap_err("Claiming unowned transactions")?;
+    tx.execute(
+        "DELETE FROM gc_cursor WHERE repo_id = ''",
+        rusqlite::params![],
+    )
+    .wrap_err("Deleting unowned GC cursor")?;
+    tx.execute(
+        "DELETE FROM observed_cursor WHERE repo_id = ''",
+        rusqlite::params![],
+    )
+    .wrap_err("Deleting unowned observed-commits cursor")?;
+    tx.execute(
+        "DELETE FROM replayer_snapshots WHERE repo_id = ''",
+        rusqlite::params![],
+    )
+    .wrap_err("Deleting unowned event replayer snapshots")?;
+    tx.commit()
+        .wrap_err("Committing transaction to claim unowned transactions")?;
+    Ok(())
+}
+
+/// The number of times to retry writing to the database if it's still locked by
+/// another process after the busy timeout (see `Repo::get_db_conn`) elapses.
+const MAX_DATABASE_BUSY_RETRIES: u32 = 5;
+
+fn is_database_busy(err: &eyre::Error) -> bool {
+    err.chain().any(|err| {
+        matches!(
+            err.downcast_ref::<rusqlite::Error>()
+                .and_then(|err| err.sqlite_error_code()),
+            Some(rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked)
+        )
+    })
+}
+
+/// Run `f`, retrying with exponential backoff if the database is locked, so
+/// that events aren't dropped when multiple processes write at the same time.
+fn retry_if_busy<T>(mut f: impl FnMut() -> eyre::Result<T>) -> eyre::Result<T> {
+    let mut num_retries = 0;
+    loop {
+        match f() {
+            Err(err) if num_retries < MAX_DATABASE_BUSY_RETRIES && is_database_busy(&err) => {
+                num_retries += 1;
+                warn!(?err, ?num_retries, "Database is busy, retrying");
+                std::thread::sleep(Duration::from_millis(50 * 2_u64.pow(num_retries)));
+            }
+            result => return result,
+        }
+    }
+}
+
+impl<'conn> EventLogDb<'conn> {
+    /// Constructor.
+    #[instrument]
+    pub fn new(conn: &'conn rusqlite::Connection) -> eyre::Result<Self> {
+        migrate(conn)?;
+        let repo_id = get_connection_repo_id(conn)?;
+        retry_if_busy(|| claim_unowned_transactions(conn, &repo_id))?;
+        Ok(EventLogDb { conn, repo_id })
+    }
+
+    /// Start a transaction for writing to the database.
+    ///
+    /// The write lock is acquired immediately, rather than when the first
+    /// write is made, so that concurrent writers (such as hooks invoked at the
+    /// same time) are serialized. Otherwise, two transactions which both
+    /// started by reading would deadlock when upgrading to write, and one
+    /// would fail without waiting for the busy timeout.
+    fn begin_write(&self) -> rusqlite::Result<rusqlite::Transaction<'conn>> {
+        rusqlite::Transaction::new_unchecked(self.conn, rusqlite::TransactionBehavior::Immediate)
+    }
+
+    /// Add events in the given order to the database, in a transaction.
+    ///
+    /// Reference updates which duplicate the most recent update of the same
+    /// reference are coalesced into it; see `EVENT_COALESCE_WINDOW_SECS`.
+    ///
+    /// Args:
+    /// * events: The events to add.
+    #[instrument]
+    pub fn add_events(&self, events: Vec<Event>) -> eyre::Result<()> {
+        let rows: Vec<Row> = events
+            .into_iter()
+            .filter_map(|event| Row::try_from(event).ok())
+            .collect();
+        retry_if_busy(|| self.add_rows(&rows))
+    }
+
+    fn add_rows(&self, rows: &[Row]) -> eyre::Result<()> {
+        let tx = self.begin_write()?;
+        for row in rows {
+            if self.is_duplicate_ref_update(&tx, row)? {
+                continue;
+            }
+            insert_row(&tx, row, None)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Add events for operations which happened in the past, such as those
+    /// recovered from the reflog. For each `(time, message, operation)` in
+    /// `operations`, a new event transaction is created, and `make_events` is
+    /// called with its ID to produce the events for that operation. The event
+    /// transactions and events are all added in a single database
+    /// transaction, so nothing is added if any of them can't be.
+    ///
+    /// The new events are appended to the event log, so existing events keep
+    /// their row IDs, but each one is given a position among this repository's
+    /// existing events according to its timestamp, which determines the order
+    /// in which the events are read. The garbage collection cursor and event
+    /// replayer snapshot for this repository, which refer to events by
+    /// position, are therefore reset.
+    ///
+    /// Returns: The number of events added.
+    #[instrument(skip(operations, make_events))]
+    pub fn add_backfilled_events<T>(
+        &self,
+        operations: Vec<(SystemTime, String, T)>,
+        mut make_events: impl FnMut(EventTransactionId, T) -> Vec<Event>,
+    ) -> eyre::Result<usize> {
+        let tx = self.begin_write()?;
+        let mut new_rows: Vec<Row> = Vec::new();
+        for (time, message, operation) in operations {
+            let event_tx_id = self.insert_transaction(&tx, time, &message)?;
+            new_rows.extend(
+                make_events(event_tx_id, operation)
+                    .into_iter()
+                    .filter_map(|event| Row::try_from(event).ok()),
+            );
+        }
+        new_rows.sort_by(|lhs, rhs| lhs.timestamp.total_cmp(&rhs.timestamp));
+        let num_new_rows = new_rows.len();
+
+        let existing_positions: Vec<(f64, f64)> = tx
+            .prepare(
+                "
+SELECT COALESCE(position, rowid), timestamp
+FROM event_log
+WHERE event_tx_id NOT IN (SELECT event_tx_id FROM event_transactions WHERE repo_id != :repo_id)
+ORDER BY COALESCE(position, rowid) ASC
+",
+            )?
+            .query_map(
+                rusqlite::named_params! { ":repo_id": self.repo_id },
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?
+            .collect::<rusqlite::Result<_>>()?;
+
+        // Each new event goes before the first existing event with a later
+        // timestamp, and is positioned evenly between it and the preceding
+        // existing event. Existing events take precedence over new events
+        // with the same timestamp, so that the relative order of existing
+        // events is preserved even if their timestamps aren't monotonic. New
+        // events after all of the existing events don't need a position,
+        // since they're inserted in order.
+        let mut new_rows = new_rows.into_iter().peekable();
+        let mut last_position = 0.0;
+        for (position, timestamp) in existing_positions {
+            let mut preceding_rows = Vec::new();
+            while let Some(new_row) = new_rows.next_if(|new_row| new_row.timestamp < timestamp) {
+                preceding_rows.push(new_row);
+            }
+            let num_gaps = f64::from(u32::try_from(preceding_rows.len())?) + 1.0;
+            let step = (position - last_position) / num_gaps;
+            let mut row_position = last_position;
+            for row in preceding_rows.iter() {
+                row_position += step;
+                insert_row(&tx, row, Some(row_position))?;
+            }
+            last_position = position;
+        }
+        for row in new_rows {
+            insert_row(&tx, &row, None)?;
+        }
+
+        tx.execute(
+            "DELETE FROM gc_cursor WHERE repo_id = :repo_id",
+            rusqlite::named_params! { ":repo_id": self.repo_id },
+        )?;
+        tx.execute(
+            "DELETE FROM observed_cursor WHERE repo_id = :repo_id",
+            rusqlite::named_params! { ":repo_id": self.repo_id },
+        )?;
+        tx.execute(
+            "DELETE FROM replayer_snapshots WHERE repo_id = :repo_id",
+            rusqlite::named_params! { ":repo_id": self.repo_id },
+        )?;
+        tx.commit()?;
+        Ok(num_new_rows)
+    }
+
+    /// Determine whether the row is a reference update which is identical to
+    /// the most recent update of the same reference, and which occurred within
+    /// `EVENT_COALESCE_WINDOW_SECS` of it. Since the reference didn't move in
+    /// the meantime, no information is lost by dropping such a row.
+    fn is_duplicate_ref_update(&self, tx: &rusqlite::Transaction, row: &Row) -> eyre::Result<bool> {
+        let Row {
+            timestamp,
+            type_,
+            event_tx_id: _,
+            ref1,
+            ref2,
+            ref_name,
+            message: _,
+        } = row;
+        let ref_name = match (type_.as_str(), ref_name) {
+            ("ref-move" | "remote-ref-move", Some(ref_name)) => ref_name,
+            _ => return Ok(false),
+        };
+
+        let latest: Option<(f64, Option<String>, Option<String>)> = tx
+            .query_row(
+                "
+SELECT timestamp, old_ref, new_ref
+FROM event_log
+WHERE type = :type
+AND ref_name = :ref_name
+AND event_tx_id NOT IN (SELECT event_tx_id FROM event_transactions WHERE repo_id != :repo_id)
+ORDER BY COALESCE(position, rowid) DESC
+LIMIT 1
+",
+                rusqlite::named_params! {
+                    ":type": type_,
+                    ":ref_name": ref_name.as_str(),
+                    ":repo_id": self.repo_id,
+                },
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()
+            .wrap_err("Querying latest reference update")?;
+        let result = match latest {
+            Some((latest_timestamp, latest_ref1, latest_ref2)) => {
+                latest_ref1.as_deref() == ref1.as_ref().map(|x| x.as_str())
+                    && latest_ref2.as_deref() == ref2.as_ref().map(|x| x.as_str())
+                    && (0.0..=EVENT_COALESCE_WINDOW_SECS).contains(&(timestamp - latest_timestamp))
+            }
+            None => false,
+        };
+        Ok(result)
+    }
+
+    /// Get all the events in the database.
+    ///
+    /// If the database is shared between multiple clones, events belonging to
+    /// other repositories are excluded.
+    ///
+    /// Returns: All the events in the database, ordered from oldest to newest.
+    #[instrument]
+    pub fn get_events(&self) -> eyre::Result<Vec<Event>> {
+        let mut stmt = self.conn.prepare(
+            "
+SELECT timestamp, type, event_tx_id, old_ref, new_ref, ref_name, message
+FROM event_log
+WHERE event_tx_id NOT IN (SELECT event_tx_id FROM event_transactions WHERE repo_id != :repo_id)
+ORDER BY COALESCE(position, rowid) ASC
+",
+        )?;
+        let rows: rusqlite::Result<Vec<Row>> = stmt
+            .query_map(
+                rusqlite::named_params! { ":repo_id": self.repo_id },
+                read_row,
+            )?
+            .collect();
+        let rows = rows?;
+        rows.into_iter().map(Event::try_from).collect()
+    }
+
+    /// Get the events which were added to the database after the event with
+    /// the given row ID, along with their row IDs. Pass `0` to get all events.
+    ///
+    /// Like `get_events`, the events are returned in event log order, so the
+    /// row IDs of backfilled events may be out of order; see
+    /// `add_backfilled_events`.
+    #[instrument]
+    pub fn get_events_after(&self, rowid: i64) -> eyre::Result<Vec<(i64, Event)>> {
+        let mut stmt = self.conn.prepare(
+            "
+SELECT rowid, timestamp, type, event_tx_id, old_ref, new_ref, ref_name, message
+FROM event_log
+WHERE rowid > :rowid
+AND event_tx_id NOT IN (SELECT event_tx_id FROM event_transactions WHERE repo_id != :repo_id)
+ORDER BY COALESCE(position, rowid) ASC
+",
+        )?;
+        let rows: rusqlite::Result<Vec<(i64, Row)>> = stmt
+            .query_map(
+                rusqlite::named_params! {
+                    ":rowid": rowid,
+                    ":repo_id": self.repo_id,
+                },
+                |row| {
+                    let rowid: i64 = row.get("rowid")?;
+                    Ok((rowid, read_row(row)?))
+                },
+            )?
+            .collect();
+        rows?
+            .into_iter()
+            .map(|(rowid, row)| Ok((rowid, Event::try_from(row)?)))
+            .collect()
+    }
+
+    /// Get the events matching all of the criteria in `filter`.
+    ///
+    /// Returns: The matching events, ordered from oldest to newest.
+    #[instrument]
+    pub fn query(&self, filter: &EventFilter) -> eyre::Result<Vec<Event>> {
+        let EventFilter {
+            event_types,
+            ref_name,
+            commit_oid,
+            event_tx_id,
+            start_time,
+            end_time,
+        } = filter;
+        let event_tx_id = match event_tx_id {
+            Some(EventTransactionId::Id(event_tx_id)) => Some(*event_tx_id),
+            Some(EventTransactionId::Suppressed) => return Ok(Vec::new()),
+            None => None,
+        };
+        let to_timestamp = |time: &Option<SystemTime>| -> eyre::Result<Option<f64>> {
+            let timestamp = match time {
+                Some(time) => Some(
+                    time.duration_since(SystemTime::UNIX_EPOCH)
+                        .wrap_err("Calculating timestamp")?
+                        .as_secs_f64(),
+                ),
+                None => None,
+            };
+            Ok(timestamp)
+        };
+        let start_timestamp = to_timestamp(start_time)?;
+        let end_timestamp = to_timestamp(end_time)?;
+
+        let mut stmt = self.conn.prepare(
+            "
+SELECT timestamp, type, event_tx_id, old_ref, new_ref, ref_name, message
+FROM event_log
+WHERE event_tx_id NOT IN (SELECT event_tx_id FROM event_transactions WHERE repo_id != :repo_id)
+AND (:ref_name IS NULL OR ref_name = :ref_name)
+AND (:commit_oid IS NULL OR old_ref = :commit_oid OR new_ref = :commit_oid)
+AND (:event_tx_id IS NULL OR event_tx_id = :event_tx_id)
+AND (:start_timestamp IS NULL OR timestamp >= :start_timestamp)
+AND (:end_timestamp IS NULL OR timestamp < :end_timestamp)
+ORDER BY COALESCE(position, rowid) ASC
+",
+        )?;
+        let rows: rusqlite::Result<Vec<Row>> = stmt
+            .query_map(
+                rusqlite::named_params! {
+                    ":repo_id": self.repo_id,
+                    ":ref_name": ref_name.as_ref().map(|ref_name| ref_name.as_str()),
+                    ":commit_oid": commit_oid.map(|oid| oid.to_string()),
+                    ":event_tx_id": event_tx_id,
+                    ":start_timestamp": start_timestamp,
+                    ":end_timestamp": end_timestamp,
+                },
+                read_row,
+            )?
+            .collect();
+        rows?
+            .into_iter()
+            .filter(|row| event_types.is_empty() || event_types.contains(&row.type_))
+            .map(Event::try_from)
+            .collect()
+    }
+
+    /// Get the row ID of the most recently-added event, or `0` if there are no
+    /// events. This can be passed to `get_events_after` or `watch_events` to
+    /// only consider events added from this point on.
+    #[instrument]
+    pub fn get_last_event_rowid(&self) -> eyre::Result<i64> {
+        let rowid: Option<i64> =
+            self.conn
+                .query_row("SELECT MAX(rowid) FROM event_log", [], |row| row.get(0))?;
+        Ok(rowid.unwrap_or(0))
+    }
+
+    /// Call `on_event` for each event added to the database after the event
+    /// with the given row ID, including events added by other processes while
+    /// this function is running. The database is checked for changes every
+    /// `poll_interval`.
+    ///
+    /// This function returns once `on_event` returns `ControlFlow::Break`, and
+    /// otherwise runs forever.
+    #[instrument(skip(on_event))]
+    pub fn watch_events(
+        &self,
+        rowid: i64,
+        poll_interval: Duration,
+        mut on_event: impl FnMut(&Event) -> eyre::Result<ControlFlow<()>>,
+    ) -> eyre::Result<()> {
+        let mut last_rowid = rowid;
+        let mut last_data_version: Option<i64> = None;
+        loop {
+            // `data_version` changes whenever another connection commits a
+            // change to the database, so we can avoid querying the event log
+            // when nothing has happened.
+            let data_version: i64 = self
+                .conn
+                .query_row("PRAGMA data_version", [], |row| row.get(0))?;
+            if last_data_version != Some(data_version) {
+                last_data_version = Some(data_version);
+                for (rowid, event) in self.get_events_after(last_rowid)? {
+                    last_rowid = last_rowid.max(rowid);
+                    if on_event(&event)?.is_break() {
+                        return Ok(());
+                    }
+                }
+            }
+            std::thread::sleep(poll_interval);
+        }
+    }
+
+    /// Delete events which happened before `cutoff`, except for the latest
+    /// event affecting each commit or reference, so that the current state of
+    /// the repository as computed from the event log doesn't change. Event
+    /// transactions which no longer have any events are deleted as well.
+    ///
+    /// Since this invalidates existing event cursors, the stored garbage
+    /// collection cursor and event replayer snapshot are also reset.
+    ///
+    /// Returns: The number of events which were deleted.
+    #[instrument]
+    pub fn prune_events_before(&self, cutoff: SystemTime) -> eyre::Result<usize> {
+        let cutoff = cutoff
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .wrap_err("Calculating cutoff timestamp")?
+            .as_secs_f64();
+
+        let mut stmt = self.conn.prepare(
+            "
+SELECT rowid, timestamp, type, event_tx_id, old_ref, new_ref, ref_name, message
+FROM event_log
+WHERE event_tx_id NOT IN (SELECT event_tx_id FROM event_transactions WHERE repo_id != :repo_id)
+ORDER BY COALESCE(position, rowid) ASC
+",
+        )?;
+        let rows: rusqlite::Result<Vec<(i64, Row)>> = stmt
+            .query_map(
+                rusqlite::named_params! { ":repo_id": self.repo_id },
+                |row| {
+                    let rowid: i64 = row.get("rowid")?;
+                    Ok((rowid, read_row(row)?))
+                },
+            )?
+            .collect();
+
+        #[derive(PartialEq, Eq, Hash)]
+        enum Key {
+            Commit(NonZeroOid),
+            Observed(NonZeroOid),
+            Pin(NonZeroOid),
+            Reference(ReferenceName),
+        }
+        let mut latest_rowids: HashMap<Key, i64> = HashMap::new();
+        let mut prunable_rowids = Vec::new();
+        for (rowid, row) in rows? {
+            let timestamp = row.timestamp;
+            let keys = match Event::try_from(row)? {
+                Event::RewriteEvent {
+                    old_commit_oid,
+                    new_commit_oid,
+                    ..
+                } => [old_commit_oid, new_commit_oid]
+                    .into_iter()
+                    .filter_map(|oid| match oid {
+                        MaybeZeroOid::NonZero(oid) => Some(Key::Commit(oid)),
+                        MaybeZeroOid::Zero => None,
+                    })
+                    .collect(),
+                Event::RefUpdateEvent { ref_name, .. }
+                | Event::RemoteRefUpdateEvent { ref_name, .. } => {
+                    vec![Key::Reference(ref_name)]
+                }
+                Event::RefRenameEvent {
+                    old_ref_name,
+                    new_ref_name,
+                    ..
+                } => vec![Key::Reference(old_ref_name), Key::Reference(new_ref_name)],
+                Event::CommitEvent { commit_oid, .. }
+                | Event::ObsoleteEvent { commit_oid, .. }
+                | Event::UnobsoleteEvent { commit_oid, .. } => vec![Key::Commit(commit_oid)],
+                Event::CommitObservedEvent { commit_oid, .. } => vec![Key::Observed(commit_oid)],
+                Event::PinEvent { commit_oid, .. } | Event::UnpinEvent { commit_oid, .. } => {
+                    vec![Key::Pin(commit_oid)]
+                }
+                Event::WorkingCopySnapshot { .. } => Vec::new(),
+            };
+            for key in keys {
+                latest_rowids.insert(key, rowid);
+            }
+            if timestamp < cutoff {
+                prunable_rowids.push(rowid);
+            }
+        }
+        let retained_rowids: HashSet<i64> = latest_rowids.into_values().collect();
+
+        let tx = self.begin_write()?;
+        let mut num_deleted = 0;
+        for rowid in prunable_rowids {
+            if retained_rowids.contains(&rowid) {
+                continue;
+            }
+            num_deleted += tx.execute(
+                "DELETE FROM event_log WHERE rowid = :rowid",
+                rusqlite::named_params! { ":rowid": rowid },
+            )?;
+        }
+        tx.execute(
+            "
+DELETE FROM event_transactions
+WHERE timestamp < :cutoff
+AND repo_id = :repo_id
+AND event_tx_id NOT IN (SELECT event_tx_id FROM event_log)
+",
+            rusqlite::named_params! {
+                ":cutoff": cutoff,
+                ":repo_id": self.repo_id,
+            },
+        )?;
+        tx.execute(
+            "DELETE FROM gc_cursor WHERE repo_id = :repo_id",
+            rusqlite::named_params! { ":repo_id": self.repo_id },
+        )?;
+        tx.execute(
+            "DELETE FROM observed_cursor WHERE repo_id = :repo_id",
+            rusqlite::named_params! { ":repo_id": self.repo_id },
+        )?;
+        tx.execute(
+            "DELETE FROM replayer_snapshots WHERE repo_id = :repo_id",
+            rusqlite::named_params! { ":repo_id": self.repo_id },
+        )?;
+        tx.commit()?;
+
+        // Actually reclaim the disk space used by the deleted rows.
+        self.vacuum()?;
+
+        Ok(num_deleted)
+    }
+
+    /// Get all the events in the database which can be read, along with the
+    /// row IDs of any rows which could not be converted into events (such as
+    /// due to corruption).
+    #[instrument]
+    pub fn get_events_and_invalid_rowids(&self) -> eyre::Result<(Vec<Event>, Vec<i64>)> {
+        let mut stmt = self.conn.prepare(
+            "
+SELECT rowid, timestamp, type, event_tx_id, old_ref, new_ref, ref_name, message
+FROM event_log
+WHERE event_tx_id NOT IN (SELECT event_tx_id FROM event_transactions WHERE repo_id != :repo_id)
+ORDER BY COALESCE(position, rowid) ASC
+",
+        )?;
+        let rows: Vec<(i64, rusqlite::Result<Row>)> = stmt
+            .query_map(
+                rusqlite::named_params! { ":repo_id": self.repo_id },
+                |row| {
+                    let rowid: i64 = row.get("rowid")?;
+                    Ok((rowid, read_row(row)))
+                },
+            )?
+            .collect::<rusqlite::Result<_>>()?;
+
+        let mut events = Vec::new();
+        let mut invalid_rowids = Vec::new();
+        for (rowid, row) in rows {
+            match row.map_err(eyre::Error::from).and_then(Event::try_from) {
+                Ok(event) => events.push(event),
+                Err(_) => invalid_rowids.push(rowid),
+            }
+        }
+        Ok((events, invalid_rowids))
+    }
+
+    /// Run SQLite's integrity check on the database.
+    ///
+    /// Returns: A description of each problem found, or an empty list if the
+    /// database is intact.
+    #[instrument]
+    pub fn check_integrity(&self) -> eyre::Result<Vec<String>> {
+        let mut stmt = self.conn.prepare("PRAGMA integrity_check")?;
+        let results: rusqlite::Result<Vec<String>> = stmt
+            .query_map(rusqlite::params![], |row| row.get(0))?
+            .collect();
+        let results = results.wrap_err("Running integrity check")?;
+        match results.as_slice() {
+            [result] if result == "ok" => Ok(Vec::new()),
+            _ => Ok(results),
+        }
+    }
+
+    /// Rebuild the database file, reclaiming the space used by deleted rows.
+    #[instrument]
+    pub fn vacuum(&self) -> eyre::Result<()> {
+        self.conn
+            .execute("VACUUM", rusqlite::params![])
+            .wrap_err("Vacuuming database")?;
+        Ok(())
+    }
+
+    /// Collapse the reference updates in all but the most recent
+    /// `num_transactions_to_preserve` event transactions, so that only the
+    /// last update to each reference remains, summarizing the net change to
+    /// that reference. The current state of the repository as computed from the
+    /// event log doesn't change, but it's no longer possible to undo to a point
+    /// in the middle of the compacted events.
+    ///
+    /// Since this invalidates existing event cursors, the stored garbage
+    /// collection cursor and event replayer snapshot are also reset.
+    ///
+    /// Returns: The number of events which were removed.
+    #[instrument]
+    pub fn compact_events(&self, num_transactions_to_preserve: usize) -> eyre::Result<usize> {
+        let mut stmt = self.conn.prepare(
+            "
+SELECT rowid, timestamp, type, event_tx_id, old_ref, new_ref, ref_name, message
+FROM event_log
+WHERE event_tx_id NOT IN (SELECT event_tx_id FROM event_transactions WHERE repo_id != :repo_id)
+ORDER BY COALESCE(position, rowid) ASC
+",
+        )?;
+        let rows: rusqlite::Result<Vec<(i64, Row)>> = stmt
+            .query_map(
+                rusqlite::named_params! { ":repo_id": self.repo_id },
+                |row| {
+                    let rowid: i64 = row.get("rowid")?;
+                    Ok((rowid, read_row(row)?))
+                },
+            )?
+            .collect();
+        let rows = rows?;
+
+        let preserved_event_tx_ids: HashSet<isize> = {
+            let mut event_tx_ids: Vec<isize> =
+                rows.iter().map(|(_, row)| row.event_tx_id).collect();
+            event_tx_ids.sort_unstable();
+            event_tx_ids.dedup();
+            let num_compacted = event_tx_ids
+                .len()
+                .saturating_sub(num_transactions_to_preserve);
+            event_tx_ids.into_iter().skip(num_compacted).collect()
+        };
+
+        // For each reference, the rows updating it which can be compacted, in
+        // order.
+        let mut ref_update_rows: HashMap<ReferenceName, Vec<(i64, Row)>> = HashMap::new();
+        for (rowid, row) in rows {
+            if preserved_event_tx_ids.contains(&row.event_tx_id) || row.type_ != "ref-move" {
+                continue;
+            }
+            if let Some(ref_name) = row.ref_name.clone() {
+                ref_update_rows
+                    .entry(ref_name)
+                    .or_default()
+                    .push((rowid, row));
+            }
+        }
+
+        let tx = self.begin_write()?;
+        let mut num_deleted = 0;
+        for (_ref_name, ref_update_rows) in ref_update_rows {
+            let (first_old_ref, redundant_rows, last_rowid) = match ref_update_rows.as_slice() {
+                [(_, first_row), redundant_rows @ .., (last_rowid, _)] => {
+                    (first_row.ref1.as_ref(), redundant_rows, *last_rowid)
+                }
+                [_] | [] => continue,
+            };
+            tx.execute(
+                "UPDATE event_log SET old_ref = :old_ref WHERE rowid = :rowid",
+                rusqlite::named_params! {
+                    ":old_ref": first_old_ref.map(|x| x.as_str()),
+                    ":rowid": last_rowid,
+                },
+            )?;
+            let first_rowid = ref_update_rows[0].0;
+            for rowid in
+                std::iter::once(first_rowid).chain(redundant_rows.iter().map(|(rowid, _)| *rowid))
+            {
+                num_deleted += tx.execute(
+                    "DELETE FROM event_log WHERE rowid = :rowid",
+                    rusqlite::named_params! { ":rowid": rowid },
+                )?;
+            }
+        }
+        if num_deleted > 0 {
+            let min_preserved_event_tx_id = preserved_event_tx_ids.iter().min().copied();
+            tx.execute(
+                "
+DELETE FROM event_transactions
+WHERE event_tx_id < :min_preserved_event_tx_id
+AND repo_id = :repo_id
+AND event_tx_id NOT IN (SELECT event_tx_id FROM event_log)
+",
+                rusqlite::named_params! {
+                    ":min_preserved_event_tx_id": min_preserved_event_tx_id.unwrap_or(isize::MAX),
+                    ":repo_id": self.repo_id,
+                },
+            )?;
+            tx.execute(
+                "DELETE FROM gc_cursor WHERE repo_id = :repo_id",
+                rusqlite::named_params! { ":repo_id": self.repo_id },
+            )?;
+            tx.execute(
+                "DELETE FROM observed_cursor WHERE repo_id = :repo_id",
+                rusqlite::named_params! { ":repo_id": self.repo_id },
+            )?;
+            tx.execute(
+                "DELETE FROM replayer_snapshots WHERE repo_id = :repo_id",
+                rusqlite::named_params! { ":repo_id": self.repo_id },
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(num_deleted)
+    }
+
+    /// Export the contents of the event log, such that it can be imported into
+    /// another repository with `import`.
+    #[instrument]
+    pub fn export(&self) -> eyre::Result<EventLogExport> {
+        let mut stmt = self.conn.prepare(
+            "
+SELECT event_tx_id, timestamp, message, command, hostname, username, reason
+FROM event_transactions
+WHERE repo_id = :repo_id
+ORDER BY event_tx_id ASC
+",
+        )?;
+        let transactions: rusqlite::Result<Vec<ExportedTransaction>> = stmt
+            .query_map(
+                rusqlite::named_params! { ":repo_id": self.repo_id },
+                |row| {
+                    Ok(ExportedTransaction {
+                        event_tx_id: row.get("event_tx_id")?,
+                        timestamp: row.get("timestamp")?,
+                        message: row.get("message")?,
+                        command: row.get("command")?,
+                        hostname: row.get("hostname")?,
+                        username: row.get("username")?,
+                        reason: row.get("reason")?,
+                    })
+                },
+            )?
+            .collect();
 
-    Ok(())
-}
+        let mut stmt = self.conn.prepare(
+            "
+SELECT timestamp, type, event_tx_id, old_ref, new_ref, ref_name, message
+FROM event_log
+WHERE event_tx_id NOT IN (SELECT event_tx_id FROM event_transactions WHERE repo_id != :repo_id)
+ORDER BY COALESCE(position, rowid) ASC
+",
+        )?;
+        let events: rusqlite::Result<Vec<ExportedEvent>> = stmt
+            .query_map(
+                rusqlite::named_params! { ":repo_id": self.repo_id },
+                |row| {
+                    Ok(ExportedEvent {
+                        timestamp: row.get("timestamp")?,
+                        type_: row.get("type")?,
+                        event_tx_id: row.get("event_tx_id")?,
+                        old_ref: row.get("old_ref")?,
+                        new_ref: row.get("new_ref")?,
+                        ref_name: row.get("ref_name")?,
+                        message: row.get("message")?,
+                    })
+                },
+            )?
+            .collect();
 
-impl<'conn> EventLogDb<'conn> {
-    /// Constructor.
-    #[instrument]
-    pub fn new(conn: &'conn rusqlite::Connection) -> eyre::Result<Self> {
-        init_tables(conn)?;
-        Ok(EventLogDb { conn })
+        Ok(EventLogExport {
+            transactions: transactions?,
+            events: events?,
+        })
     }
 
-    /// Add events in the given order to the database, in a transaction.
+    /// Import the contents of an event log previously produced by `export`.
+    /// The imported transactions and events are added after any existing ones,
+    /// and the transactions are assigned new IDs.
     ///
-    /// Args:
-    /// * events: The events to add.
+    /// Returns: The number of events which were imported.
     #[instrument]
-    pub fn add_events(&self, events: Vec<Event>) -> eyre::Result<()> {
-        let tx = self.conn.unchecked_transaction()?;
+    pub fn import(&self, export: &EventLogExport) -> eyre::Result<usize> {
+        let EventLogExport {
+            transactions,
+            events,
+        } = export;
+
+        // Validate all events before making any changes.
         for event in events {
-            let row = match Row::try_from(event) {
-                Ok(row) => row,
-                Err(()) => continue,
-            };
-            let Row {
+            let ExportedEvent {
                 timestamp,
                 type_,
                 event_tx_id,
-                ref1,
-                ref2,
+                old_ref,
+                new_ref,
                 ref_name,
                 message,
-            } = row;
+            } = event;
+            Event::try_from(Row {
+                timestamp: *timestamp,
+                type_: type_.clone(),
+                event_tx_id: *event_tx_id,
+                ref1: old_ref.clone().map(ReferenceName::from),
+                ref2: new_ref.clone().map(ReferenceName::from),
+                ref_name: ref_name.clone().map(ReferenceName::from),
+                message: message.clone().map(ReferenceName::from),
+            })
+            .wrap_err("Validating imported event")?;
+        }
 
-            let ref1 = ref1.as_ref().map(|x| x.as_str());
-            let ref2 = ref2.as_ref().map(|x| x.as_str());
-            let ref_name = ref_name.as_ref().map(|x| x.as_str());
-            let message = message.as_ref().map(|x| x.as_str());
+        let tx = self.begin_write()?;
+        let mut event_tx_id_map: HashMap<isize, isize> = HashMap::new();
+        for transaction in transactions {
+            let ExportedTransaction {
+                event_tx_id,
+                timestamp,
+                message,
+                command,
+                hostname,
+                username,
+                reason,
+            } = transaction;
+            tx.execute(
+                "
+INSERT INTO event_transactions
+(timestamp, message, command, repo_id, hostname, username, reason)
+VALUES
+(:timestamp, :message, :command, :repo_id, :hostname, :username, :reason)
+",
+                rusqlite::named_params! {
+                    ":timestamp": timestamp,
+                    ":message": message,
+                    ":command": command,
+                    ":repo_id": self.repo_id,
+                    ":hostname": hostname,
+                    ":username": username,
+                    ":reason": reason,
+                },
+            )?;
+            let new_event_tx_id: isize = tx.last_insert_rowid().try_into()?;
+            event_tx_id_map.insert(*event_tx_id, new_event_tx_id);
+        }
 
+        for event in events {
+            let ExportedEvent {
+                timestamp,
+                type_,
+                event_tx_id,
+                old_ref,
+                new_ref,
+                ref_name,
+                message,
+            } = event;
+            let event_tx_id = match event_tx_id_map.get(event_tx_id) {
+                Some(event_tx_id) => *event_tx_id,
+                None => {
+                    eyre::bail!("Imported event refers to unknown transaction ID {event_tx_id}")
+                }
+            };
             tx.execute(
                 "
-INSERT INTO event_log VALUES (
-    :timestamp,
-    :type,
-    :event_tx_id,
-    :old_ref,
-    :new_ref,
-    :ref_name,
-    :message
-)
-            ",
+INSERT INTO event_log
+(timestamp, type, event_tx_id, old_ref, new_ref, ref_name, message)
+VALUES
+(:timestamp, :type, :event_tx_id, :old_ref, :new_ref, :ref_name, :message)
+",
                 rusqlite::named_params! {
                     ":timestamp": timestamp,
-                    ":type": &type_,
+                    ":type": type_,
                     ":event_tx_id": event_tx_id,
-                    ":old_ref": &ref1,
-                    ":new_ref": &ref2,
-                    ":ref_name": &ref_name,
-                    ":message": &message,
+                    ":old_ref": old_ref,
+                    ":new_ref": new_ref,
+                    ":ref_name": ref_name,
+                    ":message": message,
                 },
             )?;
         }
         tx.commit()?;
-        Ok(())
-    }
 
-    /// Get all the events in the database.
-    ///
-    /// Returns: All the events in the database, ordered from oldest to newest.
-    #[instrument]
-
-    pub fn get_events(&self) -> eyre::Result<Vec<Event>> {
-        let mut stmt = self.conn.prepare(
-            "
-SELECT timestamp, type, event_tx_id, old_ref, new_ref, ref_name, message
-FROM event_log
-ORDER BY rowid ASC
-",
-        )?;
-        let rows: rusqlite::Result<Vec<Row>> = stmt
-            .query_map(rusqlite::params![], |row| {
-                let timestamp: f64 = row.get("timestamp")?;
-                let event_tx_id: isize = row.get("event_tx_id")?;
-                let type_: String = row.get("type")?;
-                let ref_name: Option<String> = row.get("ref_name")?;
-                let old_ref: Option<String> = row.get("old_ref")?;
-                let new_ref: Option<String> = row.get("new_ref")?;
-                let message: Option<String> = row.get("message")?;
-
-                Ok(Row {
-                    timestamp,
-                    event_tx_id,
-                    type_,
-                    ref_name: ref_name.map(ReferenceName::from),
-                    ref1: old_ref.map(ReferenceName::from),
-                    ref2: new_ref.map(ReferenceName::from),
-                    message: message.map(ReferenceName::from),
-                })
-            })?
-            .collect();
-        let rows = rows?;
-        rows.into_iter().map(Event::try_from).collect()
+        Ok(events.len())
     }
 
     #[instrument]
@@ -626,32 +2305,48 @@ ORDER BY rowid ASC
             }
         }
 
-        let tx = self.conn.unchecked_transaction()?;
+        retry_if_busy(|| {
+            let tx = self.begin_write()?;
+            let event_tx_id = self.insert_transaction(&tx, now, message)?;
+            tx.commit()?;
+            Ok(event_tx_id)
+        })
+    }
 
+    fn insert_transaction(
+        &self,
+        tx: &rusqlite::Transaction,
+        now: SystemTime,
+        message: &str,
+    ) -> eyre::Result<EventTransactionId> {
         let timestamp = now
             .duration_since(SystemTime::UNIX_EPOCH)
             .wrap_err("Calculating event transaction timestamp")?
             .as_secs_f64();
-        self.conn
-            .execute(
-                "
-            INSERT INTO event_transactions
-            (timestamp, message)
-            VALUES
-            (:timestamp, :message)
-        ",
-                rusqlite::named_params! {
-                    ":timestamp": timestamp,
-                    ":message": message,
-                },
-            )
-            .wrap_err("Creating event transaction")?;
+        let command = std::env::var(GIT_REFLOG_ACTION_ENV_VAR).ok();
+        let TransactionOrigin { hostname, username } = TransactionOrigin::current();
+        tx.execute(
+            "
+INSERT INTO event_transactions
+(timestamp, message, command, repo_id, hostname, username)
+VALUES
+(:timestamp, :message, :command, :repo_id, :hostname, :username)
+",
+            rusqlite::named_params! {
+                ":timestamp": timestamp,
+                ":message": message,
+                ":command": command,
+                ":repo_id": self.repo_id,
+                ":hostname": hostname,
+                ":username": username,
+            },
+        )
+        .wrap_err("Creating event transaction")?;
 
         // Ensure that we query `last_insert_rowid` in a transaction, in case
         // there's another thread in this process making queries with the same
         // SQLite connection.
-        let event_tx_id: isize = self.conn.last_insert_rowid().try_into()?;
-        tx.commit()?;
+        let event_tx_id: isize = tx.last_insert_rowid().try_into()?;
         Ok(EventTransactionId::Id(event_tx_id))
     }
 
@@ -675,36 +2370,430 @@ ORDER BY rowid ASC
         };
         let mut stmt = self.conn.prepare(
             "
-SELECT message
-FROM event_transactions
-WHERE event_tx_id = :event_tx_id
+SELECT message
+FROM event_transactions
+WHERE event_tx_id = :event_tx_id
+",
+        )?;
+        let result: String = stmt.query_row(
+            rusqlite::named_params![":event_tx_id": event_tx_id,],
+            |row| {
+                let message: String = row.get("message")?;
+                Ok(message)
+            },
+        )?;
+        Ok(result)
+    }
+
+    /// Get the ID of the most recent transaction in this repository with the
+    /// given message, if any.
+    #[instrument]
+    pub fn get_latest_transaction_with_message(
+        &self,
+        message: &str,
+    ) -> eyre::Result<Option<EventTransactionId>> {
+        let mut stmt = self.conn.prepare(
+            "
+SELECT event_tx_id
+FROM event_transactions
+WHERE repo_id = :repo_id
+AND message = :message
+ORDER BY event_tx_id DESC
+LIMIT 1
+",
+        )?;
+        let result: Option<isize> = stmt
+            .query_row(
+                rusqlite::named_params! {
+                    ":repo_id": self.repo_id,
+                    ":message": message,
+                },
+                |row| row.get("event_tx_id"),
+            )
+            .optional()?;
+        Ok(result.map(EventTransactionId::Id))
+    }
+
+    /// Get the Git command which caused the given transaction to be created,
+    /// if it was recorded.
+    pub fn get_transaction_command(
+        &self,
+        event_tx_id: EventTransactionId,
+    ) -> eyre::Result<Option<String>> {
+        let event_tx_id = match event_tx_id {
+            EventTransactionId::Id(event_tx_id) => event_tx_id,
+            EventTransactionId::Suppressed => return Ok(None),
+        };
+        let command: Option<Option<String>> = self
+            .conn
+            .query_row(
+                "
+SELECT command
+FROM event_transactions
+WHERE event_tx_id = :event_tx_id
+",
+                rusqlite::named_params![":event_tx_id": event_tx_id],
+                |row| row.get("command"),
+            )
+            .optional()?;
+        Ok(command.flatten())
+    }
+
+    /// Get the message associated with each transaction, such as the name of
+    /// the command or hook which created it.
+    pub fn get_transaction_messages(&self) -> eyre::Result<HashMap<EventTransactionId, String>> {
+        let mut stmt = self.conn.prepare(
+            "
+SELECT event_tx_id, message
+FROM event_transactions
+WHERE repo_id = :repo_id
+",
+        )?;
+        let messages: rusqlite::Result<HashMap<EventTransactionId, String>> = stmt
+            .query_map(
+                rusqlite::named_params! { ":repo_id": self.repo_id },
+                |row| {
+                    let event_tx_id: isize = row.get("event_tx_id")?;
+                    let message: String = row.get("message")?;
+                    Ok((EventTransactionId::Id(event_tx_id), message))
+                },
+            )?
+            .collect();
+        Ok(messages?)
+    }
+
+    /// Get the Git commands which caused each transaction to be created, for
+    /// those transactions where it was recorded.
+    pub fn get_transaction_commands(&self) -> eyre::Result<HashMap<EventTransactionId, String>> {
+        let mut stmt = self.conn.prepare(
+            "
+SELECT event_tx_id, command
+FROM event_transactions
+WHERE command IS NOT NULL
+AND repo_id = :repo_id
+",
+        )?;
+        let commands: rusqlite::Result<HashMap<EventTransactionId, String>> = stmt
+            .query_map(
+                rusqlite::named_params! { ":repo_id": self.repo_id },
+                |row| {
+                    let event_tx_id: isize = row.get("event_tx_id")?;
+                    let command: String = row.get("command")?;
+                    Ok((EventTransactionId::Id(event_tx_id), command))
+                },
+            )?
+            .collect();
+        Ok(commands?)
+    }
+
+    /// Record the reason which the user gave for the given transaction, such
+    /// as why they hid some commits.
+    pub fn set_transaction_reason(
+        &self,
+        event_tx_id: EventTransactionId,
+        reason: &str,
+    ) -> eyre::Result<()> {
+        let event_tx_id = match event_tx_id {
+            EventTransactionId::Id(event_tx_id) => event_tx_id,
+            EventTransactionId::Suppressed => return Ok(()),
+        };
+        self.conn.execute(
+            "
+UPDATE event_transactions
+SET reason = :reason
+WHERE event_tx_id = :event_tx_id
+",
+            rusqlite::named_params! {
+                ":event_tx_id": event_tx_id,
+                ":reason": reason,
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Get the reasons which the user gave for each transaction, for those
+    /// transactions where one was recorded.
+    pub fn get_transaction_reasons(&self) -> eyre::Result<HashMap<EventTransactionId, String>> {
+        let mut stmt = self.conn.prepare(
+            "
+SELECT event_tx_id, reason
+FROM event_transactions
+WHERE reason IS NOT NULL
+AND repo_id = :repo_id
+",
+        )?;
+        let reasons: rusqlite::Result<HashMap<EventTransactionId, String>> = stmt
+            .query_map(
+                rusqlite::named_params! { ":repo_id": self.repo_id },
+                |row| {
+                    let event_tx_id: isize = row.get("event_tx_id")?;
+                    let reason: String = row.get("reason")?;
+                    Ok((EventTransactionId::Id(event_tx_id), reason))
+                },
+            )?
+            .collect();
+        Ok(reasons?)
+    }
+
+    /// Get the machine and user which created each transaction, for those
+    /// transactions where it was recorded.
+    pub fn get_transaction_origins(
+        &self,
+    ) -> eyre::Result<HashMap<EventTransactionId, TransactionOrigin>> {
+        let mut stmt = self.conn.prepare(
+            "
+SELECT event_tx_id, hostname, username
+FROM event_transactions
+WHERE (hostname IS NOT NULL OR username IS NOT NULL)
+AND repo_id = :repo_id
+",
+        )?;
+        let origins: rusqlite::Result<HashMap<EventTransactionId, TransactionOrigin>> = stmt
+            .query_map(
+                rusqlite::named_params! { ":repo_id": self.repo_id },
+                |row| {
+                    let event_tx_id: isize = row.get("event_tx_id")?;
+                    let origin = TransactionOrigin {
+                        hostname: row.get("hostname")?,
+                        username: row.get("username")?,
+                    };
+                    Ok((EventTransactionId::Id(event_tx_id), origin))
+                },
+            )?
+            .collect();
+        Ok(origins?)
+    }
+
+    /// Get the most recently saved event replayer snapshot, along with the row
+    /// ID of the last event reflected in it, if any. See `EventReplayer`.
+    #[instrument]
+    fn get_replayer_snapshot(&self) -> eyre::Result<Option<(i64, ReplayerSnapshot)>> {
+        let snapshot: Option<(i64, String)> = self
+            .conn
+            .query_row(
+                "
+SELECT event_rowid, state
+FROM replayer_snapshots
+WHERE repo_id = :repo_id
+",
+                rusqlite::named_params! { ":repo_id": self.repo_id },
+                |row| Ok((row.get("event_rowid")?, row.get("state")?)),
+            )
+            .optional()?;
+        match snapshot {
+            None => Ok(None),
+            Some((event_rowid, state)) => {
+                let snapshot: ReplayerSnapshot =
+                    serde_json::from_str(&state).wrap_err("Deserializing replayer snapshot")?;
+                Ok(Some((event_rowid, snapshot)))
+            }
+        }
+    }
+
+    /// Replace the saved event replayer snapshot with the given one, which
+    /// reflects the events up to and including the event with the given row ID.
+    #[instrument]
+    fn set_replayer_snapshot(
+        &self,
+        event_rowid: i64,
+        snapshot: &ReplayerSnapshot,
+    ) -> eyre::Result<()> {
+        let state = serde_json::to_string(snapshot).wrap_err("Serializing replayer snapshot")?;
+        let tx = self.begin_write()?;
+        tx.execute(
+            "DELETE FROM replayer_snapshots WHERE repo_id = :repo_id",
+            rusqlite::named_params! { ":repo_id": self.repo_id },
+        )?;
+        tx.execute(
+            "
+INSERT INTO replayer_snapshots (event_rowid, repo_id, state)
+VALUES (:event_rowid, :repo_id, :state)
+",
+            rusqlite::named_params! {
+                ":event_rowid": event_rowid,
+                ":repo_id": self.repo_id,
+                ":state": state,
+            },
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Get the previously-computed diff stat for the given commit, if any. See
+    /// `set_cached_diff_stat`.
+    #[instrument]
+    pub fn get_cached_diff_stat(&self, commit_oid: NonZeroOid) -> eyre::Result<Option<DiffStat>> {
+        let diff_stat = self
+            .conn
+            .query_row(
+                "
+SELECT insertions, deletions
+FROM diff_stats
+WHERE commit_oid = :commit_oid
+",
+                rusqlite::named_params! { ":commit_oid": commit_oid.to_string() },
+                |row| {
+                    Ok(DiffStat {
+                        insertions: row.get("insertions")?,
+                        deletions: row.get("deletions")?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(diff_stat)
+    }
+
+    /// Save the diff stat for the given commit, so that it doesn't need to be
+    /// recomputed the next time it's displayed.
+    #[instrument]
+    pub fn set_cached_diff_stat(
+        &self,
+        commit_oid: NonZeroOid,
+        diff_stat: DiffStat,
+    ) -> eyre::Result<()> {
+        let DiffStat {
+            insertions,
+            deletions,
+        } = diff_stat;
+        retry_if_busy(|| {
+            self.conn.execute(
+                "
+INSERT OR REPLACE INTO diff_stats (commit_oid, insertions, deletions)
+VALUES (:commit_oid, :insertions, :deletions)
+",
+                rusqlite::named_params! {
+                    ":commit_oid": commit_oid.to_string(),
+                    ":insertions": insertions,
+                    ":deletions": deletions,
+                },
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Count the number of diff stats cached by `set_cached_diff_stat`.
+    #[instrument]
+    pub fn count_cached_diff_stats(&self) -> eyre::Result<usize> {
+        let num_diff_stats: isize = self.conn.query_row(
+            "
+SELECT COUNT(*)
+FROM diff_stats
+",
+            rusqlite::params![],
+            |row| row.get(0),
+        )?;
+        Ok(usize::try_from(num_diff_stats)?)
+    }
+
+    /// Get the position in the event log up to which the last garbage
+    /// collection run considered events, if any.
+    #[instrument]
+    pub fn get_gc_cursor(&self) -> eyre::Result<Option<EventCursor>> {
+        let mut stmt = self.conn.prepare(
+            "
+SELECT event_id
+FROM gc_cursor
+WHERE repo_id = :repo_id
+",
+        )?;
+        let event_ids: rusqlite::Result<Vec<isize>> = stmt
+            .query_map(
+                rusqlite::named_params! { ":repo_id": self.repo_id },
+                |row| row.get("event_id"),
+            )?
+            .collect();
+        let event_id = event_ids?.into_iter().next();
+        Ok(event_id.map(|event_id| EventCursor { event_id }))
+    }
+
+    /// Record the position in the event log up to which garbage collection has
+    /// considered events. See `get_gc_cursor`.
+    #[instrument]
+    pub fn set_gc_cursor(&self, cursor: EventCursor) -> eyre::Result<()> {
+        let EventCursor { event_id } = cursor;
+        let tx = self.begin_write()?;
+        tx.execute(
+            "DELETE FROM gc_cursor WHERE repo_id = :repo_id",
+            rusqlite::named_params! { ":repo_id": self.repo_id },
+        )?;
+        tx.execute(
+            "
+INSERT INTO gc_cursor (event_id, repo_id)
+VALUES (:event_id, :repo_id)
+",
+            rusqlite::named_params! {
+                ":event_id": event_id,
+                ":repo_id": self.repo_id,
+            },
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Get the position in the event log up to which events were last scanned
+    /// for newly observed commits, if any. See `record_observed_commits`.
+    #[instrument]
+    pub fn get_observed_cursor(&self) -> eyre::Result<Option<EventCursor>> {
+        let mut stmt = self.conn.prepare(
+            "
+SELECT event_id
+FROM observed_cursor
+WHERE repo_id = :repo_id
+",
+        )?;
+        let event_ids: rusqlite::Result<Vec<isize>> = stmt
+            .query_map(
+                rusqlite::named_params! { ":repo_id": self.repo_id },
+                |row| row.get("event_id"),
+            )?
+            .collect();
+        let event_id = event_ids?.into_iter().next();
+        Ok(event_id.map(|event_id| EventCursor { event_id }))
+    }
+
+    /// Record the position in the event log up to which events have been
+    /// scanned for newly observed commits. See `get_observed_cursor`.
+    #[instrument]
+    pub fn set_observed_cursor(&self, cursor: EventCursor) -> eyre::Result<()> {
+        let EventCursor { event_id } = cursor;
+        let tx = self.begin_write()?;
+        tx.execute(
+            "DELETE FROM observed_cursor WHERE repo_id = :repo_id",
+            rusqlite::named_params! { ":repo_id": self.repo_id },
+        )?;
+        tx.execute(
+            "
+INSERT INTO observed_cursor (event_id, repo_id)
+VALUES (:event_id, :repo_id)
 ",
-        )?;
-        let result: String = stmt.query_row(
-            rusqlite::named_params![":event_tx_id": event_tx_id,],
-            |row| {
-                let message: String = row.get("message")?;
-                Ok(message)
+            rusqlite::named_params! {
+                ":event_id": event_id,
+                ":repo_id": self.repo_id,
             },
         )?;
-        Ok(result)
+        tx.commit()?;
+        Ok(())
     }
 }
 
 /// Determine whether a given reference is used to keep a commit alive.
 ///
+/// Args:
+/// * `ref_namespace`: The namespace of references used internally by
+///   git-branchless. See `get_ref_namespace`.
+///
 /// Returns: Whether or not the given reference is used internally to keep the
 /// commit alive, so that it's not collected by Git's garbage collection
 /// mechanism.
-pub fn is_gc_ref(reference_name: &ReferenceName) -> bool {
-    reference_name.as_str().starts_with("refs/branchless/")
+pub fn is_gc_ref(reference_name: &ReferenceName, ref_namespace: &str) -> bool {
+    reference_name.as_str().starts_with(ref_namespace)
 }
 
 /// Determines whether or not updates to the given reference should be ignored.
 ///
 /// Returns: Whether or not updates to the given reference should be ignored.
-pub fn should_ignore_ref_updates(reference_name: &ReferenceName) -> bool {
-    if is_gc_ref(reference_name) {
+pub fn should_ignore_ref_updates(reference_name: &ReferenceName, ref_namespace: &str) -> bool {
+    if is_gc_ref(reference_name, ref_namespace) {
         return true;
     }
 
@@ -730,7 +2819,7 @@ enum EventClassification {
 ///
 /// This is determined by the last `Event` that affected the commit. If no
 /// activity has been observed for a commit, it's considered inactive.
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub enum CommitActivityStatus {
     /// The commit is active, and should be rendered as part of the commit graph.
     Active,
@@ -753,6 +2842,43 @@ struct EventInfo {
     event_classification: EventClassification,
 }
 
+/// The latest event affecting a commit, as recorded in a `ReplayerSnapshot`.
+#[derive(Debug, Deserialize, Serialize)]
+struct SnapshotCommit {
+    oid: String,
+    event_id: isize,
+    event: ExportedEvent,
+    is_visible: bool,
+}
+
+/// The state of an `EventReplayer` after processing a prefix of the event log,
+/// which is persisted so that later replays only need to process the events
+/// after it. Only the latest event affecting each commit is kept, so a replayer
+/// constructed from a snapshot can't inspect points in time before it.
+#[derive(Debug, Deserialize, Serialize)]
+struct ReplayerSnapshot {
+    /// The number of events which were processed.
+    num_events: isize,
+
+    /// The location of `HEAD`, if it was observed.
+    head_oid: Option<String>,
+
+    /// The latest event affecting each commit.
+    commits: Vec<SnapshotCommit>,
+
+    /// The latest pin state of each commit, along with the ID of the event
+    /// which set it.
+    pins: Vec<(String, isize, bool)>,
+
+    /// The locations of references.
+    ref_locations: Vec<(String, String)>,
+
+    /// The ID and timestamp of the event in which each commit was first
+    /// observed.
+    #[serde(default)]
+    observed_commits: Vec<(String, isize, f64)>,
+}
+
 /// Events up to this cursor (exclusive) are available to the caller.
 ///
 /// The "event cursor" is used to move the event replayer forward or
@@ -776,12 +2902,37 @@ pub struct EventReplayer {
     /// The list of observed events.
     events: Vec<Event>,
 
+    /// The ID of the first event in `events`. This is nonzero if the replayer
+    /// was constructed from a snapshot, in which case the earlier events are
+    /// not available.
+    first_event_id: isize,
+
+    /// The location of `HEAD` as of the snapshot which the replayer was
+    /// constructed from, if any.
+    snapshot_head_oid: Option<NonZeroOid>,
+
+    /// The locations of references as of the snapshot which the replayer was
+    /// constructed from, if any.
+    snapshot_ref_locations: HashMap<ReferenceName, NonZeroOid>,
+
     /// The name of the reference representing the main branch.
     main_branch_reference_name: ReferenceName,
 
+    /// The namespace of references used internally by git-branchless. See
+    /// `get_ref_namespace`.
+    ref_namespace: String,
+
     /// The events that have affected each commit.
     commit_history: HashMap<NonZeroOid, Vec<EventInfo>>,
 
+    /// The pin and unpin events that have affected each commit, along with
+    /// whether the commit was pinned as a result of that event.
+    pin_history: HashMap<NonZeroOid, Vec<(isize, bool)>>,
+
+    /// The ID and timestamp of the event in which each commit was first
+    /// observed (see `Event::CommitObservedEvent`).
+    observed_commits: HashMap<NonZeroOid, (isize, f64)>,
+
     /// Map from ref names to ref locations (an OID or another ref name). Works
     /// around <https://github.com/arxanas/git-branchless/issues/7>.
     ///
@@ -802,12 +2953,18 @@ impl std::fmt::Debug for EventReplayer {
 }
 
 impl EventReplayer {
-    fn new(main_branch_reference_name: ReferenceName) -> Self {
+    fn new(main_branch_reference_name: ReferenceName, ref_namespace: String) -> Self {
         EventReplayer {
             id_counter: 0,
             events: vec![],
+            first_event_id: 0,
+            snapshot_head_oid: None,
+            snapshot_ref_locations: HashMap::new(),
             main_branch_reference_name,
+            ref_namespace,
             commit_history: HashMap::new(),
+            pin_history: HashMap::new(),
+            observed_commits: HashMap::new(),
             ref_locations: HashMap::new(),
         }
     }
@@ -827,13 +2984,187 @@ impl EventReplayer {
         let (_effects, _progress) = effects.start_operation(OperationType::ProcessEvents);
 
         let main_branch_reference_name = repo.get_main_branch()?.get_reference_name()?;
-        let mut result = EventReplayer::new(main_branch_reference_name);
+        let ref_namespace = get_ref_namespace(repo)?;
+        let mut result = EventReplayer::new(main_branch_reference_name, ref_namespace);
         for event in event_log_db.get_events()? {
             result.process_event(&event);
         }
         Ok(result)
     }
 
+    /// Construct the replayer from the latest snapshot saved in the database,
+    /// if any, and the events recorded after it. If enough events have been
+    /// recorded since the snapshot (see `get_event_replayer_snapshot_interval`),
+    /// a new snapshot is saved.
+    ///
+    /// This is faster than `from_event_log_db` for long event logs, but the
+    /// resulting replayer can't inspect points in time before the snapshot, so
+    /// it shouldn't be used by commands which browse history, such as `undo`.
+    ///
+    /// Args:
+    /// * `event_log_db`: The database to query events and snapshots from.
+    ///
+    /// Returns: The constructed replayer.
+    #[instrument]
+    pub fn from_event_log_db_with_snapshot(
+        effects: &Effects,
+        repo: &Repo,
+        event_log_db: &EventLogDb,
+    ) -> eyre::Result<Self> {
+        let (_effects, _progress) = effects.start_operation(OperationType::ProcessEvents);
+
+        let main_branch_reference_name = repo.get_main_branch()?.get_reference_name()?;
+        let ref_namespace = get_ref_namespace(repo)?;
+        let (snapshot_rowid, mut result) = match event_log_db.get_replayer_snapshot()? {
+            Some((snapshot_rowid, snapshot)) => (
+                snapshot_rowid,
+                EventReplayer::from_snapshot(main_branch_reference_name, ref_namespace, snapshot)?,
+            ),
+            None => (
+                0,
+                EventReplayer::new(main_branch_reference_name, ref_namespace),
+            ),
+        };
+
+        let events = event_log_db.get_events_after(snapshot_rowid)?;
+        for (_rowid, event) in events.iter() {
+            result.process_event(event);
+        }
+
+        let snapshot_interval = get_event_replayer_snapshot_interval(repo)?;
+        if let Some(last_rowid) = events.iter().map(|(rowid, _event)| *rowid).max() {
+            if snapshot_interval > 0 && events.len() >= snapshot_interval {
+                event_log_db.set_replayer_snapshot(last_rowid, &result.make_snapshot()?)?;
+            }
+        }
+        Ok(result)
+    }
+
+    fn from_snapshot(
+        main_branch_reference_name: ReferenceName,
+        ref_namespace: String,
+        snapshot: ReplayerSnapshot,
+    ) -> eyre::Result<Self> {
+        let ReplayerSnapshot {
+            num_events,
+            head_oid,
+            commits,
+            pins,
+            ref_locations,
+            observed_commits,
+        } = snapshot;
+
+        let mut commit_history = HashMap::new();
+        for SnapshotCommit {
+            oid,
+            event_id,
+            event,
+            is_visible,
+        } in commits
+        {
+            let event_info = EventInfo {
+                id: event_id,
+                event: Event::try_from(event)?,
+                event_classification: if is_visible {
+                    EventClassification::Show
+                } else {
+                    EventClassification::Hide
+                },
+            };
+            commit_history.insert(NonZeroOid::from_str(&oid)?, vec![event_info]);
+        }
+
+        let mut pin_history = HashMap::new();
+        for (oid, event_id, is_pinned) in pins {
+            pin_history.insert(NonZeroOid::from_str(&oid)?, vec![(event_id, is_pinned)]);
+        }
+
+        let observed_commits = observed_commits
+            .into_iter()
+            .map(|(oid, event_id, timestamp)| {
+                Ok((NonZeroOid::from_str(&oid)?, (event_id, timestamp)))
+            })
+            .collect::<eyre::Result<_>>()?;
+
+        let ref_locations: HashMap<ReferenceName, NonZeroOid> = ref_locations
+            .into_iter()
+            .map(|(ref_name, oid)| Ok((ReferenceName::from(ref_name), NonZeroOid::from_str(&oid)?)))
+            .collect::<eyre::Result<_>>()?;
+        let head_oid = head_oid
+            .map(|head_oid| NonZeroOid::from_str(&head_oid))
+            .transpose()?;
+
+        Ok(EventReplayer {
+            id_counter: num_events,
+            events: vec![],
+            first_event_id: num_events,
+            snapshot_head_oid: head_oid,
+            snapshot_ref_locations: ref_locations.clone(),
+            main_branch_reference_name,
+            ref_namespace,
+            commit_history,
+            pin_history,
+            observed_commits,
+            ref_locations,
+        })
+    }
+
+    fn make_snapshot(&self) -> eyre::Result<ReplayerSnapshot> {
+        let commits = self
+            .commit_history
+            .iter()
+            .filter_map(|(oid, history)| Some((oid, history.last()?)))
+            .map(|(oid, event_info)| {
+                let EventInfo {
+                    id,
+                    event,
+                    event_classification,
+                } = event_info;
+                let event = ExportedEvent::try_from(event.clone()).map_err(|()| {
+                    eyre::eyre!("Event with suppressed transaction ID in replayer: {event:?}")
+                })?;
+                Ok(SnapshotCommit {
+                    oid: oid.to_string(),
+                    event_id: *id,
+                    event,
+                    is_visible: match event_classification {
+                        EventClassification::Show => true,
+                        EventClassification::Hide => false,
+                    },
+                })
+            })
+            .collect::<eyre::Result<Vec<_>>>()?;
+        let pins = self
+            .pin_history
+            .iter()
+            .filter_map(|(oid, history)| {
+                let (id, is_pinned) = history.last()?;
+                Some((oid.to_string(), *id, *is_pinned))
+            })
+            .collect();
+        let ref_locations = self
+            .ref_locations
+            .iter()
+            .map(|(ref_name, oid)| (ref_name.as_str().to_owned(), oid.to_string()))
+            .collect();
+        let observed_commits = self
+            .observed_commits
+            .iter()
+            .map(|(oid, (id, timestamp))| (oid.to_string(), *id, *timestamp))
+            .collect();
+        let head_oid = self
+            .get_cursor_head_oid(self.make_default_cursor())
+            .map(|head_oid| head_oid.to_string());
+        Ok(ReplayerSnapshot {
+            num_events: self.id_counter,
+            head_oid,
+            commits,
+            pins,
+            ref_locations,
+            observed_commits,
+        })
+    }
+
     /// Process the given event.
     ///
     /// This also sets the event cursor to point to immediately after the event
@@ -845,7 +3176,39 @@ impl EventReplayer {
     pub fn process_event(&mut self, event: &Event) {
         // Drop non-meaningful ref-update events.
         if let Event::RefUpdateEvent { ref_name, .. } = event {
-            if should_ignore_ref_updates(ref_name) {
+            if should_ignore_ref_updates(ref_name, &self.ref_namespace) {
+                return;
+            }
+        }
+
+        // Drop reference updates which move a reference to where it already
+        // points (such as duplicates of the previous update), since they don't
+        // change anything, and would otherwise show up as no-op steps in
+        // `undo`.
+        //
+        // `HEAD` also moves implicitly whenever the branch it points to is
+        // updated, without a corresponding event for `HEAD`, so its last
+        // recorded location may be stale. Only drop its updates if the event
+        // itself says that it didn't move.
+        if let Event::RefUpdateEvent {
+            ref_name,
+            old_oid,
+            new_oid: MaybeZeroOid::NonZero(new_oid),
+            ..
+        }
+        | Event::RemoteRefUpdateEvent {
+            ref_name,
+            old_oid,
+            new_oid: MaybeZeroOid::NonZero(new_oid),
+            ..
+        } = event
+        {
+            let is_redundant = if ref_name.as_str() == "HEAD" {
+                *old_oid == MaybeZeroOid::NonZero(*new_oid)
+            } else {
+                self.ref_locations.get(ref_name) == Some(new_oid)
+            };
+            if is_redundant {
                 return;
             }
         }
@@ -897,6 +3260,9 @@ impl EventReplayer {
             // needed.
             Event::RefUpdateEvent {
                 ref_name, new_oid, ..
+            }
+            | Event::RemoteRefUpdateEvent {
+                ref_name, new_oid, ..
             } => match new_oid {
                 MaybeZeroOid::NonZero(new_oid) => {
                     self.ref_locations.insert(ref_name.clone(), *new_oid);
@@ -906,6 +3272,16 @@ impl EventReplayer {
                 }
             },
 
+            Event::RefRenameEvent {
+                old_ref_name,
+                new_ref_name,
+                oid,
+                ..
+            } => {
+                self.ref_locations.remove(old_ref_name);
+                self.ref_locations.insert(new_ref_name.clone(), *oid);
+            }
+
             Event::CommitEvent {
                 timestamp: _,
                 event_tx_id: _,
@@ -948,6 +3324,38 @@ impl EventReplayer {
                     event_classification: EventClassification::Show,
                 }),
 
+            Event::PinEvent {
+                timestamp: _,
+                event_tx_id: _,
+                commit_oid,
+            } => self
+                .pin_history
+                .entry(*commit_oid)
+                .or_default()
+                .push((id, true)),
+
+            Event::UnpinEvent {
+                timestamp: _,
+                event_tx_id: _,
+                commit_oid,
+            } => self
+                .pin_history
+                .entry(*commit_oid)
+                .or_default()
+                .push((id, false)),
+
+            // Observing a commit doesn't make it active, so it's not included
+            // in the `commit_history`. Only the first observation is kept.
+            Event::CommitObservedEvent {
+                timestamp,
+                event_tx_id: _,
+                commit_oid,
+            } => {
+                self.observed_commits
+                    .entry(*commit_oid)
+                    .or_insert((id, *timestamp));
+            }
+
             Event::WorkingCopySnapshot { .. } => {
                 // Do nothing. A working copy snapshot doesn't imply that the
                 // commit has become active or inactive.
@@ -1081,9 +3489,69 @@ impl EventReplayer {
             .collect()
     }
 
+    /// Get all OIDs which have been affected by an event at or after the
+    /// provided cursor.
+    pub fn get_commits_touched_since_cursor(&self, cursor: EventCursor) -> HashSet<NonZeroOid> {
+        let touched_commits = self.commit_history.iter().filter_map(|(oid, history)| {
+            if history.iter().any(|event| event.id >= cursor.event_id) {
+                Some(*oid)
+            } else {
+                None
+            }
+        });
+        let touched_pins = self.pin_history.iter().filter_map(|(oid, history)| {
+            if history.iter().any(|(id, _)| *id >= cursor.event_id) {
+                Some(*oid)
+            } else {
+                None
+            }
+        });
+        touched_commits.chain(touched_pins).collect()
+    }
+
+    /// Determines whether a commit has been pinned by the user at the cursor's
+    /// point in time. Pinned commits are never garbage-collected.
+    pub fn get_cursor_commit_is_pinned(&self, cursor: EventCursor, oid: NonZeroOid) -> bool {
+        match self.pin_history.get(&oid) {
+            None => false,
+            Some(history) => history
+                .iter()
+                .rev()
+                .find(|(id, _)| *id < cursor.event_id)
+                .map(|(_, is_pinned)| *is_pinned)
+                .unwrap_or(false),
+        }
+    }
+
+    /// Get the time at which the commit was first observed (see
+    /// `Event::CommitObservedEvent`), as of the cursor's point in time. Returns
+    /// `None` if it hasn't been observed yet.
+    pub fn get_cursor_commit_observed_time(
+        &self,
+        cursor: EventCursor,
+        oid: NonZeroOid,
+    ) -> Option<SystemTime> {
+        match self.observed_commits.get(&oid) {
+            Some((id, timestamp)) if *id < cursor.event_id => {
+                Some(SystemTime::UNIX_EPOCH + Duration::from_secs_f64(*timestamp))
+            }
+            Some(_) | None => None,
+        }
+    }
+
+    /// Get all OIDs which are pinned at the cursor's point in time.
+    pub fn get_cursor_pinned_oids(&self, cursor: EventCursor) -> HashSet<NonZeroOid> {
+        self.pin_history
+            .keys()
+            .copied()
+            .filter(|oid| self.get_cursor_commit_is_pinned(cursor, *oid))
+            .collect()
+    }
+
     /// Create an event cursor pointing to immediately after the last event.
     pub fn make_default_cursor(&self) -> EventCursor {
-        self.make_cursor(self.events.len().try_into().unwrap())
+        let num_events: isize = self.events.len().try_into().unwrap();
+        self.make_cursor(self.first_event_id + num_events)
     }
 
     /// Create an event cursor pointing to immediately after the provided event ID.
@@ -1091,16 +3559,47 @@ impl EventReplayer {
     /// If the event ID is too low or too high, it will be clamped to the valid
     /// range for event IDs.
     pub fn make_cursor(&self, event_id: isize) -> EventCursor {
-        let event_id = if event_id < 0 { 0 } else { event_id };
         let num_events: isize = self.events.len().try_into().unwrap();
-        let event_id = if event_id > num_events {
-            num_events
-        } else {
-            event_id
-        };
+        let event_id = event_id.clamp(self.first_event_id, self.first_event_id + num_events);
         EventCursor { event_id }
     }
 
+    /// Get the index into `events` corresponding to the cursor.
+    fn get_cursor_index(&self, cursor: EventCursor) -> usize {
+        (cursor.event_id - self.first_event_id).try_into().unwrap()
+    }
+
+    /// Create an event cursor pointing to the state of the repository as of the
+    /// provided time, i.e. immediately after the last event which occurred at
+    /// or before that time.
+    ///
+    /// The resulting cursor can be passed to the `get_cursor_*` methods and to
+    /// `get_references_snapshot` to inspect commit visibility and reference
+    /// locations at that point in history.
+    pub fn make_cursor_at_time(&self, time: SystemTime) -> EventCursor {
+        let index = self
+            .events
+            .iter()
+            .position(|event| event.get_timestamp() > time)
+            .unwrap_or(self.events.len());
+        let index: isize = index.try_into().unwrap();
+        self.make_cursor(self.first_event_id + index)
+    }
+
+    /// Create an event cursor pointing to immediately after the last event in
+    /// the given transaction. Returns `None` if the transaction has no events.
+    pub fn make_cursor_after_transaction(
+        &self,
+        event_tx_id: EventTransactionId,
+    ) -> Option<EventCursor> {
+        let index = self
+            .events
+            .iter()
+            .rposition(|event| event.get_event_tx_id() == event_tx_id)?;
+        let index: isize = (index + 1).try_into().unwrap();
+        Some(self.make_cursor(self.first_event_id + index))
+    }
+
     /// Advance the event cursor by the specified number of events.
     ///
     /// Args:
@@ -1111,7 +3610,9 @@ impl EventReplayer {
         self.make_cursor(cursor.event_id + num_events)
     }
 
-    fn get_event_tx_id_before_cursor(&self, cursor: EventCursor) -> Option<EventTransactionId> {
+    /// Get the transaction ID of the event immediately before the cursor, if
+    /// any.
+    pub fn get_event_tx_id_before_cursor(&self, cursor: EventCursor) -> Option<EventTransactionId> {
         self.get_event_before_cursor(cursor)
             .map(|(_event_id, event)| event.get_event_tx_id())
     }
@@ -1194,8 +3695,7 @@ impl EventReplayer {
     /// Returns: The OID pointed to by `HEAD` at that time, or `None` if `HEAD`
     /// was never observed.
     fn get_cursor_head_oid(&self, cursor: EventCursor) -> Option<NonZeroOid> {
-        let cursor_event_id: usize = cursor.event_id.try_into().unwrap();
-        self.events[0..cursor_event_id]
+        self.events[..self.get_cursor_index(cursor)]
             .iter()
             .rev()
             .find_map(|event| {
@@ -1222,10 +3722,16 @@ impl EventReplayer {
                     } => None,
 
                     Event::RewriteEvent { .. }
+                    | Event::RefRenameEvent { .. }
+                    | Event::RemoteRefUpdateEvent { .. }
+                    | Event::CommitObservedEvent { .. }
                     | Event::ObsoleteEvent { .. }
-                    | Event::UnobsoleteEvent { .. } => None,
+                    | Event::UnobsoleteEvent { .. }
+                    | Event::PinEvent { .. }
+                    | Event::UnpinEvent { .. } => None,
                 }
             })
+            .or(self.snapshot_head_oid)
     }
 
     fn get_cursor_branch_oid(
@@ -1233,8 +3739,7 @@ impl EventReplayer {
         cursor: EventCursor,
         reference_name: &ReferenceName,
     ) -> eyre::Result<Option<NonZeroOid>> {
-        let cursor_event_id: usize = cursor.event_id.try_into().unwrap();
-        let oid = self.events[0..cursor_event_id]
+        let oid = self.events[..self.get_cursor_index(cursor)]
             .iter()
             .rev()
             .find_map(|event| match &event {
@@ -1243,11 +3748,83 @@ impl EventReplayer {
                     new_oid: MaybeZeroOid::NonZero(new_oid),
                     ..
                 } if ref_name == reference_name => Some(*new_oid),
+                Event::RefRenameEvent {
+                    new_ref_name, oid, ..
+                } if new_ref_name == reference_name => Some(*oid),
                 _ => None,
-            });
+            })
+            .or_else(|| self.snapshot_ref_locations.get(reference_name).copied());
         Ok(oid)
     }
 
+    /// Get the OID of the given remote-tracking reference at the cursor's
+    /// point in time, if it was observed moving.
+    pub fn get_cursor_remote_branch_oid(
+        &self,
+        cursor: EventCursor,
+        reference_name: &ReferenceName,
+    ) -> Option<NonZeroOid> {
+        let oid = self.events[..self.get_cursor_index(cursor)]
+            .iter()
+            .rev()
+            .find_map(|event| match &event {
+                Event::RemoteRefUpdateEvent {
+                    ref_name, new_oid, ..
+                } if ref_name == reference_name => Some(*new_oid),
+                _ => None,
+            });
+        match oid {
+            Some(MaybeZeroOid::NonZero(oid)) => Some(oid),
+            Some(MaybeZeroOid::Zero) => None,
+            None => self.snapshot_ref_locations.get(reference_name).copied(),
+        }
+    }
+
+    /// Get the commits which became public between the two cursors, because
+    /// the upstream of the main branch was observed moving to include them.
+    ///
+    /// This reflects remote-tracking reference updates (such as from `git
+    /// fetch`), even if the local main branch hasn't been updated yet.
+    #[instrument]
+    pub fn get_commits_made_public(
+        &self,
+        effects: &Effects,
+        repo: &Repo,
+        dag: &mut Dag,
+        start_cursor: EventCursor,
+        end_cursor: EventCursor,
+    ) -> eyre::Result<CommitSet> {
+        let upstream_reference_name = match repo.get_main_branch()?.get_upstream_branch()? {
+            Some(upstream_branch) => upstream_branch.get_reference_name()?,
+            None => return Ok(CommitSet::empty()),
+        };
+        let get_public_heads = |cursor: EventCursor| -> eyre::Result<CommitSet> {
+            let main_branch_oid = self.get_cursor_main_branch_oid(cursor, repo)?;
+            let upstream_oid = self.get_cursor_remote_branch_oid(cursor, &upstream_reference_name);
+            let mut heads = CommitSet::from(main_branch_oid);
+            if let Some(upstream_oid) = upstream_oid {
+                // The remote-tracking reference may have pointed to a commit
+                // which has since been garbage-collected.
+                if repo.find_commit(upstream_oid)?.is_some() {
+                    heads = heads.union(&CommitSet::from(upstream_oid));
+                }
+            }
+            Ok(heads)
+        };
+        let old_heads = get_public_heads(start_cursor)?;
+        let new_heads = get_public_heads(end_cursor)?;
+
+        // The upstream commits may not have been observed by the DAG yet.
+        dag.sync_from_oids(
+            effects,
+            repo,
+            old_heads.union(&new_heads),
+            CommitSet::empty(),
+        )?;
+        let commits = dag.query_only(new_heads, old_heads)?;
+        Ok(commits)
+    }
+
     /// Get the OID of the main branch at the cursor's point in time.
     ///
     /// Note that this doesn't handle the case of the user having changed their
@@ -1278,6 +3855,27 @@ impl EventReplayer {
         }
     }
 
+    /// Get the OIDs of the additional main branches at the cursor's point in
+    /// time.
+    fn get_cursor_additional_main_branch_oids(
+        &self,
+        cursor: EventCursor,
+        repo: &Repo,
+    ) -> eyre::Result<HashSet<NonZeroOid>> {
+        let mut result = HashSet::new();
+        for branch in repo.get_additional_main_branches()? {
+            let branch_reference_name = branch.get_reference_name()?;
+            let branch_oid = match self.get_cursor_branch_oid(cursor, &branch_reference_name)? {
+                Some(branch_oid) => Some(branch_oid),
+                // As with the main branch, assume the branch just hasn't been
+                // observed moving yet.
+                None => branch.get_oid()?,
+            };
+            result.extend(branch_oid);
+        }
+        Ok(result)
+    }
+
     /// Get the mapping of branch OIDs to names at the cursor's point in
     /// time.
     ///
@@ -1293,28 +3891,7 @@ impl EventReplayer {
         cursor: EventCursor,
         repo: &Repo,
     ) -> eyre::Result<HashMap<NonZeroOid, HashSet<ReferenceName>>> {
-        let mut ref_name_to_oid: HashMap<&ReferenceName, NonZeroOid> = HashMap::new();
-        let cursor_event_id: usize = cursor.event_id.try_into().unwrap();
-        for event in self.events[..cursor_event_id].iter() {
-            match event {
-                Event::RefUpdateEvent {
-                    new_oid: MaybeZeroOid::NonZero(new_oid),
-                    ref_name,
-                    ..
-                } => {
-                    ref_name_to_oid.insert(ref_name, *new_oid);
-                }
-                Event::RefUpdateEvent {
-                    new_oid: MaybeZeroOid::Zero,
-                    ref_name,
-                    ..
-                } => {
-                    ref_name_to_oid.remove(ref_name);
-                }
-                _ => {}
-            }
-        }
-
+        let ref_name_to_oid = self.get_cursor_ref_name_to_oid(cursor);
         let mut result: HashMap<NonZeroOid, HashSet<ReferenceName>> = HashMap::new();
         for (ref_name, ref_oid) in ref_name_to_oid.iter() {
             if let CategorizedReferenceName::LocalBranch { .. } =
@@ -1335,6 +3912,68 @@ impl EventReplayer {
         Ok(result)
     }
 
+    /// Get the commits pointed to by tags at the cursor's point in time.
+    ///
+    /// Annotated tags are peeled to the commits they refer to. Tags which
+    /// don't refer to a commit, or whose targets no longer exist, are skipped.
+    fn get_cursor_tag_oids(
+        &self,
+        cursor: EventCursor,
+        repo: &Repo,
+    ) -> eyre::Result<HashSet<NonZeroOid>> {
+        let mut result = HashSet::new();
+        for (ref_name, ref_oid) in self.get_cursor_ref_name_to_oid(cursor) {
+            if !ref_name.as_str().starts_with("refs/tags/") {
+                continue;
+            }
+            if let Some(commit) = repo.revparse_single_commit(&format!("{ref_oid}^{{commit}}"))? {
+                result.insert(commit.get_oid());
+            }
+        }
+        Ok(result)
+    }
+
+    /// Get the location of every reference at the cursor's point in time.
+    fn get_cursor_ref_name_to_oid(
+        &self,
+        cursor: EventCursor,
+    ) -> HashMap<&ReferenceName, NonZeroOid> {
+        let mut ref_name_to_oid: HashMap<&ReferenceName, NonZeroOid> = self
+            .snapshot_ref_locations
+            .iter()
+            .map(|(ref_name, oid)| (ref_name, *oid))
+            .collect();
+        for event in self.events[..self.get_cursor_index(cursor)].iter() {
+            match event {
+                Event::RefUpdateEvent {
+                    new_oid: MaybeZeroOid::NonZero(new_oid),
+                    ref_name,
+                    ..
+                } => {
+                    ref_name_to_oid.insert(ref_name, *new_oid);
+                }
+                Event::RefUpdateEvent {
+                    new_oid: MaybeZeroOid::Zero,
+                    ref_name,
+                    ..
+                } => {
+                    ref_name_to_oid.remove(ref_name);
+                }
+                Event::RefRenameEvent {
+                    old_ref_name,
+                    new_ref_name,
+                    oid,
+                    ..
+                } => {
+                    ref_name_to_oid.remove(old_ref_name);
+                    ref_name_to_oid.insert(new_ref_name, *oid);
+                }
+                _ => {}
+            }
+        }
+        ref_name_to_oid
+    }
+
     /// Get the `RepoReferencesSnapshot` at the cursor's point in time.
     pub fn get_references_snapshot(
         &self,
@@ -1343,11 +3982,16 @@ impl EventReplayer {
     ) -> eyre::Result<RepoReferencesSnapshot> {
         let head_oid = self.get_cursor_head_oid(cursor);
         let main_branch_oid = self.get_cursor_main_branch_oid(cursor, repo)?;
+        let additional_main_branch_oids =
+            self.get_cursor_additional_main_branch_oids(cursor, repo)?;
         let branch_oid_to_names = self.get_cursor_branch_oid_to_names(cursor, repo)?;
+        let tag_oids = self.get_cursor_tag_oids(cursor, repo)?;
         Ok(RepoReferencesSnapshot {
             head_oid,
             main_branch_oid,
+            additional_main_branch_oids,
             branch_oid_to_names,
+            tag_oids,
         })
     }
 
@@ -1356,11 +4000,9 @@ impl EventReplayer {
     /// Returns: A tuple of event ID and the event that most recently happened.
     /// If no event was before the event cursor, returns `None` instead.
     pub fn get_event_before_cursor(&self, cursor: EventCursor) -> Option<(isize, &Event)> {
-        if cursor.event_id == 0 {
-            None
-        } else {
-            let previous_cursor_event_id: usize = (cursor.event_id - 1).try_into().unwrap();
-            Some((cursor.event_id, &self.events[previous_cursor_event_id]))
+        match self.get_cursor_index(cursor) {
+            0 => None,
+            index => Some((cursor.event_id, &self.events[index - 1])),
         }
     }
 
@@ -1375,11 +4017,8 @@ impl EventReplayer {
         let EventCursor {
             event_id: prev_event_id,
         } = prev_tx_cursor;
-        let EventCursor {
-            event_id: curr_event_id,
-        } = cursor;
         let tx_events =
-            &self.events[prev_event_id.try_into().unwrap()..curr_event_id.try_into().unwrap()];
+            &self.events[self.get_cursor_index(prev_tx_cursor)..self.get_cursor_index(cursor)];
         match tx_events {
             [] => None,
             events => Some((prev_event_id + 1, events)),
@@ -1391,8 +4030,19 @@ impl EventReplayer {
     /// Returns: An ordered list of events that have happened since the event
     /// cursor, from least recent to most recent.
     pub fn get_events_since_cursor(&self, cursor: EventCursor) -> &[Event] {
-        let cursor_event_id: usize = cursor.event_id.try_into().unwrap();
-        &self.events[cursor_event_id..]
+        &self.events[self.get_cursor_index(cursor)..]
+    }
+
+    /// Get the number of events that have happened since the event cursor, or
+    /// since the beginning if `None` is passed.
+    ///
+    /// Unlike `get_events_since_cursor`, this also counts events which were
+    /// processed before the replayer's snapshot, if it was constructed from
+    /// one (see `from_event_log_db_with_snapshot`).
+    pub fn count_events_since_cursor(&self, cursor: Option<EventCursor>) -> usize {
+        let start_event_id = cursor.map(|cursor| cursor.event_id).unwrap_or(0);
+        let EventCursor { event_id } = self.make_default_cursor();
+        usize::try_from(event_id - start_event_id).unwrap_or(0)
     }
 }
 
@@ -1402,7 +4052,10 @@ pub mod testing {
 
     /// Create a new `EventReplayer`, for testing.
     pub fn new_event_replayer(main_branch_reference_name: ReferenceName) -> EventReplayer {
-        EventReplayer::new(main_branch_reference_name)
+        EventReplayer::new(
+            main_branch_reference_name,
+            DEFAULT_REF_NAMESPACE.to_string(),
+        )
     }
 
     /// Create a new transaction ID, for testing.
@@ -1424,6 +4077,15 @@ pub mod testing {
             | Event::RefUpdateEvent {
                 ref mut timestamp, ..
             }
+            | Event::RefRenameEvent {
+                ref mut timestamp, ..
+            }
+            | Event::RemoteRefUpdateEvent {
+                ref mut timestamp, ..
+            }
+            | Event::CommitObservedEvent {
+                ref mut timestamp, ..
+            }
             | Event::CommitEvent {
                 ref mut timestamp, ..
             }
@@ -1433,6 +4095,12 @@ pub mod testing {
             | Event::UnobsoleteEvent {
                 ref mut timestamp, ..
             }
+            | Event::PinEvent {
+                ref mut timestamp, ..
+            }
+            | Event::UnpinEvent {
+                ref mut timestamp, ..
+            }
             | Event::WorkingCopySnapshot {
                 ref mut timestamp, ..
             } => *timestamp = 0.0,
@@ -6,7 +6,7 @@
 
 use std::fmt::Display;
 
-use cursive::theme::{Effect, Style};
+use cursive::theme::{Color, ColorType, Effect, Style};
 use cursive::utils::markup::StyledString;
 use cursive::utils::span::Span;
 
@@ -130,6 +130,17 @@ pub struct Glyphs {
     /// Character used to point to the currently-checked-out branch.
     pub branch_arrow: &'static str,
 
+    /// Character preceding the number of commits a branch is ahead of its
+    /// upstream.
+    pub upstream_ahead: &'static str,
+
+    /// Character preceding the number of commits a branch is behind its
+    /// upstream.
+    pub upstream_behind: &'static str,
+
+    /// Marker for text which was cut off to fit the terminal width.
+    pub ellipsis: &'static str,
+
     /// Bullet-point character for a list of newline-separated items.
     pub bullet_point: &'static str,
 
@@ -180,6 +191,9 @@ impl Glyphs {
             commit_omitted: "#",
             commit_merge: "&",
             branch_arrow: ">",
+            upstream_ahead: "^",
+            upstream_behind: "v",
+            ellipsis: "...",
             bullet_point: "-",
             cycle_arrow: ">",
             cycle_horizontal_line: "-",
@@ -209,6 +223,9 @@ impl Glyphs {
             commit_main_obsolete: "✕",
             commit_main_obsolete_head: "❖",
             branch_arrow: "ᐅ",
+            upstream_ahead: "↑",
+            upstream_behind: "↓",
+            ellipsis: "…",
             bullet_point: "•",
             cycle_arrow: "ᐅ",
             cycle_horizontal_line: "─",
@@ -218,6 +235,33 @@ impl Glyphs {
         }
     }
 
+    /// Return a `Glyphs` object which only uses ASCII characters, but which
+    /// otherwise has the same settings as this one.
+    pub fn ascii(self) -> Self {
+        Glyphs {
+            should_write_ansi_escape_codes: self.should_write_ansi_escape_codes,
+            ..Glyphs::text()
+        }
+    }
+
+    /// Guess whether the terminal can display Unicode characters, based on the
+    /// `TERM` and locale environment variables. Unicode is assumed unless
+    /// `TERM` is `dumb`, or the locale is set to a non-UTF-8 encoding.
+    pub fn terminal_supports_unicode() -> bool {
+        if std::env::var("TERM").as_deref() == Ok("dumb") {
+            return false;
+        }
+        ["LC_ALL", "LC_CTYPE", "LANG"]
+            .into_iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .map(|value| {
+                let value = value.to_ascii_lowercase();
+                value.contains("utf-8") || value.contains("utf8")
+            })
+            .unwrap_or(true)
+    }
+
     /// Return a `Glyphs` object suitable for rendering graphs in the reverse of
     /// their usual order.
     pub fn reverse_order(mut self, reverse: bool) -> Self {
@@ -359,6 +403,15 @@ pub fn set_effect(mut string: StyledString, effect: Effect) -> StyledString {
     string
 }
 
+/// Set the provided foreground color to all the internal spans of the styled
+/// string, overriding any existing colors.
+pub fn set_color(mut string: StyledString, color: Color) -> StyledString {
+    string.spans_raw_attr_mut().for_each(|span| {
+        span.attr.color.front = ColorType::Color(color);
+    });
+    string
+}
+
 impl From<StyledStringBuilder> for StyledString {
     fn from(builder: StyledStringBuilder) -> Self {
         builder.build()
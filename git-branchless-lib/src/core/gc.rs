@@ -4,45 +4,329 @@
 //! it or one of its descendants. However, the branchless workflow requires
 //! keeping such commits reachable until the user has obsoleted them.
 //!
-//! This module is responsible for adding extra references to Git, so that Git's
+//! This module is responsible for keeping such commits reachable, so that Git's
 //! garbage collection doesn't collect commits which branchless thinks are still
-//! active.
+//! active. Rather than creating one reference per commit, which bloats
+//! `packed-refs` and slows down every reference enumeration, the kept commits
+//! are recorded as the parents of a chain of synthetic "keep-alive" commits,
+//! the tip of which is pointed to by a single reference (see
+//! [`get_keep_alive_reference_name`]).
+//!
+//! The chain is structured as follows:
+//!
+//! - The root of the chain is a commit with no parents.
+//! - Every other commit in the chain has the previous commit in the chain as
+//!   its first parent, and one or more kept commits as its remaining parents.
+//!
+//! Marking commits as reachable only appends to the chain. The chain is
+//! rewritten from scratch, and packed into as few commits as possible, only by
+//! garbage collection. The set of kept commits is cached under the `.git/branchless`
+//! directory along with the tip of the chain it was read from, so that the
+//! chain doesn't need to be walked every time a commit is marked as reachable.
+//!
+//! The keep-alive commits all have an empty tree and the message
+//! "branchless: keep-alive". They're visible to commands which consider every
+//! reference, such as `git log --all`, but `git branchless init` excludes the
+//! reference namespace from `git log`'s decorations.
 
+use std::collections::HashSet;
 use std::fmt::Write;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
 
 use eyre::Context;
+use itertools::Itertools;
 use tracing::instrument;
 
-use crate::core::effects::Effects;
-use crate::core::eventlog::{
-    is_gc_ref, CommitActivityStatus, EventCursor, EventLogDb, EventReplayer,
+use crate::core::config::{
+    get_gc_auto_events, get_gc_event_log_compaction_depth, get_gc_event_log_retention_days,
+    get_gc_retention_days, get_ref_namespace,
 };
+use crate::core::dag::Dag;
+use crate::core::effects::Effects;
+use crate::core::eventlog::{CommitActivityStatus, EventCursor, EventLogDb, EventReplayer};
 use crate::core::formatting::Pluralize;
-use crate::git::{NonZeroOid, Reference, Repo};
+use crate::core::repo_ext::RepoExt;
+use crate::git::{
+    hydrate_tree, GitRunInfo, MaybeZeroOid, NonZeroOid, ReferenceName, Repo, Signature,
+};
+use crate::try_exit_code;
+use crate::util::EyreExitOr;
 
-/// Find references under `refs/branchless/` which point to commits which are no
-/// longer active. These are safe to remove.
-pub fn find_dangling_references<'repo>(
-    repo: &'repo Repo,
-    event_replayer: &EventReplayer,
-    event_cursor: EventCursor,
-) -> eyre::Result<Vec<Reference<'repo>>> {
-    let mut result = Vec::new();
+/// Get the name of the reference pointing to the tip of the keep-alive chain.
+/// It lives under the configured reference namespace (see
+/// `get_ref_namespace`).
+#[instrument]
+pub fn get_keep_alive_reference_name(repo: &Repo) -> eyre::Result<ReferenceName> {
+    let ref_namespace = get_ref_namespace(repo)?;
+    Ok(ReferenceName::from(format!("{ref_namespace}keep-alive")))
+}
+
+/// The maximum number of kept commits to record in a single commit of the
+/// keep-alive chain.
+const KEEP_ALIVE_MAX_PARENTS: usize = 64;
+
+const KEEP_ALIVE_MESSAGE: &str = "branchless: keep-alive";
+
+fn get_keep_alive_cache_path(repo: &Repo) -> eyre::Result<PathBuf> {
+    Ok(repo.get_branchless_dir()?.join("keep-alive"))
+}
+
+/// Read the cached set of kept commits. Returns `None` if there is no cache,
+/// or if it wasn't computed for the keep-alive chain ending at `tip_oid`.
+#[instrument]
+fn read_keep_alive_cache(
+    repo: &Repo,
+    tip_oid: NonZeroOid,
+) -> eyre::Result<Option<HashSet<NonZeroOid>>> {
+    let contents = match std::fs::read_to_string(get_keep_alive_cache_path(repo)?) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).wrap_err("Reading keep-alive cache"),
+    };
+    let mut lines = contents.lines();
+    if lines.next() != Some(tip_oid.to_string().as_str()) {
+        return Ok(None);
+    }
+    let mut result = HashSet::new();
+    for line in lines {
+        match line.parse::<NonZeroOid>() {
+            Ok(commit_oid) => {
+                result.insert(commit_oid);
+            }
+            Err(_) => return Ok(None),
+        }
+    }
+    Ok(Some(result))
+}
+
+/// Cache the set of commits kept alive by the keep-alive chain ending at
+/// `tip_oid`.
+#[instrument]
+fn write_keep_alive_cache(
+    repo: &Repo,
+    tip_oid: NonZeroOid,
+    kept_oids: &HashSet<NonZeroOid>,
+) -> eyre::Result<()> {
+    let mut contents = format!("{tip_oid}\n");
+    for commit_oid in kept_oids.iter().sorted() {
+        writeln!(contents, "{commit_oid}")?;
+    }
+
+    // Write to a temporary file first, so that concurrent readers never see a
+    // partially-written cache.
+    let path = get_keep_alive_cache_path(repo)?;
+    let temp_path = path.with_extension(format!("tmp{}", std::process::id()));
+    std::fs::write(&temp_path, contents).wrap_err("Writing keep-alive cache")?;
+    std::fs::rename(&temp_path, &path).wrap_err("Writing keep-alive cache")?;
+    Ok(())
+}
+
+/// Read the tip of the keep-alive chain, if any, along with the OIDs of all
+/// commits which are currently being kept alive.
+#[instrument]
+fn read_keep_alive_chain(repo: &Repo) -> eyre::Result<(MaybeZeroOid, HashSet<NonZeroOid>)> {
+    let tip = match repo.find_reference(&get_keep_alive_reference_name(repo)?)? {
+        Some(reference) => reference.peel_to_commit()?,
+        None => None,
+    };
+    let tip_oid = match &tip {
+        Some(tip) => tip.get_oid(),
+        None => return Ok((MaybeZeroOid::Zero, HashSet::new())),
+    };
+    if let Some(kept_oids) = read_keep_alive_cache(repo, tip_oid)? {
+        return Ok((MaybeZeroOid::NonZero(tip_oid), kept_oids));
+    }
+
+    let mut kept_oids = HashSet::new();
+    let mut current = tip;
+    while let Some(commit) = current {
+        let parent_oids = commit.get_parent_oids();
+        match parent_oids.split_first() {
+            None => break,
+            Some((previous_oid, commit_oids)) => {
+                kept_oids.extend(commit_oids.iter().copied());
+                current = repo.find_commit(*previous_oid)?;
+            }
+        }
+    }
+    write_keep_alive_cache(repo, tip_oid, &kept_oids)?;
+    Ok((MaybeZeroOid::NonZero(tip_oid), kept_oids))
+}
+
+/// Create the commits for a keep-alive chain containing the given commits, and
+/// return the OID of the tip commit. `base_oid` is the existing chain to
+/// append to, if any. All of the given commits must exist.
+#[instrument]
+fn write_keep_alive_chain(
+    repo: &Repo,
+    base_oid: MaybeZeroOid,
+    commit_oids: &[NonZeroOid],
+) -> eyre::Result<NonZeroOid> {
+    let signature = Signature::automated()?;
+    let tree_oid = hydrate_tree(repo, None, Default::default())?;
+    let tree = repo.find_tree_or_fail(tree_oid)?;
+
+    let mut previous_oid = match base_oid {
+        MaybeZeroOid::NonZero(base_oid) => base_oid,
+        MaybeZeroOid::Zero => repo.create_commit(
+            None,
+            &signature,
+            &signature,
+            KEEP_ALIVE_MESSAGE,
+            &tree,
+            Vec::new(),
+        )?,
+    };
+    for chunk in &commit_oids.iter().chunks(KEEP_ALIVE_MAX_PARENTS) {
+        let mut parents = vec![repo.find_commit_or_fail(previous_oid)?];
+        for commit_oid in chunk {
+            parents.push(repo.find_commit_or_fail(*commit_oid)?);
+        }
+
+        // Git's revision walks assume that commits aren't older than their
+        // parents, so they may not notice that a commit is reachable from an
+        // older keep-alive commit (such as when `git checkout` checks for
+        // commits being left behind).
+        let mut timestamp = SystemTime::UNIX_EPOCH;
+        for parent in parents.iter() {
+            timestamp = timestamp.max(parent.get_time().to_system_time()?);
+        }
+        let chunk_signature = Signature::automated()?.update_timestamp(timestamp)?;
+        previous_oid = repo.create_commit(
+            None,
+            &chunk_signature,
+            &chunk_signature,
+            KEEP_ALIVE_MESSAGE,
+            &tree,
+            parents.iter().collect(),
+        )?;
+    }
+    Ok(previous_oid)
+}
+
+/// Get the OIDs of all commits which are currently kept alive by branchless.
+#[instrument]
+pub fn get_kept_commit_oids(repo: &Repo) -> eyre::Result<HashSet<NonZeroOid>> {
+    let (_tip_oid, kept_oids) = read_keep_alive_chain(repo)?;
+    Ok(kept_oids)
+}
+
+/// Fold any per-commit references created by older versions of branchless
+/// into the keep-alive chain, and delete them. These are named after the commit
+/// they keep alive, under the reference namespace. Other references under the
+/// namespace are left alone.
+#[instrument]
+fn migrate_legacy_references(repo: &Repo) -> eyre::Result<()> {
+    let ref_namespace = get_ref_namespace(repo)?;
+    let keep_alive_reference_name = get_keep_alive_reference_name(repo)?;
+    let mut legacy_reference_names = Vec::new();
+    let mut legacy_oids = Vec::new();
     for reference in repo.get_all_references()? {
         let reference_name = reference.get_name()?;
-        if !is_gc_ref(&reference_name) {
-            continue;
+        let is_legacy_reference = reference_name
+            .as_str()
+            .strip_prefix(ref_namespace.as_str())
+            .map(|suffix| suffix.parse::<NonZeroOid>().is_ok())
+            .unwrap_or(false);
+        if is_legacy_reference {
+            if let Some(commit) = reference.peel_to_commit()? {
+                legacy_oids.push(commit.get_oid());
+            }
+            legacy_reference_names.push(reference_name);
         }
+    }
+    if legacy_reference_names.is_empty() {
+        return Ok(());
+    }
+
+    let mut transaction = repo.start_reference_transaction()?;
+    transaction.lock(&keep_alive_reference_name)?;
+    for reference_name in legacy_reference_names.iter() {
+        transaction.lock(reference_name)?;
+    }
+    let (tip_oid, mut kept_oids) = read_keep_alive_chain(repo)?;
+    let new_oids = legacy_oids
+        .into_iter()
+        .filter(|commit_oid| !kept_oids.contains(commit_oid))
+        .unique()
+        .collect_vec();
+    let new_tip_oid = if new_oids.is_empty() {
+        None
+    } else {
+        let new_tip_oid = write_keep_alive_chain(repo, tip_oid, &new_oids)?;
+        transaction.set_target(
+            &keep_alive_reference_name,
+            new_tip_oid,
+            "branchless: migrating legacy references",
+        )?;
+        Some(new_tip_oid)
+    };
+    for reference_name in legacy_reference_names.iter() {
+        transaction.remove(reference_name)?;
+    }
+    transaction
+        .commit()
+        .wrap_err("Migrating legacy references")?;
+
+    if let Some(new_tip_oid) = new_tip_oid {
+        kept_oids.extend(new_oids);
+        write_keep_alive_cache(repo, new_tip_oid, &kept_oids)?;
+    }
+    Ok(())
+}
+
+/// The result of scanning for dangling commits.
+#[derive(Debug)]
+pub struct DanglingCommits {
+    /// The kept commits which are no longer active.
+    pub dangling_commit_oids: Vec<NonZeroOid>,
+
+    /// The number of kept commits which were examined.
+    pub num_scanned: usize,
+}
+
+/// Find commits kept alive by branchless which are no longer active. These are
+/// safe to stop keeping alive.
+///
+/// If `retention_cutoff` is provided, then commits which were hidden after that
+/// point in time are not considered dangling, so that they can still be
+/// unhidden later.
+///
+/// If `candidate_oids` is provided, then only those commits are considered,
+/// rather than every kept commit.
+///
+/// Commits which are checked out or being merged in any worktree, or which a
+/// stash entry is based on, are never considered dangling.
+pub fn find_dangling_commits(
+    repo: &Repo,
+    event_replayer: &EventReplayer,
+    event_cursor: EventCursor,
+    retention_cutoff: Option<SystemTime>,
+    candidate_oids: Option<&HashSet<NonZeroOid>>,
+) -> eyre::Result<DanglingCommits> {
+    let kept_oids = get_kept_commit_oids(repo)?;
+    let commit_oids = match candidate_oids {
+        None => kept_oids,
+        Some(candidate_oids) => kept_oids.intersection(candidate_oids).copied().collect(),
+    };
 
-        // The graph only contains commits, so we don't need to handle the
-        // case of the reference not peeling to a valid commit. (It might be
-        // a reference to a different kind of object.)
-        let commit = match reference.peel_to_commit()? {
-            Some(commit) => commit,
-            None => continue,
-        };
+    // Commits which are checked out in some other worktree may not be active
+    // according to the event log for this worktree, but the user still expects
+    // them to stick around.
+    let worktree_head_oids = repo.get_all_worktree_head_oids()?;
 
-        match event_replayer.get_cursor_commit_activity_status(event_cursor, commit.get_oid()) {
+    // Likewise, commits which a stash entry was based on should stick around
+    // for as long as the stash entry does.
+    let stash_oids = repo.get_stash_oids()?;
+
+    let mut result = Vec::new();
+    let num_scanned = commit_oids.len();
+    for commit_oid in commit_oids.into_iter().sorted() {
+        if worktree_head_oids.contains(&commit_oid) || stash_oids.contains(&commit_oid) {
+            continue;
+        }
+        match event_replayer.get_cursor_commit_activity_status(event_cursor, commit_oid) {
             CommitActivityStatus::Active => {
                 // Do nothing.
             }
@@ -50,13 +334,31 @@ pub fn find_dangling_references<'repo>(
                 // This commit hasn't been observed, but it's possible that the user expected it
                 // to remain. Do nothing. See https://github.com/arxanas/git-branchless/issues/412.
             }
+            CommitActivityStatus::Obsolete
+                if event_replayer.get_cursor_commit_is_pinned(event_cursor, commit_oid) =>
+            {
+                // This commit was explicitly pinned by the user. Do nothing.
+            }
             CommitActivityStatus::Obsolete => {
-                // This commit was explicitly hidden by some operation.
-                result.push(reference)
+                // This commit was explicitly hidden by some operation. Keep it
+                // around anyways if it was hidden too recently.
+                let hidden_recently = match retention_cutoff {
+                    Some(retention_cutoff) => event_replayer
+                        .get_cursor_commit_latest_event(event_cursor, commit_oid)
+                        .map(|event| event.get_timestamp() > retention_cutoff)
+                        .unwrap_or(false),
+                    None => false,
+                };
+                if !hidden_recently {
+                    result.push(commit_oid)
+                }
             }
         }
     }
-    Ok(result)
+    Ok(DanglingCommits {
+        dangling_commit_oids: result,
+        num_scanned,
+    })
 }
 
 /// Mark a commit as reachable.
@@ -72,57 +374,415 @@ pub fn find_dangling_references<'repo>(
 /// * `commit_oid`: The commit OID to mark as reachable.
 #[instrument]
 pub fn mark_commit_reachable(repo: &Repo, commit_oid: NonZeroOid) -> eyre::Result<()> {
-    let ref_name = format!("refs/branchless/{commit_oid}");
-    eyre::ensure!(
-        Reference::is_valid_name(&ref_name),
-        format!("Invalid ref name to mark commit as reachable: {ref_name}")
-    );
+    mark_commits_reachable(repo, &[commit_oid])
+}
+
+/// Mark several commits as reachable at once. See `mark_commit_reachable`.
+///
+/// The keep-alive reference is locked and updated only once, so this is much
+/// faster than calling `mark_commit_reachable` for each commit individually.
+#[instrument]
+pub fn mark_commits_reachable(repo: &Repo, commit_oids: &[NonZeroOid]) -> eyre::Result<()> {
+    let reference_name = get_keep_alive_reference_name(repo)?;
+    let mut transaction = repo.start_reference_transaction()?;
+    transaction.lock(&reference_name)?;
 
-    // NB: checking for the commit first with `find_commit` is racy, as the `create_reference` call
-    // could still fail if the commit is deleted by then, but it's too hard to propagate whether the
-    // commit was not found from `create_reference`.
-    if repo.find_commit(commit_oid)?.is_some() {
-        repo.create_reference(
-            &ref_name.into(),
-            commit_oid,
-            true,
-            "branchless: marking commit as reachable",
-        )
-        .wrap_err("Creating reference")?;
+    let (tip_oid, mut kept_oids) = read_keep_alive_chain(repo)?;
+    let mut new_oids = Vec::new();
+    for commit_oid in commit_oids.iter().unique() {
+        // NB: checking for the commit first with `find_commit` is racy, as the
+        // commit could still be deleted by the time that the chain is written.
+        if !kept_oids.contains(commit_oid) && repo.find_commit(*commit_oid)?.is_some() {
+            new_oids.push(*commit_oid);
+        }
+    }
+    if new_oids.is_empty() {
+        return Ok(());
+    }
+
+    let new_tip_oid = write_keep_alive_chain(repo, tip_oid, &new_oids)?;
+    transaction.set_target(
+        &reference_name,
+        new_tip_oid,
+        "branchless: marking commits as reachable",
+    )?;
+    transaction
+        .commit()
+        .wrap_err("Marking commits as reachable")?;
+
+    kept_oids.extend(new_oids);
+    write_keep_alive_cache(repo, new_tip_oid, &kept_oids)?;
+    Ok(())
+}
+
+/// Mark every commit which is currently active according to the event log as
+/// reachable. Normally, commits are marked as reachable as they're created,
+/// but this may not have happened if the commit was created by a tool which
+/// doesn't invoke the branchless hooks.
+///
+/// Returns the number of commits which were newly marked as reachable.
+#[instrument]
+pub fn mark_active_commits_reachable(effects: &Effects, repo: &Repo) -> eyre::Result<usize> {
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+
+    let kept_oids = get_kept_commit_oids(repo)?;
+    let mut active_oids = Vec::new();
+    for commit_oid in event_replayer
+        .get_cursor_oids(event_cursor)
+        .into_iter()
+        .sorted()
+    {
+        if kept_oids.contains(&commit_oid) {
+            continue;
+        }
+        match event_replayer.get_cursor_commit_activity_status(event_cursor, commit_oid) {
+            CommitActivityStatus::Active => {
+                if repo.find_commit(commit_oid)?.is_some() {
+                    active_oids.push(commit_oid);
+                }
+            }
+            CommitActivityStatus::Inactive | CommitActivityStatus::Obsolete => {}
+        }
+    }
+    mark_commits_reachable(repo, &active_oids)?;
+    Ok(active_oids.len())
+}
+
+/// Stop keeping a commit reachable, so that it can be collected by Git's
+/// garbage collection mechanism (unless it's reachable by some other means).
+///
+/// If the commit was not marked as reachable, then this is a no-op.
+#[instrument]
+pub fn unmark_commit_reachable(repo: &Repo, commit_oid: NonZeroOid) -> eyre::Result<()> {
+    unmark_commits_reachable(repo, &[commit_oid])
+}
+
+/// Stop keeping several commits reachable at once. See
+/// `unmark_commit_reachable`.
+///
+/// This rewrites the whole keep-alive chain, so all of the commits to release
+/// should be passed in a single call.
+#[instrument]
+pub fn unmark_commits_reachable(repo: &Repo, commit_oids: &[NonZeroOid]) -> eyre::Result<()> {
+    let reference_name = get_keep_alive_reference_name(repo)?;
+    let mut transaction = repo.start_reference_transaction()?;
+    transaction.lock(&reference_name)?;
+
+    let (_tip_oid, kept_oids) = read_keep_alive_chain(repo)?;
+    let commit_oids: HashSet<NonZeroOid> = commit_oids.iter().copied().collect();
+    if kept_oids.is_disjoint(&commit_oids) {
+        return Ok(());
     }
 
+    let mut remaining_oids = Vec::new();
+    for commit_oid in kept_oids.into_iter().sorted() {
+        if !commit_oids.contains(&commit_oid) && repo.find_commit(commit_oid)?.is_some() {
+            remaining_oids.push(commit_oid);
+        }
+    }
+    if remaining_oids.is_empty() {
+        transaction.remove(&reference_name)?;
+        transaction
+            .commit()
+            .wrap_err("Unmarking commits as reachable")?;
+        return Ok(());
+    }
+
+    let new_tip_oid = write_keep_alive_chain(repo, MaybeZeroOid::Zero, &remaining_oids)?;
+    transaction.set_target(
+        &reference_name,
+        new_tip_oid,
+        "branchless: unmarking commits as reachable",
+    )?;
+    transaction
+        .commit()
+        .wrap_err("Unmarking commits as reachable")?;
+
+    write_keep_alive_cache(repo, new_tip_oid, &remaining_oids.into_iter().collect())?;
     Ok(())
 }
 
+/// Options for garbage collection. See `gc`.
+#[derive(Clone, Debug, Default)]
+pub struct GcOptions {
+    /// Print the commits which would be released, but don't actually change
+    /// anything.
+    pub dry_run: bool,
+
+    /// After releasing the commits, also delete the reflog entries which
+    /// refer to them, so that Git can actually reclaim the space used by the
+    /// newly-unreachable commits. Other reflog entries are left untouched.
+    pub full: bool,
+
+    /// Invoke `git gc --auto` afterwards.
+    pub git_gc: bool,
+
+    /// Consider all references, rather than only the commits affected by events
+    /// since the last garbage collection. All references are also considered if
+    /// garbage collection has never been run before.
+    pub full_scan: bool,
+
+    /// Also compact the event log and delete old entries in it. See
+    /// `EventLogDb::compact_events` and `EventLogDb::prune_events_before`.
+    pub aggressive: bool,
+
+    /// Also rebuild the commit graph cache from the current references. See
+    /// `Dag::clear_cache`.
+    pub refresh_cache: bool,
+}
+
 /// Run branchless's garbage collection.
 ///
-/// Frees any references to commits which are no longer visible in the smartlog.
+/// Stops keeping alive any commits which are no longer visible in the smartlog.
 #[instrument]
-pub fn gc(effects: &Effects) -> eyre::Result<()> {
+pub fn gc(effects: &Effects, git_run_info: &GitRunInfo, options: &GcOptions) -> EyreExitOr<()> {
+    let GcOptions {
+        dry_run,
+        full,
+        git_gc,
+        full_scan,
+        aggressive,
+        refresh_cache,
+    } = *options;
+
     let repo = Repo::from_current_dir()?;
     let conn = repo.get_db_conn()?;
     let event_log_db = EventLogDb::new(&conn)?;
     let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
     let event_cursor = event_replayer.make_default_cursor();
+    let gc_cursor = if full_scan {
+        None
+    } else {
+        event_log_db
+            .get_gc_cursor()?
+            .map(|gc_cursor| event_replayer.advance_cursor(gc_cursor, 0))
+    };
+    let candidate_oids =
+        gc_cursor.map(|gc_cursor| event_replayer.get_commits_touched_since_cursor(gc_cursor));
 
     writeln!(
         effects.get_output_stream(),
         "branchless: collecting garbage"
     )?;
-    let dangling_references = find_dangling_references(&repo, &event_replayer, event_cursor)?;
-    let num_dangling_references = Pluralize {
+    let retention_cutoff = match get_gc_retention_days(&repo)? {
+        Some(retention_days) if retention_days > 0 => {
+            let retention_period =
+                Duration::from_secs(u64::try_from(retention_days)? * 24 * 60 * 60);
+            SystemTime::now().checked_sub(retention_period)
+        }
+        Some(_) | None => None,
+    };
+    // NB: this is done even on a dry run, since it doesn't change which
+    // commits are kept alive.
+    migrate_legacy_references(&repo)?;
+    let DanglingCommits {
+        dangling_commit_oids,
+        num_scanned,
+    } = find_dangling_commits(
+        &repo,
+        &event_replayer,
+        event_cursor,
+        retention_cutoff,
+        candidate_oids.as_ref(),
+    )?;
+    let num_protected = num_scanned - dangling_commit_oids.len();
+    let scan_summary = format!(
+        "{} scanned, {} still protected",
+        Pluralize {
+            determiner: None,
+            amount: num_scanned,
+            unit: ("kept commit", "kept commits"),
+        },
+        Pluralize {
+            determiner: None,
+            amount: num_protected,
+            unit: ("commit", "commits"),
+        },
+    );
+    let num_dangling_commits = Pluralize {
         determiner: None,
-        amount: dangling_references.len(),
-        unit: ("dangling reference", "dangling references"),
+        amount: dangling_commit_oids.len(),
+        unit: ("dangling commit", "dangling commits"),
     }
     .to_string();
-    for mut reference in dangling_references.into_iter() {
-        reference.delete()?;
+
+    if dry_run {
+        let glyphs = effects.get_glyphs();
+        for commit_oid in dangling_commit_oids.iter() {
+            writeln!(
+                effects.get_output_stream(),
+                "branchless: would release {}",
+                glyphs.render(repo.friendly_describe_commit_from_oid(glyphs, *commit_oid)?)?,
+            )?;
+        }
+        writeln!(
+            effects.get_output_stream(),
+            "branchless: {num_dangling_commits} would be released (dry run)",
+        )?;
+        writeln!(effects.get_output_stream(), "branchless: {scan_summary}")?;
+        return Ok(Ok(()));
     }
 
+    // Each released commit may have been kept alive only by branchless. This is
+    // only an upper bound, since the commit may still be reachable from some
+    // other reference (or its reflog).
+    let num_eligible_commits = dangling_commit_oids.len();
+    unmark_commits_reachable(&repo, &dangling_commit_oids)?;
+
+    // Commits hidden within the retention period weren't collected, so the
+    // next run needs to consider them again.
+    let next_gc_cursor = {
+        let start_cursor = gc_cursor.unwrap_or_else(|| event_replayer.make_cursor(0));
+        let retained_event_index = retention_cutoff.and_then(|retention_cutoff| {
+            event_replayer
+                .get_events_since_cursor(start_cursor)
+                .iter()
+                .position(|event| event.get_timestamp() > retention_cutoff)
+        });
+        match retained_event_index {
+            Some(index) => event_replayer.advance_cursor(start_cursor, isize::try_from(index)?),
+            None => event_cursor,
+        }
+    };
+    event_log_db.set_gc_cursor(next_gc_cursor)?;
+
     writeln!(
         effects.get_output_stream(),
-        "branchless: {num_dangling_references} deleted",
+        "branchless: {num_dangling_commits} released",
     )?;
-    Ok(())
+    writeln!(effects.get_output_stream(), "branchless: {scan_summary}")?;
+    writeln!(
+        effects.get_output_stream(),
+        "branchless: up to {} newly eligible for Git's garbage collection",
+        Pluralize {
+            determiner: None,
+            amount: num_eligible_commits,
+            unit: ("commit", "commits"),
+        },
+    )?;
+
+    if aggressive {
+        let retention_days = get_gc_event_log_retention_days(&repo)?;
+        let retention_period = Duration::from_secs(u64::from(retention_days) * 24 * 60 * 60);
+        let cutoff = SystemTime::now()
+            .checked_sub(retention_period)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        let num_compacted =
+            event_log_db.compact_events(get_gc_event_log_compaction_depth(&repo)?)?;
+        let num_pruned = event_log_db.prune_events_before(cutoff)?;
+        writeln!(
+            effects.get_output_stream(),
+            "branchless: compacted {} into summarized entries",
+            Pluralize {
+                determiner: None,
+                amount: num_compacted,
+                unit: ("redundant event log entry", "redundant event log entries"),
+            },
+        )?;
+        writeln!(
+            effects.get_output_stream(),
+            "branchless: pruned {} older than {}",
+            Pluralize {
+                determiner: None,
+                amount: num_pruned,
+                unit: ("event log entry", "event log entries"),
+            },
+            Pluralize {
+                determiner: None,
+                amount: usize::try_from(retention_days)?,
+                unit: ("day", "days"),
+            },
+        )?;
+    }
+
+    if refresh_cache {
+        Dag::clear_cache(&repo)?;
+        let references_snapshot = repo.get_references_snapshot()?;
+        let dag = Dag::open_and_sync(
+            effects,
+            &repo,
+            &event_replayer,
+            event_cursor,
+            &references_snapshot,
+        )?;
+        writeln!(
+            effects.get_output_stream(),
+            "branchless: rebuilt commit graph cache with {}",
+            Pluralize {
+                determiner: None,
+                amount: dag.set_count(&dag.query_all()?)?,
+                unit: ("commit", "commits"),
+            },
+        )?;
+    }
+
+    if full {
+        let released_oids: HashSet<NonZeroOid> = dangling_commit_oids.into_iter().collect();
+        let mut num_expired_entries = 0;
+        if !released_oids.is_empty() {
+            let mut reference_names = vec![ReferenceName::from("HEAD")];
+            for reference in repo.get_all_references()? {
+                reference_names.push(reference.get_name()?);
+            }
+            for reference_name in reference_names {
+                num_expired_entries +=
+                    repo.remove_reflog_entries(&reference_name, &released_oids)?;
+            }
+        }
+        writeln!(
+            effects.get_output_stream(),
+            "branchless: expired {} for released commits",
+            Pluralize {
+                determiner: None,
+                amount: num_expired_entries,
+                unit: ("reflog entry", "reflog entries"),
+            },
+        )?;
+    }
+
+    if git_gc {
+        try_exit_code!(git_run_info.run(effects, None, &["gc", "--auto"])?);
+    }
+
+    Ok(Ok(()))
+}
+
+/// Run garbage collection if enough events have been recorded since the last
+/// garbage collection, according to the `branchless.gc.autoEvents` setting.
+/// This is meant to be called by hooks after they've recorded events.
+#[instrument]
+pub fn maybe_auto_gc(effects: &Effects, git_run_info: &GitRunInfo) -> EyreExitOr<()> {
+    let repo = Repo::from_current_dir()?;
+    let auto_events = get_gc_auto_events(&repo)?;
+    if auto_events == 0 {
+        return Ok(Ok(()));
+    }
+
+    // Count the events as seen by the replayer, since that's what the GC cursor
+    // refers to. (The replayer drops some events, such as redundant reference
+    // updates, so the number of rows in the event log would overcount.)
+    let num_events_since_gc = {
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_replayer =
+            EventReplayer::from_event_log_db_with_snapshot(effects, &repo, &event_log_db)?;
+        event_replayer.count_events_since_cursor(event_log_db.get_gc_cursor()?)
+    };
+    if num_events_since_gc < auto_events {
+        return Ok(Ok(()));
+    }
+
+    writeln!(
+        effects.get_output_stream(),
+        "branchless: {} recorded since the last garbage collection, running gc automatically",
+        Pluralize {
+            determiner: None,
+            amount: num_events_since_gc,
+            unit: ("event", "events"),
+        },
+    )?;
+    gc(effects, git_run_info, &GcOptions::default())
 }
@@ -4,26 +4,38 @@
 //! commit message.
 
 use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
 use std::sync::{Arc, Mutex};
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 use bstr::{ByteSlice, ByteVec};
-use cursive::theme::BaseColor;
+use cursive::theme::{BaseColor, Color};
 use cursive::utils::markup::StyledString;
+use eyre::Context;
 use lazy_static::lazy_static;
 use regex::Regex;
 use tracing::instrument;
 
 use crate::core::config::{
-    get_commit_descriptors_branches, get_commit_descriptors_differential_revision,
-    get_commit_descriptors_relative_time,
+    get_commit_descriptors_ahead_behind, get_commit_descriptors_branches,
+    get_commit_descriptors_diff_stat, get_commit_descriptors_differential_revision,
+    get_commit_descriptors_relative_time, get_commit_descriptors_remote_branches,
+    get_commit_descriptors_signature, get_commit_descriptors_status_command,
+    get_commit_descriptors_status_command_cache_seconds,
+    get_commit_descriptors_upstream_divergence, get_commit_descriptors_working_copy_status,
+    get_smartlog_colors, get_smartlog_ticket_pattern,
 };
 use crate::git::{
-    CategorizedReferenceName, Commit, NonZeroOid, ReferenceName, Repo, ResolvedReferenceInfo,
+    BranchType, CategorizedReferenceName, Commit, DiffStat, GitRunInfo, GitRunOpts, NonZeroOid,
+    ReferenceName, Repo, ResolvedReferenceInfo,
 };
+use crate::util::get_sh;
 
-use super::eventlog::{Event, EventCursor, EventReplayer};
-use super::formatting::{Glyphs, StyledStringBuilder};
+use super::dag::{CommitSet, Dag};
+use super::effects::Effects;
+use super::eventlog::{Event, EventCursor, EventLogDb, EventReplayer, EventTransactionId};
+use super::formatting::{Glyphs, Pluralize, StyledStringBuilder};
 use super::repo_ext::RepoReferencesSnapshot;
 use super::rewrite::find_rewrite_target;
 
@@ -45,7 +57,8 @@ pub enum NodeObject<'repo> {
 }
 
 impl<'repo> NodeObject<'repo> {
-    fn get_oid(&self) -> NonZeroOid {
+    /// Get the OID of the underlying commit.
+    pub fn get_oid(&self) -> NonZeroOid {
         match self {
             NodeObject::Commit { commit } => commit.get_oid(),
             NodeObject::GarbageCollected { oid } => *oid,
@@ -237,6 +250,7 @@ impl<'a> NodeDescriptor for CommitMessageDescriptor<'a> {
 pub struct ObsolescenceExplanationDescriptor<'a> {
     event_replayer: &'a EventReplayer,
     event_cursor: EventCursor,
+    transaction_reasons: HashMap<EventTransactionId, String>,
 }
 
 impl<'a> ObsolescenceExplanationDescriptor<'a> {
@@ -245,8 +259,21 @@ impl<'a> ObsolescenceExplanationDescriptor<'a> {
         Ok(ObsolescenceExplanationDescriptor {
             event_replayer,
             event_cursor,
+            transaction_reasons: Default::default(),
         })
     }
+
+    /// Also show the reasons which the user gave when hiding commits, as
+    /// returned by [`EventLogDb::get_transaction_reasons`].
+    pub fn with_transaction_reasons(
+        self,
+        transaction_reasons: HashMap<EventTransactionId, String>,
+    ) -> Self {
+        Self {
+            transaction_reasons,
+            ..self
+        }
+    }
 }
 
 impl<'a> NodeDescriptor for ObsolescenceExplanationDescriptor<'a> {
@@ -276,14 +303,22 @@ impl<'a> NodeDescriptor for ObsolescenceExplanationDescriptor<'a> {
                 })
             }
 
-            Event::ObsoleteEvent { .. } => Some(StyledString::styled(
-                "(manually hidden)",
+            Event::ObsoleteEvent { event_tx_id, .. } => Some(StyledString::styled(
+                match self.transaction_reasons.get(event_tx_id) {
+                    Some(reason) => format!("(manually hidden: {reason})"),
+                    None => "(manually hidden)".to_string(),
+                },
                 BaseColor::Black.light(),
             )),
 
             Event::RefUpdateEvent { .. }
+            | Event::RefRenameEvent { .. }
+            | Event::RemoteRefUpdateEvent { .. }
+            | Event::CommitObservedEvent { .. }
             | Event::CommitEvent { .. }
             | Event::UnobsoleteEvent { .. }
+            | Event::PinEvent { .. }
+            | Event::UnpinEvent { .. }
             | Event::WorkingCopySnapshot { .. } => None,
         };
         Ok(result)
@@ -294,8 +329,12 @@ impl<'a> NodeDescriptor for ObsolescenceExplanationDescriptor<'a> {
 #[derive(Debug)]
 pub struct BranchesDescriptor<'a> {
     is_enabled: bool,
+    color: Color,
+    remote_branch_color: Color,
     head_info: &'a ResolvedReferenceInfo,
     references_snapshot: &'a RepoReferencesSnapshot,
+    remote_branch_oid_to_names: HashMap<NonZeroOid, HashSet<ReferenceName>>,
+    upstream_divergence: HashMap<ReferenceName, (usize, usize)>,
     redactor: &'a Redactor,
 }
 
@@ -308,15 +347,98 @@ impl<'a> BranchesDescriptor<'a> {
         redactor: &'a Redactor,
     ) -> eyre::Result<Self> {
         let is_enabled = get_commit_descriptors_branches(repo)?;
+        let colors = get_smartlog_colors(repo)?;
+        let remote_branch_oid_to_names = get_remote_branch_oid_to_names(repo)?;
+        let upstream_divergence = get_upstream_divergence(repo, references_snapshot)?;
         Ok(BranchesDescriptor {
             is_enabled,
+            color: colors.branch,
+            remote_branch_color: colors.remote_branch,
             head_info,
             references_snapshot,
+            remote_branch_oid_to_names,
+            upstream_divergence,
             redactor,
         })
     }
 }
 
+/// Get how many commits each local branch is ahead of and behind its upstream
+/// branch, if upstream divergence should be displayed. Branches without an
+/// upstream are omitted.
+fn get_upstream_divergence(
+    repo: &Repo,
+    references_snapshot: &RepoReferencesSnapshot,
+) -> eyre::Result<HashMap<ReferenceName, (usize, usize)>> {
+    let mut result = HashMap::new();
+    if !get_commit_descriptors_upstream_divergence(repo)? {
+        return Ok(result);
+    }
+
+    for (branch_oid, branch_names) in references_snapshot.branch_oid_to_names.iter() {
+        for branch_name in branch_names {
+            let branch_name_suffix = match CategorizedReferenceName::new(branch_name) {
+                name @ CategorizedReferenceName::LocalBranch { .. } => name.render_suffix(),
+                CategorizedReferenceName::RemoteBranch { .. }
+                | CategorizedReferenceName::OtherRef { .. } => continue,
+            };
+            let upstream_oid = match repo.find_branch(&branch_name_suffix, BranchType::Local)? {
+                Some(branch) => branch.get_upstream_branch_target()?,
+                None => None,
+            };
+            if let Some(upstream_oid) = upstream_oid {
+                let divergence = repo.count_ahead_behind(*branch_oid, upstream_oid)?;
+                result.insert(branch_name.clone(), divergence);
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Render how many commits a branch is ahead of and behind its upstream, such
+/// as ` ↑2 ↓1`. Returns an empty string if the branch is up-to-date.
+fn render_upstream_divergence(glyphs: &Glyphs, divergence: Option<&(usize, usize)>) -> String {
+    let mut result = String::new();
+    if let Some((ahead, behind)) = divergence {
+        if *ahead > 0 {
+            result.push_str(&format!(" {}{ahead}", glyphs.upstream_ahead));
+        }
+        if *behind > 0 {
+            result.push_str(&format!(" {}{behind}", glyphs.upstream_behind));
+        }
+    }
+    result
+}
+
+/// Get a mapping from OID to the names of the remote-tracking branches which
+/// point to that OID, if remote-tracking branches should be displayed.
+fn get_remote_branch_oid_to_names(
+    repo: &Repo,
+) -> eyre::Result<HashMap<NonZeroOid, HashSet<ReferenceName>>> {
+    let mut result: HashMap<NonZeroOid, HashSet<ReferenceName>> = HashMap::new();
+    if !get_commit_descriptors_remote_branches(repo)? {
+        return Ok(result);
+    }
+
+    for reference in repo.get_all_references()? {
+        let reference_name = reference.get_name()?;
+        // Skip symbolic references like `refs/remotes/origin/HEAD`, which
+        // would otherwise duplicate the branch they point to.
+        if !reference_name.as_str().starts_with("refs/remotes/")
+            || reference_name.as_str().ends_with("/HEAD")
+        {
+            continue;
+        }
+        if let Some(reference_oid) = repo.resolve_reference(&reference)?.oid {
+            result
+                .entry(reference_oid)
+                .or_default()
+                .insert(reference_name);
+        }
+    }
+    Ok(result)
+}
+
 impl<'a> NodeDescriptor for BranchesDescriptor<'a> {
     #[instrument]
     fn describe_node(
@@ -328,24 +450,43 @@ impl<'a> NodeDescriptor for BranchesDescriptor<'a> {
             return Ok(None);
         }
 
-        let branch_names: HashSet<ReferenceName> = match self
+        let branch_names: Vec<(ReferenceName, Option<&(usize, usize)>)> = match self
             .references_snapshot
             .branch_oid_to_names
             .get(&object.get_oid())
         {
             Some(branch_names) => branch_names
                 .iter()
-                .map(|branch_name| self.redactor.redact_ref_name(branch_name.to_owned()))
+                .map(|branch_name| {
+                    (
+                        self.redactor.redact_ref_name(branch_name.to_owned()),
+                        self.upstream_divergence.get(branch_name),
+                    )
+                })
                 .collect(),
-            None => HashSet::new(),
+            None => Vec::new(),
         };
+        let mut remote_branch_names: Vec<String> =
+            match self.remote_branch_oid_to_names.get(&object.get_oid()) {
+                Some(remote_branch_names) => remote_branch_names
+                    .iter()
+                    .map(|branch_name| {
+                        CategorizedReferenceName::new(
+                            &self.redactor.redact_ref_name(branch_name.to_owned()),
+                        )
+                        .render_suffix()
+                    })
+                    .collect(),
+                None => Vec::new(),
+            };
+        remote_branch_names.sort_unstable();
 
-        if branch_names.is_empty() {
+        if branch_names.is_empty() && remote_branch_names.is_empty() {
             Ok(None)
         } else {
             let mut branch_names: Vec<String> = branch_names
                 .into_iter()
-                .map(|branch_name| {
+                .map(|(branch_name, divergence)| {
                     let is_checked_out_branch =
                         self.head_info.reference_name.as_ref() == Some(&branch_name);
                     let icon = if is_checked_out_branch {
@@ -356,7 +497,12 @@ impl<'a> NodeDescriptor for BranchesDescriptor<'a> {
 
                     match CategorizedReferenceName::new(&branch_name) {
                         reference_name @ CategorizedReferenceName::LocalBranch { .. } => {
-                            format!("{}{}", icon, reference_name.render_suffix())
+                            format!(
+                                "{}{}{}",
+                                icon,
+                                reference_name.render_suffix(),
+                                render_upstream_divergence(glyphs, divergence)
+                            )
                         }
                         reference_name @ CategorizedReferenceName::RemoteBranch { .. } => {
                             format!("{}remote {}", icon, reference_name.render_suffix())
@@ -368,16 +514,32 @@ impl<'a> NodeDescriptor for BranchesDescriptor<'a> {
                 })
                 .collect();
             branch_names.sort_unstable();
-            let result = StyledString::styled(
-                format!("({})", branch_names.join(", ")),
-                BaseColor::Green.light(),
-            );
+
+            let mut result = StyledStringBuilder::new().append_styled("(", self.color);
+            let names = branch_names
+                .into_iter()
+                .map(|name| (name, self.color))
+                .chain(
+                    remote_branch_names
+                        .into_iter()
+                        .map(|name| (name, self.remote_branch_color)),
+                );
+            for (i, (name, color)) in names.enumerate() {
+                if i > 0 {
+                    result = result.append_styled(", ", self.color);
+                }
+                result = result.append_styled(name, color);
+            }
+            let result = result.append_styled(")", self.color).build();
             Ok(Some(result))
         }
     }
 }
 
-/// Display the associated Phabricator revision for a given commit.
+/// Display the code review associated with a given commit, as determined by
+/// the trailers and links in its message: a Phabricator `Differential
+/// Revision:` (`D123`), a GitHub pull request or GitLab merge request URL
+/// (`#123` or `!123`), or a Gerrit `Change-Id:` (abbreviated to `I1234567`).
 #[derive(Debug)]
 pub struct DifferentialRevisionDescriptor<'a> {
     is_enabled: bool,
@@ -412,6 +574,48 @@ $",
     Some(diff_number.to_owned())
 }
 
+fn extract_pull_request_number(message: &str) -> Option<String> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(
+            r"(?x)
+https?://[^\s]+?
+    (?:
+        /pull/(?P<pull>[0-9]+)
+        | /-/merge_requests/(?P<merge>[0-9]+)
+    )
+\b",
+        )
+        .expect("Failed to compile `extract_pull_request_number` regex");
+    }
+    let captures = RE.captures(message)?;
+    match (captures.name("pull"), captures.name("merge")) {
+        (Some(pull), _) => Some(format!("#{}", pull.as_str())),
+        (None, Some(merge)) => Some(format!("!{}", merge.as_str())),
+        (None, None) => None,
+    }
+}
+
+fn extract_change_id(message: &str) -> Option<String> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(
+            r"(?mx)
+^
+Change-Id:[\ ]
+    (?P<change_id>I[0-9a-f]{7})[0-9a-f]*
+$",
+        )
+        .expect("Failed to compile `extract_change_id` regex");
+    }
+    let captures = RE.captures(message)?;
+    Some(captures["change_id"].to_owned())
+}
+
+fn extract_review_id(message: &str) -> Option<String> {
+    extract_diff_number(message)
+        .or_else(|| extract_pull_request_number(message))
+        .or_else(|| extract_change_id(message))
+}
+
 impl<'a> NodeDescriptor for DifferentialRevisionDescriptor<'a> {
     #[instrument]
     fn describe_node(
@@ -431,11 +635,11 @@ impl<'a> NodeDescriptor for DifferentialRevisionDescriptor<'a> {
             NodeObject::GarbageCollected { oid: _ } => return Ok(None),
         };
 
-        let diff_number = match extract_diff_number(&commit.get_message_raw().to_str_lossy()) {
-            Some(diff_number) => diff_number,
+        let review_id = match extract_review_id(&commit.get_message_raw().to_str_lossy()) {
+            Some(review_id) => review_id,
             None => return Ok(None),
         };
-        let result = StyledString::styled(diff_number, BaseColor::Green.dark());
+        let result = StyledString::styled(review_id, BaseColor::Green.dark());
         Ok(Some(result))
     }
 }
@@ -454,6 +658,15 @@ impl RelativeTimeDescriptor {
         Ok(RelativeTimeDescriptor { is_enabled, now })
     }
 
+    /// Constructor which shows relative times regardless of the user's
+    /// settings.
+    pub fn new_enabled(now: SystemTime) -> Self {
+        RelativeTimeDescriptor {
+            is_enabled: true,
+            now,
+        }
+    }
+
     /// Whether or not relative times should be shown, according to the user's
     /// settings.
     pub fn is_enabled(&self) -> bool {
@@ -516,6 +729,600 @@ impl NodeDescriptor for RelativeTimeDescriptor {
     }
 }
 
+/// Display the time at which the given commit was committed, in the format
+/// used by `git log --date=iso`.
+#[derive(Debug)]
+pub struct AbsoluteTimeDescriptor;
+
+impl AbsoluteTimeDescriptor {
+    /// Constructor.
+    pub fn new() -> eyre::Result<Self> {
+        Ok(AbsoluteTimeDescriptor)
+    }
+}
+
+impl NodeDescriptor for AbsoluteTimeDescriptor {
+    #[instrument]
+    fn describe_node(
+        &mut self,
+        _glyphs: &Glyphs,
+        object: &NodeObject,
+    ) -> eyre::Result<Option<StyledString>> {
+        let commit = match object {
+            NodeObject::Commit { commit } => commit,
+            NodeObject::GarbageCollected { oid: _ } => return Ok(None),
+        };
+
+        let date_time = match commit.get_time().to_date_time() {
+            Some(date_time) => date_time,
+            None => return Ok(None),
+        };
+        let description = date_time.format("%Y-%m-%d %H:%M:%S %z").to_string();
+        let result = StyledString::styled(description, BaseColor::Green.dark());
+        Ok(Some(result))
+    }
+}
+
+/// A callback which verifies a commit's signature. It's passed the commit, the
+/// signature, and the data which was signed, and returns whether the signature
+/// is valid.
+pub type SignatureVerifier<'a> = Box<dyn FnMut(&Commit, &[u8], &[u8]) -> eyre::Result<bool> + 'a>;
+
+/// Display whether each commit is signed, and whether its signature is valid,
+/// as configured by `branchless.commitDescriptors.signature`.
+pub struct SignatureDescriptor<'a> {
+    is_enabled: bool,
+    repo: &'a Repo,
+    verifier: SignatureVerifier<'a>,
+}
+
+impl<'a> SignatureDescriptor<'a> {
+    /// Constructor.
+    pub fn new(repo: &'a Repo, verifier: SignatureVerifier<'a>) -> eyre::Result<Self> {
+        let is_enabled = get_commit_descriptors_signature(repo)?;
+        Ok(SignatureDescriptor {
+            is_enabled,
+            repo,
+            verifier,
+        })
+    }
+}
+
+impl<'a> NodeDescriptor for SignatureDescriptor<'a> {
+    fn describe_node(
+        &mut self,
+        _glyphs: &Glyphs,
+        object: &NodeObject,
+    ) -> eyre::Result<Option<StyledString>> {
+        if !self.is_enabled {
+            return Ok(None);
+        }
+        let commit = match object {
+            NodeObject::Commit { commit } => commit,
+            NodeObject::GarbageCollected { oid: _ } => return Ok(None),
+        };
+
+        let result = match self.repo.extract_commit_signature(commit.get_oid())? {
+            None => StyledString::styled("(unsigned)", BaseColor::Yellow.light()),
+            Some((signature, signed_data)) => {
+                if (self.verifier)(commit, &signature, &signed_data)? {
+                    StyledString::styled("(signed)", BaseColor::Green.light())
+                } else {
+                    StyledString::styled("(bad signature)", BaseColor::Red.light())
+                }
+            }
+        };
+        Ok(Some(result))
+    }
+}
+
+/// Display the number of files with uncommitted changes next to the commit at
+/// `HEAD`, so that it's clear whether it's safe to move away from it.
+#[derive(Debug)]
+pub struct WorkingCopyStatusDescriptor {
+    head_oid: Option<NonZeroOid>,
+    num_changed_files: usize,
+}
+
+impl WorkingCopyStatusDescriptor {
+    /// Constructor. `head_oid` should be `None` if the working copy doesn't
+    /// correspond to the commit graph being rendered, such as when rendering
+    /// a historical smartlog.
+    pub fn new(
+        repo: &Repo,
+        git_run_info: &GitRunInfo,
+        head_oid: Option<NonZeroOid>,
+    ) -> eyre::Result<Self> {
+        let head_oid = if get_commit_descriptors_working_copy_status(repo)? {
+            head_oid
+        } else {
+            None
+        };
+        let num_changed_files = match head_oid {
+            None => 0,
+            Some(_) => {
+                // Compare against `HEAD` to include both staged and unstaged
+                // changes. Untracked files are not included.
+                let result = git_run_info.run_silent(
+                    repo,
+                    None,
+                    &["diff", "HEAD", "--name-only", "-z"],
+                    GitRunOpts {
+                        treat_git_failure_as_error: false,
+                        stdin: None,
+                    },
+                )?;
+                if result.exit_code.is_success() {
+                    result
+                        .stdout
+                        .split(|c| *c == 0)
+                        .filter(|path| !path.is_empty())
+                        .count()
+                } else {
+                    0
+                }
+            }
+        };
+        Ok(WorkingCopyStatusDescriptor {
+            head_oid,
+            num_changed_files,
+        })
+    }
+}
+
+impl NodeDescriptor for WorkingCopyStatusDescriptor {
+    #[instrument]
+    fn describe_node(
+        &mut self,
+        _glyphs: &Glyphs,
+        object: &NodeObject,
+    ) -> eyre::Result<Option<StyledString>> {
+        if self.num_changed_files == 0 || self.head_oid != Some(object.get_oid()) {
+            return Ok(None);
+        }
+        let result = StyledString::styled(
+            format!(
+                "(* {})",
+                Pluralize {
+                    determiner: None,
+                    amount: self.num_changed_files,
+                    unit: ("modified file", "modified files"),
+                }
+            ),
+            BaseColor::Red.light(),
+        );
+        Ok(Some(result))
+    }
+}
+
+/// Display the output of a user-provided command for each commit, such as its
+/// CI status, as configured by `branchless.commitDescriptors.statusCommand`.
+///
+/// The command is run with the `BRANCHLESS_STATUS_COMMIT` environment variable
+/// set to the commit's OID, and the first line of its output is displayed. The
+/// output is cached in the `.git/branchless/status-cache` directory for
+/// `branchless.commitDescriptors.statusCommandCacheSeconds` seconds.
+#[derive(Debug)]
+pub struct StatusCommandDescriptor {
+    command: Option<String>,
+    cache_dir: PathBuf,
+    cache_duration: Duration,
+    working_directory: PathBuf,
+}
+
+impl StatusCommandDescriptor {
+    /// Constructor.
+    pub fn new(repo: &Repo) -> eyre::Result<Self> {
+        let command = get_commit_descriptors_status_command(repo)?;
+        let cache_duration =
+            Duration::from_secs(get_commit_descriptors_status_command_cache_seconds(repo)?);
+        let cache_dir = repo.get_branchless_dir()?.join("status-cache");
+        let working_directory = repo
+            .get_working_copy_path()
+            .unwrap_or_else(|| repo.get_path().to_owned());
+        Ok(StatusCommandDescriptor {
+            command,
+            cache_dir,
+            cache_duration,
+            working_directory,
+        })
+    }
+
+    fn read_cache(&self, command: &str, oid: NonZeroOid) -> Option<String> {
+        let path = self.cache_dir.join(oid.to_string());
+        let modified = std::fs::metadata(&path).ok()?.modified().ok()?;
+        if modified.elapsed().ok()? > self.cache_duration {
+            return None;
+        }
+
+        // The cached command is stored alongside the output, so that changing
+        // the command invalidates the cache.
+        let contents = std::fs::read_to_string(&path).ok()?;
+        let (cached_command, status) = contents.split_once('\0')?;
+        if cached_command == command {
+            Some(status.to_owned())
+        } else {
+            None
+        }
+    }
+
+    fn run_command(&self, command: &str, oid: NonZeroOid) -> eyre::Result<String> {
+        let sh = get_sh().ok_or_else(|| eyre::eyre!("could not get sh"))?;
+        let output = Command::new(sh)
+            .arg("-c")
+            .arg(command)
+            .current_dir(&self.working_directory)
+            .env("BRANCHLESS_STATUS_COMMIT", oid.to_string())
+            .stdin(Stdio::null())
+            .output()
+            .wrap_err_with(|| format!("Running status command: {command}"))?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let status = stdout.lines().next().unwrap_or_default().trim().to_owned();
+
+        std::fs::create_dir_all(&self.cache_dir).wrap_err("Creating status cache dir")?;
+        std::fs::write(
+            self.cache_dir.join(oid.to_string()),
+            format!("{command}\0{status}"),
+        )
+        .wrap_err("Writing status cache")?;
+        Ok(status)
+    }
+}
+
+impl NodeDescriptor for StatusCommandDescriptor {
+    #[instrument]
+    fn describe_node(
+        &mut self,
+        _glyphs: &Glyphs,
+        object: &NodeObject,
+    ) -> eyre::Result<Option<StyledString>> {
+        let command = match &self.command {
+            Some(command) => command,
+            None => return Ok(None),
+        };
+        let oid = match object {
+            NodeObject::Commit { commit } => commit.get_oid(),
+            NodeObject::GarbageCollected { oid: _ } => return Ok(None),
+        };
+
+        let status = match self.read_cache(command, oid) {
+            Some(status) => status,
+            None => self.run_command(command, oid)?,
+        };
+        if status.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(StyledString::styled(status, BaseColor::Blue.light())))
+        }
+    }
+}
+
+/// For the root commit of each stack, display how many commits the stack is
+/// ahead of and behind the main branch.
+pub struct AheadBehindDescriptor<'a> {
+    is_enabled: bool,
+    dag: &'a Dag,
+    stack_roots: CommitSet,
+}
+
+impl<'a> AheadBehindDescriptor<'a> {
+    /// Constructor.
+    pub fn new(repo: &Repo, dag: &'a Dag) -> eyre::Result<Self> {
+        let is_enabled = get_commit_descriptors_ahead_behind(repo)?;
+        let stack_roots = if is_enabled {
+            dag.query_roots(dag.query_draft_commits()?.clone())?
+        } else {
+            CommitSet::empty()
+        };
+        Ok(AheadBehindDescriptor {
+            is_enabled,
+            dag,
+            stack_roots,
+        })
+    }
+}
+
+impl<'a> NodeDescriptor for AheadBehindDescriptor<'a> {
+    fn describe_node(
+        &mut self,
+        _glyphs: &Glyphs,
+        object: &NodeObject,
+    ) -> eyre::Result<Option<StyledString>> {
+        if !self.is_enabled {
+            return Ok(None);
+        }
+        let oid = object.get_oid();
+        if !self.dag.set_contains(&self.stack_roots, oid)? {
+            return Ok(None);
+        }
+
+        let root = CommitSet::from(oid);
+        let stack_commits = self
+            .dag
+            .query_descendants(root.clone())?
+            .intersection(self.dag.query_draft_commits()?);
+        let ahead = self.dag.set_count(&stack_commits)?;
+        let behind = self.dag.set_count(
+            &self
+                .dag
+                .query_only(self.dag.main_branch_commit.clone(), root)?,
+        )?;
+        Ok(Some(StyledString::styled(
+            format!("({ahead} ahead, {behind} behind)"),
+            BaseColor::Cyan.dark(),
+        )))
+    }
+}
+
+/// Display the number of lines inserted and deleted by each draft commit. Diff
+/// stats are cached in the database, since computing them requires diffing
+/// the commit against its parent.
+pub struct DiffStatDescriptor<'a> {
+    is_enabled: bool,
+    effects: &'a Effects,
+    repo: &'a Repo,
+    dag: &'a Dag,
+    event_log_db: &'a EventLogDb<'a>,
+}
+
+impl<'a> DiffStatDescriptor<'a> {
+    /// Constructor.
+    pub fn new(
+        effects: &'a Effects,
+        repo: &'a Repo,
+        dag: &'a Dag,
+        event_log_db: &'a EventLogDb<'a>,
+        is_enabled: bool,
+    ) -> eyre::Result<Self> {
+        let is_enabled = is_enabled || get_commit_descriptors_diff_stat(repo)?;
+        Ok(DiffStatDescriptor {
+            is_enabled,
+            effects,
+            repo,
+            dag,
+            event_log_db,
+        })
+    }
+}
+
+impl<'a> NodeDescriptor for DiffStatDescriptor<'a> {
+    fn describe_node(
+        &mut self,
+        _glyphs: &Glyphs,
+        object: &NodeObject,
+    ) -> eyre::Result<Option<StyledString>> {
+        if !self.is_enabled {
+            return Ok(None);
+        }
+        let commit = match object {
+            NodeObject::Commit { commit } => commit,
+            NodeObject::GarbageCollected { oid: _ } => return Ok(None),
+        };
+        let oid = commit.get_oid();
+        if !self
+            .dag
+            .set_contains(self.dag.query_draft_commits()?, oid)?
+        {
+            return Ok(None);
+        }
+
+        let diff_stat = match self.event_log_db.get_cached_diff_stat(oid)? {
+            Some(diff_stat) => diff_stat,
+            None => match self.repo.get_patch_for_commit(self.effects, commit)? {
+                // Merge commits don't have a single patch to measure.
+                None => return Ok(None),
+                Some(diff) => {
+                    let diff_stat = diff.get_stat()?;
+                    self.event_log_db.set_cached_diff_stat(oid, diff_stat)?;
+                    diff_stat
+                }
+            },
+        };
+        let DiffStat {
+            insertions,
+            deletions,
+        } = diff_stat;
+        Ok(Some(
+            StyledStringBuilder::new()
+                .append_styled(format!("+{insertions}"), BaseColor::Green.dark())
+                .append_plain(" ")
+                .append_styled(format!("-{deletions}"), BaseColor::Red.dark())
+                .build(),
+        ))
+    }
+}
+
+/// A piece of a commit template. See [`TemplateDescriptor`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TemplateItem {
+    /// Text to be displayed as-is.
+    Literal(String),
+
+    /// `%h`: the abbreviated commit hash.
+    ShortOid,
+
+    /// `%H`: the full commit hash.
+    Oid,
+
+    /// `%an`: the name of the commit author.
+    AuthorName,
+
+    /// `%ar`: how long ago the commit was committed.
+    RelativeTime,
+
+    /// `%d`: the branches pointing to the commit.
+    Branches,
+
+    /// `%o`: the reason that the commit is obsolete, if any.
+    Obsolescence,
+
+    /// `%s`: the first line of the commit message.
+    Subject,
+
+    /// `%t`: the ticket ID extracted from the commit message.
+    Ticket,
+}
+
+/// Parse a commit template in the style of `git log --format`. Unrecognized
+/// placeholders are kept as literal text, as Git does.
+pub fn parse_commit_template(template: &str) -> Vec<TemplateItem> {
+    let mut items = Vec::new();
+    let mut literal = String::new();
+    let mut rest = template;
+    while let Some(index) = rest.find('%') {
+        literal.push_str(&rest[..index]);
+        rest = &rest[index + 1..];
+
+        let placeholders = [
+            ("%", None),
+            ("an", Some(TemplateItem::AuthorName)),
+            ("ar", Some(TemplateItem::RelativeTime)),
+            ("h", Some(TemplateItem::ShortOid)),
+            ("H", Some(TemplateItem::Oid)),
+            ("d", Some(TemplateItem::Branches)),
+            ("o", Some(TemplateItem::Obsolescence)),
+            ("s", Some(TemplateItem::Subject)),
+            ("t", Some(TemplateItem::Ticket)),
+        ];
+        match placeholders
+            .into_iter()
+            .find(|(placeholder, _)| rest.starts_with(placeholder))
+        {
+            Some((placeholder, None)) => {
+                literal.push('%');
+                rest = &rest[placeholder.len()..];
+            }
+            Some((placeholder, Some(item))) => {
+                if !literal.is_empty() {
+                    items.push(TemplateItem::Literal(std::mem::take(&mut literal)));
+                }
+                items.push(item);
+                rest = &rest[placeholder.len()..];
+            }
+            None => literal.push('%'),
+        }
+    }
+    literal.push_str(rest);
+    if !literal.is_empty() {
+        items.push(TemplateItem::Literal(literal));
+    }
+    items
+}
+
+/// Display a commit according to a user-provided template, as configured by
+/// `branchless.smartlog.commitTemplate`. This replaces the other descriptors,
+/// so placeholders are rendered even if the corresponding descriptor has been
+/// disabled.
+pub struct TemplateDescriptor<'a> {
+    items: Vec<TemplateItem>,
+    ticket_pattern: Option<Regex>,
+    redactor: &'a Redactor,
+    oid_descriptor: CommitOidDescriptor,
+    relative_time_descriptor: RelativeTimeDescriptor,
+    branches_descriptor: BranchesDescriptor<'a>,
+    obsolescence_descriptor: ObsolescenceExplanationDescriptor<'a>,
+    message_descriptor: CommitMessageDescriptor<'a>,
+}
+
+impl<'a> TemplateDescriptor<'a> {
+    /// Constructor.
+    pub fn new(
+        repo: &Repo,
+        template: &str,
+        now: SystemTime,
+        event_replayer: &'a EventReplayer,
+        event_cursor: EventCursor,
+        head_info: &'a ResolvedReferenceInfo,
+        references_snapshot: &'a RepoReferencesSnapshot,
+        redactor: &'a Redactor,
+    ) -> eyre::Result<Self> {
+        let ticket_pattern = match get_smartlog_ticket_pattern(repo)? {
+            Some(pattern) => Some(Regex::new(&pattern).map_err(|err| {
+                eyre::eyre!("Invalid branchless.smartlog.ticketPattern {pattern:?}: {err}")
+            })?),
+            None => None,
+        };
+        Ok(TemplateDescriptor {
+            items: parse_commit_template(template),
+            ticket_pattern,
+            redactor,
+            oid_descriptor: CommitOidDescriptor::new(true)?,
+            relative_time_descriptor: RelativeTimeDescriptor {
+                is_enabled: true,
+                now,
+            },
+            branches_descriptor: BranchesDescriptor {
+                is_enabled: true,
+                color: get_smartlog_colors(repo)?.branch,
+                remote_branch_color: get_smartlog_colors(repo)?.remote_branch,
+                head_info,
+                references_snapshot,
+                remote_branch_oid_to_names: get_remote_branch_oid_to_names(repo)?,
+                upstream_divergence: get_upstream_divergence(repo, references_snapshot)?,
+                redactor,
+            },
+            obsolescence_descriptor: ObsolescenceExplanationDescriptor::new(
+                event_replayer,
+                event_cursor,
+            )?,
+            message_descriptor: CommitMessageDescriptor::new(redactor)?,
+        })
+    }
+
+    fn describe_ticket(&self, object: &NodeObject) -> Option<StyledString> {
+        let ticket_pattern = self.ticket_pattern.as_ref()?;
+        let commit = match object {
+            NodeObject::Commit { commit } => commit,
+            NodeObject::GarbageCollected { oid: _ } => return None,
+        };
+        let message = commit.get_message_raw().to_str_lossy().into_owned();
+        let ticket = ticket_pattern.find(&message)?.as_str().to_owned();
+        let ticket = self.redactor.redact_commit_summary(ticket);
+        Some(StyledString::styled(ticket, BaseColor::Magenta.light()))
+    }
+}
+
+impl<'a> NodeDescriptor for TemplateDescriptor<'a> {
+    fn describe_node(
+        &mut self,
+        glyphs: &Glyphs,
+        object: &NodeObject,
+    ) -> eyre::Result<Option<StyledString>> {
+        let mut result = StyledStringBuilder::new();
+        for item in self.items.clone() {
+            let description = match item {
+                TemplateItem::Literal(text) => Some(StyledString::plain(text)),
+                TemplateItem::ShortOid => self.oid_descriptor.describe_node(glyphs, object)?,
+                TemplateItem::Oid => Some(StyledString::styled(
+                    object.get_oid().to_string(),
+                    BaseColor::Yellow.dark(),
+                )),
+                TemplateItem::AuthorName => match object {
+                    NodeObject::Commit { commit } => commit
+                        .get_author()
+                        .get_name()
+                        .map(|name| self.redactor.redact_commit_summary(name.to_owned()))
+                        .map(StyledString::plain),
+                    NodeObject::GarbageCollected { oid: _ } => None,
+                },
+                TemplateItem::RelativeTime => self
+                    .relative_time_descriptor
+                    .describe_node(glyphs, object)?,
+                TemplateItem::Branches => self.branches_descriptor.describe_node(glyphs, object)?,
+                TemplateItem::Obsolescence => {
+                    self.obsolescence_descriptor.describe_node(glyphs, object)?
+                }
+                TemplateItem::Subject => self.message_descriptor.describe_node(glyphs, object)?,
+                TemplateItem::Ticket => self.describe_ticket(object),
+            };
+            if let Some(description) = description {
+                result = result.append(description);
+            }
+        }
+        Ok(Some(result.build()))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::ops::{Add, Sub};
@@ -543,6 +1350,68 @@ Differential Revision: phabricator.com/D123";
         Ok(())
     }
 
+    #[test]
+    fn test_extract_review_id() -> eyre::Result<()> {
+        let message = "\
+This is a message
+
+Differential Revision: https://phabricator.example.com/D123";
+        assert_eq!(extract_review_id(message), Some(String::from("D123")));
+
+        let message = "\
+This is a message
+
+Pull request: https://github.com/arxanas/git-branchless/pull/456";
+        assert_eq!(extract_review_id(message), Some(String::from("#456")));
+
+        let message = "\
+This is a message
+
+See https://gitlab.com/group/project/-/merge_requests/789 for details.";
+        assert_eq!(extract_review_id(message), Some(String::from("!789")));
+
+        let message = "\
+This is a message
+
+Change-Id: I0123456789abcdef0123456789abcdef01234567";
+        assert_eq!(extract_review_id(message), Some(String::from("I0123456")));
+
+        let message = "\
+This is a message
+
+See https://github.com/arxanas/git-branchless/issues/123";
+        assert_eq!(extract_review_id(message), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_commit_template() {
+        assert_eq!(
+            parse_commit_template("%h %d [%t] %s"),
+            vec![
+                TemplateItem::ShortOid,
+                TemplateItem::Literal(" ".to_string()),
+                TemplateItem::Branches,
+                TemplateItem::Literal(" [".to_string()),
+                TemplateItem::Ticket,
+                TemplateItem::Literal("] ".to_string()),
+                TemplateItem::Subject,
+            ]
+        );
+        assert_eq!(
+            parse_commit_template("100%% %an (%ar)%x%"),
+            vec![
+                TemplateItem::Literal("100% ".to_string()),
+                TemplateItem::AuthorName,
+                TemplateItem::Literal(" (".to_string()),
+                TemplateItem::RelativeTime,
+                TemplateItem::Literal(")%x%".to_string()),
+            ]
+        );
+        assert!(parse_commit_template("").is_empty());
+    }
+
     #[test]
     fn test_describe_time_delta() -> eyre::Result<()> {
         let test_cases: Vec<(isize, &str)> = vec![
@@ -10,7 +10,7 @@ use crate::git::{
     Branch, BranchType, CategorizedReferenceName, ConfigRead, NonZeroOid, ReferenceName, Repo,
 };
 
-use super::config::get_main_branch_name;
+use super::config::{get_additional_main_branch_names, get_main_branch_name};
 
 /// A snapshot of all the positions of references we care about in the repository.
 #[derive(Debug)]
@@ -21,8 +21,17 @@ pub struct RepoReferencesSnapshot {
     /// The location of the main branch.
     pub main_branch_oid: NonZeroOid,
 
+    /// The locations of any additional main branches, such as long-lived
+    /// release branches. Commits reachable from these are also considered
+    /// public.
+    pub additional_main_branch_oids: HashSet<NonZeroOid>,
+
     /// A mapping from commit OID to the branches which point to that commit.
     pub branch_oid_to_names: HashMap<NonZeroOid, HashSet<ReferenceName>>,
+
+    /// The commits pointed to by tags. Annotated tags are peeled to the commit
+    /// they refer to.
+    pub tag_oids: HashSet<NonZeroOid>,
 }
 
 /// Helper functions on [`Repo`].
@@ -33,12 +42,22 @@ pub trait RepoExt {
     /// Get the OID corresponding to the main branch.
     fn get_main_branch_oid(&self) -> eyre::Result<NonZeroOid>;
 
+    /// Get the `Branch`es for any additional main branches configured for the
+    /// repository. Configured branches which don't exist are skipped.
+    fn get_additional_main_branches(&self) -> eyre::Result<Vec<Branch>>;
+
+    /// Get the OIDs corresponding to the additional main branches.
+    fn get_additional_main_branch_oids(&self) -> eyre::Result<HashSet<NonZeroOid>>;
+
     /// Get a mapping from OID to the names of branches which point to that OID.
     ///
     /// The returned branch names include the `refs/heads/` prefix, so it must
     /// be stripped if desired.
     fn get_branch_oid_to_names(&self) -> eyre::Result<HashMap<NonZeroOid, HashSet<ReferenceName>>>;
 
+    /// Get the OIDs of the commits which are pointed to by tags.
+    fn get_tag_oids(&self) -> eyre::Result<HashSet<NonZeroOid>>;
+
     /// Get the positions of references in the repository.
     fn get_references_snapshot(&self) -> eyre::Result<RepoReferencesSnapshot>;
 
@@ -95,6 +114,31 @@ https://github.com/arxanas/git-branchless/discussions/595 for more details.",
         }
     }
 
+    fn get_additional_main_branches(&self) -> eyre::Result<Vec<Branch>> {
+        let main_branch_name = get_main_branch_name(self)?;
+        let mut result = Vec::new();
+        for branch_name in get_additional_main_branch_names(self)? {
+            if branch_name == main_branch_name {
+                continue;
+            }
+            if let Some(branch) = self.find_branch(&branch_name, BranchType::Local)? {
+                result.push(branch);
+            }
+        }
+        Ok(result)
+    }
+
+    #[instrument]
+    fn get_additional_main_branch_oids(&self) -> eyre::Result<HashSet<NonZeroOid>> {
+        let mut result = HashSet::new();
+        for branch in self.get_additional_main_branches()? {
+            if let Some(branch_oid) = branch.get_oid()? {
+                result.insert(branch_oid);
+            }
+        }
+        Ok(result)
+    }
+
     #[instrument]
     fn get_branch_oid_to_names(&self) -> eyre::Result<HashMap<NonZeroOid, HashSet<ReferenceName>>> {
         let mut result: HashMap<NonZeroOid, HashSet<ReferenceName>> = HashMap::new();
@@ -113,15 +157,33 @@ https://github.com/arxanas/git-branchless/discussions/595 for more details.",
         Ok(result)
     }
 
+    #[instrument]
+    fn get_tag_oids(&self) -> eyre::Result<HashSet<NonZeroOid>> {
+        let mut result = HashSet::new();
+        for reference in self.get_all_references()? {
+            if !reference.get_name()?.as_str().starts_with("refs/tags/") {
+                continue;
+            }
+            if let Some(reference_oid) = self.resolve_reference(&reference)?.oid {
+                result.insert(reference_oid);
+            }
+        }
+        Ok(result)
+    }
+
     fn get_references_snapshot(&self) -> eyre::Result<RepoReferencesSnapshot> {
         let head_oid = self.get_head_info()?.oid;
         let main_branch_oid = self.get_main_branch_oid()?;
+        let additional_main_branch_oids = self.get_additional_main_branch_oids()?;
         let branch_oid_to_names = self.get_branch_oid_to_names()?;
+        let tag_oids = self.get_tag_oids()?;
 
         Ok(RepoReferencesSnapshot {
             head_oid,
             main_branch_oid,
+            additional_main_branch_oids,
             branch_oid_to_names,
+            tag_oids,
         })
     }
 
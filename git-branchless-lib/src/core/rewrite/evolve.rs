@@ -54,9 +54,14 @@ pub fn find_rewrite_target(
             new_commit_oid: _,
         }
         | Event::RefUpdateEvent { .. }
+        | Event::RefRenameEvent { .. }
+        | Event::RemoteRefUpdateEvent { .. }
+        | Event::CommitObservedEvent { .. }
         | Event::CommitEvent { .. }
         | Event::ObsoleteEvent { .. }
         | Event::UnobsoleteEvent { .. }
+        | Event::PinEvent { .. }
+        | Event::UnpinEvent { .. }
         | Event::WorkingCopySnapshot { .. } => None,
     }
 }
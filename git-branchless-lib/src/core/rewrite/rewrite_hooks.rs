@@ -16,7 +16,9 @@ use tempfile::NamedTempFile;
 use tracing::instrument;
 
 use crate::core::check_out::CheckOutCommitOptions;
-use crate::core::config::{get_hint_enabled, print_hint_suppression_notice, Hint};
+use crate::core::config::{
+    get_hint_enabled, get_undo_create_snapshots, print_hint_suppression_notice, Hint,
+};
 use crate::core::dag::Dag;
 use crate::core::effects::Effects;
 use crate::core::eventlog::{Event, EventLogDb, EventReplayer};
@@ -190,6 +192,22 @@ pub fn hook_post_rewrite(
     )?;
     event_log_db.add_events(rewrite_events)?;
 
+    // Record which changes were staged at the time of the amend, so that
+    // undoing it can restore the index, and not just `HEAD`.
+    if rewrite_type == "amend" && get_undo_create_snapshots(&repo)? {
+        let head_info = repo.get_head_info()?;
+        let index = repo.get_index()?;
+        let (snapshot, _status) =
+            repo.get_status(effects, git_run_info, &index, &head_info, Some(event_tx_id))?;
+        event_log_db.add_events(vec![Event::WorkingCopySnapshot {
+            timestamp,
+            event_tx_id,
+            head_oid: MaybeZeroOid::from(head_info.oid),
+            commit_oid: snapshot.base_commit.get_oid(),
+            ref_name: head_info.reference_name,
+        }])?;
+    }
+
     if repo
         .get_rebase_state_dir_path()
         .join(EXTRA_POST_REWRITE_FILE_NAME)
@@ -0,0 +1,370 @@
+//! Reader for Git's `commit-graph` file, which stores the parents of commits
+//! so that they can be looked up without inflating the commit objects
+//! themselves.
+//!
+//! See <https://git-scm.com/docs/gitformat-commit-graph> for the format.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use eyre::Context;
+use memmap::Mmap;
+use tracing::{instrument, warn};
+
+use super::{ConfigRead, NonZeroOid, Repo};
+
+const SIGNATURE: &[u8] = b"CGPH";
+const HEADER_LEN: usize = 8;
+const CHUNK_TABLE_ENTRY_LEN: usize = 12;
+const FANOUT_LEN: usize = 256 * 4;
+
+const CHUNK_OID_FANOUT: &[u8] = b"OIDF";
+const CHUNK_OID_LOOKUP: &[u8] = b"OIDL";
+const CHUNK_COMMIT_DATA: &[u8] = b"CDAT";
+const CHUNK_EXTRA_EDGES: &[u8] = b"EDGE";
+
+const PARENT_NONE: u32 = 0x7000_0000;
+const PARENT_EXTRA_EDGES: u32 = 0x8000_0000;
+const PARENT_LAST_EDGE: u32 = 0x8000_0000;
+
+/// A single `commit-graph` file, which may be one layer of a split
+/// commit-graph chain.
+struct CommitGraphLayer {
+    data: Mmap,
+    hash_len: usize,
+    num_commits: usize,
+
+    /// The number of commits in the layers below this one. Parents are
+    /// referred to by their position in the entire chain, so the commits in
+    /// this layer start at this position.
+    num_base_commits: usize,
+
+    fanout_offset: usize,
+    oid_lookup_offset: usize,
+    commit_data_offset: usize,
+    extra_edges_offset: Option<usize>,
+}
+
+impl CommitGraphLayer {
+    fn parse(data: Mmap, num_base_graphs: usize, num_base_commits: usize) -> Option<Self> {
+        if data.len() < HEADER_LEN || &data[0..4] != SIGNATURE {
+            return None;
+        }
+        let version = data[4];
+        let hash_version = data[5];
+        let num_chunks = usize::from(data[6]);
+        if version != 1 || usize::from(data[7]) != num_base_graphs {
+            return None;
+        }
+        let hash_len = match hash_version {
+            1 => 20,
+            _ => return None,
+        };
+
+        let mut fanout_offset = None;
+        let mut oid_lookup_offset = None;
+        let mut commit_data_offset = None;
+        let mut extra_edges_offset = None;
+        for i in 0..num_chunks {
+            let entry_offset = HEADER_LEN + i * CHUNK_TABLE_ENTRY_LEN;
+            let chunk_id = data.get(entry_offset..entry_offset + 4)?;
+            let chunk_offset = usize::try_from(read_u64(&data, entry_offset + 4)?).ok()?;
+            if chunk_offset > data.len() {
+                return None;
+            }
+            match chunk_id {
+                CHUNK_OID_FANOUT => fanout_offset = Some(chunk_offset),
+                CHUNK_OID_LOOKUP => oid_lookup_offset = Some(chunk_offset),
+                CHUNK_COMMIT_DATA => commit_data_offset = Some(chunk_offset),
+                CHUNK_EXTRA_EDGES => extra_edges_offset = Some(chunk_offset),
+                _ => {}
+            }
+        }
+
+        let fanout_offset = fanout_offset?;
+        let oid_lookup_offset = oid_lookup_offset?;
+        let commit_data_offset = commit_data_offset?;
+        if fanout_offset + FANOUT_LEN > data.len() {
+            return None;
+        }
+        let num_commits = usize::try_from(read_u32(&data, fanout_offset + FANOUT_LEN - 4)?).ok()?;
+        if oid_lookup_offset + num_commits * hash_len > data.len()
+            || commit_data_offset + num_commits * (hash_len + 16) > data.len()
+        {
+            return None;
+        }
+
+        Some(Self {
+            data,
+            hash_len,
+            num_commits,
+            num_base_commits,
+            fanout_offset,
+            oid_lookup_offset,
+            commit_data_offset,
+            extra_edges_offset,
+        })
+    }
+
+    fn get_oid_at(&self, index: usize) -> Option<NonZeroOid> {
+        let offset = self.oid_lookup_offset + index * self.hash_len;
+        NonZeroOid::try_from(self.data.get(offset..offset + self.hash_len)?).ok()
+    }
+
+    fn find_index(&self, oid: NonZeroOid) -> Option<usize> {
+        let oid_bytes = oid.as_bytes();
+        let first_byte = usize::from(*oid_bytes.first()?);
+        let start = match first_byte {
+            0 => 0,
+            _ => read_u32(&self.data, self.fanout_offset + (first_byte - 1) * 4)?,
+        };
+        let end = read_u32(&self.data, self.fanout_offset + first_byte * 4)?;
+        let (mut lo, mut hi) = (usize::try_from(start).ok()?, usize::try_from(end).ok()?);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let offset = self.oid_lookup_offset + mid * self.hash_len;
+            let mid_bytes = self.data.get(offset..offset + self.hash_len)?;
+            match mid_bytes.cmp(oid_bytes) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+
+    /// Get the positions of the parents of the commit at the given index in
+    /// this layer.
+    fn get_parent_positions(&self, index: usize) -> Option<Vec<u32>> {
+        let offset = self.commit_data_offset + index * (self.hash_len + 16) + self.hash_len;
+        let parent1 = read_u32(&self.data, offset)?;
+        let parent2 = read_u32(&self.data, offset + 4)?;
+
+        let mut result = Vec::new();
+        if parent1 == PARENT_NONE {
+            return Some(result);
+        }
+        result.push(parent1);
+
+        if parent2 == PARENT_NONE {
+            // Only one parent.
+        } else if parent2 & PARENT_EXTRA_EDGES == 0 {
+            result.push(parent2);
+        } else {
+            let extra_edges_offset = self.extra_edges_offset?;
+            let mut edge_index = usize::try_from(parent2 & !PARENT_EXTRA_EDGES).ok()?;
+            loop {
+                let edge = read_u32(&self.data, extra_edges_offset + edge_index * 4)?;
+                result.push(edge & !PARENT_LAST_EDGE);
+                if edge & PARENT_LAST_EDGE != 0 {
+                    break;
+                }
+                edge_index += 1;
+            }
+        }
+        Some(result)
+    }
+}
+
+/// Git's `commit-graph`, read either from a single `commit-graph` file or
+/// from a split commit-graph chain.
+///
+/// Only commit-graphs using SHA-1 hashes are supported. Commits which aren't
+/// in the commit-graph (such as those created after it was last written)
+/// must be looked up in the object database instead.
+pub struct CommitGraph {
+    path: PathBuf,
+
+    /// The layers of the commit-graph, starting with the base layer. A
+    /// single `commit-graph` file has only one layer.
+    layers: Vec<CommitGraphLayer>,
+}
+
+impl std::fmt::Debug for CommitGraph {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "<CommitGraph path={:?} num_layers={} num_commits={}>",
+            self.path,
+            self.layers.len(),
+            self.len()
+        )
+    }
+}
+
+impl CommitGraph {
+    /// Open the commit-graph for the given repository, if it has one and
+    /// `core.commitGraph` hasn't been disabled. Like Git, this uses the
+    /// `commit-graph` file if there is one, and the split commit-graph chain
+    /// otherwise.
+    ///
+    /// Returns `None` for shallow repositories, since Git ignores the
+    /// commit-graph there: it records the parents of the shallow commits even
+    /// though they aren't present.
+    #[instrument]
+    pub fn open_for_repo(repo: &Repo) -> eyre::Result<Option<Self>> {
+        if repo.is_shallow() {
+            return Ok(None);
+        }
+        if !repo
+            .get_readonly_config()?
+            .get_or("core.commitGraph", true)?
+        {
+            return Ok(None);
+        }
+
+        let maybe_worktree_parent_repo = repo.open_worktree_parent_repo()?;
+        let repo = match maybe_worktree_parent_repo.as_ref() {
+            Some(repo) => repo,
+            None => repo,
+        };
+        let info_dir = repo.get_path().join("objects").join("info");
+        match Self::open(&info_dir.join("commit-graph"))? {
+            Some(commit_graph) => Ok(Some(commit_graph)),
+            None => Self::open_chain(&info_dir.join("commit-graphs")),
+        }
+    }
+
+    /// Open and parse the `commit-graph` file at the given path. Returns
+    /// `None` if the file doesn't exist or is in a format that we don't
+    /// support.
+    #[instrument]
+    pub fn open(path: &Path) -> eyre::Result<Option<Self>> {
+        let data = match map_file(path)? {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        match CommitGraphLayer::parse(data, 0, 0) {
+            Some(layer) => Ok(Some(Self {
+                path: path.to_owned(),
+                layers: vec![layer],
+            })),
+            None => {
+                warn!(
+                    ?path,
+                    "Unsupported or malformed commit-graph file, ignoring"
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    /// Open and parse the split commit-graph chain in the given
+    /// `commit-graphs` directory. Returns `None` if there is no chain, or if
+    /// any of its files are missing or in a format that we don't support.
+    #[instrument]
+    pub fn open_chain(dir: &Path) -> eyre::Result<Option<Self>> {
+        let chain_path = dir.join("commit-graph-chain");
+        let chain = match read_file(&chain_path)? {
+            Some(chain) => chain,
+            None => return Ok(None),
+        };
+        let chain = String::from_utf8_lossy(&chain);
+
+        let mut layers: Vec<CommitGraphLayer> = Vec::new();
+        for hash in chain.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let layer_path = dir.join(format!("graph-{hash}.graph"));
+            let num_base_commits = layers.iter().map(|layer| layer.num_commits).sum();
+            let layer = map_file(&layer_path)?
+                .and_then(|data| CommitGraphLayer::parse(data, layers.len(), num_base_commits));
+            match layer {
+                Some(layer) => layers.push(layer),
+                None => {
+                    warn!(
+                        ?chain_path,
+                        ?layer_path,
+                        "Missing, unsupported, or malformed commit-graph chain layer, ignoring chain"
+                    );
+                    return Ok(None);
+                }
+            }
+        }
+
+        if layers.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            path: chain_path,
+            layers,
+        }))
+    }
+
+    /// The number of commits stored in the commit-graph.
+    pub fn len(&self) -> usize {
+        self.layers.iter().map(|layer| layer.num_commits).sum()
+    }
+
+    /// Whether the commit-graph stores no commits.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the OID of the commit at the given position in the whole chain.
+    fn get_oid_at_position(&self, position: u32) -> Option<NonZeroOid> {
+        let position = usize::try_from(position).ok()?;
+        let layer = self.layers.iter().find(|layer| {
+            layer.num_base_commits <= position
+                && position < layer.num_base_commits + layer.num_commits
+        })?;
+        layer.get_oid_at(position - layer.num_base_commits)
+    }
+
+    /// Get the parents of the given commit, in order. Returns `None` if the
+    /// commit isn't stored in the commit-graph.
+    pub fn get_parent_oids(&self, oid: NonZeroOid) -> Option<Vec<NonZeroOid>> {
+        let (layer, index) = self
+            .layers
+            .iter()
+            .find_map(|layer| Some((layer, layer.find_index(oid)?)))?;
+        layer
+            .get_parent_positions(index)?
+            .into_iter()
+            .map(|position| self.get_oid_at_position(position))
+            .collect()
+    }
+}
+
+fn read_file(path: &Path) -> eyre::Result<Option<Vec<u8>>> {
+    match std::fs::read(path) {
+        Ok(data) => Ok(Some(data)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).wrap_err_with(|| format!("Reading commit-graph at {path:?}")),
+    }
+}
+
+/// Memory-map the file at the given path, since commit-graphs can be large and
+/// only a few entries are typically looked up. Returns `None` if the file
+/// doesn't exist or is empty.
+fn map_file(path: &Path) -> eyre::Result<Option<Mmap>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(err).wrap_err_with(|| format!("Opening commit-graph at {path:?}"));
+        }
+    };
+    let len = file
+        .metadata()
+        .wrap_err_with(|| format!("Reading metadata for commit-graph at {path:?}"))?
+        .len();
+    if len == 0 {
+        return Ok(None);
+    }
+
+    // SAFETY: Git never modifies a commit-graph file in place. New
+    // commit-graphs are written to a temporary file and renamed into place,
+    // so the mapped contents don't change while we hold the mapping.
+    let data = unsafe { Mmap::map(&file) }
+        .wrap_err_with(|| format!("Mapping commit-graph at {path:?}"))?;
+    Ok(Some(data))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes(bytes.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset + 8)?;
+    Some(u64::from_be_bytes(bytes.try_into().ok()?))
+}
@@ -15,6 +15,27 @@ pub struct Diff<'repo> {
     pub(super) inner: git2::Diff<'repo>,
 }
 
+impl Diff<'_> {
+    /// Count the lines inserted and deleted by this diff.
+    pub fn get_stat(&self) -> eyre::Result<DiffStat> {
+        let stats = self.inner.stats().wrap_err("Computing diff stats")?;
+        Ok(DiffStat {
+            insertions: stats.insertions(),
+            deletions: stats.deletions(),
+        })
+    }
+}
+
+/// The number of lines inserted and deleted by a diff.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DiffStat {
+    /// The number of inserted lines.
+    pub insertions: usize,
+
+    /// The number of deleted lines.
+    pub deletions: usize,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 struct GitHunk {
     old_start: usize,
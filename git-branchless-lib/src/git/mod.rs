@@ -1,5 +1,6 @@
 //! Tools for interfacing with the Git repository.
 
+mod commit_graph;
 mod config;
 mod diff;
 mod index;
@@ -13,18 +14,20 @@ mod status;
 mod test;
 mod tree;
 
+pub use commit_graph::CommitGraph;
 pub use config::{Config, ConfigRead, ConfigValue, ConfigWrite};
-pub use diff::{process_diff_for_record, Diff};
+pub use diff::{process_diff_for_record, Diff, DiffStat};
 pub use index::{update_index, Index, IndexEntry, Stage, UpdateIndexCommand};
 pub use object::Commit;
 pub use oid::{MaybeZeroOid, NonZeroOid};
 pub use reference::{
     Branch, BranchType, CategorizedReferenceName, Reference, ReferenceName, ReferenceTarget,
+    ReferenceTransaction,
 };
 pub use repo::{
     message_prettify, AmendFastOptions, CherryPickFastOptions, CreateCommitFastError,
-    Error as RepoError, GitErrorCode, GitVersion, PatchId, Repo, ResolvedReferenceInfo,
-    Result as RepoResult, Time,
+    Error as RepoError, GitErrorCode, GitVersion, PatchId, ReflogEntry, Repo,
+    ResolvedReferenceInfo, Result as RepoResult, Signature, Time,
 };
 pub use run::{GitRunInfo, GitRunOpts, GitRunResult};
 pub use snapshot::{WorkingCopyChangesType, WorkingCopySnapshot};
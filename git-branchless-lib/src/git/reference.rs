@@ -130,6 +130,64 @@ impl<'repo> Reference<'repo> {
     }
 }
 
+/// A set of reference updates which are applied all at once. References must
+/// be locked with `lock` before they can be updated, and stay locked until the
+/// transaction is committed or dropped. Dropping the transaction without
+/// committing it discards the updates.
+pub struct ReferenceTransaction<'repo> {
+    pub(super) inner: git2::Transaction<'repo>,
+}
+
+impl std::fmt::Debug for ReferenceTransaction<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<ReferenceTransaction>")
+    }
+}
+
+impl<'repo> ReferenceTransaction<'repo> {
+    /// Lock the given reference, so that it can't be updated by anyone else
+    /// until this transaction is finished. Fails if the reference is already
+    /// locked.
+    #[instrument]
+    pub fn lock(&mut self, name: &ReferenceName) -> Result<()> {
+        self.inner
+            .lock_ref(name.as_str())
+            .map_err(|err| Error::LockReference {
+                source: err,
+                name: name.clone(),
+            })
+    }
+
+    /// Create the given locked reference or update it to point to `oid`.
+    #[instrument]
+    pub fn set_target(
+        &mut self,
+        name: &ReferenceName,
+        oid: NonZeroOid,
+        log_message: &str,
+    ) -> Result<()> {
+        self.inner
+            .set_target(name.as_str(), oid.inner, None, log_message)
+            .map_err(Error::CreateReference)
+    }
+
+    /// Delete the given locked reference.
+    #[instrument]
+    pub fn remove(&mut self, name: &ReferenceName) -> Result<()> {
+        self.inner
+            .remove(name.as_str())
+            .map_err(Error::DeleteReference)
+    }
+
+    /// Apply all of the updates in this transaction and release the locks.
+    #[instrument]
+    pub fn commit(self) -> Result<()> {
+        self.inner
+            .commit()
+            .map_err(Error::CommitReferenceTransaction)
+    }
+}
+
 /// Determine what kind of branch a reference is, given its name. The returned
 /// `suffix` value is converted to a `String` to be rendered to the screen, so
 /// it may have lost some information if the reference name had unusual
@@ -19,7 +19,7 @@ use std::time::{Duration, SystemTime};
 use std::{io, time};
 
 use bstr::ByteVec;
-use chrono::NaiveDateTime;
+use chrono::{DateTime, FixedOffset, NaiveDateTime, TimeZone};
 use cursive::theme::BaseColor;
 use cursive::utils::markup::StyledString;
 use git2::DiffOptions;
@@ -36,13 +36,17 @@ use crate::git::oid::{make_non_zero_oid, MaybeZeroOid, NonZeroOid};
 use crate::git::reference::ReferenceNameError;
 use crate::git::run::GitRunInfo;
 use crate::git::tree::{dehydrate_tree, get_changed_paths_between_trees, hydrate_tree, Tree};
-use crate::git::{Branch, BranchType, Commit, Reference, ReferenceName};
+use crate::git::{Branch, BranchType, Commit, Reference, ReferenceName, ReferenceTransaction};
 
 use super::index::{Index, IndexEntry};
 use super::snapshot::WorkingCopySnapshot;
 use super::status::FileMode;
 use super::{tree, Diff, StatusEntry};
 
+/// How long a database connection waits for another process to release its
+/// lock on the database before failing. See `Repo::get_db_conn`.
+const DB_BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
 #[allow(missing_docs)]
 #[derive(Debug, Error)]
 pub enum Error {
@@ -67,6 +71,21 @@ pub enum Error {
         path: PathBuf,
     },
 
+    #[error("could not read shared database configuration: {0}")]
+    ReadSharedDatabaseConfig(#[source] eyre::Error),
+
+    #[error("could not configure database connection at {path}: {source}")]
+    ConfigureDatabase {
+        source: rusqlite::Error,
+        path: PathBuf,
+    },
+
+    #[error("could not set repository ID for shared database at {path}: {source}")]
+    SetDatabaseRepoId {
+        source: rusqlite::Error,
+        path: PathBuf,
+    },
+
     #[error("this repository does not have an associated working copy")]
     NoWorkingCopyPath,
 
@@ -82,6 +101,9 @@ pub enum Error {
     #[error("could not find object {oid}")]
     FindObject { oid: NonZeroOid },
 
+    #[error("could not read object database: {0}")]
+    ReadObjectDatabase(#[source] git2::Error),
+
     #[error("could not calculate merge-base between {lhs} and {rhs}: {source}")]
     FindMergeBase {
         source: git2::Error,
@@ -89,6 +111,20 @@ pub enum Error {
         rhs: NonZeroOid,
     },
 
+    #[error("could not determine whether {ancestor} is an ancestor of {descendant}: {source}")]
+    IsAncestor {
+        source: git2::Error,
+        ancestor: NonZeroOid,
+        descendant: NonZeroOid,
+    },
+
+    #[error("could not count commits between {local} and {upstream}: {source}")]
+    CountAheadBehind {
+        source: git2::Error,
+        local: NonZeroOid,
+        upstream: NonZeroOid,
+    },
+
     #[error("could not find blob {oid}: {source} ")]
     FindBlob {
         source: git2::Error,
@@ -107,6 +143,12 @@ pub enum Error {
         oid: NonZeroOid,
     },
 
+    #[error("could not extract signature for commit {oid}: {source}")]
+    ExtractSignature {
+        source: git2::Error,
+        oid: NonZeroOid,
+    },
+
     #[error("could not create commit: {0}")]
     CreateCommit(#[source] git2::Error),
 
@@ -178,6 +220,21 @@ pub enum Error {
     #[error("could not delete reference: {0}")]
     DeleteReference(#[source] git2::Error),
 
+    #[error("could not write reflog: {0}")]
+    WriteReflog(#[source] git2::Error),
+
+    #[error("could not lock reference '{}': {source}", name.as_str())]
+    LockReference {
+        source: git2::Error,
+        name: ReferenceName,
+    },
+
+    #[error("could not start reference transaction: {0}")]
+    StartReferenceTransaction(#[source] git2::Error),
+
+    #[error("could not commit reference transaction: {0}")]
+    CommitReferenceTransaction(#[source] git2::Error),
+
     #[error("could not resolve reference: {0}")]
     ResolveReference(#[source] git2::Error),
 
@@ -221,6 +278,9 @@ pub enum Error {
     #[error("could not get remote names: {0}")]
     GetRemoteNames(#[source] git2::Error),
 
+    #[error("could not get worktrees: {0}")]
+    GetWorktrees(#[source] git2::Error),
+
     #[error("HEAD is unborn (try making a commit?)")]
     UnbornHead,
 
@@ -352,6 +412,22 @@ impl ResolvedReferenceInfo {
     }
 }
 
+/// An entry in the reflog of a reference, recording one update to it.
+#[derive(Clone, Debug)]
+pub struct ReflogEntry {
+    /// The OID which the reference pointed to before the update.
+    pub old_oid: MaybeZeroOid,
+
+    /// The OID which the reference pointed to after the update.
+    pub new_oid: MaybeZeroOid,
+
+    /// The time at which the update was made.
+    pub time: Time,
+
+    /// The message describing the update, such as `commit: add foo`.
+    pub message: Option<String>,
+}
+
 /// The parsed version of Git.
 #[derive(Debug, PartialEq, PartialOrd, Eq)]
 pub struct GitVersion(pub isize, pub isize, pub isize);
@@ -515,6 +591,50 @@ impl Repo {
         self.inner.path().join("packed-refs")
     }
 
+    /// Get the path to the directory shared by the repository and all of its
+    /// worktrees. For a worktree, this is read from the `commondir` file in
+    /// its `.git` directory; otherwise, it's the `.git` directory itself.
+    fn get_common_dir_path(&self) -> PathBuf {
+        let path = self.inner.path();
+        let commondir_file = path.join("commondir");
+        match std::fs::read_to_string(&commondir_file) {
+            Ok(commondir) => {
+                let commondir = commondir.trim_end_matches(['\n', '\r']);
+                path.join(commondir)
+            }
+            Err(err) => {
+                if err.kind() != std::io::ErrorKind::NotFound {
+                    warn!(
+                        ?commondir_file,
+                        ?err,
+                        "commondir file could not be read; using repository path"
+                    );
+                }
+                path.to_path_buf()
+            }
+        }
+    }
+
+    /// Get the path to the `shallow` file for the repository, which lists the
+    /// commits at the boundary of a shallow clone. The file only exists if
+    /// the repository is shallow.
+    pub fn get_shallow_path(&self) -> PathBuf {
+        self.get_common_dir_path().join("shallow")
+    }
+
+    /// Get the path to the `info/grafts` file for the repository, which
+    /// overrides the parents of the listed commits. The file is deprecated in
+    /// favor of `git replace`, but is still respected by Git.
+    pub fn get_grafts_path(&self) -> PathBuf {
+        self.get_common_dir_path().join("info").join("grafts")
+    }
+
+    /// Determine whether the repository is a shallow clone, i.e. whether some
+    /// of its history is missing.
+    pub fn is_shallow(&self) -> bool {
+        self.inner.is_shallow()
+    }
+
     /// Get the path to the directory inside the `.git` directory which contains
     /// state used for the current rebase (if any).
     pub fn get_rebase_state_dir_path(&self) -> PathBuf {
@@ -595,6 +715,157 @@ impl Repo {
         Ok(Some(parent_repo))
     }
 
+    /// Get the OIDs of the commits which are checked out (`HEAD`) or being
+    /// merged (`MERGE_HEAD`) in the main worktree or any linked worktree of
+    /// this repository.
+    ///
+    /// Worktrees which can't be opened (such as if their directory was deleted
+    /// without running `git worktree prune`) are skipped.
+    #[instrument]
+    pub fn get_all_worktree_head_oids(&self) -> Result<HashSet<NonZeroOid>> {
+        let maybe_worktree_parent_repo = self.open_worktree_parent_repo()?;
+        let main_repo = match maybe_worktree_parent_repo.as_ref() {
+            Some(repo) => repo,
+            None => self,
+        };
+
+        let mut git_dirs = vec![main_repo.get_path().to_owned()];
+        let worktree_names = main_repo.inner.worktrees().map_err(Error::GetWorktrees)?;
+        for worktree_name in worktree_names.iter().flatten() {
+            let worktree = match main_repo.inner.find_worktree(worktree_name) {
+                Ok(worktree) => worktree,
+                Err(err) => {
+                    warn!(?worktree_name, ?err, "Could not find worktree");
+                    continue;
+                }
+            };
+            match git2::Repository::open_from_worktree(&worktree) {
+                Ok(worktree_repo) => git_dirs.push(worktree_repo.path().to_owned()),
+                Err(err) => {
+                    warn!(?worktree_name, ?err, "Could not open worktree");
+                }
+            }
+        }
+
+        let mut result = HashSet::new();
+        for git_dir in git_dirs {
+            let repo = match git2::Repository::open(&git_dir) {
+                Ok(repo) => Repo { inner: repo },
+                Err(err) => {
+                    warn!(?git_dir, ?err, "Could not open worktree repository");
+                    continue;
+                }
+            };
+            if let Some(head_oid) = repo.get_head_info()?.oid {
+                result.insert(head_oid);
+            }
+
+            let merge_head_path = git_dir.join("MERGE_HEAD");
+            let merge_head_contents = match std::fs::read_to_string(&merge_head_path) {
+                Ok(contents) => contents,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(Error::Io(err)),
+            };
+            for line in merge_head_contents.lines() {
+                match line.trim().parse::<NonZeroOid>() {
+                    Ok(oid) => {
+                        result.insert(oid);
+                    }
+                    Err(err) => {
+                        warn!(
+                            ?merge_head_path,
+                            ?line,
+                            ?err,
+                            "Could not parse MERGE_HEAD line"
+                        );
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Get the OIDs of the commits referred to by all stash entries, along with
+    /// their parents (which include the commit that was checked out at the time
+    /// that the stash entry was created).
+    #[instrument]
+    pub fn get_stash_oids(&self) -> Result<HashSet<NonZeroOid>> {
+        let reflog = self
+            .inner
+            .reflog("refs/stash")
+            .map_err(Error::ReadReference)?;
+        let mut result = HashSet::new();
+        for entry in reflog.iter() {
+            let stash_oid = match MaybeZeroOid::from(entry.id_new()) {
+                MaybeZeroOid::NonZero(stash_oid) => stash_oid,
+                MaybeZeroOid::Zero => continue,
+            };
+            result.insert(stash_oid);
+            if let Some(stash_commit) = self.find_commit(stash_oid)? {
+                result.extend(stash_commit.get_parent_oids());
+            }
+        }
+        Ok(result)
+    }
+
+    /// Get the entries in the reflog of the given reference, from oldest to
+    /// newest. Returns an empty list if the reference has no reflog.
+    #[instrument]
+    pub fn get_reflog_entries(&self, reference_name: &ReferenceName) -> Result<Vec<ReflogEntry>> {
+        let reflog = self
+            .inner
+            .reflog(reference_name.as_str())
+            .map_err(Error::ReadReference)?;
+        let mut result: Vec<ReflogEntry> = reflog
+            .iter()
+            .map(|entry| ReflogEntry {
+                old_oid: MaybeZeroOid::from(entry.id_old()),
+                new_oid: MaybeZeroOid::from(entry.id_new()),
+                time: Time {
+                    inner: entry.committer().when(),
+                },
+                message: entry.message().map(ToOwned::to_owned),
+            })
+            .collect();
+        // Reflogs are stored from newest to oldest.
+        result.reverse();
+        Ok(result)
+    }
+
+    /// Remove the entries in the reflog of the given reference which updated
+    /// the reference to point to one of the given commits. Returns the number
+    /// of entries which were removed.
+    #[instrument]
+    pub fn remove_reflog_entries(
+        &self,
+        reference_name: &ReferenceName,
+        commit_oids: &HashSet<NonZeroOid>,
+    ) -> Result<usize> {
+        let mut reflog = self
+            .inner
+            .reflog(reference_name.as_str())
+            .map_err(Error::ReadReference)?;
+        let indexes: Vec<usize> = reflog
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entry)| match MaybeZeroOid::from(entry.id_new()) {
+                MaybeZeroOid::NonZero(oid) if commit_oids.contains(&oid) => Some(index),
+                MaybeZeroOid::NonZero(_) | MaybeZeroOid::Zero => None,
+            })
+            .collect();
+        if indexes.is_empty() {
+            return Ok(0);
+        }
+
+        // Remove the entries from last to first, so that the indexes of the
+        // remaining entries don't change.
+        for index in indexes.iter().rev() {
+            reflog.remove(*index, true).map_err(Error::WriteReflog)?;
+        }
+        reflog.write().map_err(Error::WriteReflog)?;
+        Ok(indexes.len())
+    }
+
     /// Get the configuration object for the repository.
     ///
     /// **Warning**: This object should only be used for read operations. Write
@@ -655,17 +926,98 @@ impl Repo {
     }
 
     /// Get the connection to the SQLite database for this repository.
+    ///
+    /// If `branchless.core.sharedDatabasePath` is set, then the database at that
+    /// path is used instead of the one under the `.git/branchless` directory,
+    /// so that multiple clones of the same project can share their event logs.
+    /// A relative path is resolved against the working copy (or the `.git`
+    /// directory, for a bare repository), and `~` is expanded.
+    /// The connection is then tagged with the repository ID (see
+    /// `get_db_repo_id`) in the `temp.repo_id` table, so that the event log
+    /// only operates on events belonging to this project.
+    ///
+    /// The database is put in WAL mode so that readers don't block writers, and
+    /// the connection waits up to `DB_BUSY_TIMEOUT` for other processes (such
+    /// as concurrently-running hooks) to release their locks. A shared database
+    /// uses a rollback journal instead, since WAL mode doesn't work on network
+    /// filesystems, where shared databases are likely to be stored.
     #[instrument]
     pub fn get_db_conn(&self) -> Result<rusqlite::Connection> {
-        let dir = self.get_branchless_dir()?;
-        let path = dir.join("db.sqlite3");
+        let shared_path: Option<PathBuf> = self
+            .get_readonly_config()?
+            .get("branchless.core.sharedDatabasePath")
+            .map_err(Error::ReadSharedDatabaseConfig)?;
+        let path = match &shared_path {
+            Some(shared_path) => match self.get_working_copy_path() {
+                Some(working_copy_path) => working_copy_path.join(shared_path),
+                None => self.get_path().join(shared_path),
+            },
+            None => self.get_branchless_dir()?.join("db.sqlite3"),
+        };
         let conn = rusqlite::Connection::open(&path).map_err(|err| Error::OpenDatabase {
             source: err,
             path: path.clone(),
         })?;
+        let journal_mode = match shared_path {
+            Some(_) => "DELETE",
+            None => "WAL",
+        };
+        conn.busy_timeout(DB_BUSY_TIMEOUT)
+            .and_then(|()| {
+                conn.query_row(
+                    &format!("PRAGMA journal_mode = {journal_mode}"),
+                    [],
+                    |row| row.get::<_, String>(0),
+                )
+            })
+            .map_err(|err| Error::ConfigureDatabase {
+                source: err,
+                path: path.clone(),
+            })?;
+
+        if shared_path.is_some() {
+            let repo_id = self.get_db_repo_id()?;
+            conn.execute_batch("CREATE TEMP TABLE IF NOT EXISTS repo_id (repo_id TEXT NOT NULL)")
+                .and_then(|()| {
+                    conn.execute(
+                        "INSERT INTO temp.repo_id VALUES (:repo_id)",
+                        rusqlite::named_params! { ":repo_id": repo_id },
+                    )
+                })
+                .map_err(|err| Error::SetDatabaseRepoId {
+                    source: err,
+                    path: path.clone(),
+                })?;
+        }
         Ok(conn)
     }
 
+    /// Get the ID which identifies this repository's events in a shared
+    /// database. Clones of the same project should have the same ID.
+    ///
+    /// This is the value of `branchless.core.repoId` if set, or else the URL
+    /// of the `origin` remote, or else the path to the repository.
+    #[instrument]
+    pub fn get_db_repo_id(&self) -> Result<String> {
+        let config = self.get_readonly_config()?;
+        let repo_id: Option<String> = config
+            .get("branchless.core.repoId")
+            .map_err(Error::ReadSharedDatabaseConfig)?;
+        if let Some(repo_id) = repo_id {
+            return Ok(repo_id);
+        }
+
+        let origin_url: Option<String> = config
+            .get("remote.origin.url")
+            .map_err(Error::ReadSharedDatabaseConfig)?;
+        if let Some(origin_url) = origin_url {
+            return Ok(origin_url);
+        }
+
+        let branchless_dir = self.get_branchless_dir()?;
+        Ok(branchless_dir.to_string_lossy().into_owned())
+    }
+
     /// Get a snapshot of information about a given reference.
     #[instrument]
     pub fn resolve_reference(&self, reference: &Reference) -> Result<ResolvedReferenceInfo> {
@@ -786,6 +1138,41 @@ impl Repo {
         }
     }
 
+    /// Determine whether `ancestor` is an ancestor of `descendant`. A commit
+    /// is considered to be an ancestor of itself. This is cheaper than
+    /// comparing against the merge-base, since the walk can stop as soon as
+    /// `ancestor` is reached.
+    #[instrument]
+    pub fn is_ancestor(&self, ancestor: NonZeroOid, descendant: NonZeroOid) -> Result<bool> {
+        if ancestor == descendant {
+            return Ok(true);
+        }
+        self.inner
+            .graph_descendant_of(descendant.inner, ancestor.inner)
+            .map_err(|err| Error::IsAncestor {
+                source: err,
+                ancestor,
+                descendant,
+            })
+    }
+
+    /// Count the number of commits which `local` has that `upstream` doesn't,
+    /// and vice-versa. Returns a tuple of `(ahead, behind)`.
+    #[instrument]
+    pub fn count_ahead_behind(
+        &self,
+        local: NonZeroOid,
+        upstream: NonZeroOid,
+    ) -> Result<(usize, usize)> {
+        self.inner
+            .graph_ahead_behind(local.inner, upstream.inner)
+            .map_err(|err| Error::CountAheadBehind {
+                source: err,
+                local,
+                upstream,
+            })
+    }
+
     /// Get the patch for a commit, i.e. the diff between that commit and its
     /// parent.
     ///
@@ -947,6 +1334,23 @@ impl Repo {
         Ok(all_references)
     }
 
+    /// Get all references created by `git replace`, i.e. those under
+    /// `refs/replace/`. The name of each reference is the OID of the replaced
+    /// object, and its target is the replacement object.
+    #[instrument]
+    pub fn get_replace_references(&self) -> Result<Vec<Reference>> {
+        let mut replace_references = Vec::new();
+        for reference in self
+            .inner
+            .references_glob("refs/replace/*")
+            .map_err(Error::GetReferences)?
+        {
+            let reference = reference.map_err(Error::ReadReference)?;
+            replace_references.push(Reference { inner: reference });
+        }
+        Ok(replace_references)
+    }
+
     /// Check if the repository has staged or unstaged changes. Untracked files
     /// are not included. This operation may take a while.
     #[instrument]
@@ -1075,6 +1479,17 @@ impl Repo {
         Ok(Reference { inner: reference })
     }
 
+    /// Start a transaction for updating several references at once. See
+    /// `ReferenceTransaction`.
+    #[instrument]
+    pub fn start_reference_transaction(&self) -> Result<ReferenceTransaction> {
+        let transaction = self
+            .inner
+            .transaction()
+            .map_err(Error::StartReferenceTransaction)?;
+        Ok(ReferenceTransaction { inner: transaction })
+    }
+
     /// Get a list of all remote names.
     #[instrument]
     pub fn get_all_remote_names(&self) -> Result<Vec<String>> {
@@ -1150,6 +1565,25 @@ impl Repo {
         }
     }
 
+    /// Determine whether an object with the given OID exists in the object
+    /// database. Unlike `find_commit`, this works for objects of any type.
+    #[instrument]
+    pub fn contains_object(&self, oid: NonZeroOid) -> Result<bool> {
+        let odb = self.inner.odb().map_err(Error::ReadObjectDatabase)?;
+        Ok(odb.exists(oid.inner))
+    }
+
+    /// Extract the signature of the given commit, along with the data that
+    /// was signed. Returns `None` if the commit is not signed.
+    #[instrument]
+    pub fn extract_commit_signature(&self, oid: NonZeroOid) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        match self.inner.extract_signature(&oid.inner, None) {
+            Ok((signature, signed_data)) => Ok(Some((signature.to_vec(), signed_data.to_vec()))),
+            Err(err) if err.code() == git2::ErrorCode::NotFound => Ok(None),
+            Err(err) => Err(Error::ExtractSignature { source: err, oid }),
+        }
+    }
+
     /// Like `find_commit`, but raises a generic error if the commit could not
     /// be found.
     #[instrument]
@@ -1631,6 +2065,8 @@ impl std::fmt::Debug for Signature<'_> {
 }
 
 impl<'repo> Signature<'repo> {
+    /// Get the signature used for commits which are created by git-branchless
+    /// itself, rather than on behalf of the user.
     #[instrument]
     pub fn automated() -> Result<Self> {
         Ok(Signature {
@@ -1680,10 +2116,12 @@ impl<'repo> Signature<'repo> {
         }
     }
 
+    /// Get the name of the signer, if it's valid UTF-8.
     pub fn get_name(&self) -> Option<&str> {
         self.inner.name()
     }
 
+    /// Get the email address of the signer, if it's valid UTF-8.
     pub fn get_email(&self) -> Option<&str> {
         self.inner.email()
     }
@@ -1729,4 +2167,12 @@ impl Time {
     pub fn to_naive_date_time(&self) -> Option<NaiveDateTime> {
         NaiveDateTime::from_timestamp_opt(self.inner.seconds(), 0)
     }
+
+    /// Calculate the associated [`DateTime`] in the timezone in which the
+    /// timestamp was recorded.
+    pub fn to_date_time(&self) -> Option<DateTime<FixedOffset>> {
+        let offset = FixedOffset::east_opt(self.inner.offset_minutes() * 60)?;
+        let naive_date_time = self.to_naive_date_time()?;
+        Some(offset.from_utc_datetime(&naive_date_time))
+    }
 }
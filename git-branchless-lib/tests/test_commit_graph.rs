@@ -0,0 +1,131 @@
+use branchless::git::CommitGraph;
+use branchless::testing::make_git;
+
+#[test]
+fn test_commit_graph_parents() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["checkout", &test1_oid.to_string()])?;
+    let test3_oid = git.commit_file("test3", 3)?;
+    git.run(&["checkout", &test1_oid.to_string()])?;
+    let test4_oid = git.commit_file("test4", 4)?;
+    git.run(&["checkout", &test1_oid.to_string()])?;
+    git.run(&[
+        "merge",
+        "-m",
+        "octopus",
+        &test2_oid.to_string(),
+        &test3_oid.to_string(),
+        &test4_oid.to_string(),
+    ])?;
+    let (merge_oid, _stderr) = git.run(&["rev-parse", "HEAD"])?;
+    let merge_oid = merge_oid.trim().parse()?;
+
+    let repo = git.get_repo()?;
+    assert!(CommitGraph::open_for_repo(&repo)?.is_none());
+
+    git.run(&["branch", "octopus"])?;
+    git.run(&["commit-graph", "write", "--reachable"])?;
+    let commit_graph = match CommitGraph::open_for_repo(&repo)? {
+        Some(commit_graph) => commit_graph,
+        None => panic!("commit-graph file should have been written"),
+    };
+    // The commit-graph also includes the commits kept alive by branchless.
+    let (num_reachable, _stderr) = git.run(&["rev-list", "--all", "--count"])?;
+    assert_eq!(commit_graph.len(), num_reachable.trim().parse::<usize>()?);
+
+    for oid in [test1_oid, test2_oid, test3_oid, test4_oid, merge_oid] {
+        let commit = repo.find_commit_or_fail(oid)?;
+        assert_eq!(
+            commit_graph.get_parent_oids(oid),
+            Some(commit.get_parent_oids())
+        );
+    }
+    // `test1` is an ancestor of `test2`, so it's not a parent of the merge.
+    assert_eq!(
+        commit_graph.get_parent_oids(merge_oid),
+        Some(vec![test2_oid, test3_oid, test4_oid])
+    );
+
+    // Commits created after the file was written aren't in it.
+    let test5_oid = git.commit_file("test5", 5)?;
+    assert_eq!(commit_graph.get_parent_oids(test5_oid), None);
+
+    git.run(&["config", "core.commitGraph", "false"])?;
+    let repo = git.get_repo()?;
+    assert!(CommitGraph::open_for_repo(&repo)?.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_commit_graph_chain() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["branch", "foo"])?;
+    git.run(&["commit-graph", "write", "--reachable", "--split"])?;
+
+    git.run(&["checkout", &test1_oid.to_string()])?;
+    let test3_oid = git.commit_file("test3", 3)?;
+    git.run(&["merge", "-m", "merge", &test2_oid.to_string()])?;
+    let (merge_oid, _stderr) = git.run(&["rev-parse", "HEAD"])?;
+    let merge_oid = merge_oid.trim().parse()?;
+    git.run(&["branch", "bar"])?;
+    git.run(&["commit-graph", "write", "--reachable", "--split=no-merge"])?;
+
+    let repo = git.get_repo()?;
+    let chain = std::fs::read_to_string(
+        repo.get_path()
+            .join("objects")
+            .join("info")
+            .join("commit-graphs")
+            .join("commit-graph-chain"),
+    )?;
+    assert_eq!(chain.lines().count(), 2);
+    let commit_graph = match CommitGraph::open_for_repo(&repo)? {
+        Some(commit_graph) => commit_graph,
+        None => panic!("commit-graph chain should have been written"),
+    };
+    let (num_reachable, _stderr) = git.run(&["rev-list", "--all", "--count"])?;
+    assert_eq!(commit_graph.len(), num_reachable.trim().parse::<usize>()?);
+
+    // The merge commit is in the second layer, but its parents are in both.
+    assert_eq!(
+        commit_graph.get_parent_oids(merge_oid),
+        Some(vec![test3_oid, test2_oid])
+    );
+    for oid in [test1_oid, test2_oid, test3_oid, merge_oid] {
+        let commit = repo.find_commit_or_fail(oid)?;
+        assert_eq!(
+            commit_graph.get_parent_oids(oid),
+            Some(commit.get_parent_oids())
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_commit_graph_shallow() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["commit-graph", "write", "--reachable"])?;
+    let repo = git.get_repo()?;
+    assert!(CommitGraph::open_for_repo(&repo)?.is_some());
+
+    // Mark `test1` as a shallow boundary, as `git fetch --depth` would. The
+    // commit-graph still records its parent, so it can't be used.
+    std::fs::write(repo.get_path().join("shallow"), format!("{test1_oid}\n"))?;
+    let repo = git.get_repo()?;
+    assert!(CommitGraph::open_for_repo(&repo)?.is_none());
+
+    Ok(())
+}
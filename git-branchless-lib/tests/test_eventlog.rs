@@ -1,10 +1,17 @@
+use std::ops::ControlFlow;
 use std::str::FromStr;
+use std::time::{Duration, SystemTime};
 
-use branchless::core::eventlog::testing::{new_event_cursor, new_event_transaction_id};
+use branchless::core::effects::Effects;
+use branchless::core::eventlog::testing::{
+    get_event_replayer_events, new_event_cursor, new_event_transaction_id,
+};
 use branchless::core::eventlog::{
-    testing::new_event_replayer, Event, EventLogDb, EventTransactionId,
+    get_schema_version, testing::new_event_replayer, CommitActivityStatus, Event, EventFilter,
+    EventLogDb, EventReplayer, EventTransactionId, EVENT_LOG_SCHEMA_VERSION,
 };
-use branchless::git::{MaybeZeroOid, NonZeroOid, ReferenceName};
+use branchless::core::formatting::Glyphs;
+use branchless::git::{MaybeZeroOid, NonZeroOid, ReferenceName, Repo};
 use branchless::testing::make_git;
 
 #[test]
@@ -42,6 +49,50 @@ fn test_drop_non_meaningful_events() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_drop_redundant_ref_updates() -> eyre::Result<()> {
+    let event_tx_id = new_event_transaction_id(123);
+    let make_ref_update = |ref_name: &str, old_oid: &str, new_oid: &str| -> eyre::Result<Event> {
+        Ok(Event::RefUpdateEvent {
+            timestamp: 0.0,
+            event_tx_id,
+            ref_name: ReferenceName::from(ref_name),
+            old_oid: MaybeZeroOid::from_str(old_oid)?,
+            new_oid: MaybeZeroOid::from_str(new_oid)?,
+            message: None,
+        })
+    };
+
+    let mut replayer = new_event_replayer("refs/heads/master".into());
+    let events = [
+        make_ref_update("refs/heads/foo", "abc", "def")?,
+        // Duplicate of the previous update.
+        make_ref_update("refs/heads/foo", "abc", "def")?,
+        // Moves the reference to where it already points.
+        make_ref_update("refs/heads/foo", "def", "def")?,
+        make_ref_update("refs/heads/foo", "def", "abc")?,
+        make_ref_update("HEAD", "abc", "def")?,
+        // `HEAD` may have moved in the meantime without an event (such as by
+        // committing to the checked-out branch), so this isn't redundant.
+        make_ref_update("HEAD", "fed", "def")?,
+        make_ref_update("HEAD", "def", "def")?,
+    ];
+    for event in events.iter() {
+        replayer.process_event(event);
+    }
+
+    assert_eq!(
+        get_event_replayer_events(&replayer),
+        &vec![
+            events[0].clone(),
+            events[3].clone(),
+            events[4].clone(),
+            events[5].clone()
+        ]
+    );
+    Ok(())
+}
+
 #[test]
 fn test_different_event_transaction_ids() -> eyre::Result<()> {
     let git = make_git()?;
@@ -164,3 +215,540 @@ fn test_advance_cursor_by_transaction() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_make_cursor_at_time() -> eyre::Result<()> {
+    let commit_oid = NonZeroOid::from_str("abc")?;
+    let mut event_replayer = new_event_replayer("refs/heads/master".into());
+    event_replayer.process_event(&Event::CommitEvent {
+        timestamp: 10.0,
+        event_tx_id: new_event_transaction_id(1),
+        commit_oid,
+    });
+    event_replayer.process_event(&Event::ObsoleteEvent {
+        timestamp: 20.0,
+        event_tx_id: new_event_transaction_id(2),
+        commit_oid,
+    });
+
+    let at_time = |secs: u64| {
+        event_replayer.make_cursor_at_time(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+    };
+    assert_eq!(at_time(5), new_event_cursor(0));
+    assert_eq!(at_time(10), new_event_cursor(1));
+    assert_eq!(at_time(15), new_event_cursor(1));
+    assert_eq!(at_time(20), new_event_cursor(2));
+    assert_eq!(at_time(25), new_event_cursor(2));
+
+    assert_eq!(
+        event_replayer.get_cursor_commit_activity_status(at_time(5), commit_oid),
+        CommitActivityStatus::Inactive
+    );
+    assert_eq!(
+        event_replayer.get_cursor_commit_activity_status(at_time(15), commit_oid),
+        CommitActivityStatus::Active
+    );
+    assert_eq!(
+        event_replayer.get_cursor_commit_activity_status(at_time(25), commit_oid),
+        CommitActivityStatus::Obsolete
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_compact_events() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+
+    let ref_name = ReferenceName::from("refs/heads/foo");
+    let oids = [
+        MaybeZeroOid::Zero,
+        MaybeZeroOid::from_str("abc")?,
+        MaybeZeroOid::from_str("def")?,
+        MaybeZeroOid::from_str("123")?,
+    ];
+    for (old_oid, new_oid) in oids.iter().zip(oids.iter().skip(1)) {
+        let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "test")?;
+        event_log_db.add_events(vec![Event::RefUpdateEvent {
+            timestamp: 0.0,
+            event_tx_id,
+            ref_name: ref_name.clone(),
+            old_oid: *old_oid,
+            new_oid: *new_oid,
+            message: None,
+        }])?;
+    }
+
+    let get_ref_updates = || -> eyre::Result<Vec<(MaybeZeroOid, MaybeZeroOid)>> {
+        Ok(event_log_db
+            .get_events()?
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::RefUpdateEvent {
+                    ref_name: event_ref_name,
+                    old_oid,
+                    new_oid,
+                    ..
+                } if event_ref_name == ref_name => Some((old_oid, new_oid)),
+                _ => None,
+            })
+            .collect())
+    };
+    assert_eq!(get_ref_updates()?.len(), 3);
+
+    // The last operation is preserved, and the two before it are summarized
+    // into a single reference update.
+    assert_eq!(event_log_db.compact_events(1)?, 1);
+    assert_eq!(
+        get_ref_updates()?,
+        vec![(oids[0], oids[2]), (oids[2], oids[3])]
+    );
+
+    // Nothing left to compact.
+    assert_eq!(event_log_db.compact_events(1)?, 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_coalesce_duplicate_ref_updates() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+
+    let ref_name = ReferenceName::from("refs/heads/foo");
+    let abc = MaybeZeroOid::from_str("abc")?;
+    let def = MaybeZeroOid::from_str("def")?;
+    let updates = [
+        (100.0, abc, def),
+        // Duplicate within the window: coalesced.
+        (100.5, abc, def),
+        // Duplicate outside of the window: recorded.
+        (110.0, abc, def),
+        // The reference moves back and forth: recorded.
+        (110.1, def, abc),
+        (110.2, abc, def),
+    ];
+    for (timestamp, old_oid, new_oid) in updates {
+        let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "test")?;
+        event_log_db.add_events(vec![Event::RefUpdateEvent {
+            timestamp,
+            event_tx_id,
+            ref_name: ref_name.clone(),
+            old_oid,
+            new_oid,
+            message: None,
+        }])?;
+    }
+
+    let ref_updates: Vec<(f64, MaybeZeroOid, MaybeZeroOid)> = event_log_db
+        .get_events()?
+        .into_iter()
+        .filter_map(|event| match event {
+            Event::RefUpdateEvent {
+                timestamp,
+                ref_name: event_ref_name,
+                old_oid,
+                new_oid,
+                ..
+            } if event_ref_name == ref_name => Some((timestamp, old_oid, new_oid)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        ref_updates,
+        vec![
+            (100.0, abc, def),
+            (110.0, abc, def),
+            (110.1, def, abc),
+            (110.2, abc, def),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_concurrent_writers() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    let num_threads = 4;
+    let num_events_per_thread = 25;
+    let threads: Vec<_> = (0..num_threads)
+        .map(|thread_num| {
+            let repo_path = git.repo_path.clone();
+            std::thread::spawn(move || -> eyre::Result<()> {
+                let repo = Repo::from_dir(&repo_path)?;
+                let conn = repo.get_db_conn()?;
+                let event_log_db = EventLogDb::new(&conn)?;
+                for i in 0..num_events_per_thread {
+                    let event_tx_id =
+                        event_log_db.make_transaction_id(SystemTime::now(), "test")?;
+                    event_log_db.add_events(vec![Event::RefUpdateEvent {
+                        timestamp: 0.0,
+                        event_tx_id,
+                        ref_name: ReferenceName::from(format!("refs/heads/thread-{thread_num}")),
+                        old_oid: MaybeZeroOid::Zero,
+                        new_oid: MaybeZeroOid::from_str(&format!("{:x}", i + 1))?,
+                        message: None,
+                    }])?;
+                }
+                Ok(())
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().expect("thread panicked")?;
+    }
+
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let num_events = event_log_db
+        .get_events()?
+        .into_iter()
+        .filter(|event| {
+            matches!(event, Event::RefUpdateEvent { ref_name, .. }
+                if ref_name.as_str().starts_with("refs/heads/thread-"))
+        })
+        .count();
+    assert_eq!(num_events, num_threads * num_events_per_thread);
+
+    Ok(())
+}
+
+#[test]
+fn test_query_events() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+
+    let foo = ReferenceName::from("refs/heads/foo");
+    let bar = ReferenceName::from("refs/heads/bar");
+    let abc = NonZeroOid::from_str("abc")?;
+    let event_tx_id1 = event_log_db.make_transaction_id(SystemTime::now(), "test1")?;
+    let event_tx_id2 = event_log_db.make_transaction_id(SystemTime::now(), "test2")?;
+    let events = vec![
+        Event::CommitEvent {
+            timestamp: 100.0,
+            event_tx_id: event_tx_id1,
+            commit_oid: abc,
+        },
+        Event::RefUpdateEvent {
+            timestamp: 200.0,
+            event_tx_id: event_tx_id1,
+            ref_name: foo.clone(),
+            old_oid: MaybeZeroOid::Zero,
+            new_oid: abc.into(),
+            message: None,
+        },
+        Event::RefUpdateEvent {
+            timestamp: 300.0,
+            event_tx_id: event_tx_id2,
+            ref_name: bar.clone(),
+            old_oid: MaybeZeroOid::Zero,
+            new_oid: MaybeZeroOid::from_str("def")?,
+            message: None,
+        },
+    ];
+    event_log_db.add_events(events.clone())?;
+
+    let query = |filter: EventFilter| -> eyre::Result<Vec<Event>> {
+        let events = event_log_db
+            .query(&filter)?
+            .into_iter()
+            .filter(|event| events.contains(event))
+            .collect();
+        Ok(events)
+    };
+    assert_eq!(query(EventFilter::default())?, events);
+    assert_eq!(
+        query(EventFilter {
+            event_types: vec!["ref-move".to_string()],
+            ..Default::default()
+        })?,
+        events[1..]
+    );
+    assert_eq!(
+        query(EventFilter {
+            ref_name: Some(bar),
+            ..Default::default()
+        })?,
+        events[2..]
+    );
+    assert_eq!(
+        query(EventFilter {
+            commit_oid: Some(abc),
+            ..Default::default()
+        })?,
+        events[..2]
+    );
+    assert_eq!(
+        query(EventFilter {
+            event_tx_id: Some(event_tx_id2),
+            ..Default::default()
+        })?,
+        events[2..]
+    );
+    assert_eq!(
+        query(EventFilter {
+            start_time: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(200)),
+            end_time: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(300)),
+            ..Default::default()
+        })?,
+        events[1..2]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_schema_migration() -> eyre::Result<()> {
+    // Simulate a database created before schema versioning was introduced.
+    let conn = rusqlite::Connection::open_in_memory()?;
+    conn.execute_batch(
+        "
+CREATE TABLE event_log (
+    timestamp REAL NOT NULL,
+    type TEXT NOT NULL,
+    event_tx_id INTEGER NOT NULL,
+    old_ref TEXT,
+    new_ref TEXT,
+    ref_name TEXT,
+    message TEXT
+);
+INSERT INTO event_log VALUES (
+    0.0, 'commit', 1, 'abc', NULL, NULL, NULL
+);
+",
+    )?;
+    assert_eq!(get_schema_version(&conn)?, 0);
+
+    let event_log_db = EventLogDb::new(&conn)?;
+    assert_eq!(get_schema_version(&conn)?, EVENT_LOG_SCHEMA_VERSION);
+    assert_eq!(
+        event_log_db.get_events()?,
+        vec![Event::CommitEvent {
+            timestamp: 0.0,
+            event_tx_id: new_event_transaction_id(1),
+            commit_oid: NonZeroOid::from_str("abc")?,
+        }]
+    );
+
+    // Opening an up-to-date database is a no-op.
+    EventLogDb::new(&conn)?;
+    assert_eq!(get_schema_version(&conn)?, EVENT_LOG_SCHEMA_VERSION);
+
+    // A database from a newer version of git-branchless is rejected.
+    conn.execute(
+        "UPDATE schema_version SET version = ?",
+        [EVENT_LOG_SCHEMA_VERSION + 1],
+    )?;
+    assert!(EventLogDb::new(&conn).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_open_up_to_date_database_without_write_lock() -> eyre::Result<()> {
+    let git = make_git()?;
+    let db_path = git.repo_path.join("db.sqlite3");
+    {
+        let conn = rusqlite::Connection::open(&db_path)?;
+        EventLogDb::new(&conn)?;
+    }
+
+    // Opening a database which doesn't need to be migrated shouldn't wait for
+    // another process holding the write lock.
+    let writer_conn = rusqlite::Connection::open(&db_path)?;
+    writer_conn.execute_batch("BEGIN IMMEDIATE")?;
+    let conn = rusqlite::Connection::open(&db_path)?;
+    conn.busy_timeout(Duration::ZERO)?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    assert_eq!(event_log_db.get_events()?, Vec::new());
+    writer_conn.execute_batch("COMMIT")?;
+
+    Ok(())
+}
+
+#[test]
+fn test_watch_events() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let rowid = event_log_db.get_last_event_rowid()?;
+
+    let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "test")?;
+    let events = vec![
+        Event::CommitEvent {
+            timestamp: 1.0,
+            event_tx_id,
+            commit_oid: NonZeroOid::from_str("abc")?,
+        },
+        Event::ObsoleteEvent {
+            timestamp: 2.0,
+            event_tx_id,
+            commit_oid: NonZeroOid::from_str("abc")?,
+        },
+    ];
+    event_log_db.add_events(events.clone())?;
+
+    let new_events = event_log_db.get_events_after(rowid)?;
+    assert_eq!(
+        new_events
+            .into_iter()
+            .map(|(_rowid, event)| event)
+            .collect::<Vec<_>>(),
+        events
+    );
+
+    let mut watched_events = Vec::new();
+    event_log_db.watch_events(rowid, Duration::from_millis(10), |event| {
+        watched_events.push(event.clone());
+        if watched_events.len() == events.len() {
+            Ok(ControlFlow::Break(()))
+        } else {
+            Ok(ControlFlow::Continue(()))
+        }
+    })?;
+    assert_eq!(watched_events, events);
+
+    Ok(())
+}
+
+#[test]
+fn test_event_replayer_snapshot() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.run(&["config", "branchless.core.replayerSnapshotInterval", "1"])?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+
+    // Saves a snapshot of the events so far.
+    EventReplayer::from_event_log_db_with_snapshot(&effects, &repo, &event_log_db)?;
+
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["commit", "--amend", "-m", "amended test2"])?;
+    git.run(&["branch", "-f", "foo", "HEAD"])?;
+
+    let full_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+    let snapshot_replayer =
+        EventReplayer::from_event_log_db_with_snapshot(&effects, &repo, &event_log_db)?;
+    let full_cursor = full_replayer.make_default_cursor();
+    let snapshot_cursor = snapshot_replayer.make_default_cursor();
+
+    for oid in [test1_oid, test2_oid] {
+        assert_eq!(
+            snapshot_replayer.get_cursor_commit_activity_status(snapshot_cursor, oid),
+            full_replayer.get_cursor_commit_activity_status(full_cursor, oid),
+        );
+    }
+    assert_eq!(
+        snapshot_replayer.get_cursor_commit_activity_status(snapshot_cursor, test2_oid),
+        CommitActivityStatus::Obsolete
+    );
+    assert_eq!(
+        snapshot_replayer.get_cursor_oids(snapshot_cursor),
+        full_replayer.get_cursor_oids(full_cursor),
+    );
+
+    let full_snapshot = full_replayer.get_references_snapshot(&repo, full_cursor)?;
+    let snapshot_snapshot = snapshot_replayer.get_references_snapshot(&repo, snapshot_cursor)?;
+    assert_eq!(snapshot_snapshot.head_oid, full_snapshot.head_oid);
+    assert_eq!(
+        snapshot_snapshot.main_branch_oid,
+        full_snapshot.main_branch_oid
+    );
+    assert_eq!(
+        snapshot_snapshot.branch_oid_to_names,
+        full_snapshot.branch_oid_to_names
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_add_backfilled_events() -> eyre::Result<()> {
+    let conn = rusqlite::Connection::open_in_memory()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let make_commit_event = |timestamp: f64, event_tx_id: EventTransactionId, commit_oid: &str| {
+        eyre::Ok(Event::CommitEvent {
+            timestamp,
+            event_tx_id,
+            commit_oid: NonZeroOid::from_str(commit_oid)?,
+        })
+    };
+
+    // Existing events whose timestamps aren't monotonic should keep their
+    // relative order.
+    let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "test")?;
+    event_log_db.add_events(vec![
+        make_commit_event(10.0, event_tx_id, "aaa")?,
+        make_commit_event(30.0, event_tx_id, "bbb")?,
+        make_commit_event(20.0, event_tx_id, "ccc")?,
+    ])?;
+    let last_rowid = event_log_db.get_last_event_rowid()?;
+
+    let operations = [(5.0, "ddd"), (15.0, "eee"), (25.0, "fff"), (40.0, "111")]
+        .into_iter()
+        .map(|(timestamp, commit_oid)| {
+            let commit_oid = NonZeroOid::from_str(commit_oid)?;
+            eyre::Ok((
+                SystemTime::now(),
+                "backfill".to_string(),
+                (timestamp, commit_oid),
+            ))
+        })
+        .collect::<eyre::Result<Vec<_>>>()?;
+    let num_events = event_log_db.add_backfilled_events(
+        operations,
+        |event_tx_id, (timestamp, commit_oid)| {
+            vec![Event::CommitEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid,
+            }]
+        },
+    )?;
+    assert_eq!(num_events, 4);
+
+    // The backfilled events are added after the existing events, so the
+    // existing events keep their row IDs.
+    assert_eq!(event_log_db.get_events_after(last_rowid)?.len(), 4);
+
+    let timestamps: Vec<f64> = event_log_db
+        .get_events()?
+        .iter()
+        .map(|event| {
+            event
+                .get_timestamp()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_secs_f64()
+        })
+        .collect();
+    assert_eq!(timestamps, vec![5.0, 10.0, 15.0, 25.0, 30.0, 20.0, 40.0]);
+
+    Ok(())
+}
@@ -303,3 +303,23 @@ fn test_worktree_working_copy_path() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_is_ancestor() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["checkout", &test1_oid.to_string()])?;
+    let test3_oid = git.commit_file("test3", 3)?;
+
+    let repo = git.get_repo()?;
+    assert!(repo.is_ancestor(test1_oid, test1_oid)?);
+    assert!(repo.is_ancestor(test1_oid, test2_oid)?);
+    assert!(repo.is_ancestor(test1_oid, test3_oid)?);
+    assert!(!repo.is_ancestor(test2_oid, test1_oid)?);
+    assert!(!repo.is_ancestor(test2_oid, test3_oid)?);
+    assert!(!repo.is_ancestor(test3_oid, test2_oid)?);
+
+    Ok(())
+}
@@ -508,6 +508,7 @@ pub fn switch(
         event_cursor,
         &commits,
         false,
+        false,
     )?;
 
     let initial_query = match switch_options {
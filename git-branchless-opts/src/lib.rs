@@ -341,7 +341,8 @@ pub struct SmartlogArgs {
     pub revset: Option<Revset>,
 
     /// Print the smartlog in the opposite of the usual order, with the latest
-    /// commits first.
+    /// commits first. If `branchless.smartlog.reverse` is set, prints the
+    /// latest commits last instead.
     #[clap(long)]
     pub reverse: bool,
 
@@ -350,11 +351,126 @@ pub struct SmartlogArgs {
     #[clap(long)]
     pub exact: bool,
 
+    /// Print the smartlog graph as JSON instead of rendering it, so that it
+    /// can be consumed by other tools. Same as `--format=json`.
+    #[clap(long, conflicts_with = "format")]
+    pub json: bool,
+
+    /// The format in which to print the smartlog.
+    #[clap(value_parser, long = "format", value_enum)]
+    pub format: Option<SmartlogFormat>,
+
+    /// How to display the time at which each commit was made. If not
+    /// provided, relative times are shown unless disabled by
+    /// `branchless.commitDescriptors.relativeTime`.
+    #[clap(value_parser, long = "date", value_enum)]
+    pub date_format: Option<SmartlogDateFormat>,
+
+    /// How to order sibling commits and stacks. If not provided, uses the
+    /// `branchless.smartlog.order` setting, or `topological` by default.
+    #[clap(value_parser, long = "order", value_enum)]
+    pub order: Option<SmartlogOrder>,
+
+    /// Show long linear runs of commits in full, rather than collapsing them.
+    /// See `branchless.smartlog.collapseThreshold`.
+    #[clap(action, long = "expand")]
+    pub expand: bool,
+
+    /// Mark the commits which are new or have moved since the last time the
+    /// smartlog was rendered with `--diff`, and list the commits which are no
+    /// longer shown. See `branchless.smartlog.trackChanges`.
+    #[clap(action, long = "diff")]
+    pub diff: bool,
+
+    /// Only show the stacks containing the commits in the revset (or `HEAD`,
+    /// if no revset is provided), rather than every stack in the repository.
+    #[clap(action, short = 's', long = "stack")]
+    pub stack: bool,
+
+    /// Show the number of lines inserted and deleted by each draft commit.
+    /// See `branchless.commitDescriptors.diffStat`.
+    #[clap(action, long = "stat")]
+    pub stat: bool,
+
+    /// Only show the `n` stacks with the most recently committed commits, in
+    /// addition to the stack containing `HEAD`. A value of `0` shows all
+    /// stacks. Defaults to the `branchless.smartlog.stackLimit` setting.
+    #[clap(value_parser, long = "limit", value_name = "n")]
+    pub limit: Option<usize>,
+
+    /// Browse the smartlog interactively, with keybindings to check out,
+    /// hide, or restack the selected commit.
+    #[clap(action, short = 'i', long = "interactive")]
+    pub interactive: bool,
+
+    /// Options for filtering the draft commits in the smartlog.
+    #[clap(flatten)]
+    pub filter_options: SmartlogFilterOptions,
+
     /// Options for resolving revset expressions.
     #[clap(flatten)]
     pub resolve_revset_options: ResolveRevsetOptions,
 }
 
+/// The format in which to print the smartlog.
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum SmartlogFormat {
+    /// Render the smartlog as text for display in the terminal. This is the
+    /// default behavior.
+    #[default]
+    Text,
+
+    /// Print the smartlog graph as a JSON object.
+    Json,
+
+    /// Print the smartlog graph in the Graphviz DOT language.
+    Dot,
+}
+
+/// Options for filtering the draft commits shown in the smartlog. Public
+/// commits are always shown. The patterns use the same syntax as the pattern
+/// arguments to revset functions such as `author.name`.
+#[derive(Args, Clone, Debug, Default)]
+pub struct SmartlogFilterOptions {
+    /// Only show draft commits whose author name or email matches the given
+    /// pattern.
+    #[clap(value_parser, long = "author")]
+    pub author: Option<String>,
+
+    /// Only show draft commits which touch a path matching the given pattern.
+    #[clap(value_parser, long = "path")]
+    pub path: Option<String>,
+
+    /// Only show draft commits committed after the given date, such as
+    /// `2020-01-01` or `1 week ago`.
+    #[clap(value_parser, long = "since")]
+    pub since: Option<String>,
+}
+
+/// How to display commit times in the smartlog.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum SmartlogDateFormat {
+    /// Show how long ago the commit was made, e.g. `3h`.
+    Relative,
+
+    /// Show the commit time in ISO 8601-like format, as with `git log
+    /// --date=iso`.
+    Iso,
+}
+
+/// How to order sibling commits and stacks in the smartlog.
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum SmartlogOrder {
+    /// Order siblings topologically, breaking ties by commit time. This is the
+    /// default behavior.
+    #[default]
+    Topological,
+
+    /// Order sibling stacks by the committer date of their most recent
+    /// commit, so that the most recently updated stacks are shown last.
+    Chronological,
+}
+
 /// The Git hosting provider to use, called a "forge".
 #[derive(Clone, Debug, ValueEnum)]
 pub enum ForgeKind {
@@ -440,12 +556,105 @@ pub enum Command {
     /// report.
     BugReport,
 
+    /// Check the internal database for corruption or references to missing
+    /// commits, and vacuum it if no problems are found.
+    CheckDb,
+
+    /// Save the positions of all branches and `HEAD` under a name, or restore
+    /// them later.
+    Checkpoint {
+        /// The subcommand to run.
+        #[clap(subcommand)]
+        subcommand: CheckpointSubcommand,
+    },
+
+    /// Print internal diagnostic information.
+    Debug {
+        /// The subcommand to run.
+        #[clap(subcommand)]
+        subcommand: DebugSubcommand,
+    },
+
     /// Use the partial commit selector UI as a Git-compatible difftool; see
     /// git-difftool(1) for more information on Git difftools.
     Difftool(scm_record::scm_diff_editor::Opts),
 
+    /// Show, export, or import the event log, which records the operations
+    /// used to determine which commits are visible and to undo changes.
+    EventLog {
+        /// The subcommand to run.
+        #[clap(subcommand)]
+        subcommand: EventLogSubcommand,
+    },
+
+    /// Print the events in the event log which match the given filters, one
+    /// JSON object per line.
+    Events {
+        /// Only print events of this type, such as `commit`, `ref-move`, or
+        /// `rewrite`. May be passed multiple times.
+        #[clap(value_parser, long = "type")]
+        event_types: Vec<String>,
+
+        /// Only print events which affect the reference with this full name,
+        /// such as `refs/heads/main`.
+        #[clap(value_parser, long = "ref")]
+        ref_name: Option<String>,
+
+        /// Only print events which refer to this commit hash.
+        #[clap(value_parser, long = "commit")]
+        commit_oid: Option<NonZeroOid>,
+
+        /// Only print events belonging to this transaction ID.
+        #[clap(value_parser, long = "transaction")]
+        event_tx_id: Option<isize>,
+
+        /// Only print events which happened at or after this time, given in
+        /// seconds since the Unix epoch.
+        #[clap(value_parser, long = "since")]
+        since: Option<f64>,
+
+        /// Only print events which happened before this time, given in
+        /// seconds since the Unix epoch.
+        #[clap(value_parser, long = "until")]
+        until: Option<f64>,
+    },
+
     /// Run internal garbage collection.
-    Gc,
+    Gc {
+        /// Print the commits which would no longer be kept alive, without
+        /// actually releasing them.
+        #[clap(action, short = 'n', long = "dry-run")]
+        dry_run: bool,
+
+        /// After releasing commits, also delete the reflog entries which refer
+        /// to them, so that the disk space used by collected commits can
+        /// actually be reclaimed.
+        #[clap(action, long = "full", conflicts_with("dry_run"))]
+        full: bool,
+
+        /// After releasing commits, also run `git gc --auto`.
+        #[clap(action, long = "git-gc", conflicts_with("dry_run"))]
+        git_gc: bool,
+
+        /// Consider all commits, rather than only the commits affected by
+        /// operations since the last garbage collection.
+        #[clap(action, long = "full-scan")]
+        full_scan: bool,
+
+        /// Also compact the event log, summarizing the reference updates in all
+        /// but the last `branchless.gc.eventLogCompactionDepth` operations
+        /// (default 1000), and delete event log entries older than
+        /// `branchless.gc.eventLogRetentionDays` days (default 90). Older
+        /// operations can no longer be undone precisely.
+        #[clap(action, long = "aggressive", conflicts_with("dry_run"))]
+        aggressive: bool,
+
+        /// Also rebuild the cached commit graph from the current references,
+        /// discarding commits which are no longer referenced. This is done
+        /// automatically when a main branch is rewound.
+        #[clap(action, long = "refresh-cache", conflicts_with("dry_run"))]
+        refresh_cache: bool,
+    },
 
     /// Hide the provided commits from the smartlog.
     Hide {
@@ -466,6 +675,16 @@ pub enum Command {
         /// commits.
         #[clap(action, short = 'r', long = "recursive")]
         recursive: bool,
+
+        /// Also hide any draft commits whose changes have already been applied
+        /// to the main branch, as determined by comparing patch IDs.
+        #[clap(action, long = "merged")]
+        merged: bool,
+
+        /// The reason for hiding the commits, which will be shown alongside
+        /// them in the smartlog and in `git undo`.
+        #[clap(value_parser, short = 'm', long = "message")]
+        message: Option<String>,
     },
 
     /// Internal use.
@@ -545,6 +764,18 @@ pub enum Command {
         traverse_commits_options: TraverseCommitsOptions,
     },
 
+    /// Pin the provided commits, so that they're never garbage-collected, even
+    /// if they're hidden.
+    Pin {
+        /// Zero or more commits to pin.
+        #[clap(value_parser)]
+        revsets: Vec<Revset>,
+
+        /// Options for resolving revset expressions.
+        #[clap(flatten)]
+        resolve_revset_options: ResolveRevsetOptions,
+    },
+
     /// Move to an earlier commit in the current stack.
     Prev {
         /// Options for traversing commits.
@@ -589,6 +820,13 @@ pub enum Command {
     /// Create a commit by interactively selecting which changes to include.
     Record(RecordArgs),
 
+    /// Reapply the changes reverted by the most recent `git undo`.
+    Redo {
+        /// Skip confirmation and apply changes immediately.
+        #[clap(action, short = 'y', long = "yes")]
+        yes: bool,
+    },
+
     /// Reword commits.
     Reword {
         /// Zero or more commits to reword.
@@ -685,6 +923,33 @@ pub enum Command {
         #[clap(action, short = 'i', long = "interactive")]
         interactive: bool,
 
+        /// Undo only the event with this ID, as shown by `git undo -i`,
+        /// leaving later changes in place. Refuses to do so if a later event
+        /// updated the same references or commits.
+        #[clap(value_parser, long = "event", conflicts_with = "interactive")]
+        event_id: Option<isize>,
+
+        /// The number of transactions to undo, when not running
+        /// interactively.
+        #[clap(
+            value_parser,
+            short = 'n',
+            long = "steps",
+            default_value_t = 1,
+            conflicts_with_all(&["interactive", "event_id"])
+        )]
+        steps: usize,
+
+        /// Return to the state of the repository as of this time, which may be
+        /// absolute (such as `2023-01-01 12:00`) or relative (such as `2 hours
+        /// ago`).
+        #[clap(
+            value_parser,
+            long = "to",
+            conflicts_with_all(&["interactive", "event_id", "steps"])
+        )]
+        to: Option<String>,
+
         /// Skip confirmation and apply changes immediately.
         #[clap(action, short = 'y', long = "yes")]
         yes: bool,
@@ -703,8 +968,31 @@ pub enum Command {
         /// Also recursively unhide all children commits of the provided commits.
         #[clap(action, short = 'r', long = "recursive")]
         recursive: bool,
+
+        /// Also unhide any hidden draft ancestors of the provided commits, so
+        /// that they're connected to the rest of the commit graph in the
+        /// smartlog.
+        #[clap(action, short = 'a', long = "ancestors")]
+        ancestors: bool,
     },
 
+    /// Unpin previously-pinned commits, so that they can be garbage-collected
+    /// once hidden.
+    Unpin {
+        /// Zero or more commits to unpin.
+        #[clap(value_parser)]
+        revsets: Vec<Revset>,
+
+        /// Options for resolving revset expressions.
+        #[clap(flatten)]
+        resolve_revset_options: ResolveRevsetOptions,
+    },
+
+    /// Precompute the cached commit graph for all visible commits and the
+    /// upstream of the main branch. This can be run after a large fetch so
+    /// that the next command doesn't have to do it.
+    WarmCache,
+
     /// Wrap a Git command inside a branchless transaction.
     Wrap {
         /// The `git` executable to invoke.
@@ -729,6 +1017,19 @@ pub enum ColorSetting {
     Never,
 }
 
+/// Which characters to use when drawing graphs.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum GlyphsSetting {
+    /// Use Unicode box-drawing characters, unless the terminal appears not to
+    /// support them (`TERM=dumb` or a non-UTF-8 locale), in which case use
+    /// plain ASCII. This is the default behavior.
+    Auto,
+    /// Always use Unicode box-drawing characters when writing to a terminal.
+    Unicode,
+    /// Always use plain ASCII characters.
+    Ascii,
+}
+
 /// How to execute tests.
 #[derive(Clone, Copy, Debug, ValueEnum)]
 pub enum TestExecutionStrategy {
@@ -770,6 +1071,11 @@ pub struct GlobalArgs {
     /// Flag to force enable or disable terminal colors.
     #[clap(value_parser, long = "color", value_enum, global = true)]
     pub color: Option<ColorSetting>,
+
+    /// Which characters to use when drawing graphs. If not provided, the
+    /// `branchless.glyphs` config setting is used.
+    #[clap(value_parser, long = "glyphs", value_enum, global = true)]
+    pub glyphs: Option<GlyphsSetting>,
 }
 
 /// Branchless workflow for Git.
@@ -787,6 +1093,68 @@ pub struct Opts {
     pub command: Command,
 }
 
+/// `checkpoint` subcommands.
+#[derive(Debug, Parser)]
+pub enum CheckpointSubcommand {
+    /// Record the current positions of all branches and `HEAD`, as well as
+    /// the working copy contents, in the event log under the given name.
+    Save {
+        /// The name of the checkpoint.
+        #[clap(value_parser)]
+        name: String,
+    },
+
+    /// Return all branches, `HEAD`, and the working copy to the state recorded
+    /// by the most recent checkpoint with the given name, by undoing
+    /// everything that happened since.
+    Restore {
+        /// The name of the checkpoint.
+        #[clap(value_parser)]
+        name: String,
+
+        /// Skip confirmation and apply changes immediately.
+        #[clap(action, short = 'y', long = "yes")]
+        yes: bool,
+    },
+}
+
+/// `debug` subcommands.
+#[derive(Debug, Parser)]
+pub enum DebugSubcommand {
+    /// Print statistics about the caches used to speed up commit graph
+    /// queries, such as the number of commits in the cached commit graph and
+    /// its size on disk.
+    CacheStats,
+}
+
+/// `event-log` subcommands.
+#[derive(Debug, Parser)]
+pub enum EventLogSubcommand {
+    /// Print the events in the event log to stdout, one JSON object per line.
+    Show {
+        /// After printing the existing events, keep running and print new
+        /// events as they're added.
+        #[clap(action, short = 'f', long = "follow")]
+        follow: bool,
+    },
+
+    /// Print the contents of the event log as JSON to stdout.
+    Export,
+
+    /// Add the events from a JSON file previously produced by `git
+    /// branchless event-log export` to the event log.
+    Import {
+        /// The path to the JSON file to import.
+        #[clap(value_parser)]
+        path: PathBuf,
+    },
+
+    /// Add events for the reference updates recorded in the reflogs of `HEAD`
+    /// and the local branches which are missing from the event log, such as
+    /// ones made before `git-branchless` was initialized.
+    Backfill,
+}
+
 /// `snapshot` subcommands.
 #[derive(Debug, Parser)]
 pub enum SnapshotSubcommand {
@@ -13,40 +13,60 @@
 #![allow(clippy::too_many_arguments, clippy::blocks_in_if_conditions)]
 
 use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
+use std::process::Stdio;
 use std::time::SystemTime;
 
+use clap::ValueEnum;
+use cursive_core::theme::BaseColor;
+use cursive_core::utils::markup::StyledString;
+use eyre::Context;
 use git_branchless_invoke::CommandContext;
-use git_branchless_opts::{Revset, SmartlogArgs};
+use git_branchless_opts::{
+    Revset, SmartlogArgs, SmartlogDateFormat, SmartlogFilterOptions, SmartlogFormat, SmartlogOrder,
+};
+use itertools::Itertools;
 use lib::core::config::{
-    get_hint_enabled, get_hint_string, get_smartlog_default_revset, print_hint_suppression_notice,
-    Hint,
+    get_hint_enabled, get_hint_string, get_smartlog_collapse_threshold, get_smartlog_colors,
+    get_smartlog_commit_template, get_smartlog_default_revset, get_smartlog_reverse,
+    get_smartlog_stack_limit, get_smartlog_track_changes, print_hint_suppression_notice, Hint,
 };
 use lib::core::repo_ext::RepoExt;
 use lib::core::rewrite::find_rewrite_target;
-use lib::util::{ExitCode, EyreExitOr};
-use tracing::instrument;
+use lib::util::{get_sh, ExitCode, EyreExitOr};
+use tracing::{instrument, warn};
 
-use lib::core::dag::{CommitSet, Dag};
+use lib::core::dag::{record_observed_commits, CommitSet, Dag};
 use lib::core::effects::Effects;
 use lib::core::eventlog::{EventLogDb, EventReplayer};
-use lib::core::formatting::Pluralize;
+use lib::core::formatting::{Glyphs, Pluralize};
 use lib::core::node_descriptors::{
-    BranchesDescriptor, CommitMessageDescriptor, CommitOidDescriptor,
-    DifferentialRevisionDescriptor, ObsolescenceExplanationDescriptor, Redactor,
-    RelativeTimeDescriptor,
+    AbsoluteTimeDescriptor, AheadBehindDescriptor, BranchesDescriptor, CommitMessageDescriptor,
+    CommitOidDescriptor, DiffStatDescriptor, DifferentialRevisionDescriptor, NodeDescriptor,
+    NodeObject, ObsolescenceExplanationDescriptor, Redactor, RelativeTimeDescriptor,
+    SignatureDescriptor, StatusCommandDescriptor, TemplateDescriptor, WorkingCopyStatusDescriptor,
 };
-use lib::git::{GitRunInfo, Repo};
+use lib::git::{Commit, ConfigRead, GitRunInfo, GitRunOpts, NonZeroOid, Repo};
 
-pub use graph::{make_smartlog_graph, SmartlogGraph};
-pub use render::{render_graph, SmartlogOptions};
+pub use api::{make_commit_graph, CommitEdge, CommitEdgeKind, CommitGraph, CommitNode};
+pub use dot::render_graph_dot;
+pub use graph::{
+    collapse_linear_runs, load_graph_objects, make_smartlog_graph, make_smartlog_topology,
+    sort_children_chronologically, SmartlogGraph, SmartlogTopology,
+};
+pub use render::{
+    fit_lines_to_width, render_graph, render_graph_with_oids, GraphLine, SmartlogLongLines,
+    SmartlogOptions,
+};
 
 use git_branchless_revset::resolve_commits;
 
 mod graph {
-    use std::collections::HashMap;
+    use std::collections::{HashMap, HashSet};
 
-    use lib::core::gc::mark_commit_reachable;
+    use lib::core::gc::mark_commits_reachable;
+    use lib::core::rewrite::find_rewrite_target;
     use tracing::instrument;
 
     use lib::core::dag::{CommitSet, CommitVertex, Dag};
@@ -54,7 +74,7 @@ mod graph {
     use lib::core::eventlog::{EventCursor, EventReplayer};
     use lib::core::node_descriptors::NodeObject;
     use lib::git::{Commit, Time};
-    use lib::git::{NonZeroOid, Repo};
+    use lib::git::{MaybeZeroOid, NonZeroOid, Repo};
 
     #[derive(Debug)]
     pub struct AncestorInfo {
@@ -68,10 +88,13 @@ mod graph {
         pub is_merge_child: bool,
     }
     /// Node contained in the smartlog commit graph.
+    ///
+    /// `T` is the commit data stored for the node: its OID in a
+    /// [`SmartlogTopology`], or its commit object in a [`SmartlogGraph`].
     #[derive(Debug)]
-    pub struct Node<'repo> {
+    pub struct Node<T> {
         /// The underlying commit object.
-        pub object: NodeObject<'repo>,
+        pub object: T,
 
         /// The OIDs of the parent nodes in the smartlog commit graph.
         ///
@@ -117,12 +140,65 @@ mod graph {
         /// This allows us to indicate a "false head" to the user. Otherwise,
         /// this commit would look like a normal, descendant-less head.
         pub num_omitted_descendants: usize,
+
+        /// The merge parents of this commit which are not included in the
+        /// graph. These are rendered alongside the commit so that the topology
+        /// of merge commits isn't misrepresented.
+        pub omitted_merge_parents: Vec<T>,
+
+        /// For obsolete commits which were rewritten (such as by amending or
+        /// rebasing), the OID of the commit which they were rewritten into.
+        pub rewritten_as: Option<NonZeroOid>,
+    }
+
+    impl<T> Node<T> {
+        fn try_map_objects<U>(
+            self,
+            mut f: impl FnMut(T) -> eyre::Result<U>,
+        ) -> eyre::Result<Node<U>> {
+            let Node {
+                object,
+                parents,
+                children,
+                ancestor_info,
+                descendants,
+                is_main,
+                is_obsolete,
+                num_omitted_descendants,
+                omitted_merge_parents,
+                rewritten_as,
+            } = self;
+            Ok(Node {
+                object: f(object)?,
+                parents,
+                children,
+                ancestor_info,
+                descendants,
+                is_main,
+                is_obsolete,
+                num_omitted_descendants,
+                omitted_merge_parents: omitted_merge_parents
+                    .into_iter()
+                    .map(f)
+                    .collect::<eyre::Result<_>>()?,
+                rewritten_as,
+            })
+        }
+    }
+
+    /// The shape of the graph of commits that the user is working on, without
+    /// any commit metadata. Building it doesn't require reading any commit
+    /// objects, so it's suitable for callers which only need the topology.
+    #[derive(Debug)]
+    pub struct SmartlogTopology {
+        /// The nodes in the graph, whose objects are their own OIDs.
+        pub nodes: HashMap<NonZeroOid, Node<NonZeroOid>>,
     }
 
     /// Graph of commits that the user is working on.
     pub struct SmartlogGraph<'repo> {
         /// The nodes in the graph for use in rendering the smartlog.
-        pub nodes: HashMap<NonZeroOid, Node<'repo>>,
+        pub nodes: HashMap<NonZeroOid, Node<NodeObject<'repo>>>,
     }
 
     impl<'repo> SmartlogGraph<'repo> {
@@ -155,39 +231,44 @@ mod graph {
     /// between it and the main branch, those intermediate commits should be shown
     /// (or else you won't get a good idea of the line of development that happened
     /// for this commit since the main branch).
-    #[instrument]
-    fn build_graph<'repo>(
+    ///
+    /// `visibility_dag` is used to determine which descendants not included
+    /// in the graph are worth mentioning. It may differ from `dag` when
+    /// hidden commits are being shown.
+    #[instrument(skip(visibility_dag))]
+    fn build_topology(
         effects: &Effects,
-        repo: &'repo Repo,
         dag: &Dag,
+        visibility_dag: &Dag,
         commits: &CommitSet,
-    ) -> eyre::Result<SmartlogGraph<'repo>> {
+    ) -> eyre::Result<SmartlogTopology> {
         let commits_include_main =
             !dag.set_is_empty(&dag.main_branch_commit.intersection(commits))?;
-        let mut graph: HashMap<NonZeroOid, Node> = {
+        let mut graph: HashMap<NonZeroOid, Node<NonZeroOid>> = {
             let mut result = HashMap::new();
-            for vertex in dag.commit_set_to_vec(commits)? {
-                let vertex = CommitSet::from(vertex);
+            let commit_oids = dag.commit_set_to_vec(commits)?;
+            let main_branch_merge_bases = if commits_include_main {
+                // There may be several main branches, so find the nearest
+                // public ancestors rather than the common ancestors of
+                // every main branch head.
+                dag.query_main_branch_merge_bases_many(&commit_oids)?
+            } else {
+                HashMap::new()
+            };
+            for vertex_oid in commit_oids {
+                let vertex = CommitSet::from(vertex_oid);
                 let merge_bases = if commits_include_main {
-                    dag.query_gca_all(dag.main_branch_commit.union(&vertex))?
+                    main_branch_merge_bases[&vertex_oid].clone()
                 } else {
                     dag.query_gca_all(commits.union(&vertex))?
                 };
                 let vertices = vertex.union(&merge_bases);
 
                 for oid in dag.commit_set_to_vec(&vertices)? {
-                    let object = match repo.find_commit(oid)? {
-                        Some(commit) => NodeObject::Commit { commit },
-                        None => {
-                            // Assume that this commit was garbage collected.
-                            NodeObject::GarbageCollected { oid }
-                        }
-                    };
-
                     result.insert(
                         oid,
                         Node {
-                            object,
+                            object: oid,
                             parents: Vec::new(),  // populated below
                             children: Vec::new(), // populated below
                             ancestor_info: None,
@@ -195,6 +276,8 @@ mod graph {
                             is_main: dag.is_public_commit(oid)?,
                             is_obsolete: dag.set_contains(&dag.query_obsolete_commits(), oid)?,
                             num_omitted_descendants: 0, // populated below
+                            omitted_merge_parents: Vec::new(), // populated below
+                            rewritten_as: None,         // populated later
                         },
                     );
                 }
@@ -204,6 +287,7 @@ mod graph {
 
         let mut immediate_links: Vec<(NonZeroOid, NonZeroOid, bool)> = Vec::new();
         let mut non_immediate_links: Vec<(NonZeroOid, NonZeroOid, bool)> = Vec::new();
+        let mut omitted_merge_parent_links: Vec<(NonZeroOid, NonZeroOid)> = Vec::new();
 
         let non_main_node_oids =
             graph
@@ -211,6 +295,13 @@ mod graph {
                 .filter_map(|(child_oid, node)| if !node.is_main { Some(child_oid) } else { None });
 
         let graph_vertices: CommitSet = graph.keys().cloned().collect();
+        let excluded_parent_merge_bases = {
+            let non_main_vertices: CommitSet = non_main_node_oids.clone().copied().collect();
+            let excluded_parents = dag
+                .query_parents(non_main_vertices)?
+                .difference(&graph_vertices);
+            dag.query_main_branch_merge_bases_many(&dag.commit_set_to_vec(&excluded_parents)?)?
+        };
         for child_oid in non_main_node_oids {
             let parent_vertices = dag.query_parent_names(CommitVertex::from(*child_oid))?;
 
@@ -223,10 +314,11 @@ mod graph {
                         immediate_links.push((*child_oid, first_parent_oid, false));
                     }
                     for merge_parent_vertex in merge_parent_vertices {
+                        let merge_parent_oid = NonZeroOid::try_from(merge_parent_vertex.clone())?;
                         if dag.set_contains(&graph_vertices, merge_parent_vertex.clone())? {
-                            let merge_parent_oid =
-                                NonZeroOid::try_from(merge_parent_vertex.clone())?;
                             immediate_links.push((*child_oid, merge_parent_oid, true));
+                        } else {
+                            omitted_merge_parent_links.push((*child_oid, merge_parent_oid));
                         }
                     }
                 }
@@ -241,13 +333,10 @@ mod graph {
                 // Find the nearest ancestor that is included in the graph and
                 // also on the same branch.
 
-                let parent_set = CommitSet::from(excluded_parent_vertex);
-                let merge_base = dag.query_gca_one(dag.main_branch_commit.union(&parent_set))?;
-
-                let path_to_main_branch = match merge_base {
-                    Some(merge_base) => dag.query_range(CommitSet::from(merge_base), parent_set)?,
-                    None => CommitSet::empty(),
-                };
+                let excluded_parent_oid = NonZeroOid::try_from(excluded_parent_vertex)?;
+                let merge_bases = excluded_parent_merge_bases[&excluded_parent_oid].clone();
+                let path_to_main_branch =
+                    dag.query_range(merge_bases, CommitSet::from(excluded_parent_oid))?;
                 let nearest_branch_ancestor =
                     dag.query_heads_ancestors(path_to_main_branch.intersection(&graph_vertices))?;
 
@@ -288,6 +377,14 @@ mod graph {
                 })
         }
 
+        for (child_oid, merge_parent_oid) in omitted_merge_parent_links {
+            graph
+                .get_mut(&child_oid)
+                .unwrap()
+                .omitted_merge_parents
+                .push(merge_parent_oid);
+        }
+
         for (oid, node) in graph.iter_mut() {
             let oid_set = CommitSet::from(*oid);
             let is_main_head = !dag.set_is_empty(&dag.main_branch_commit.intersection(&oid_set))?;
@@ -303,12 +400,39 @@ mod graph {
             // false head if it has *any* visible descendants.
             let descendants_not_in_graph =
                 dag.query_descendants(oid_set.clone())?.difference(&oid_set);
-            let descendants_not_in_graph = dag.filter_visible_commits(descendants_not_in_graph)?;
+            let descendants_not_in_graph =
+                visibility_dag.filter_visible_commits(descendants_not_in_graph)?;
 
             node.num_omitted_descendants = dag.set_count(&descendants_not_in_graph)?;
         }
 
-        Ok(SmartlogGraph { nodes: graph })
+        Ok(SmartlogTopology { nodes: graph })
+    }
+
+    /// Load the commit objects for the nodes in `topology`, so that the graph
+    /// can be rendered.
+    #[instrument(skip(topology))]
+    pub fn load_graph_objects<'repo>(
+        repo: &'repo Repo,
+        topology: SmartlogTopology,
+    ) -> eyre::Result<SmartlogGraph<'repo>> {
+        let load_object = |oid: NonZeroOid| -> eyre::Result<NodeObject<'repo>> {
+            match repo.find_commit(oid)? {
+                Some(commit) => Ok(NodeObject::Commit { commit }),
+                None => {
+                    // Assume that this commit was garbage collected.
+                    Ok(NodeObject::GarbageCollected { oid })
+                }
+            }
+        };
+        let nodes = topology
+            .nodes
+            .into_iter()
+            .map(|(oid, node)| Ok((oid, node.try_map_objects(load_object)?)))
+            .collect::<eyre::Result<_>>()?;
+        let mut graph = SmartlogGraph { nodes };
+        sort_children(&mut graph);
+        Ok(graph)
     }
 
     /// Sort children nodes of the commit graph in a standard order, for determinism
@@ -337,7 +461,187 @@ mod graph {
         }
     }
 
+    /// Sort the children of each node by the time of the most recent commit
+    /// among their descendants in the graph, rather than by their own commit
+    /// times. This keeps the most recently updated stacks rendered last.
+    pub fn sort_children_chronologically(graph: &mut SmartlogGraph) {
+        let mut latest_times: HashMap<NonZeroOid, Option<Time>> = HashMap::new();
+        for start_oid in graph.nodes.keys() {
+            // Walk the graph iteratively, since stacks can be deep.
+            let mut stack = vec![(*start_oid, false)];
+            while let Some((oid, children_visited)) = stack.pop() {
+                if latest_times.contains_key(&oid) {
+                    continue;
+                }
+                let node = &graph.nodes[&oid];
+                let child_oids = node
+                    .children
+                    .iter()
+                    .chain(node.descendants.iter())
+                    .map(|child| child.oid);
+                if children_visited {
+                    let own_time = match &node.object {
+                        NodeObject::Commit { commit } => Some(commit.get_time()),
+                        NodeObject::GarbageCollected { oid: _ } => None,
+                    };
+                    let latest_time = child_oids
+                        .filter_map(|child_oid| latest_times.get(&child_oid).cloned().flatten())
+                        .chain(own_time)
+                        .max();
+                    latest_times.insert(oid, latest_time);
+                } else {
+                    stack.push((oid, true));
+                    stack.extend(
+                        child_oids
+                            .filter(|child_oid| !latest_times.contains_key(child_oid))
+                            .map(|child_oid| (child_oid, false)),
+                    );
+                }
+            }
+        }
+
+        for node in graph.nodes.values_mut() {
+            for children in [&mut node.children, &mut node.descendants] {
+                children.sort_by_key(
+                    |ChildInfo {
+                         oid,
+                         is_merge_child,
+                     }| {
+                        (latest_times[oid].clone(), *is_merge_child, oid.to_string())
+                    },
+                );
+            }
+        }
+    }
+
+    /// The number of commits to keep visible at each end of a collapsed run of
+    /// commits.
+    const COLLAPSED_RUN_CONTEXT: usize = 2;
+
+    /// Collapse linear runs of more than `threshold` draft commits in the
+    /// graph, so that only the first and last few commits of each run are
+    /// rendered. The removed commits are represented as omitted commits
+    /// between the remaining ones. Commits in `keep_oids` (such as `HEAD` or
+    /// commits with branches) are never removed and break up runs.
+    #[instrument(skip(graph))]
+    pub fn collapse_linear_runs(
+        graph: &mut SmartlogGraph,
+        keep_oids: &HashSet<NonZeroOid>,
+        threshold: usize,
+    ) {
+        if threshold == 0 {
+            return;
+        }
+
+        // A commit is part of a linear run if it has exactly one parent and
+        // one child in the graph, and nothing else is attached to it.
+        let is_linear = |oid: &NonZeroOid| -> bool {
+            let node = match graph.nodes.get(oid) {
+                Some(node) => node,
+                None => return false,
+            };
+            !node.is_main
+                && !keep_oids.contains(oid)
+                && node.parents.len() == 1
+                && node.ancestor_info.is_none()
+                && node.descendants.is_empty()
+                && node.omitted_merge_parents.is_empty()
+                && matches!(
+                    node.children.as_slice(),
+                    [ChildInfo {
+                        oid: _,
+                        is_merge_child: false
+                    }]
+                )
+        };
+
+        let mut runs: Vec<Vec<NonZeroOid>> = Vec::new();
+        let mut run_starts: Vec<NonZeroOid> = graph
+            .nodes
+            .iter()
+            .filter(|(oid, node)| is_linear(oid) && !is_linear(&node.parents[0]))
+            .map(|(oid, _node)| *oid)
+            .collect();
+        run_starts.sort();
+        for run_start in run_starts {
+            let mut run = vec![run_start];
+            loop {
+                let last_oid = run[run.len() - 1];
+                let next_oid = graph.nodes[&last_oid].children[0].oid;
+                if is_linear(&next_oid) {
+                    run.push(next_oid);
+                } else {
+                    break;
+                }
+            }
+            if run.len() > threshold && run.len() > 2 * COLLAPSED_RUN_CONTEXT {
+                runs.push(run);
+            }
+        }
+
+        for run in runs {
+            let removed_oids = &run[COLLAPSED_RUN_CONTEXT..run.len() - COLLAPSED_RUN_CONTEXT];
+            let first_removed_oid = removed_oids[0];
+            let last_removed_oid = removed_oids[removed_oids.len() - 1];
+            let ancestor_oid = graph.nodes[&first_removed_oid].parents[0];
+            let descendant_oid = graph.nodes[&last_removed_oid].children[0].oid;
+            for oid in removed_oids {
+                graph.nodes.remove(oid);
+            }
+
+            let ancestor_node = graph.nodes.get_mut(&ancestor_oid).unwrap();
+            ancestor_node.children.clear();
+            ancestor_node.descendants.push(ChildInfo {
+                oid: descendant_oid,
+                is_merge_child: false,
+            });
+
+            let descendant_node = graph.nodes.get_mut(&descendant_oid).unwrap();
+            descendant_node.parents.clear();
+            descendant_node.ancestor_info = Some(AncestorInfo {
+                oid: ancestor_oid,
+                distance: removed_oids.len(),
+            });
+        }
+    }
+
+    /// Construct the topology of the smartlog graph for the repo, without
+    /// loading any commit metadata. See [`make_smartlog_graph`].
+    #[instrument]
+    pub fn make_smartlog_topology(
+        effects: &Effects,
+        repo: &Repo,
+        dag: &Dag,
+        commits: &CommitSet,
+        exact: bool,
+        show_hidden_commits: bool,
+    ) -> eyre::Result<SmartlogTopology> {
+        let (effects, _progress) = effects.start_operation(OperationType::WalkCommits);
+
+        // HEAD and main head are automatically included unless `exact` is set
+        let commits = if exact {
+            commits.clone()
+        } else {
+            commits
+                .union(&dag.head_commit)
+                .union(&dag.main_branch_commit)
+        };
+
+        mark_commits_reachable(repo, &dag.commit_set_to_vec(&commits)?)?;
+
+        if show_hidden_commits {
+            let dag_with_hidden = dag.clear_obsolete_commits(repo)?;
+            build_topology(&effects, dag, &dag_with_hidden, &commits)
+        } else {
+            build_topology(&effects, dag, dag, &commits)
+        }
+    }
+
     /// Construct the smartlog graph for the repo.
+    ///
+    /// If `show_hidden_commits` is set, then hidden commits are kept when
+    /// considering which descendants have been omitted from the graph.
+    /// Obsolete commits in `commits` are always included in the graph.
     #[instrument]
     pub fn make_smartlog_graph<'repo>(
         effects: &Effects,
@@ -347,29 +651,353 @@ mod graph {
         event_cursor: EventCursor,
         commits: &CommitSet,
         exact: bool,
+        show_hidden_commits: bool,
     ) -> eyre::Result<SmartlogGraph<'repo>> {
         let (effects, _progress) = effects.start_operation(OperationType::MakeGraph);
+        let mut topology =
+            make_smartlog_topology(&effects, repo, dag, commits, exact, show_hidden_commits)?;
+        for (oid, node) in topology.nodes.iter_mut() {
+            if node.is_obsolete {
+                node.rewritten_as = find_rewrite_target(event_replayer, event_cursor, *oid)
+                    .and_then(|rewritten_oid| match rewritten_oid {
+                        MaybeZeroOid::NonZero(rewritten_oid) => Some(rewritten_oid),
+                        MaybeZeroOid::Zero => None,
+                    });
+            }
+        }
+        load_graph_objects(repo, topology)
+    }
+}
 
-        let mut graph = {
-            let (effects, _progress) = effects.start_operation(OperationType::WalkCommits);
+mod api {
+    use serde::{Deserialize, Serialize};
+    use tracing::instrument;
 
-            // HEAD and main head are automatically included unless `exact` is set
-            let commits = if exact {
-                commits.clone()
-            } else {
-                commits
-                    .union(&dag.head_commit)
-                    .union(&dag.main_branch_commit)
+    use git_branchless_opts::{ResolveRevsetOptions, Revset};
+    use git_branchless_revset::resolve_commits;
+    use lib::core::config::get_smartlog_default_revset;
+    use lib::core::dag::{CommitSet, Dag};
+    use lib::core::effects::Effects;
+    use lib::core::eventlog::{EventLogDb, EventReplayer};
+    use lib::core::node_descriptors::NodeObject;
+    use lib::core::repo_ext::{RepoExt, RepoReferencesSnapshot};
+    use lib::git::{CategorizedReferenceName, Repo};
+
+    use super::graph::{make_smartlog_graph, SmartlogGraph};
+
+    /// A commit in a [`CommitGraph`]. Unlike the nodes of the smartlog graph,
+    /// this owns all of its data, so it can be stored or serialized freely.
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct CommitNode {
+        /// The full OID of the commit.
+        pub oid: String,
+
+        /// Whether this commit is part of the main branch.
+        pub is_main: bool,
+
+        /// Whether this commit has been marked as obsolete.
+        pub is_obsolete: bool,
+
+        /// Whether `HEAD` points to this commit.
+        pub is_head: bool,
+
+        /// Whether the main branch points to this commit.
+        pub is_main_branch_head: bool,
+
+        /// The names of the local branches which point to this commit, without
+        /// the `refs/heads/` prefix.
+        pub branches: Vec<String>,
+
+        /// The first line of the commit message. This is `None` if the commit
+        /// has been garbage-collected.
+        pub summary: Option<String>,
+
+        /// The number of visible descendants of this commit which are not
+        /// included in the graph.
+        pub num_omitted_descendants: usize,
+
+        /// If this commit is obsolete because it was rewritten, the OID of the
+        /// commit which it was rewritten into.
+        pub rewritten_as: Option<String>,
+    }
+
+    /// The relationship between the two commits of a [`CommitEdge`].
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum CommitEdgeKind {
+        /// The parent commit is an immediate parent of the child commit.
+        Parent,
+
+        /// The parent commit is an ancestor of the child commit, but the
+        /// commits in between are not included in the graph.
+        Ancestor,
+
+        /// The child commit is the result of rewriting the parent commit, such
+        /// as by amending or rebasing it. The parent commit is obsolete.
+        Rewritten,
+    }
+
+    /// An edge from a commit to one of its parents or ancestors in a
+    /// [`CommitGraph`].
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct CommitEdge {
+        /// The OID of the parent or ancestor commit.
+        pub parent: String,
+
+        /// The OID of the child commit.
+        pub child: String,
+
+        /// How the two commits are related.
+        pub kind: CommitEdgeKind,
+    }
+
+    /// The commits which are visible in the smartlog and the relationships
+    /// between them. This is the stable public form of the smartlog graph,
+    /// intended for use by other tools.
+    #[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct CommitGraph {
+        /// The commits in the graph, in topological order (parents first).
+        pub nodes: Vec<CommitNode>,
+
+        /// The edges between commits in the graph.
+        pub edges: Vec<CommitEdge>,
+    }
+
+    /// Convert a smartlog graph into a [`CommitGraph`].
+    #[instrument(skip(graph))]
+    pub(crate) fn make_commit_graph_from_smartlog_graph(
+        dag: &Dag,
+        graph: &SmartlogGraph,
+        references_snapshot: &RepoReferencesSnapshot,
+    ) -> eyre::Result<CommitGraph> {
+        let oids = dag.sort(&graph.nodes.keys().copied().collect())?;
+        let main_oids: CommitSet = graph
+            .nodes
+            .iter()
+            .filter_map(|(oid, node)| if node.is_main { Some(*oid) } else { None })
+            .collect();
+        let mut result = CommitGraph::default();
+        for oid in oids {
+            let node = &graph.nodes[&oid];
+            let mut branches: Vec<String> = references_snapshot
+                .branch_oid_to_names
+                .get(&oid)
+                .into_iter()
+                .flatten()
+                .map(|name| CategorizedReferenceName::new(name).render_suffix())
+                .collect();
+            branches.sort();
+            let summary = match &node.object {
+                NodeObject::Commit { commit } => Some(commit.get_summary()?.to_string()),
+                NodeObject::GarbageCollected { oid: _ } => None,
             };
+            result.nodes.push(CommitNode {
+                oid: oid.to_string(),
+                is_main: node.is_main,
+                is_obsolete: node.is_obsolete,
+                is_head: references_snapshot.head_oid == Some(oid),
+                is_main_branch_head: references_snapshot.main_branch_oid == oid,
+                branches,
+                summary,
+                num_omitted_descendants: node.num_omitted_descendants,
+                rewritten_as: node.rewritten_as.map(|oid| oid.to_string()),
+            });
 
-            for oid in dag.commit_set_to_vec(&commits)? {
-                mark_commit_reachable(repo, oid)?;
+            result
+                .edges
+                .extend(node.parents.iter().map(|parent_oid| CommitEdge {
+                    parent: parent_oid.to_string(),
+                    child: oid.to_string(),
+                    kind: CommitEdgeKind::Parent,
+                }));
+            if let Some(ancestor_info) = &node.ancestor_info {
+                result.edges.push(CommitEdge {
+                    parent: ancestor_info.oid.to_string(),
+                    child: oid.to_string(),
+                    kind: CommitEdgeKind::Ancestor,
+                });
             }
+            if node.is_main {
+                // The smartlog graph doesn't link main branch commits to each
+                // other, since they're always rendered in a single column, so
+                // link each one to its nearest main branch ancestors here.
+                let parent_oids = dag.query_parents(CommitSet::from(oid))?;
+                let ancestor_oids = dag.query_heads_ancestors(
+                    dag.query_ancestors(parent_oids.clone())?
+                        .intersection(&main_oids),
+                )?;
+                for ancestor_oid in dag.commit_set_to_vec(&ancestor_oids)? {
+                    let kind = if dag.set_contains(&parent_oids, ancestor_oid)? {
+                        CommitEdgeKind::Parent
+                    } else {
+                        CommitEdgeKind::Ancestor
+                    };
+                    result.edges.push(CommitEdge {
+                        parent: ancestor_oid.to_string(),
+                        child: oid.to_string(),
+                        kind,
+                    });
+                }
+            }
+            if let Some(rewritten_oid) = node.rewritten_as {
+                if graph.nodes.contains_key(&rewritten_oid) {
+                    result.edges.push(CommitEdge {
+                        parent: oid.to_string(),
+                        child: rewritten_oid.to_string(),
+                        kind: CommitEdgeKind::Rewritten,
+                    });
+                }
+            }
+        }
+        Ok(result)
+    }
 
-            build_graph(&effects, repo, dag, &commits)?
+    /// Build the [`CommitGraph`] for the commits in `revset`, as it would be
+    /// displayed by `git smartlog`. If `revset` is `None`, the configured
+    /// default smartlog revset is used.
+    #[instrument]
+    pub fn make_commit_graph(
+        effects: &Effects,
+        repo: &Repo,
+        revset: Option<Revset>,
+        resolve_revset_options: &ResolveRevsetOptions,
+    ) -> eyre::Result<CommitGraph> {
+        let references_snapshot = repo.get_references_snapshot()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_replayer =
+            EventReplayer::from_event_log_db_with_snapshot(effects, repo, &event_log_db)?;
+        let event_cursor = event_replayer.make_default_cursor();
+        let mut dag = Dag::open_and_sync(
+            effects,
+            repo,
+            &event_replayer,
+            event_cursor,
+            &references_snapshot,
+        )?;
+
+        let revset = match revset {
+            Some(revset) => revset,
+            None => Revset(get_smartlog_default_revset(repo)?),
         };
-        sort_children(&mut graph);
-        Ok(graph)
+        let commits =
+            match resolve_commits(effects, repo, &mut dag, &[revset], resolve_revset_options)?
+                .as_slice()
+            {
+                [commit_set] => commit_set.clone(),
+                other => {
+                    eyre::bail!("Expected exactly 1 result from resolve commits, got: {other:?}")
+                }
+            };
+
+        let graph = make_smartlog_graph(
+            effects,
+            repo,
+            &dag,
+            &event_replayer,
+            event_cursor,
+            &commits,
+            false,
+            resolve_revset_options.show_hidden_commits,
+        )?;
+        make_commit_graph_from_smartlog_graph(&dag, &graph, &references_snapshot)
+    }
+}
+
+mod dot {
+    use std::fmt::Write;
+
+    use itertools::Itertools;
+    use tracing::instrument;
+
+    use lib::core::dag::Dag;
+    use lib::core::node_descriptors::NodeObject;
+    use lib::core::repo_ext::RepoReferencesSnapshot;
+    use lib::git::CategorizedReferenceName;
+
+    use super::graph::SmartlogGraph;
+
+    /// Escape a string for use inside a double-quoted DOT identifier.
+    fn escape(s: &str) -> String {
+        s.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    }
+
+    /// Render the smartlog graph in the Graphviz DOT language. Edges point
+    /// from parent commits to child commits. Main branch commits are drawn as
+    /// boxes, obsolete commits are dashed and greyed out, and the commit at
+    /// `HEAD` is drawn with a thicker border.
+    #[instrument(skip(graph))]
+    pub fn render_graph_dot(
+        dag: &Dag,
+        graph: &SmartlogGraph,
+        references_snapshot: &RepoReferencesSnapshot,
+    ) -> eyre::Result<String> {
+        let mut output = String::new();
+        writeln!(output, "digraph smartlog {{")?;
+
+        let oids = dag.sort(&graph.nodes.keys().copied().collect())?;
+        for oid in oids.iter() {
+            let node = &graph.nodes[oid];
+
+            let mut label = match &node.object {
+                NodeObject::Commit { commit } => commit.get_short_oid()?,
+                NodeObject::GarbageCollected { oid } => oid.to_string(),
+            };
+            if let Some(names) = references_snapshot.branch_oid_to_names.get(oid) {
+                let names = names
+                    .iter()
+                    .map(|name| CategorizedReferenceName::new(name).render_suffix())
+                    .sorted()
+                    .join(", ");
+                write!(label, " ({names})")?;
+            }
+            match &node.object {
+                NodeObject::Commit { commit } => write!(label, " {}", commit.get_summary()?)?,
+                NodeObject::GarbageCollected { oid: _ } => write!(label, " <garbage collected>")?,
+            }
+            if node.num_omitted_descendants > 0 {
+                write!(
+                    label,
+                    "\n({} omitted descendants)",
+                    node.num_omitted_descendants
+                )?;
+            }
+
+            let mut attributes = vec![format!("label=\"{}\"", escape(&label))];
+            attributes.push(if node.is_main {
+                "shape=box".to_string()
+            } else {
+                "shape=ellipse".to_string()
+            });
+            if node.is_obsolete {
+                attributes.push("style=dashed".to_string());
+                attributes.push("color=gray50".to_string());
+                attributes.push("fontcolor=gray50".to_string());
+            }
+            if references_snapshot.head_oid == Some(*oid) {
+                attributes.push("penwidth=2".to_string());
+            }
+            writeln!(output, "  \"{oid}\" [{}];", attributes.join(", "))?;
+        }
+
+        for oid in oids.iter() {
+            let node = &graph.nodes[oid];
+            for parent_oid in node.parents.iter() {
+                writeln!(output, "  \"{parent_oid}\" -> \"{oid}\";")?;
+            }
+            if let Some(ancestor_info) = &node.ancestor_info {
+                writeln!(
+                    output,
+                    "  \"{}\" -> \"{oid}\" [style=dotted, label=\"{} omitted\"];",
+                    ancestor_info.oid, ancestor_info.distance
+                )?;
+            }
+        }
+
+        writeln!(output, "}}")?;
+        Ok(output)
     }
 }
 
@@ -379,16 +1007,21 @@ mod render {
 
     use cursive_core::theme::{BaseColor, Effect};
     use cursive_core::utils::markup::StyledString;
+    use cursive_core::utils::span::Span;
     use tracing::instrument;
 
+    use lib::core::config::{get_smartlog_colors, SmartlogColors};
     use lib::core::dag::{CommitSet, Dag};
     use lib::core::effects::Effects;
-    use lib::core::formatting::{set_effect, Pluralize};
+    use lib::core::formatting::{set_color, set_effect, Pluralize};
     use lib::core::formatting::{Glyphs, StyledStringBuilder};
     use lib::core::node_descriptors::{render_node_descriptors, NodeDescriptor};
     use lib::git::{NonZeroOid, Repo};
 
-    use git_branchless_opts::{ResolveRevsetOptions, Revset};
+    use git_branchless_opts::{
+        ResolveRevsetOptions, Revset, SmartlogDateFormat, SmartlogFilterOptions, SmartlogFormat,
+        SmartlogOrder,
+    };
 
     use super::graph::{AncestorInfo, ChildInfo, SmartlogGraph};
 
@@ -421,23 +1054,23 @@ mod render {
                 _ => return lhs_oid.cmp(rhs_oid),
             };
 
-            let merge_base_oid =
-                dag.query_gca_one(vec![*lhs_oid, *rhs_oid].into_iter().collect::<CommitSet>());
-            let merge_base_oid = match merge_base_oid {
-                Err(_) => return lhs_oid.cmp(rhs_oid),
-                Ok(None) => None,
-                Ok(Some(merge_base_oid)) => NonZeroOid::try_from(merge_base_oid).ok(),
-            };
+            // Only the ancestry relationship is needed here, not the full
+            // merge-base.
+            let is_ancestor = (
+                dag.query_is_ancestor(*lhs_oid, *rhs_oid),
+                dag.query_is_ancestor(*rhs_oid, *lhs_oid),
+            );
+            match is_ancestor {
+                (Err(_), _) | (_, Err(_)) => lhs_oid.cmp(rhs_oid),
 
-            match merge_base_oid {
                 // lhs was topologically first, so it should be sorted earlier in the list.
-                Some(merge_base_oid) if merge_base_oid == *lhs_oid => Ordering::Less,
-                Some(merge_base_oid) if merge_base_oid == *rhs_oid => Ordering::Greater,
+                (Ok(true), _) => Ordering::Less,
+                (_, Ok(true)) => Ordering::Greater,
 
                 // The commits were not orderable (pathlogical situation). Let's
                 // just order them by timestamp in that case to produce a consistent
                 // and reasonable guess at the intended topological ordering.
-                Some(_) | None => match lhs_commit.get_time().cmp(&rhs_commit.get_time()) {
+                (Ok(false), Ok(false)) => match lhs_commit.get_time().cmp(&rhs_commit.get_time()) {
                     result @ Ordering::Less | result @ Ordering::Greater => result,
                     Ordering::Equal => lhs_oid.cmp(rhs_oid),
                 },
@@ -448,23 +1081,36 @@ mod render {
         root_commit_oids
     }
 
+    /// Render the provided graph characters in the configured graph color.
+    fn style_graph(colors: &SmartlogColors, text: impl Into<String>) -> StyledString {
+        match colors.graph {
+            Some(color) => StyledString::styled(text, color),
+            None => StyledString::plain(text),
+        }
+    }
+
+    /// A rendered line of the graph, along with the OID of the commit it
+    /// describes, if any.
+    pub type GraphLine = (StyledString, Option<NonZeroOid>);
+
     #[instrument(skip(commit_descriptors, graph))]
     fn get_child_output(
         glyphs: &Glyphs,
+        colors: &SmartlogColors,
         graph: &SmartlogGraph,
         root_oids: &[NonZeroOid],
         commit_descriptors: &mut [&mut dyn NodeDescriptor],
         head_oid: Option<NonZeroOid>,
         current_oid: NonZeroOid,
         last_child_line_char: Option<&str>,
-    ) -> eyre::Result<Vec<StyledString>> {
+    ) -> eyre::Result<Vec<GraphLine>> {
         let current_node = &graph.nodes[&current_oid];
         let is_head = Some(current_oid) == head_oid;
 
         let mut lines = vec![];
 
         if let Some(AncestorInfo { oid: _, distance }) = current_node.ancestor_info {
-            lines.push(
+            lines.push((
                 StyledStringBuilder::new()
                     .append_plain(glyphs.commit_omitted)
                     .append_plain(" ")
@@ -478,38 +1124,61 @@ mod render {
                         Effect::Dim,
                     )
                     .build(),
-            );
-            lines.push(StyledString::plain(glyphs.vertical_ellipsis));
+                None,
+            ));
+            lines.push((style_graph(colors, glyphs.vertical_ellipsis), None));
         };
 
-        if let [_, merge_parents @ ..] = current_node.parents.as_slice() {
-            if !merge_parents.is_empty() {
-                for merge_parent_oid in merge_parents {
-                    let merge_parent_node = &graph.nodes[merge_parent_oid];
-                    lines.push(
-                        StyledStringBuilder::new()
-                            .append_plain(last_child_line_char.unwrap_or(glyphs.line))
-                            .append_plain(" ")
-                            .append_styled(
-                                format!("{} (merge) ", glyphs.commit_merge),
-                                BaseColor::Blue.dark(),
-                            )
-                            .append(render_node_descriptors(
-                                glyphs,
-                                &merge_parent_node.object,
-                                commit_descriptors,
-                            )?)
-                            .build(),
-                    );
+        {
+            let merge_parents = current_node.parents.get(1..).unwrap_or_default();
+            let merge_parent_objects = merge_parents
+                .iter()
+                .map(|merge_parent_oid| (&graph.nodes[merge_parent_oid].object, false))
+                .chain(
+                    current_node
+                        .omitted_merge_parents
+                        .iter()
+                        .map(|object| (object, true)),
+                )
+                .collect::<Vec<_>>();
+            if !merge_parent_objects.is_empty() {
+                for (merge_parent_object, is_omitted) in merge_parent_objects {
+                    let line = StyledStringBuilder::new()
+                        .append(style_graph(
+                            colors,
+                            last_child_line_char.unwrap_or(glyphs.line),
+                        ))
+                        .append_plain(" ")
+                        .append_styled(
+                            format!("{} (merge) ", glyphs.commit_merge),
+                            BaseColor::Blue.dark(),
+                        )
+                        .append(render_node_descriptors(
+                            glyphs,
+                            merge_parent_object,
+                            commit_descriptors,
+                        )?)
+                        .build();
+                    // Merge parents which aren't otherwise in the graph are
+                    // dimmed, since they won't appear anywhere else.
+                    let line = if is_omitted {
+                        set_effect(line, Effect::Dim)
+                    } else {
+                        line
+                    };
+                    lines.push((line, None));
                 }
-                lines.push(StyledString::plain(format!(
-                    "{}{}",
-                    glyphs.line_with_offshoot, glyphs.merge,
-                )));
+                lines.push((
+                    style_graph(
+                        colors,
+                        format!("{}{}", glyphs.line_with_offshoot, glyphs.merge),
+                    ),
+                    None,
+                ));
             }
         }
 
-        lines.push({
+        let commit_line = {
             let cursor = match (current_node.is_main, current_node.is_obsolete, is_head) {
                 (false, false, false) => glyphs.commit_visible,
                 (false, false, true) => glyphs.commit_visible_head,
@@ -520,22 +1189,40 @@ mod render {
                 (true, true, false) => glyphs.commit_main_obsolete,
                 (true, true, true) => glyphs.commit_main_obsolete_head,
             };
+            let cursor_color = if is_head {
+                colors.head
+            } else if current_node.is_main {
+                colors.main
+            } else {
+                None
+            };
+            let cursor = match cursor_color {
+                Some(color) => StyledString::styled(cursor, color),
+                None => StyledString::plain(cursor),
+            };
             let text = render_node_descriptors(glyphs, &current_node.object, commit_descriptors)?;
             let first_line = StyledStringBuilder::new()
-                .append_plain(cursor)
+                .append(cursor)
                 .append_plain(" ")
                 .append(text)
                 .build();
             if is_head {
                 set_effect(first_line, Effect::Bold)
+            } else if current_node.is_obsolete {
+                let first_line = match colors.hidden {
+                    Some(color) => set_color(first_line, color),
+                    None => first_line,
+                };
+                set_effect(first_line, Effect::Dim)
             } else {
                 first_line
             }
-        });
+        };
+        lines.push((commit_line, Some(current_oid)));
 
         if current_node.num_omitted_descendants > 0 {
-            lines.push(StyledString::plain(glyphs.vertical_ellipsis));
-            lines.push(
+            lines.push((style_graph(colors, glyphs.vertical_ellipsis), None));
+            lines.push((
                 StyledStringBuilder::new()
                     .append_plain(glyphs.commit_omitted)
                     .append_plain(" ")
@@ -549,7 +1236,8 @@ mod render {
                         Effect::Dim,
                     )
                     .build(),
-            );
+                None,
+            ));
         };
 
         let children: Vec<ChildInfo> = current_node
@@ -588,7 +1276,7 @@ mod render {
                 //     "{}{}",
                 //     glyphs.line_with_offshoot, glyphs.split
                 // )));
-                lines.push(
+                lines.push((
                     StyledStringBuilder::new()
                         // .append_plain(last_child_line_char.unwrap_or(glyphs.line))
                         // .append_plain(" ")
@@ -602,23 +1290,29 @@ mod render {
                             commit_descriptors,
                         )?)
                         .build(),
-                );
+                    None,
+                ));
                 continue;
             }
 
             let is_last_child = child_idx == (children.len() + descendants.len()) - 1;
-            lines.push(StyledString::plain(
-                if !is_last_child || last_child_line_char.is_some() {
-                    format!("{}{}", glyphs.line_with_offshoot, glyphs.split)
-                } else if current_node.descendants.is_empty() {
-                    glyphs.line.to_string()
-                } else {
-                    glyphs.vertical_ellipsis.to_string()
-                },
+            lines.push((
+                style_graph(
+                    colors,
+                    if !is_last_child || last_child_line_char.is_some() {
+                        format!("{}{}", glyphs.line_with_offshoot, glyphs.split)
+                    } else if current_node.descendants.is_empty() {
+                        glyphs.line.to_string()
+                    } else {
+                        glyphs.vertical_ellipsis.to_string()
+                    },
+                ),
+                None,
             ));
 
             let child_output = get_child_output(
                 glyphs,
+                colors,
                 graph,
                 root_oids,
                 commit_descriptors,
@@ -626,22 +1320,24 @@ mod render {
                 *child_oid,
                 None,
             )?;
-            for child_line in child_output {
+            for (child_line, child_line_oid) in child_output {
                 let line = if is_last_child {
                     match last_child_line_char {
                         Some(last_child_line_char) => StyledStringBuilder::new()
-                            .append_plain(format!("{last_child_line_char} "))
+                            .append(style_graph(colors, last_child_line_char))
+                            .append_plain(" ")
                             .append(child_line)
                             .build(),
                         None => child_line,
                     }
                 } else {
                     StyledStringBuilder::new()
-                        .append_plain(format!("{} ", glyphs.line))
+                        .append(style_graph(colors, glyphs.line))
+                        .append_plain(" ")
                         .append(child_line)
                         .build()
                 };
-                lines.push(line)
+                lines.push((line, child_line_oid))
             }
         }
         Ok(lines)
@@ -651,12 +1347,13 @@ mod render {
     #[instrument(skip(commit_descriptors, graph))]
     fn get_output(
         glyphs: &Glyphs,
+        colors: &SmartlogColors,
         dag: &Dag,
         graph: &SmartlogGraph,
         commit_descriptors: &mut [&mut dyn NodeDescriptor],
         head_oid: Option<NonZeroOid>,
         root_oids: &[NonZeroOid],
-    ) -> eyre::Result<Vec<StyledString>> {
+    ) -> eyre::Result<Vec<GraphLine>> {
         let mut lines = Vec::new();
 
         // Determine if the provided OID has the provided parent OID as a parent.
@@ -673,15 +1370,15 @@ mod render {
         for (root_idx, root_oid) in root_oids.iter().enumerate() {
             if !dag.set_is_empty(&dag.query_parents(CommitSet::from(*root_oid))?)? {
                 let line = if root_idx > 0 && has_real_parent(*root_oid, root_oids[root_idx - 1])? {
-                    StyledString::plain(glyphs.line.to_owned())
+                    style_graph(colors, glyphs.line)
                 } else {
-                    StyledString::plain(glyphs.vertical_ellipsis.to_owned())
+                    style_graph(colors, glyphs.vertical_ellipsis)
                 };
-                lines.push(line);
+                lines.push((line, None));
             } else if root_idx > 0 {
                 // Pathological case: multiple topologically-unrelated roots.
                 // Separate them with a newline.
-                lines.push(StyledString::new());
+                lines.push((StyledString::new(), None));
             }
 
             let last_child_line_char = {
@@ -696,6 +1393,7 @@ mod render {
 
             let child_output = get_child_output(
                 glyphs,
+                colors,
                 graph,
                 root_oids,
                 commit_descriptors,
@@ -719,9 +1417,28 @@ mod render {
         head_oid: Option<NonZeroOid>,
         commit_descriptors: &mut [&mut dyn NodeDescriptor],
     ) -> eyre::Result<Vec<StyledString>> {
+        let lines =
+            render_graph_with_oids(effects, repo, dag, graph, head_oid, commit_descriptors)?;
+        Ok(lines.into_iter().map(|(line, _oid)| line).collect())
+    }
+
+    /// Same as `render_graph`, but also returns the OID of the commit
+    /// described by each line, for lines which correspond to a node in the
+    /// graph.
+    #[instrument(skip(commit_descriptors, graph))]
+    pub fn render_graph_with_oids(
+        effects: &Effects,
+        repo: &Repo,
+        dag: &Dag,
+        graph: &SmartlogGraph,
+        head_oid: Option<NonZeroOid>,
+        commit_descriptors: &mut [&mut dyn NodeDescriptor],
+    ) -> eyre::Result<Vec<GraphLine>> {
         let root_oids = split_commit_graph_by_roots(repo, dag, graph);
+        let colors = get_smartlog_colors(repo)?;
         let lines = get_output(
             effects.get_glyphs(),
+            &colors,
             dag,
             graph,
             commit_descriptors,
@@ -731,6 +1448,201 @@ mod render {
         Ok(lines)
     }
 
+    /// How to display lines which are wider than the terminal.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum SmartlogLongLines {
+        /// Print long lines as-is, and let the terminal wrap them.
+        #[default]
+        Overflow,
+
+        /// Cut off long lines with an ellipsis.
+        Truncate,
+
+        /// Wrap long lines onto continuation lines which are indented past the
+        /// graph, so that the graph stays aligned.
+        Wrap,
+    }
+
+    /// Get the index of the first character of `text` which would extend
+    /// past `width` columns.
+    fn get_char_index_at_width(text: &str, width: usize) -> usize {
+        let mut total_width = 0;
+        for (i, c) in text.chars().enumerate() {
+            total_width += console::measure_text_width(c.encode_utf8(&mut [0; 4]));
+            if total_width > width {
+                return i;
+            }
+        }
+        text.chars().count()
+    }
+
+    /// Split a styled string into the first `index` characters and the rest.
+    fn split_styled_string(line: &StyledString, index: usize) -> (StyledString, StyledString) {
+        let mut left = StyledString::new();
+        let mut right = StyledString::new();
+        let mut remaining = index;
+        for Span {
+            content,
+            attr,
+            width: _,
+        } in line.spans()
+        {
+            let num_chars = content.chars().count();
+            if remaining >= num_chars {
+                left.append_styled(content, *attr);
+                remaining -= num_chars;
+            } else {
+                let (byte_index, _) = content.char_indices().nth(remaining).unwrap();
+                if byte_index > 0 {
+                    left.append_styled(&content[..byte_index], *attr);
+                }
+                right.append_styled(&content[byte_index..], *attr);
+                remaining = 0;
+            }
+        }
+        (left, right)
+    }
+
+    /// Get the number of characters at the start of `line` which make up the
+    /// graph, including the cursor for the commit, if any.
+    fn get_graph_prefix_len(glyphs: &Glyphs, line: &str) -> usize {
+        let graph_glyphs = [
+            glyphs.line,
+            glyphs.line_with_offshoot,
+            glyphs.vertical_ellipsis,
+            " ",
+        ];
+        let cursor_glyphs = [
+            glyphs.commit_visible,
+            glyphs.commit_visible_head,
+            glyphs.commit_obsolete,
+            glyphs.commit_obsolete_head,
+            glyphs.commit_main,
+            glyphs.commit_main_head,
+            glyphs.commit_main_obsolete,
+            glyphs.commit_main_obsolete_head,
+            glyphs.commit_omitted,
+        ];
+        let chars: Vec<String> = line.chars().map(|c| c.to_string()).collect();
+        let is_glyph_at = |glyphs: &[&str], i: usize| {
+            chars.get(i).map_or(false, |c| glyphs.contains(&c.as_str()))
+                && chars.get(i + 1).map(|c| c.as_str()) == Some(" ")
+        };
+
+        let mut prefix_len = 0;
+        while is_glyph_at(&graph_glyphs, prefix_len) {
+            prefix_len += 2;
+        }
+        if is_glyph_at(&cursor_glyphs, prefix_len) {
+            prefix_len += 2;
+        }
+        prefix_len
+    }
+
+    /// Make the prefix for a continuation of a wrapped line, which continues
+    /// any graph lines passing through the line below it.
+    fn make_continuation_prefix(glyphs: &Glyphs, prefix_len: usize, next_line: &str) -> String {
+        let next_line_chars: Vec<String> = next_line.chars().map(|c| c.to_string()).collect();
+        (0..prefix_len)
+            .map(|i| match next_line_chars.get(i) {
+                Some(c) if i % 2 == 0 => {
+                    if c == glyphs.line || c == glyphs.line_with_offshoot {
+                        glyphs.line.to_owned()
+                    } else if c == glyphs.vertical_ellipsis {
+                        glyphs.vertical_ellipsis.to_owned()
+                    } else {
+                        " ".to_owned()
+                    }
+                }
+                Some(_) | None => " ".to_owned(),
+            })
+            .collect()
+    }
+
+    /// Truncate or wrap the rendered smartlog lines which are wider than
+    /// `width` columns, according to `long_lines`. The lines must be in the
+    /// order that they will be displayed.
+    pub fn fit_lines_to_width(
+        glyphs: &Glyphs,
+        colors: &SmartlogColors,
+        lines: Vec<StyledString>,
+        width: usize,
+        long_lines: SmartlogLongLines,
+    ) -> Vec<StyledString> {
+        let mut result = Vec::new();
+        for (i, line) in lines.iter().enumerate() {
+            if long_lines == SmartlogLongLines::Overflow
+                || console::measure_text_width(line.source()) <= width
+            {
+                result.push(line.clone());
+                continue;
+            }
+
+            match long_lines {
+                SmartlogLongLines::Overflow => unreachable!("handled above"),
+
+                SmartlogLongLines::Truncate => {
+                    let ellipsis_width = console::measure_text_width(glyphs.ellipsis);
+                    let index = get_char_index_at_width(
+                        line.source(),
+                        width.saturating_sub(ellipsis_width),
+                    );
+                    let (mut line, _rest) = split_styled_string(line, index);
+                    line.append_plain(glyphs.ellipsis);
+                    result.push(line);
+                }
+
+                SmartlogLongLines::Wrap => {
+                    let prefix_len = get_graph_prefix_len(glyphs, line.source());
+                    let next_line = lines.get(i + 1).map(|line| line.source()).unwrap_or("");
+                    let continuation_prefix =
+                        make_continuation_prefix(glyphs, prefix_len, next_line);
+                    let continuation_prefix_width =
+                        console::measure_text_width(&continuation_prefix);
+                    if continuation_prefix_width >= width {
+                        result.push(line.clone());
+                        continue;
+                    }
+
+                    let mut line = line.clone();
+                    loop {
+                        let index = get_char_index_at_width(line.source(), width);
+                        if index >= line.source().chars().count() {
+                            result.push(line);
+                            break;
+                        }
+
+                        // Prefer to break at the last space before the
+                        // cutoff, so that words aren't split.
+                        let break_index = line
+                            .source()
+                            .chars()
+                            .enumerate()
+                            .take(index + 1)
+                            .skip(prefix_len + 1)
+                            .filter(|(_, c)| *c == ' ')
+                            .map(|(i, _)| i)
+                            .last();
+                        let (first, rest) = match break_index {
+                            Some(break_index) => {
+                                let (first, rest) = split_styled_string(&line, break_index);
+                                let (_space, rest) = split_styled_string(&rest, 1);
+                                (first, rest)
+                            }
+                            None => split_styled_string(&line, index),
+                        };
+                        result.push(first);
+                        line = StyledStringBuilder::new()
+                            .append(style_graph(colors, continuation_prefix.as_str()))
+                            .append(rest)
+                            .build();
+                    }
+                }
+            }
+        }
+        result
+    }
+
     /// Options for rendering the smartlog.
     #[derive(Debug, Default)]
     pub struct SmartlogOptions {
@@ -747,12 +1659,332 @@ mod render {
         /// The options to use when resolving the revset.
         pub resolve_revset_options: ResolveRevsetOptions,
 
-        /// Reverse the ordering of items in the smartlog output, list the most
-        /// recent commits first.
+        /// Reverse the ordering of items in the smartlog output relative to
+        /// the user's `branchless.smartlog.reverse` setting. By default, this
+        /// lists the most recent commits first.
         pub reverse: bool,
 
         /// Normally HEAD and the main branch are included. Set this to exclude them.
         pub exact: bool,
+
+        /// The format in which to print the graph.
+        pub format: SmartlogFormat,
+
+        /// How to display commit times. If not provided, the user's settings
+        /// are used.
+        pub date_format: Option<SmartlogDateFormat>,
+
+        /// How to order sibling commits. If not provided, the user's settings
+        /// are used.
+        pub order: Option<SmartlogOrder>,
+
+        /// Filters to apply to the draft commits in the graph.
+        pub filter_options: SmartlogFilterOptions,
+
+        /// Whether to render long linear runs of commits in full, rather
+        /// than collapsing them.
+        pub expand: bool,
+
+        /// Whether to mark the commits which changed since the last time the
+        /// smartlog was rendered.
+        pub diff: bool,
+
+        /// Whether to only render the stacks containing the commits in
+        /// `revset` (or `HEAD`, if not provided).
+        pub stack: bool,
+
+        /// The maximum number of stacks to render, keeping the most recently
+        /// active ones. If not provided, the user's settings are used.
+        pub limit: Option<usize>,
+
+        /// Whether to show the number of lines inserted and deleted by each
+        /// draft commit, regardless of the user's settings.
+        pub stat: bool,
+    }
+}
+
+/// Remove all but the `limit` most recently active stacks of draft commits from
+/// `commits`, where a stack's activity is the time of its most recent commit.
+/// The stack containing `HEAD` is always kept in addition to these. Returns the remaining commits
+/// and the number of stacks which were removed.
+#[instrument]
+fn limit_stacks(
+    repo: &Repo,
+    dag: &Dag,
+    commits: CommitSet,
+    limit: usize,
+) -> eyre::Result<(CommitSet, usize)> {
+    if limit == 0 {
+        return Ok((commits, 0));
+    }
+
+    let draft_commits = commits.intersection(dag.query_draft_commits()?);
+    let mut stacks = Vec::new();
+    for stack in dag.get_connected_components(&draft_commits)? {
+        if !dag.set_is_empty(&stack.intersection(&dag.head_commit))? {
+            continue;
+        }
+        let mut latest_time = None;
+        for oid in dag.commit_set_to_vec(&stack)? {
+            if let Some(commit) = repo.find_commit(oid)? {
+                latest_time = latest_time.max(Some(commit.get_time()));
+            }
+        }
+        stacks.push((latest_time, stack));
+    }
+    stacks.sort_by(|(lhs_time, _), (rhs_time, _)| rhs_time.cmp(lhs_time));
+
+    let omitted_stacks = stacks.split_off(limit.min(stacks.len()));
+    let num_omitted_stacks = omitted_stacks.len();
+    let omitted_commits = omitted_stacks
+        .into_iter()
+        .fold(CommitSet::empty(), |acc, (_, stack)| acc.union(&stack));
+    Ok((commits.difference(&omitted_commits), num_omitted_stacks))
+}
+
+/// Get the configured ordering of sibling commits in the smartlog.
+fn get_smartlog_order(repo: &Repo) -> eyre::Result<SmartlogOrder> {
+    let value: Option<String> = repo
+        .get_readonly_config()?
+        .get("branchless.smartlog.order")?;
+    match value {
+        Some(value) => Ok(SmartlogOrder::from_str(&value, true).unwrap_or_else(|err| {
+            warn!(?value, ?err, "Invalid value for branchless.smartlog.order");
+            SmartlogOrder::default()
+        })),
+        None => Ok(SmartlogOrder::default()),
+    }
+}
+
+/// Get the configured handling of lines which are wider than the terminal.
+fn get_smartlog_long_lines(repo: &Repo) -> eyre::Result<SmartlogLongLines> {
+    let value: Option<String> = repo
+        .get_readonly_config()?
+        .get("branchless.smartlog.longLines")?;
+    match value.as_deref() {
+        None => Ok(SmartlogLongLines::default()),
+        Some(value) => match value.to_ascii_lowercase().as_str() {
+            "overflow" => Ok(SmartlogLongLines::Overflow),
+            "truncate" => Ok(SmartlogLongLines::Truncate),
+            "wrap" => Ok(SmartlogLongLines::Wrap),
+            _ => {
+                warn!(?value, "Invalid value for branchless.smartlog.longLines");
+                Ok(SmartlogLongLines::default())
+            }
+        },
+    }
+}
+
+/// Quote a string for use as a string literal in a revset expression.
+fn quote_revset_string(s: &str) -> String {
+    if s.contains('"') {
+        format!("'{s}'")
+    } else {
+        format!("\"{s}\"")
+    }
+}
+
+/// Restrict the draft commits in `revset` to those matching the provided
+/// filters. Public commits are left as-is, and omitted draft commits are
+/// elided when rendering the graph.
+fn make_filtered_revset(revset: Revset, filter_options: &SmartlogFilterOptions) -> Revset {
+    let SmartlogFilterOptions {
+        author,
+        path,
+        since,
+    } = filter_options;
+
+    let mut filters = Vec::new();
+    if let Some(author) = author {
+        let author = quote_revset_string(author);
+        filters.push(format!("(author.name({author}) | author.email({author}))"));
+    }
+    if let Some(path) = path {
+        filters.push(format!("paths.changed({})", quote_revset_string(path)));
+    }
+    if let Some(since) = since {
+        filters.push(format!(
+            "committer.date({})",
+            quote_revset_string(&format!("after:{since}"))
+        ));
+    }
+
+    if filters.is_empty() {
+        revset
+    } else {
+        let Revset(revset) = revset;
+        Revset(format!("({revset}) - (draft() - {})", filters.join(" & ")))
+    }
+}
+
+/// Get the pager command configured for the repository, if any. This follows
+/// the same rules as Git: `GIT_PAGER`, then `core.pager`, then `PAGER`.
+fn get_pager(git_run_info: &GitRunInfo, repo: &Repo) -> eyre::Result<Option<String>> {
+    let result = git_run_info.run_silent(
+        repo,
+        None,
+        &["var", "GIT_PAGER"],
+        GitRunOpts {
+            treat_git_failure_as_error: false,
+            stdin: None,
+        },
+    )?;
+    if !result.exit_code.is_success() {
+        return Ok(None);
+    }
+    let pager = String::from_utf8_lossy(&result.stdout).trim().to_owned();
+    match pager.as_str() {
+        "" | "cat" => Ok(None),
+        _ => Ok(Some(pager)),
+    }
+}
+
+/// Write `output` through the user's pager, like `git log` does, if stdout is a
+/// terminal and the output wouldn't fit on the screen. Returns `false` if the
+/// output was not written, in which case the caller should write it instead.
+#[instrument(skip(output))]
+fn write_to_pager(git_run_info: &GitRunInfo, repo: &Repo, output: &str) -> eyre::Result<bool> {
+    let term = console::Term::stdout();
+    if !term.is_term() {
+        return Ok(false);
+    }
+    let (num_rows, _num_cols) = term.size();
+    if output.lines().count() < usize::from(num_rows) {
+        return Ok(false);
+    }
+    let pager = match get_pager(git_run_info, repo)? {
+        Some(pager) => pager,
+        None => return Ok(false),
+    };
+    let sh = match get_sh() {
+        Some(sh) => sh,
+        None => return Ok(false),
+    };
+
+    let mut command = std::process::Command::new(sh);
+    command.arg("-c").arg(&pager).stdin(Stdio::piped());
+    // Use the same defaults as Git, so that colors are rendered and the pager
+    // exits immediately if the output happens to fit after all.
+    if std::env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+    if std::env::var_os("LV").is_none() {
+        command.env("LV", "-c");
+    }
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(err) => {
+            warn!(?pager, ?err, "Could not start pager");
+            return Ok(false);
+        }
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // The user may quit the pager before reading all of the output, in
+        // which case the write fails with a broken pipe. That's not an error.
+        let _ = std::io::Write::write_all(&mut stdin, output.as_bytes());
+    }
+    child.wait()?;
+    Ok(true)
+}
+
+/// The name of the file in the branchless directory which records the commits
+/// in the most recently rendered smartlog, for use with `--diff`. It's only
+/// written when `--diff` is passed, unless `branchless.smartlog.trackChanges`
+/// is set.
+const LAST_SMARTLOG_FILE_NAME: &str = "last-smartlog";
+
+/// The commits in a rendered smartlog, each mapped to the OIDs of its parents
+/// or nearest ancestor in the smartlog graph.
+type SmartlogSnapshot = HashMap<NonZeroOid, Vec<NonZeroOid>>;
+
+fn make_smartlog_snapshot(graph: &SmartlogGraph) -> SmartlogSnapshot {
+    graph
+        .nodes
+        .iter()
+        .map(|(oid, node)| {
+            let mut parent_oids = node.parents.clone();
+            parent_oids.extend(node.ancestor_info.as_ref().map(|ancestor| ancestor.oid));
+            parent_oids.sort();
+            (*oid, parent_oids)
+        })
+        .collect()
+}
+
+/// Read the snapshot of the last rendered smartlog, if any. Each line of the
+/// file consists of a commit OID followed by its parent OIDs.
+fn read_last_smartlog_snapshot(repo: &Repo) -> eyre::Result<Option<SmartlogSnapshot>> {
+    let path = repo.get_branchless_dir()?.join(LAST_SMARTLOG_FILE_NAME);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).wrap_err_with(|| format!("Reading {path:?}")),
+    };
+    let mut snapshot = SmartlogSnapshot::new();
+    for line in contents.lines() {
+        let oids: Result<Vec<NonZeroOid>, _> = line.split_whitespace().map(str::parse).collect();
+        match oids.as_deref() {
+            Ok([oid, parent_oids @ ..]) => {
+                snapshot.insert(*oid, parent_oids.to_vec());
+            }
+            Ok([]) => {}
+            Err(err) => {
+                warn!(
+                    ?line,
+                    ?err,
+                    "Ignoring invalid line in last smartlog snapshot"
+                );
+            }
+        }
+    }
+    Ok(Some(snapshot))
+}
+
+fn write_last_smartlog_snapshot(repo: &Repo, snapshot: &SmartlogSnapshot) -> eyre::Result<()> {
+    let path = repo.get_branchless_dir()?.join(LAST_SMARTLOG_FILE_NAME);
+    let mut contents = String::new();
+    for (oid, parent_oids) in snapshot.iter().sorted_by_key(|(oid, _)| **oid) {
+        write!(contents, "{oid}")?;
+        for parent_oid in parent_oids {
+            write!(contents, " {parent_oid}")?;
+        }
+        writeln!(contents)?;
+    }
+    std::fs::write(&path, contents).wrap_err_with(|| format!("Writing {path:?}"))?;
+    Ok(())
+}
+
+/// Mark commits which are new or have moved since the last rendered smartlog.
+#[derive(Debug)]
+struct SmartlogDiffDescriptor<'a> {
+    last_snapshot: Option<&'a SmartlogSnapshot>,
+    current_snapshot: &'a SmartlogSnapshot,
+}
+
+impl<'a> NodeDescriptor for SmartlogDiffDescriptor<'a> {
+    #[instrument]
+    fn describe_node(
+        &mut self,
+        _glyphs: &Glyphs,
+        object: &NodeObject,
+    ) -> eyre::Result<Option<StyledString>> {
+        let last_snapshot = match self.last_snapshot {
+            Some(last_snapshot) => last_snapshot,
+            None => return Ok(None),
+        };
+        let oid = object.get_oid();
+        match last_snapshot.get(&oid) {
+            None => Ok(Some(StyledString::styled(
+                "(new)",
+                BaseColor::Green.light(),
+            ))),
+            Some(last_parent_oids) if self.current_snapshot.get(&oid) != Some(last_parent_oids) => {
+                Ok(Some(StyledString::styled(
+                    "(moved)",
+                    BaseColor::Yellow.light(),
+                )))
+            }
+            Some(_) => Ok(None),
+        }
     }
 }
 
@@ -769,13 +2001,28 @@ pub fn smartlog(
         resolve_revset_options,
         reverse,
         exact,
+        format,
+        date_format,
+        order,
+        filter_options,
+        expand,
+        diff,
+        stack,
+        limit,
+        stat,
     } = options;
 
     let repo = Repo::from_dir(&git_run_info.working_directory)?;
+    let reverse = reverse != get_smartlog_reverse(&repo)?;
     let head_info = repo.get_head_info()?;
     let conn = repo.get_db_conn()?;
     let event_log_db = EventLogDb::new(&conn)?;
-    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    // Replaying from a snapshot is faster, but the snapshot can't be used to
+    // show points in time before it.
+    let event_replayer = match event_id {
+        None => EventReplayer::from_event_log_db_with_snapshot(effects, &repo, &event_log_db)?,
+        Some(_) => EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?,
+    };
     let (references_snapshot, event_cursor) = {
         let default_cursor = event_replayer.make_default_cursor();
         match event_id {
@@ -798,11 +2045,17 @@ pub fn smartlog(
         event_cursor,
         &references_snapshot,
     )?;
+    if event_id.is_none() {
+        record_observed_commits(effects, &repo, &event_log_db, &event_replayer, &mut dag)?;
+    }
 
-    let revset = match revset {
-        Some(revset) => revset,
-        None => Revset(get_smartlog_default_revset(&repo)?),
+    let revset = match (revset, stack) {
+        (Some(Revset(revset)), true) => Revset(format!("stack({revset})")),
+        (None, true) => Revset("stack(@)".to_string()),
+        (Some(revset), false) => revset,
+        (None, false) => Revset(get_smartlog_default_revset(&repo)?),
     };
+    let revset = make_filtered_revset(revset, &filter_options);
     let commits =
         match resolve_commits(effects, &repo, &mut dag, &[revset], &resolve_revset_options) {
             Ok(result) => match result.as_slice() {
@@ -815,7 +2068,13 @@ pub fn smartlog(
             }
         };
 
-    let graph = make_smartlog_graph(
+    let limit = match limit {
+        Some(limit) => limit,
+        None => get_smartlog_stack_limit(&repo)?,
+    };
+    let (commits, num_omitted_stacks) = limit_stacks(&repo, &dag, commits, limit)?;
+
+    let mut graph = make_smartlog_graph(
         effects,
         &repo,
         &dag,
@@ -823,43 +2082,203 @@ pub fn smartlog(
         event_cursor,
         &commits,
         exact,
+        resolve_revset_options.show_hidden_commits,
     )?;
 
-    let mut lines = render_graph(
-        &effects.reverse_order(reverse),
-        &repo,
-        &dag,
-        &graph,
-        references_snapshot.head_oid,
-        &mut [
-            &mut CommitOidDescriptor::new(true)?,
-            &mut RelativeTimeDescriptor::new(&repo, SystemTime::now())?,
-            &mut ObsolescenceExplanationDescriptor::new(
-                &event_replayer,
-                event_replayer.make_default_cursor(),
-            )?,
-            &mut BranchesDescriptor::new(
-                &repo,
-                &head_info,
-                &references_snapshot,
-                &Redactor::Disabled,
-            )?,
-            &mut DifferentialRevisionDescriptor::new(&repo, &Redactor::Disabled)?,
-            &mut CommitMessageDescriptor::new(&Redactor::Disabled)?,
-        ],
-    )?
-    .into_iter();
-    while let Some(line) = if reverse {
-        lines.next_back()
+    match format {
+        SmartlogFormat::Text => {}
+        SmartlogFormat::Json => {
+            let commit_graph =
+                api::make_commit_graph_from_smartlog_graph(&dag, &graph, &references_snapshot)?;
+            writeln!(
+                effects.get_output_stream(),
+                "{}",
+                serde_json::to_string(&commit_graph)?
+            )?;
+            return Ok(Ok(()));
+        }
+        SmartlogFormat::Dot => {
+            let dot = render_graph_dot(&dag, &graph, &references_snapshot)?;
+            write!(effects.get_output_stream(), "{dot}")?;
+            return Ok(Ok(()));
+        }
+    }
+
+    let order = match order {
+        Some(order) => order,
+        None => get_smartlog_order(&repo)?,
+    };
+    match order {
+        SmartlogOrder::Topological => {}
+        SmartlogOrder::Chronological => sort_children_chronologically(&mut graph),
+    }
+
+    // Take the snapshot before collapsing the graph, so that collapsed commits
+    // aren't considered to have disappeared.
+    let current_snapshot = make_smartlog_snapshot(&graph);
+    let last_snapshot = if diff {
+        read_last_smartlog_snapshot(&repo)?
     } else {
-        lines.next()
-    } {
+        None
+    };
+    let mut diff_descriptor = SmartlogDiffDescriptor {
+        last_snapshot: last_snapshot.as_ref(),
+        current_snapshot: &current_snapshot,
+    };
+
+    if !expand {
+        let keep_oids: HashSet<NonZeroOid> = references_snapshot
+            .head_oid
+            .into_iter()
+            .chain(references_snapshot.branch_oid_to_names.keys().copied())
+            .collect();
+        collapse_linear_runs(
+            &mut graph,
+            &keep_oids,
+            get_smartlog_collapse_threshold(&repo)?,
+        );
+    }
+
+    let now = SystemTime::now();
+    let mut time_descriptor: Box<dyn NodeDescriptor> = match date_format {
+        None => Box::new(RelativeTimeDescriptor::new(&repo, now)?),
+        Some(SmartlogDateFormat::Relative) => Box::new(RelativeTimeDescriptor::new_enabled(now)),
+        Some(SmartlogDateFormat::Iso) => Box::new(AbsoluteTimeDescriptor::new()?),
+    };
+    let mut lines = match get_smartlog_commit_template(&repo)? {
+        Some(template) => render_graph(
+            &effects.reverse_order(reverse),
+            &repo,
+            &dag,
+            &graph,
+            references_snapshot.head_oid,
+            &mut [
+                &mut TemplateDescriptor::new(
+                    &repo,
+                    &template,
+                    now,
+                    &event_replayer,
+                    event_replayer.make_default_cursor(),
+                    &head_info,
+                    &references_snapshot,
+                    &Redactor::Disabled,
+                )?,
+                &mut diff_descriptor,
+            ],
+        )?,
+        None => render_graph(
+            &effects.reverse_order(reverse),
+            &repo,
+            &dag,
+            &graph,
+            references_snapshot.head_oid,
+            &mut [
+                &mut CommitOidDescriptor::new(true)?,
+                &mut diff_descriptor,
+                time_descriptor.as_mut(),
+                &mut ObsolescenceExplanationDescriptor::new(
+                    &event_replayer,
+                    event_replayer.make_default_cursor(),
+                )?
+                .with_transaction_reasons(event_log_db.get_transaction_reasons()?),
+                &mut BranchesDescriptor::new(
+                    &repo,
+                    &head_info,
+                    &references_snapshot,
+                    &Redactor::Disabled,
+                )?,
+                &mut WorkingCopyStatusDescriptor::new(
+                    &repo,
+                    git_run_info,
+                    match event_id {
+                        None => references_snapshot.head_oid,
+                        Some(_) => None,
+                    },
+                )?,
+                &mut DifferentialRevisionDescriptor::new(&repo, &Redactor::Disabled)?,
+                &mut SignatureDescriptor::new(
+                    &repo,
+                    Box::new(|commit: &Commit, _signature: &[u8], _signed_data: &[u8]| {
+                        let oid = commit.get_oid().to_string();
+                        let result = git_run_info.run_silent(
+                            &repo,
+                            None,
+                            &["verify-commit", oid.as_str()],
+                            GitRunOpts {
+                                treat_git_failure_as_error: false,
+                                stdin: None,
+                            },
+                        )?;
+                        Ok(result.exit_code.is_success())
+                    }),
+                )?,
+                &mut StatusCommandDescriptor::new(&repo)?,
+                &mut AheadBehindDescriptor::new(&repo, &dag)?,
+                &mut DiffStatDescriptor::new(effects, &repo, &dag, &event_log_db, stat)?,
+                &mut CommitMessageDescriptor::new(&Redactor::Disabled)?,
+            ],
+        )?,
+    };
+    if reverse {
+        lines.reverse();
+    }
+    let long_lines = get_smartlog_long_lines(&repo)?;
+    let term = console::Term::stdout();
+    if long_lines != SmartlogLongLines::Overflow && term.is_term() {
+        let (_num_rows, num_cols) = term.size();
+        lines = fit_lines_to_width(
+            effects.get_glyphs(),
+            &get_smartlog_colors(&repo)?,
+            lines,
+            usize::from(num_cols),
+            long_lines,
+        );
+    }
+    let mut output = String::new();
+    for line in lines {
+        writeln!(output, "{}", effects.get_glyphs().render(line)?)?;
+    }
+    if num_omitted_stacks > 0 {
         writeln!(
-            effects.get_output_stream(),
-            "{}",
-            effects.get_glyphs().render(line)?
+            output,
+            "{} not shown (use --limit 0 to show all stacks)",
+            Pluralize {
+                determiner: None,
+                amount: num_omitted_stacks,
+                unit: ("less recently active stack", "less recently active stacks"),
+            }
         )?;
     }
+    if diff {
+        match &last_snapshot {
+            None => {
+                writeln!(output, "There is no previous smartlog to compare against.")?;
+            }
+            Some(last_snapshot) => {
+                let removed_oids = last_snapshot
+                    .keys()
+                    .filter(|oid| !current_snapshot.contains_key(oid))
+                    .sorted()
+                    .collect_vec();
+                if !removed_oids.is_empty() {
+                    writeln!(output, "Removed since the last smartlog:")?;
+                }
+                for oid in removed_oids {
+                    let description = match repo.find_commit(*oid)? {
+                        Some(commit) => commit.friendly_describe(effects.get_glyphs())?,
+                        None => StyledString::plain(oid.to_string()),
+                    };
+                    writeln!(output, "- {}", effects.get_glyphs().render(description)?)?;
+                }
+            }
+        }
+    }
+    if event_id.is_none() && (diff || get_smartlog_track_changes(&repo)?) {
+        write_last_smartlog_snapshot(&repo, &current_snapshot)?;
+    }
+    if !write_to_pager(git_run_info, &repo, &output)? {
+        write!(effects.get_output_stream(), "{output}")?;
+    }
 
     if !resolve_revset_options.show_hidden_commits
         && get_hint_enabled(&repo, Hint::SmartlogFixAbandoned)?
@@ -900,6 +2319,42 @@ pub fn smartlog(
         }
     }
 
+    if repo.is_shallow() && get_hint_enabled(&repo, Hint::SmartlogUnshallow)? {
+        let draft_oids = graph
+            .nodes
+            .iter()
+            .filter(|(_oid, node)| !node.is_main)
+            .map(|(oid, _node)| *oid)
+            .collect_vec();
+        let mut num_disconnected_commits = 0;
+        for merge_bases in dag
+            .query_main_branch_merge_bases_many(&draft_oids)?
+            .values()
+        {
+            if dag.set_is_empty(merge_bases)? {
+                num_disconnected_commits += 1;
+            }
+        }
+        if num_disconnected_commits > 0 {
+            writeln!(
+                effects.get_output_stream(),
+                "{}: this repository is a shallow clone, so {} could not be connected to the main branch",
+                effects.get_glyphs().render(get_hint_string())?,
+                Pluralize {
+                    determiner: None,
+                    amount: num_disconnected_commits,
+                    unit: ("commit", "commits"),
+                },
+            )?;
+            writeln!(
+                effects.get_output_stream(),
+                "{}: to fetch the missing history, run: git fetch --unshallow",
+                effects.get_glyphs().render(get_hint_string())?,
+            )?;
+            print_hint_suppression_notice(effects, Hint::SmartlogUnshallow)?;
+        }
+    }
+
     Ok(Ok(()))
 }
 
@@ -916,7 +2371,33 @@ pub fn command_main(ctx: CommandContext, args: SmartlogArgs) -> EyreExitOr<()> {
         resolve_revset_options,
         reverse,
         exact,
+        json,
+        format,
+        date_format,
+        order,
+        expand,
+        diff,
+        stack,
+        limit,
+        stat,
+        interactive,
+        filter_options,
     } = args;
+    if interactive {
+        // The interactive smartlog needs to invoke other commands, such as
+        // `hide` and `restack`, so it's implemented in the main
+        // `git-branchless` binary instead.
+        writeln!(
+            effects.get_error_stream(),
+            "The interactive smartlog is only available via: git branchless smartlog --interactive"
+        )?;
+        return Ok(Err(ExitCode(1)));
+    }
+    let format = if json {
+        SmartlogFormat::Json
+    } else {
+        format.unwrap_or_default()
+    };
 
     smartlog(
         &effects,
@@ -927,6 +2408,15 @@ pub fn command_main(ctx: CommandContext, args: SmartlogArgs) -> EyreExitOr<()> {
             resolve_revset_options,
             reverse,
             exact,
+            format,
+            date_format,
+            order,
+            filter_options,
+            expand,
+            diff,
+            stack,
+            limit,
+            stat,
         },
     )
 }
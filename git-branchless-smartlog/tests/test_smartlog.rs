@@ -1,4 +1,17 @@
-use lib::testing::{extract_hint_command, make_git, GitRunOptions};
+use cursive_core::theme::{BaseColor, Color};
+use cursive_core::utils::markup::StyledString;
+use git_branchless_opts::ResolveRevsetOptions;
+use git_branchless_smartlog::{
+    fit_lines_to_width, make_commit_graph, CommitEdge, CommitEdgeKind, SmartlogLongLines,
+};
+use lib::core::config::get_smartlog_colors;
+use lib::core::effects::Effects;
+use lib::core::formatting::Glyphs;
+use lib::git::NonZeroOid;
+use lib::testing::{
+    extract_hint_command, make_git, make_git_with_remote_repo, GitInitOptions, GitRunOptions,
+    GitWrapperWithRemoteRepo,
+};
 
 #[test]
 fn test_init_smartlog() -> eyre::Result<()> {
@@ -35,6 +48,82 @@ fn test_show_reachable_commit() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_smartlog_json() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "foo"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--json"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        {"nodes":[{"oid":"f777ecc9b0db5ed372b2615695191a8a17f79f24","is_main":true,"is_obsolete":false,"is_head":false,"is_main_branch_head":true,"branches":["master"],"summary":"create initial.txt","num_omitted_descendants":0,"rewritten_as":null},{"oid":"62fc20d2a290daea0d52bdc2ed2ad4be6491010e","is_main":false,"is_obsolete":false,"is_head":false,"is_main_branch_head":false,"branches":[],"summary":"create test1.txt","num_omitted_descendants":0,"rewritten_as":null},{"oid":"96d1c37a3d4363611c49f7e52186e189a04c531f","is_main":false,"is_obsolete":false,"is_head":true,"is_main_branch_head":false,"branches":["foo"],"summary":"create test2.txt","num_omitted_descendants":0,"rewritten_as":null}],"edges":[{"parent":"f777ecc9b0db5ed372b2615695191a8a17f79f24","child":"62fc20d2a290daea0d52bdc2ed2ad4be6491010e","kind":"parent"},{"parent":"62fc20d2a290daea0d52bdc2ed2ad4be6491010e","child":"96d1c37a3d4363611c49f7e52186e189a04c531f","kind":"parent"}]}
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_dot() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "foo"])?;
+    git.branchless("hide", &[&test1_oid.to_string()])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--format", "dot"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        digraph smartlog {
+          "f777ecc9b0db5ed372b2615695191a8a17f79f24" [label="f777ecc (master) create initial.txt", shape=box];
+          "62fc20d2a290daea0d52bdc2ed2ad4be6491010e" [label="62fc20d create test1.txt", shape=ellipse, style=dashed, color=gray50, fontcolor=gray50];
+          "96d1c37a3d4363611c49f7e52186e189a04c531f" [label="96d1c37 (foo) create test2.txt", shape=ellipse, penwidth=2];
+          "f777ecc9b0db5ed372b2615695191a8a17f79f24" -> "62fc20d2a290daea0d52bdc2ed2ad4be6491010e";
+          "62fc20d2a290daea0d52bdc2ed2ad4be6491010e" -> "96d1c37a3d4363611c49f7e52186e189a04c531f";
+        }
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_commit_template() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file_with_contents_and_message("test2", 2, "test2 contents\n", "PROJ-123 create")?;
+    git.run(&["config", "branchless.smartlog.commitTemplate", "%d[%t] %s"])?;
+    git.run(&[
+        "config",
+        "branchless.smartlog.ticketPattern",
+        "[A-Z]+-[0-9]+",
+    ])?;
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O (master)[] create initial.txt
+        |
+        o [] create test1.txt
+        |
+        @ [PROJ-123] PROJ-123 create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_tree() -> eyre::Result<()> {
     let git = make_git()?;
@@ -178,6 +267,35 @@ fn test_merge_commit() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_merge_commit_omitted_parent() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["checkout", "HEAD^"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&["merge", &test1_oid.to_string()])?;
+
+    {
+        // Both parents of the merge commit should be indicated, even though
+        // neither is included in the graph.
+        let (stdout, _stderr) = git.branchless("smartlog", &["@"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        :
+        # 2 omitted commits
+        :
+        | & (merge) 62fc20d create test1.txt
+        |/
+        @ 91a5ccb Merge commit '62fc20d2a290daea0d52bdc2ed2ad4be6491010e' into HEAD
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_merge_commit_reverse_order() -> eyre::Result<()> {
     let git = make_git()?;
@@ -279,6 +397,36 @@ fn test_non_adjacent_commits() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_smartlog_ahead_behind() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.commitDescriptors.aheadBehind", "true"])?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.detach_head()?;
+    git.commit_file("test4", 4)?;
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc create initial.txt
+        |\
+        : o 62fc20d (1 ahead, 2 behind) create test1.txt
+        :
+        O 0206717 (master) create test3.txt
+        |
+        @ 8e62740 (1 ahead, 0 behind) create test4.txt
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_non_adjacent_commits2() -> eyre::Result<()> {
     let git = make_git()?;
@@ -651,53 +799,49 @@ fn test_smartlog_sparse_main_false_head() -> eyre::Result<()> {
 }
 
 #[test]
-fn test_smartlog_hidden() -> eyre::Result<()> {
+fn test_smartlog_status_command() -> eyre::Result<()> {
     let git = make_git()?;
     git.init_repo()?;
 
     git.detach_head()?;
     git.commit_file("test1", 1)?;
-    git.run(&["commit", "--amend", "-m", "amended test1"])?;
+    git.run(&[
+        "config",
+        "branchless.commitDescriptors.statusCommand",
+        "echo >> .git/status-calls && printf 'ci:%.7s' \"$BRANCHLESS_STATUS_COMMIT\"",
+    ])?;
 
     {
-        let (stdout, _stderr) = git.branchless("smartlog", &["--hidden"])?;
+        let stdout = git.smartlog()?;
         insta::assert_snapshot!(stdout, @r###"
-        O f777ecc (master) create initial.txt
-        |\
-        | @ ae94dc2 amended test1
+        O f777ecc (master) ci:f777ecc create initial.txt
         |
-        x 62fc20d (rewritten as ae94dc2a) create test1.txt
+        @ 62fc20d ci:62fc20d create test1.txt
         "###);
     }
 
+    // The results should be cached, so the command shouldn't be run again.
+    git.smartlog()?;
+    let status_calls = std::fs::read_to_string(git.repo_path.join(".git").join("status-calls"))?;
+    assert_eq!(status_calls.lines().count(), 2);
+
     Ok(())
 }
 
 #[test]
-fn test_smartlog_sparse_vertical_ellipsis_sibling_commits() -> eyre::Result<()> {
+fn test_smartlog_date_iso() -> eyre::Result<()> {
     let git = make_git()?;
     git.init_repo()?;
 
     git.detach_head()?;
     git.commit_file("test1", 1)?;
-    git.run(&["checkout", "HEAD~"])?;
-    git.commit_file("test2", 2)?;
-    git.run(&["checkout", "HEAD~"])?;
-    git.commit_file("test3", 3)?;
-    git.commit_file("test4", 4)?;
 
     {
-        let (stdout, _stderr) = git.branchless("smartlog", &["heads(draft())"])?;
+        let (stdout, _stderr) = git.branchless("smartlog", &["--date", "iso"])?;
         insta::assert_snapshot!(stdout, @r###"
-        O f777ecc (master) create initial.txt
-        |\
-        | o 62fc20d create test1.txt
-        |\
-        | o fe65c1f create test2.txt
-        :
-        # 1 omitted commit
-        :
-        @ 2b633ed create test4.txt
+        O f777ecc 2020-10-29 12:34:56 +0000 (master) create initial.txt
+        |
+        @ 62fc20d 2020-10-29 12:34:56 -0100 create test1.txt
         "###);
     }
 
@@ -705,38 +849,34 @@ fn test_smartlog_sparse_vertical_ellipsis_sibling_commits() -> eyre::Result<()>
 }
 
 #[test]
-fn test_default_smartlog_revset() -> eyre::Result<()> {
+fn test_smartlog_filter_path() -> eyre::Result<()> {
     let git = make_git()?;
     git.init_repo()?;
 
     git.detach_head()?;
     git.commit_file("test1", 1)?;
     git.commit_file("test2", 2)?;
-    git.commit_file("test3", 3)?;
 
     {
-        let smartlog = git.smartlog()?;
-        insta::assert_snapshot!(smartlog, @r###"
+        let (stdout, _stderr) = git.branchless("smartlog", &["--path", "test2.txt"])?;
+        insta::assert_snapshot!(stdout, @r###"
         O f777ecc (master) create initial.txt
-        |
-        o 62fc20d create test1.txt
-        |
-        o 96d1c37 create test2.txt
-        |
-        @ 70deb1e create test3.txt
+        :
+        # 1 omitted commit
+        :
+        @ 96d1c37 create test2.txt
         "###);
     }
 
-    git.run(&["config", "branchless.smartlog.defaultRevset", "none()"])?;
-
     {
-        let smartlog = git.smartlog()?;
-        insta::assert_snapshot!(smartlog, @r###"
+        // `HEAD` is always shown, even if it doesn't match the filters.
+        let (stdout, _stderr) = git.branchless("smartlog", &["--author", "nobody"])?;
+        insta::assert_snapshot!(stdout, @r###"
         O f777ecc (master) create initial.txt
         :
-        # 2 omitted commits
+        # 1 omitted commit
         :
-        @ 70deb1e create test3.txt
+        @ 96d1c37 create test2.txt
         "###);
     }
 
@@ -744,70 +884,1027 @@ fn test_default_smartlog_revset() -> eyre::Result<()> {
 }
 
 #[test]
-fn test_exact() -> eyre::Result<()> {
+fn test_smartlog_collapse_linear_runs() -> eyre::Result<()> {
     let git = make_git()?;
     git.init_repo()?;
+    git.run(&["config", "branchless.smartlog.collapseThreshold", "4"])?;
 
-    git.commit_file("test1", 1)?;
-    git.detach_head()?;
-    git.commit_file("test2", 2)?;
-    git.commit_file("test3", 3)?;
-    git.commit_file("test4", 4)?;
-    git.run(&["checkout", "master"])?;
-    git.commit_file("test5", 5)?;
     git.detach_head()?;
-    git.commit_file("test6", 6)?;
-    git.run(&["checkout", "96d1c37"])?;
+    for i in 1..=7 {
+        git.commit_file(&format!("test{i}"), i)?;
+    }
 
     {
-        // Here '--exact' doesn't change anything because draft() covers all these commits
-        let (stdout, _stderr) = git.branchless("smartlog", &["--exact"])?;
+        let stdout = git.smartlog()?;
         insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 62fc20d create test1.txt
+        |
+        o 96d1c37 create test2.txt
         :
-        O 62fc20d create test1.txt
-        |\
-        | @ 96d1c37 create test2.txt
-        | |
-        | o 70deb1e create test3.txt
-        | |
-        | o 355e173 create test4.txt
+        # 2 omitted commits
+        :
+        o f81d55c create test5.txt
         |
-        O ea7aa06 (master) create test5.txt
+        o 2831fb5 create test6.txt
         |
-        o da42aeb create test6.txt
+        @ c8933b3 create test7.txt
         "###);
     }
 
     {
-        // Show no commits
-        let (stdout, _stderr) = git.branchless("smartlog", &["--exact", "none()"])?;
-        insta::assert_snapshot!(stdout, @"");
+        let (stdout, _stderr) = git.branchless("smartlog", &["--expand"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 62fc20d create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        |
+        o 70deb1e create test3.txt
+        |
+        o 355e173 create test4.txt
+        |
+        o f81d55c create test5.txt
+        |
+        o 2831fb5 create test6.txt
+        |
+        @ c8933b3 create test7.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_colors_config() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    {
+        let colors = get_smartlog_colors(&git.get_repo()?)?;
+        assert_eq!(colors.branch, Color::Light(BaseColor::Green));
+        assert_eq!(colors.head, None);
+        assert_eq!(colors.graph, None);
     }
 
+    git.run(&["config", "branchless.smartlog.color.branch", "blue"])?;
+    git.run(&["config", "branchless.smartlog.color.head", "light red"])?;
+    git.run(&["config", "branchless.smartlog.color.graph", "not a color"])?;
     {
-        // Show one commit - no master or HEAD
-        let (stdout, _stderr) = git.branchless("smartlog", &["--exact", "70deb1e"])?;
+        let colors = get_smartlog_colors(&git.get_repo()?)?;
+        assert_eq!(colors.branch, Color::Dark(BaseColor::Blue));
+        assert_eq!(colors.head, Some(Color::Light(BaseColor::Red)));
+        assert_eq!(colors.graph, None);
+    }
+
+    {
+        // Rendering should still succeed with the custom colors.
+        let stdout = git.smartlog()?;
         insta::assert_snapshot!(stdout, @r###"
-        :
-        o 70deb1e create test3.txt
-        :
-        # 1 omitted descendant commit
+        @ f777ecc (> master) create initial.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_signature() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.run(&["config", "branchless.commitDescriptors.signature", "true"])?;
+
+    // Create a commit with a signature which can't be verified.
+    let (tree_oid, _stderr) = git.run(&["rev-parse", "HEAD^{tree}"])?;
+    let (parent_oid, _stderr) = git.run(&["rev-parse", "HEAD"])?;
+    let commit_contents = format!(
+        "tree {}\n\
+parent {}\n\
+author Testy McTestface <test@example.com> 1604000000 +0000\n\
+committer Testy McTestface <test@example.com> 1604000000 +0000\n\
+gpgsig -----BEGIN PGP SIGNATURE-----\n \n bogus\n -----END PGP SIGNATURE-----\n\
+\n\
+signed commit\n",
+        tree_oid.trim(),
+        parent_oid.trim(),
+    );
+    let (signed_oid, _stderr) = git.run_with_options(
+        &["hash-object", "-t", "commit", "-w", "--stdin"],
+        &GitRunOptions {
+            input: Some(commit_contents),
+            ..Default::default()
+        },
+    )?;
+    git.run(&["checkout", signed_oid.trim()])?;
+
+    {
+        let (short_oid, _stderr) = git.run(&["rev-parse", "--short", signed_oid.trim()])?;
+        let stdout = git.smartlog()?.replace(short_oid.trim(), "<signed>");
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) (unsigned) create initial.txt
+        |
+        @ <signed> (bad signature) signed commit
         "###);
     }
 
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_hidden() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["commit", "--amend", "-m", "amended test1"])?;
+
     {
-        // Show head commits and their common ancestor, which is not main.
-        let (stdout, _stderr) = git.branchless("smartlog", &["--exact", "heads(draft())"])?;
+        let (stdout, _stderr) = git.branchless("smartlog", &["--hidden"])?;
         insta::assert_snapshot!(stdout, @r###"
-        :
-        O 62fc20d create test1.txt
+        O f777ecc (master) create initial.txt
         |\
-        : # 2 omitted commits
-        : :
-        : o 355e173 create test4.txt
-        :
-        o da42aeb create test6.txt
-        "###);
+        | @ ae94dc2 amended test1
+        |
+        x 62fc20d (rewritten as ae94dc2a) create test1.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_hidden_omitted_descendants() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+    git.branchless("hide", &["--recursive", &test1_oid.to_string()])?;
+
+    {
+        let (stdout, _stderr) =
+            git.branchless("smartlog", &["--hidden", &test1_oid.to_string()])?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc (> master) create initial.txt
+        |
+        x 62fc20d (manually hidden) create test1.txt
+        :
+        # 1 omitted descendant commit
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_sparse_vertical_ellipsis_sibling_commits() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "HEAD~"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "HEAD~"])?;
+    git.commit_file("test3", 3)?;
+    git.commit_file("test4", 4)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["heads(draft())"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |\
+        | o 62fc20d create test1.txt
+        |\
+        | o fe65c1f create test2.txt
+        :
+        # 1 omitted commit
+        :
+        @ 2b633ed create test4.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_default_smartlog_revset() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+
+    {
+        let smartlog = git.smartlog()?;
+        insta::assert_snapshot!(smartlog, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 62fc20d create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        |
+        @ 70deb1e create test3.txt
+        "###);
+    }
+
+    git.run(&["config", "branchless.smartlog.defaultRevset", "none()"])?;
+
+    {
+        let smartlog = git.smartlog()?;
+        insta::assert_snapshot!(smartlog, @r###"
+        O f777ecc (master) create initial.txt
+        :
+        # 2 omitted commits
+        :
+        @ 70deb1e create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_exact() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.commit_file("test4", 4)?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test5", 5)?;
+    git.detach_head()?;
+    git.commit_file("test6", 6)?;
+    git.run(&["checkout", "96d1c37"])?;
+
+    {
+        // Here '--exact' doesn't change anything because draft() covers all these commits
+        let (stdout, _stderr) = git.branchless("smartlog", &["--exact"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d create test1.txt
+        |\
+        | @ 96d1c37 create test2.txt
+        | |
+        | o 70deb1e create test3.txt
+        | |
+        | o 355e173 create test4.txt
+        |
+        O ea7aa06 (master) create test5.txt
+        |
+        o da42aeb create test6.txt
+        "###);
+    }
+
+    {
+        // Show no commits
+        let (stdout, _stderr) = git.branchless("smartlog", &["--exact", "none()"])?;
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    {
+        // Show one commit - no master or HEAD
+        let (stdout, _stderr) = git.branchless("smartlog", &["--exact", "70deb1e"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        o 70deb1e create test3.txt
+        :
+        # 1 omitted descendant commit
+        "###);
+    }
+
+    {
+        // Show head commits and their common ancestor, which is not main.
+        let (stdout, _stderr) = git.branchless("smartlog", &["--exact", "heads(draft())"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d create test1.txt
+        |\
+        : # 2 omitted commits
+        : :
+        : o 355e173 create test4.txt
+        :
+        o da42aeb create test6.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_remote_branches() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&[
+        "update-ref",
+        "refs/remotes/origin/feature",
+        &test2_oid.to_string(),
+    ])?;
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        |
+        @ 70deb1e create test3.txt
+        "###);
+    }
+
+    git.run(&[
+        "config",
+        "branchless.commitDescriptors.remoteBranches",
+        "true",
+    ])?;
+    git.run(&["branch", "feature", &test2_oid.to_string()])?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        o 96d1c37 (feature, origin/feature) create test2.txt
+        |
+        @ 70deb1e create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_working_copy_status() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&[
+        "config",
+        "branchless.commitDescriptors.workingCopyStatus",
+        "true",
+    ])?;
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 96d1c37 (> master) create test2.txt
+        "###);
+    }
+
+    git.write_file_txt("test1", "updated contents\n")?;
+    git.write_file_txt("test2", "updated contents\n")?;
+    git.run(&["add", "test1.txt"])?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 96d1c37 (> master) (* 2 modified files) create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_make_commit_graph() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+    let test3_oid = git.commit_file("test3", 3)?;
+
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let repo = git.get_repo()?;
+    let graph = make_commit_graph(&effects, &repo, None, &ResolveRevsetOptions::default())?;
+
+    // Sibling commits may appear in either order, so sort before comparing.
+    let mut nodes: Vec<(String, bool, bool, Vec<String>)> = graph
+        .nodes
+        .iter()
+        .map(|node| {
+            (
+                node.oid.clone(),
+                node.is_main,
+                node.is_head,
+                node.branches.clone(),
+            )
+        })
+        .collect();
+    nodes.sort();
+    let mut expected_nodes = vec![
+        (test1_oid.to_string(), true, false, vec![]),
+        (test2_oid.to_string(), false, false, vec![]),
+        (
+            test3_oid.to_string(),
+            true,
+            true,
+            vec!["master".to_string()],
+        ),
+    ];
+    expected_nodes.sort();
+    assert_eq!(nodes, expected_nodes);
+
+    let mut edges = graph.edges;
+    edges.sort_by_key(|edge| edge.child.clone());
+    let mut expected_edges = vec![
+        CommitEdge {
+            parent: test1_oid.to_string(),
+            child: test2_oid.to_string(),
+            kind: CommitEdgeKind::Parent,
+        },
+        CommitEdge {
+            parent: test1_oid.to_string(),
+            child: test3_oid.to_string(),
+            kind: CommitEdgeKind::Parent,
+        },
+    ];
+    expected_edges.sort_by_key(|edge| edge.child.clone());
+    assert_eq!(edges, expected_edges);
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_diff() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--diff"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        @ 96d1c37 create test2.txt
+        There is no previous smartlog to compare against.
+        "###);
+    }
+
+    git.commit_file("test3", 3)?;
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--diff"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        |
+        @ 70deb1e (new) create test3.txt
+        "###);
+    }
+
+    git.run(&["checkout", "HEAD^"])?;
+    git.branchless("hide", &["70deb1e"])?;
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--diff"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        @ 96d1c37 create test2.txt
+        Removed since the last smartlog:
+        - 70deb1e create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_diff_track_changes() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.branchless("smartlog", &["--diff"])?;
+
+    // A smartlog rendered without `--diff` isn't recorded by default.
+    git.commit_file("test3", 3)?;
+    git.smartlog()?;
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--diff"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        |
+        @ 70deb1e (new) create test3.txt
+        "###);
+    }
+
+    git.run(&["config", "branchless.smartlog.trackChanges", "true"])?;
+    git.commit_file("test4", 4)?;
+    git.smartlog()?;
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--diff"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        |
+        o 70deb1e create test3.txt
+        |
+        @ 355e173 create test4.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_stack() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", "--detach", "master"])?;
+    git.commit_file("test4", 4)?;
+    git.run(&["checkout", "70deb1e"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--stack"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        |
+        @ 70deb1e create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--stack", "96d1c37"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        |
+        @ 70deb1e create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_chronological_order() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "--detach", "master"])?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", "96d1c37"])?;
+    git.commit_file("test4", 4)?;
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |\
+        | o 96d1c37 create test2.txt
+        | |
+        | @ f57e36f create test4.txt
+        |
+        o 4838e49 create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--order", "chronological"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |\
+        | o 4838e49 create test3.txt
+        |
+        o 96d1c37 create test2.txt
+        |
+        @ f57e36f create test4.txt
+        "###);
+    }
+
+    git.run(&["config", "branchless.smartlog.order", "chronological"])?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |\
+        | o 4838e49 create test3.txt
+        |
+        o 96d1c37 create test2.txt
+        |
+        @ f57e36f create test4.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_make_commit_graph_rewritten() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", "HEAD^"])?;
+    git.run(&["commit", "--amend", "-m", "amended test2"])?;
+
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let repo = git.get_repo()?;
+    let amended_oid = repo.get_head_info()?.oid.unwrap();
+    let graph = make_commit_graph(&effects, &repo, None, &ResolveRevsetOptions::default())?;
+
+    let test2_node = graph
+        .nodes
+        .iter()
+        .find(|node| node.oid == test2_oid.to_string())
+        .unwrap();
+    assert!(test2_node.is_obsolete);
+    assert_eq!(test2_node.rewritten_as, Some(amended_oid.to_string()));
+    assert!(graph.edges.contains(&CommitEdge {
+        parent: test2_oid.to_string(),
+        child: amended_oid.to_string(),
+        kind: CommitEdgeKind::Rewritten,
+    }));
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_limit_stacks() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    for (name, time) in [("test2", 2), ("test3", 3), ("test4", 4)] {
+        git.run(&["checkout", "--detach", "master"])?;
+        git.commit_file(name, time)?;
+    }
+    git.run(&["checkout", "master"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--limit", "1"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d (> master) create test1.txt
+        |
+        o bf0d52a create test4.txt
+        2 less recently active stacks not shown (use --limit 0 to show all stacks)
+        "###);
+    }
+
+    // The stack containing `HEAD` is always shown.
+    git.run(&["checkout", "96d1c37"])?;
+    git.run(&["config", "branchless.smartlog.stackLimit", "1"])?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |\
+        | @ 96d1c37 create test2.txt
+        |
+        o bf0d52a create test4.txt
+        1 less recently active stack not shown (use --limit 0 to show all stacks)
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_make_commit_graph_additional_main_branches() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "release-1.x"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "release-1.x"])?;
+    let test3_oid = git.commit_file("test3", 3)?;
+    git.detach_head()?;
+    let test4_oid = git.commit_file("test4", 4)?;
+    git.run(&[
+        "config",
+        "branchless.core.additionalMainBranches",
+        "release-1.x",
+    ])?;
+
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let repo = git.get_repo()?;
+    let graph = make_commit_graph(&effects, &repo, None, &ResolveRevsetOptions::default())?;
+
+    let find_node = |oid: NonZeroOid| {
+        graph
+            .nodes
+            .iter()
+            .find(|node| node.oid == oid.to_string())
+            .unwrap()
+    };
+    assert!(find_node(test3_oid).is_main);
+    assert!(!find_node(test4_oid).is_main);
+    assert!(graph.edges.contains(&CommitEdge {
+        parent: test3_oid.to_string(),
+        child: test4_oid.to_string(),
+        kind: CommitEdgeKind::Parent,
+    }));
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_diff_stat() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--stat"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        @ 96d1c37 +1 -0 create test2.txt
+        "###);
+    }
+
+    // The second time, the diff stat is read from the cache.
+    git.run(&["config", "branchless.commitDescriptors.diffStat", "true"])?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        @ 96d1c37 +1 -0 create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_upstream_divergence() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "-b", "feature"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "--set-upstream-to", "master"])?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test3", 3)?;
+    git.run(&[
+        "config",
+        "branchless.commitDescriptors.upstreamDivergence",
+        "true",
+    ])?;
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d create test1.txt
+        |\
+        | o 96d1c37 (feature ^1 v1) create test2.txt
+        |
+        @ 4838e49 (> master) create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_fit_lines_to_width() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    let repo = git.get_repo()?;
+    let glyphs = Glyphs::text();
+    let colors = get_smartlog_colors(&repo)?;
+
+    let lines = [
+        "O 62fc20d create test1.txt",
+        "|\\",
+        "| o 96d1c37 create a long subject line here",
+        "|",
+        "@ 4838e49 create another long subject line",
+    ]
+    .into_iter()
+    .map(StyledString::plain)
+    .collect::<Vec<_>>();
+    let render = |lines: Vec<StyledString>| -> eyre::Result<String> {
+        let mut result = String::new();
+        for line in lines {
+            result.push_str(&glyphs.render(line)?);
+            result.push('\n');
+        }
+        Ok(result)
+    };
+
+    let truncated = fit_lines_to_width(
+        &glyphs,
+        &colors,
+        lines.clone(),
+        30,
+        SmartlogLongLines::Truncate,
+    );
+    insta::assert_snapshot!(render(truncated)?, @r###"
+    O 62fc20d create test1.txt
+    |\
+    | o 96d1c37 create a long s...
+    |
+    @ 4838e49 create another lo...
+    "###);
+
+    let wrapped = fit_lines_to_width(&glyphs, &colors, lines, 30, SmartlogLongLines::Wrap);
+    insta::assert_snapshot!(render(wrapped)?, @r###"
+    O 62fc20d create test1.txt
+    |\
+    | o 96d1c37 create a long
+    |   subject line here
+    |
+    @ 4838e49 create another long
+      subject line
+    "###);
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_reverse_config() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.run(&["config", "branchless.smartlog.reverse", "true"])?;
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ 96d1c37 create test2.txt
+        |
+        O 62fc20d (master) create test1.txt
+        :
+        "###);
+    }
+
+    // `--reverse` inverts the configured order.
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--reverse"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        @ 96d1c37 create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_shallow_clone() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.commit_file("test2", 2)?;
+    original_repo.run(&["checkout", "-b", "feature", "master~2"])?;
+    original_repo.commit_file("test3", 3)?;
+    original_repo.run(&["checkout", "master"])?;
+
+    original_repo.clone_repo_into(&cloned_repo, &["--depth", "1", "--no-single-branch"])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.run(&["branch", "feature", "origin/feature"])?;
+
+    {
+        let stdout = cloned_repo.smartlog()?;
+        assert!(
+            stdout.contains(
+                "hint: this repository is a shallow clone, so 1 commit could not be connected to the main branch"
+            ),
+            "{stdout}"
+        );
+        assert!(
+            stdout.contains("hint: to fetch the missing history, run: git fetch --unshallow"),
+            "{stdout}"
+        );
+    }
+
+    // Deepening the clone invalidates the cached commit graph, so the
+    // previously-disconnected commit is now attached to the main branch.
+    cloned_repo.run(&["fetch", "--unshallow"])?;
+    {
+        let stdout = cloned_repo.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc create initial.txt
+        |\
+        : o 98b9119 (feature) create test3.txt
+        :
+        @ 96d1c37 (> master) create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_replace_refs() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    let test3_oid = git.commit_file("test3", 3)?;
+
+    let smartlog_before = git.smartlog()?;
+    insta::assert_snapshot!(smartlog_before, @r###"
+    :
+    O 62fc20d (master) create test1.txt
+    |
+    o 96d1c37 create test2.txt
+    |
+    @ 70deb1e create test3.txt
+    "###);
+
+    git.run(&[
+        "replace",
+        "--graft",
+        &test3_oid.to_string(),
+        &test1_oid.to_string(),
+    ])?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |\
+        | o 96d1c37 create test2.txt
+        |
+        @ 70deb1e create test3.txt
+        "###);
+    }
+
+    git.run(&["replace", "-d", &test3_oid.to_string()])?;
+    {
+        let stdout = git.smartlog()?;
+        assert_eq!(stdout, smartlog_before);
     }
 
     Ok(())
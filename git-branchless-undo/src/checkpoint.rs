@@ -0,0 +1,76 @@
+//! Save named checkpoints of the repository state, and restore them later.
+//!
+//! A checkpoint is an event transaction containing a working copy snapshot.
+//! Restoring a checkpoint undoes every event which happened after it.
+
+use std::fmt::Write;
+use std::io::stdin;
+use std::time::SystemTime;
+
+use lib::core::check_out::create_snapshot;
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::git::{GitRunInfo, Repo};
+use lib::util::{ExitCode, EyreExitOr};
+use tracing::instrument;
+
+use crate::undo_events;
+
+fn make_checkpoint_message(name: &str) -> String {
+    format!("checkpoint save {name}")
+}
+
+/// Record the current positions of all branches and `HEAD`, as well as the
+/// working copy contents, under the given name.
+#[instrument]
+pub fn save(effects: &Effects, git_run_info: &GitRunInfo, name: &str) -> EyreExitOr<()> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_tx_id =
+        event_log_db.make_transaction_id(SystemTime::now(), make_checkpoint_message(name))?;
+    create_snapshot(effects, git_run_info, &repo, &event_log_db, event_tx_id)?;
+    writeln!(effects.get_output_stream(), "Saved checkpoint {name}.")?;
+    Ok(Ok(()))
+}
+
+/// Return the repository to the state recorded by the most recent checkpoint
+/// with the given name.
+#[instrument]
+pub fn restore(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    name: &str,
+    skip_confirmation: bool,
+) -> EyreExitOr<()> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let mut event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+
+    let event_cursor = event_log_db
+        .get_latest_transaction_with_message(&make_checkpoint_message(name))?
+        .and_then(|event_tx_id| event_replayer.make_cursor_after_transaction(event_tx_id));
+    let event_cursor = match event_cursor {
+        Some(event_cursor) => event_cursor,
+        None => {
+            writeln!(
+                effects.get_output_stream(),
+                "There is no checkpoint named {name}."
+            )?;
+            return Ok(Err(ExitCode(1)));
+        }
+    };
+
+    undo_events(
+        &mut stdin(),
+        effects,
+        &repo,
+        git_run_info,
+        &mut event_log_db,
+        &event_replayer,
+        event_cursor,
+        &format!("checkpoint restore {name}"),
+        skip_confirmation,
+    )
+}
@@ -12,13 +12,18 @@
 )]
 #![allow(clippy::too_many_arguments, clippy::blocks_in_if_conditions)]
 
+pub mod checkpoint;
 pub mod tui;
 
+use std::collections::HashMap;
 use std::fmt::Write;
 use std::io::{stdin, BufRead, BufReader, Read};
 use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
 use std::time::SystemTime;
 
+use chrono::{DateTime, Local};
+use chrono_english::{parse_date_string, parse_duration, Dialect, Interval};
+use chronoutil::RelativeDuration;
 use cursive_core::event::Key;
 use cursive_core::traits::Resizable;
 use cursive_core::utils::markup::StyledString;
@@ -27,7 +32,9 @@ use cursive_core::views::{
 };
 use cursive_core::{Cursive, CursiveRunner};
 use eyre::Context;
-use lib::core::check_out::{check_out_commit, CheckOutCommitOptions, CheckoutTarget};
+use lib::core::check_out::{
+    check_out_commit, create_snapshot, CheckOutCommitOptions, CheckoutTarget,
+};
 use lib::core::repo_ext::RepoExt;
 use lib::try_exit_code;
 use lib::util::{ExitCode, EyreExitOr};
@@ -38,14 +45,19 @@ use git_branchless_revset::resolve_default_smartlog_commits;
 use git_branchless_smartlog::{make_smartlog_graph, render_graph};
 use lib::core::dag::{CommitSet, Dag};
 use lib::core::effects::Effects;
-use lib::core::eventlog::{Event, EventCursor, EventLogDb, EventReplayer, EventTransactionId};
+use lib::core::eventlog::{
+    Event, EventCursor, EventLogDb, EventReplayer, EventTransactionId, TransactionOrigin,
+};
 use lib::core::formatting::{Glyphs, Pluralize, StyledStringBuilder};
 use lib::core::node_descriptors::{
     BranchesDescriptor, CommitMessageDescriptor, CommitOidDescriptor,
     DifferentialRevisionDescriptor, ObsolescenceExplanationDescriptor, Redactor,
     RelativeTimeDescriptor,
 };
-use lib::git::{CategorizedReferenceName, GitRunInfo, MaybeZeroOid, Repo, ResolvedReferenceInfo};
+use lib::git::{
+    CategorizedReferenceName, GitRunInfo, GitRunResult, MaybeZeroOid, NonZeroOid, Repo,
+    ResolvedReferenceInfo, WorkingCopySnapshot,
+};
 
 fn render_cursor_smartlog(
     effects: &Effects,
@@ -85,6 +97,7 @@ fn render_cursor_smartlog(
         event_cursor,
         &commits,
         false,
+        false,
     )?;
     let result = render_graph(
         effects,
@@ -128,6 +141,20 @@ fn describe_event(glyphs: &Glyphs, repo: &Repo, event: &Event) -> eyre::Result<V
             ]
         }
 
+        Event::CommitObservedEvent {
+            timestamp: _,
+            event_tx_id: _,
+            commit_oid,
+        } => {
+            vec![
+                StyledStringBuilder::new()
+                    .append_plain("Observe commit ")
+                    .append(repo.friendly_describe_commit_from_oid(glyphs, *commit_oid)?)
+                    .build(),
+                StyledString::new(),
+            ]
+        }
+
         Event::ObsoleteEvent {
             timestamp: _,
             event_tx_id: _,
@@ -168,6 +195,34 @@ fn describe_event(glyphs: &Glyphs, repo: &Repo, event: &Event) -> eyre::Result<V
             ]
         }
 
+        Event::PinEvent {
+            timestamp: _,
+            event_tx_id: _,
+            commit_oid,
+        } => {
+            vec![
+                StyledStringBuilder::new()
+                    .append_plain("Pin commit ")
+                    .append(repo.friendly_describe_commit_from_oid(glyphs, *commit_oid)?)
+                    .build(),
+                StyledString::new(),
+            ]
+        }
+
+        Event::UnpinEvent {
+            timestamp: _,
+            event_tx_id: _,
+            commit_oid,
+        } => {
+            vec![
+                StyledStringBuilder::new()
+                    .append_plain("Unpin commit ")
+                    .append(repo.friendly_describe_commit_from_oid(glyphs, *commit_oid)?)
+                    .build(),
+                StyledString::new(),
+            ]
+        }
+
         Event::RefUpdateEvent {
             timestamp: _,
             event_tx_id: _,
@@ -288,6 +343,45 @@ fn describe_event(glyphs: &Glyphs, repo: &Repo, event: &Event) -> eyre::Result<V
             ]
         }
 
+        Event::RefRenameEvent {
+            timestamp: _,
+            event_tx_id: _,
+            old_ref_name,
+            new_ref_name,
+            oid,
+        } => {
+            vec![
+                StyledStringBuilder::new()
+                    .append_plain("Rename ")
+                    .append_plain(CategorizedReferenceName::new(old_ref_name).friendly_describe())
+                    .append_plain(" to ")
+                    .append_plain(CategorizedReferenceName::new(new_ref_name).friendly_describe())
+                    .append_plain(" at ")
+                    .append(repo.friendly_describe_commit_from_oid(glyphs, *oid)?)
+                    .build(),
+                StyledString::new(),
+            ]
+        }
+
+        Event::RemoteRefUpdateEvent {
+            timestamp,
+            event_tx_id,
+            ref_name,
+            old_oid,
+            new_oid,
+        } => describe_event(
+            glyphs,
+            repo,
+            &Event::RefUpdateEvent {
+                timestamp: *timestamp,
+                event_tx_id: *event_tx_id,
+                ref_name: ref_name.clone(),
+                old_oid: *old_oid,
+                new_oid: *new_oid,
+                message: None,
+            },
+        )?,
+
         Event::RewriteEvent {
             timestamp: _,
             event_tx_id: _,
@@ -406,10 +500,30 @@ fn describe_events_numbered(
     repo: &Repo,
     events: &[Event],
 ) -> Result<Vec<StyledString>, eyre::Error> {
+    let index_snapshot_oids = find_index_snapshots(events);
     let mut lines = Vec::new();
     for (i, event) in (1..).zip(events) {
         let num_header = format!("{i}. ");
-        for (j, event_line) in (0..).zip(describe_event(glyphs, repo, event)?) {
+        let event_lines = match event {
+            Event::WorkingCopySnapshot {
+                timestamp: _,
+                event_tx_id: _,
+                head_oid: MaybeZeroOid::NonZero(head_oid),
+                commit_oid,
+                ref_name: _,
+            } if index_snapshot_oids.contains_key(head_oid) => vec![
+                StyledStringBuilder::new()
+                    .append_plain("Restore staged changes from ")
+                    .append(repo.friendly_describe_commit_from_oid(glyphs, *head_oid)?)
+                    .build(),
+                StyledStringBuilder::new()
+                    .append_plain("            backed up using ")
+                    .append(repo.friendly_describe_commit_from_oid(glyphs, *commit_oid)?)
+                    .build(),
+            ],
+            event => describe_event(glyphs, repo, event)?,
+        };
+        for (j, event_line) in (0..).zip(event_lines) {
             let prefix = if j == 0 {
                 num_header.clone()
             } else {
@@ -441,6 +555,7 @@ fn select_past_event(
         Previous,
         GoToEvent,
         SetEventReplayerCursor { event_id: isize },
+        ToggleComparison,
         Help,
         Quit,
         SelectEventIdAndQuit,
@@ -459,6 +574,8 @@ fn select_past_event(
         ('?'.into(), Message::Help),
         ('g'.into(), Message::GoToEvent),
         ('G'.into(), Message::GoToEvent),
+        ('c'.into(), Message::ToggleComparison),
+        ('C'.into(), Message::ToggleComparison),
         ('q'.into(), Message::Quit),
         ('Q'.into(), Message::Quit),
         (
@@ -475,7 +592,20 @@ fn select_past_event(
         });
     });
 
+    let (transaction_messages, transaction_reasons, transaction_commands, transaction_origins) = {
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        (
+            event_log_db.get_transaction_messages()?,
+            event_log_db.get_transaction_reasons()?,
+            event_log_db.get_transaction_commands()?,
+            event_log_db.get_transaction_origins()?,
+        )
+    };
+    let current_origin = TransactionOrigin::current();
+
     let mut cursor = event_replayer.make_default_cursor();
+    let mut show_comparison = false;
     let now = SystemTime::now();
     main_tx.send(Message::Init)?;
     while siv.is_running() {
@@ -495,10 +625,35 @@ fn select_past_event(
 
         let redraw = |siv: &mut Cursive,
                       event_replayer: &mut EventReplayer,
-                      event_cursor: EventCursor|
+                      event_cursor: EventCursor,
+                      show_comparison: bool|
          -> eyre::Result<()> {
-            let smartlog =
-                render_cursor_smartlog(effects, repo, dag, event_replayer, event_cursor)?;
+            let smartlog = if show_comparison {
+                // Show the state before the selected transaction alongside the
+                // state after it, so that it's clear what the transaction did.
+                let previous_cursor =
+                    event_replayer.advance_cursor_by_transaction(event_cursor, -1);
+                let mut lines = vec![StyledString::plain("Before this transaction:")];
+                lines.extend(render_cursor_smartlog(
+                    effects,
+                    repo,
+                    dag,
+                    event_replayer,
+                    previous_cursor,
+                )?);
+                lines.push(StyledString::new());
+                lines.push(StyledString::plain("After this transaction:"));
+                lines.extend(render_cursor_smartlog(
+                    effects,
+                    repo,
+                    dag,
+                    event_replayer,
+                    event_cursor,
+                )?);
+                lines
+            } else {
+                render_cursor_smartlog(effects, repo, dag, event_replayer, event_cursor)?
+            };
             SmartlogView::find(siv)
                 .get_inner_mut()
                 .set_content(StyledStringBuilder::from_lines(smartlog));
@@ -513,16 +668,39 @@ fn select_past_event(
                         describe_events_numbered(effects.get_glyphs(), repo, events)?;
                     let relative_time_provider = RelativeTimeDescriptor::new(repo, now)?;
                     let relative_time = if relative_time_provider.is_enabled() {
+                        let timestamp = events[0].get_timestamp();
                         format!(
-                            " ({} ago)",
-                            RelativeTimeDescriptor::describe_time_delta(
-                                now,
-                                events[0].get_timestamp()
-                            )?
+                            " ({}, {} ago)",
+                            DateTime::<Local>::from(timestamp).format("%H:%M"),
+                            RelativeTimeDescriptor::describe_time_delta(now, timestamp)?
                         )
                     } else {
                         String::new()
                     };
+                    // Describe the transaction by what the user did (e.g.
+                    // `rebase feature main`), so that it's easier to pick out
+                    // the step to return to.
+                    let message = match (
+                        transaction_messages.get(&events[0].get_event_tx_id()),
+                        transaction_reasons.get(&events[0].get_event_tx_id()),
+                    ) {
+                        (Some(message), Some(reason)) => format!(": {message} ({reason})"),
+                        (Some(message), None) => format!(": {message}"),
+                        (None, Some(reason)) => format!(": {reason}"),
+                        (None, None) => String::new(),
+                    };
+                    let command = match transaction_commands.get(&events[0].get_event_tx_id()) {
+                        Some(command) => format!(" via `{command}`"),
+                        None => String::new(),
+                    };
+                    // Only call out transactions made on other machines or by
+                    // other users.
+                    let origin = match transaction_origins.get(&events[0].get_event_tx_id()) {
+                        Some(origin) if origin.differs_from(&current_origin) => {
+                            format!(" on {origin}")
+                        }
+                        Some(_) | None => String::new(),
+                    };
 
                     let mut lines = vec![StyledStringBuilder::new()
                         .append_plain("Repo after transaction ")
@@ -530,6 +708,9 @@ fn select_past_event(
                         .append_plain(" (event ")
                         .append_plain(event_id.to_string())
                         .append_plain(")")
+                        .append_plain(message)
+                        .append_plain(command)
+                        .append_plain(origin)
                         .append_plain(relative_time)
                         .append_plain(". Press 'h' for help, 'q' to quit.")
                         .build()];
@@ -563,22 +744,27 @@ fn select_past_event(
                         .child(Panel::new(ScrollView::new(info_view)).title("Events"))
                         .full_width(),
                 );
-                redraw(&mut siv, event_replayer, cursor)?;
+                redraw(&mut siv, event_replayer, cursor, show_comparison)?;
             }
 
             Ok(Message::Next) => {
                 cursor = event_replayer.advance_cursor_by_transaction(cursor, 1);
-                redraw(&mut siv, event_replayer, cursor)?;
+                redraw(&mut siv, event_replayer, cursor, show_comparison)?;
             }
 
             Ok(Message::Previous) => {
                 cursor = event_replayer.advance_cursor_by_transaction(cursor, -1);
-                redraw(&mut siv, event_replayer, cursor)?;
+                redraw(&mut siv, event_replayer, cursor, show_comparison)?;
             }
 
             Ok(Message::SetEventReplayerCursor { event_id }) => {
                 cursor = event_replayer.make_cursor(event_id);
-                redraw(&mut siv, event_replayer, cursor)?;
+                redraw(&mut siv, event_replayer, cursor, show_comparison)?;
+            }
+
+            Ok(Message::ToggleComparison) => {
+                show_comparison = !show_comparison;
+                redraw(&mut siv, event_replayer, cursor, show_comparison)?;
             }
 
             Ok(Message::GoToEvent) => {
@@ -621,6 +807,7 @@ h/?: Show this help.
 q: Quit.
 p/n or <left>/<right>: View next/previous state.
 g: Go to a provided event ID.
+c: Compare the commit graph before and after the given state.
 <enter>: Revert the repository to the given state (requires confirmation).
 
 You can also copy a commit hash from the past and manually run `git unhide` or `git rebase` on it.
@@ -678,6 +865,26 @@ fn inverse_event(
             commit_oid,
         },
 
+        Event::PinEvent {
+            timestamp: _,
+            event_tx_id: _,
+            commit_oid,
+        } => Event::UnpinEvent {
+            timestamp,
+            event_tx_id,
+            commit_oid,
+        },
+
+        Event::UnpinEvent {
+            timestamp: _,
+            event_tx_id: _,
+            commit_oid,
+        } => Event::PinEvent {
+            timestamp,
+            event_tx_id,
+            commit_oid,
+        },
+
         Event::RewriteEvent {
             timestamp: _,
             event_tx_id: _,
@@ -706,6 +913,38 @@ fn inverse_event(
             message: None,
         },
 
+        Event::RefRenameEvent {
+            timestamp: _,
+            event_tx_id: _,
+            old_ref_name,
+            new_ref_name,
+            oid,
+        } => Event::RefRenameEvent {
+            timestamp,
+            event_tx_id,
+            old_ref_name: new_ref_name,
+            new_ref_name: old_ref_name,
+            oid,
+        },
+
+        Event::RemoteRefUpdateEvent {
+            timestamp: _,
+            event_tx_id: _,
+            ref_name,
+            old_oid,
+            new_oid,
+        } => Event::RemoteRefUpdateEvent {
+            timestamp,
+            event_tx_id,
+            ref_name,
+            old_oid: new_oid,
+            new_oid: old_oid,
+        },
+
+        Event::CommitObservedEvent { .. } => {
+            eyre::bail!("Cannot invert commit-observed event: {event:?}")
+        }
+
         // This isn't really an "invertible" event, in that there's no way to
         // calculate an inverse event that restores the working copy state to
         // *before* this snapshot.
@@ -722,11 +961,59 @@ fn inverse_event(
 struct UndoCheckoutTarget {
     target: CheckoutTarget,
     options: CheckOutCommitOptions,
+
+    /// The working copy snapshot whose index should be restored after
+    /// checking out the target, if any.
+    index_snapshot_oid: Option<NonZeroOid>,
+}
+
+/// Find the working copy snapshots which were taken at a commit which is being
+/// un-rewritten (such as by `git commit --amend`), keyed by that commit. They
+/// record the index at the time of the rewrite, and aren't checkout targets by
+/// themselves; instead, the index is restored from them after moving `HEAD`
+/// back to the original commit. If there are several for the same commit, the
+/// earliest one (the last inverse event) wins.
+///
+/// Only snapshots recorded right alongside the rewrite are considered, since
+/// snapshots taken before a later checkout (such as by `git branchless amend`)
+/// are regular checkout targets, even if they happen to be at the same commit.
+fn find_index_snapshots(events: &[Event]) -> HashMap<NonZeroOid, NonZeroOid> {
+    let is_rewrite_of = |event: Option<&Event>, oid: NonZeroOid| {
+        matches!(
+            event,
+            Some(Event::RewriteEvent {
+                timestamp: _,
+                event_tx_id: _,
+                old_commit_oid: MaybeZeroOid::NonZero(old_commit_oid),
+                new_commit_oid: _,
+            }) if *old_commit_oid == oid
+        )
+    };
+    events
+        .iter()
+        .enumerate()
+        .filter_map(|(i, event)| match event {
+            Event::WorkingCopySnapshot {
+                timestamp: _,
+                event_tx_id: _,
+                head_oid: MaybeZeroOid::NonZero(head_oid),
+                commit_oid,
+                ref_name: _,
+            } if is_rewrite_of(i.checked_sub(1).and_then(|i| events.get(i)), *head_oid)
+                || is_rewrite_of(events.get(i + 1), *head_oid) =>
+            {
+                Some((*head_oid, *commit_oid))
+            }
+            _ => None,
+        })
+        .collect()
 }
 
 fn extract_checkout_target(
     events: &[Event],
 ) -> eyre::Result<(Option<UndoCheckoutTarget>, Vec<&Event>)> {
+    let index_snapshot_oids = find_index_snapshots(events);
+
     let mut new_events = Vec::new();
     let mut checkout_target = None;
     for event in events.iter() {
@@ -735,20 +1022,58 @@ fn extract_checkout_target(
                 timestamp: _,
                 event_tx_id: _,
                 ref_name,
-                old_oid: _,
+                old_oid,
                 new_oid: MaybeZeroOid::NonZero(new_oid),
                 message: _,
             } if ref_name.as_str() == "HEAD" => {
+                // If `HEAD` is moving back to the commit that it was rewritten
+                // from (such as by `git commit --amend`), then only move `HEAD`
+                // and keep the index, so that the changes which were included
+                // in the rewrite are staged again, as they were beforehand.
+                let is_unrewrite = events.iter().any(|event| match event {
+                    Event::RewriteEvent {
+                        timestamp: _,
+                        event_tx_id: _,
+                        old_commit_oid,
+                        new_commit_oid: MaybeZeroOid::NonZero(new_commit_oid),
+                    } => old_commit_oid == old_oid && new_commit_oid == new_oid,
+                    _ => false,
+                });
+                let index_snapshot_oid = match old_oid {
+                    MaybeZeroOid::NonZero(old_oid) if is_unrewrite => {
+                        index_snapshot_oids.get(old_oid).copied()
+                    }
+                    _ => None,
+                };
                 checkout_target = Some(UndoCheckoutTarget {
                     target: CheckoutTarget::Oid(*new_oid),
-                    options: CheckOutCommitOptions {
-                        additional_args: vec!["--detach".into()],
-                        reset: false,
-                        render_smartlog: true,
+                    index_snapshot_oid,
+                    options: if is_unrewrite {
+                        CheckOutCommitOptions {
+                            additional_args: vec!["--soft".into()],
+                            reset: true,
+                            render_smartlog: true,
+                        }
+                    } else {
+                        CheckOutCommitOptions {
+                            additional_args: vec!["--detach".into()],
+                            reset: false,
+                            render_smartlog: true,
+                        }
                     },
                 });
             }
 
+            Event::WorkingCopySnapshot {
+                timestamp: _,
+                event_tx_id: _,
+                head_oid: MaybeZeroOid::NonZero(head_oid),
+                commit_oid: _,
+                ref_name: _,
+            } if index_snapshot_oids.contains_key(head_oid) => {
+                // Applied along with the corresponding `HEAD` update.
+            }
+
             Event::WorkingCopySnapshot {
                 timestamp: _,
                 event_tx_id: _,
@@ -758,6 +1083,7 @@ fn extract_checkout_target(
             } => {
                 checkout_target = Some(UndoCheckoutTarget {
                     target: CheckoutTarget::Oid(*commit_oid),
+                    index_snapshot_oid: None,
                     options: CheckOutCommitOptions {
                         additional_args: match ref_name {
                             Some(ref_name) => {
@@ -788,11 +1114,101 @@ fn undo_events(
     event_log_db: &mut EventLogDb,
     event_replayer: &EventReplayer,
     event_cursor: EventCursor,
+    event_tx_message: &str,
     skip_confirmation: bool,
 ) -> EyreExitOr<()> {
     let now = SystemTime::now();
-    let event_tx_id = event_log_db.make_transaction_id(now, "undo")?;
-    let head_info = repo.get_head_info()?;
+    let event_tx_id = event_log_db.make_transaction_id(now, event_tx_message)?;
+    let UndoPlan { inverse_events } = plan(event_replayer, event_cursor, now, event_tx_id)?;
+    apply_inverse_events(
+        in_,
+        effects,
+        repo,
+        git_run_info,
+        event_log_db,
+        event_tx_id,
+        &inverse_events,
+        skip_confirmation,
+    )
+}
+
+/// The actions needed to return the repository to the state it was in at a
+/// previous point in the event log. Use [`plan`] to create one, and [`apply`]
+/// to carry it out.
+#[derive(Clone, Debug)]
+pub struct UndoPlan {
+    /// The events which invert each event since the target point, in the
+    /// order in which they will be applied.
+    pub inverse_events: Vec<Event>,
+}
+
+impl UndoPlan {
+    /// Whether applying this plan would have no effect.
+    pub fn is_empty(&self) -> bool {
+        self.inverse_events.is_empty()
+    }
+
+    /// Get the commit which will be checked out when applying this plan, if
+    /// `HEAD` or the working copy will change.
+    pub fn get_checkout_target(&self) -> eyre::Result<Option<CheckoutTarget>> {
+        let (checkout_target, _events) = extract_checkout_target(&self.inverse_events)?;
+        Ok(checkout_target.map(
+            |UndoCheckoutTarget {
+                 target,
+                 options: _,
+                 index_snapshot_oid: _,
+             }| target,
+        ))
+    }
+
+    /// Get the updates to local and remote references which will be made
+    /// when applying this plan. Updates to `HEAD` are described by
+    /// [`UndoPlan::get_checkout_target`] instead.
+    pub fn get_ref_updates(&self) -> eyre::Result<Vec<&Event>> {
+        let (_checkout_target, events) = extract_checkout_target(&self.inverse_events)?;
+        Ok(events
+            .into_iter()
+            .filter(|event| {
+                matches!(
+                    event,
+                    Event::RefUpdateEvent { .. }
+                        | Event::RefRenameEvent { .. }
+                        | Event::RemoteRefUpdateEvent { .. }
+                )
+            })
+            .collect())
+    }
+
+    /// Get the events which will change the visibility of commits when
+    /// applying this plan, such as hiding or unhiding them.
+    pub fn get_visibility_changes(&self) -> Vec<&Event> {
+        self.inverse_events
+            .iter()
+            .filter(|event| {
+                matches!(
+                    event,
+                    Event::CommitEvent { .. }
+                        | Event::ObsoleteEvent { .. }
+                        | Event::UnobsoleteEvent { .. }
+                        | Event::PinEvent { .. }
+                        | Event::UnpinEvent { .. }
+                        | Event::RewriteEvent { .. }
+                )
+            })
+            .collect()
+    }
+}
+
+/// Compute the plan to return the repository to the state it was in at the
+/// given cursor, without making any changes. The inverse events are stamped
+/// with the given time and transaction ID, which should be the ones later
+/// passed to [`apply`].
+pub fn plan(
+    event_replayer: &EventReplayer,
+    event_cursor: EventCursor,
+    now: SystemTime,
+    event_tx_id: EventTransactionId,
+) -> eyre::Result<UndoPlan> {
     let inverse_events: Vec<Event> = event_replayer
         .get_events_since_cursor(event_cursor)
         .iter()
@@ -810,9 +1226,348 @@ fn undo_events(
                 } if ref_name.as_str() == "HEAD"
             )
         })
+        // Having observed a commit can't be undone.
+        .filter(|event| !matches!(event, Event::CommitObservedEvent { .. }))
         .map(|event| inverse_event(event.clone(), now, event_tx_id))
         .collect::<eyre::Result<Vec<Event>>>()?;
+    Ok(UndoPlan { inverse_events })
+}
+
+/// Carry out the given plan without asking for confirmation, recording the
+/// applied events under the given transaction ID.
+#[instrument]
+pub fn apply(
+    effects: &Effects,
+    repo: &Repo,
+    git_run_info: &GitRunInfo,
+    event_log_db: &mut EventLogDb,
+    event_tx_id: EventTransactionId,
+    plan: &UndoPlan,
+) -> EyreExitOr<()> {
+    apply_inverse_events(
+        &mut std::io::empty(),
+        effects,
+        repo,
+        git_run_info,
+        event_log_db,
+        event_tx_id,
+        &plan.inverse_events,
+        true,
+    )
+}
+
+/// Get the OID of the object which must exist in order to apply the given
+/// inverse event, if any.
+fn get_required_object_oid(event: &Event) -> Option<NonZeroOid> {
+    match event {
+        Event::RefUpdateEvent {
+            new_oid: MaybeZeroOid::NonZero(oid),
+            ..
+        }
+        | Event::RemoteRefUpdateEvent {
+            new_oid: MaybeZeroOid::NonZero(oid),
+            ..
+        }
+        | Event::RefRenameEvent { oid, .. }
+        | Event::WorkingCopySnapshot {
+            commit_oid: oid, ..
+        } => Some(*oid),
+
+        Event::RefUpdateEvent { .. }
+        | Event::RemoteRefUpdateEvent { .. }
+        | Event::CommitEvent { .. }
+        | Event::CommitObservedEvent { .. }
+        | Event::ObsoleteEvent { .. }
+        | Event::UnobsoleteEvent { .. }
+        | Event::PinEvent { .. }
+        | Event::UnpinEvent { .. }
+        | Event::RewriteEvent { .. } => None,
+    }
+}
+
+/// Uncommitted changes which were removed from the working copy so that `HEAD`
+/// could be moved, and which should be reapplied afterwards.
+#[derive(Debug)]
+struct StashedChanges {
+    /// The working copy snapshot recording the changes, which can be restored
+    /// if reapplying them fails.
+    snapshot_oid: NonZeroOid,
+
+    /// The stash commit created by `git stash create`, which is used to
+    /// reapply the changes on top of the new `HEAD`.
+    stash_oid: NonZeroOid,
+}
+
+/// If there are uncommitted changes to tracked files, record them in a working
+/// copy snapshot and then discard them, so that checking out another commit
+/// doesn't fail or clobber them. Returns `None` if there were no changes.
+#[instrument]
+fn stash_working_copy_changes(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    event_tx_id: EventTransactionId,
+) -> EyreExitOr<Option<StashedChanges>> {
+    // `git stash create` prints nothing if there are no changes, and fails if
+    // there are unresolved merge conflicts.
+    let GitRunResult {
+        exit_code,
+        stdout,
+        stderr: _,
+    } = git_run_info
+        .run_silent(
+            repo,
+            Some(event_tx_id),
+            &["stash", "create"],
+            Default::default(),
+        )
+        .wrap_err("Saving working copy changes")?;
+    if !exit_code.is_success() {
+        writeln!(
+            effects.get_output_stream(),
+            "Could not save the uncommitted changes in the working copy. Resolve any merge conflicts and try again."
+        )?;
+        return Ok(Err(exit_code));
+    }
+    let stash_oid: NonZeroOid = match std::str::from_utf8(&stdout)?.trim() {
+        "" => return Ok(Ok(None)),
+        stash_oid => stash_oid.parse()?,
+    };
+
+    let snapshot = create_snapshot(effects, git_run_info, repo, event_log_db, event_tx_id)?;
+    let GitRunResult {
+        exit_code,
+        stdout: _,
+        stderr: _,
+    } = git_run_info
+        .run_silent(
+            repo,
+            Some(event_tx_id),
+            &["reset", "--hard", "HEAD", "--"],
+            Default::default(),
+        )
+        .wrap_err("Discarding working copy changes")?;
+    if !exit_code.is_success() {
+        return Ok(Err(exit_code));
+    }
+
+    Ok(Ok(Some(StashedChanges {
+        snapshot_oid: snapshot.base_commit.get_oid(),
+        stash_oid,
+    })))
+}
+
+/// Reapply the changes removed by `stash_working_copy_changes` on top of the
+/// current `HEAD`.
+#[instrument]
+fn unstash_working_copy_changes(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    stashed_changes: &StashedChanges,
+) -> EyreExitOr<()> {
+    let StashedChanges {
+        snapshot_oid,
+        stash_oid,
+    } = stashed_changes;
+    let GitRunResult {
+        exit_code,
+        stdout: _,
+        stderr: _,
+    } = git_run_info
+        .run_silent(
+            repo,
+            Some(event_tx_id),
+            &["stash", "apply", "--index", &stash_oid.to_string()],
+            Default::default(),
+        )
+        .wrap_err("Reapplying working copy changes")?;
+    if !exit_code.is_success() {
+        writeln!(
+            effects.get_output_stream(),
+            "Could not reapply the uncommitted changes from before the undo. They were saved in working copy snapshot {snapshot_oid}; to return to that working copy, run: git branchless snapshot restore {snapshot_oid}"
+        )?;
+        return Ok(Err(exit_code));
+    }
+
+    writeln!(
+        effects.get_output_stream(),
+        "Reapplied uncommitted changes."
+    )?;
+    Ok(Ok(()))
+}
+
+/// Replace the index with the staged contents of the given working copy
+/// snapshot, leaving `HEAD` and the working copy as they are.
+fn restore_index(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    snapshot_oid: NonZeroOid,
+) -> EyreExitOr<()> {
+    let snapshot = match repo.find_commit(snapshot_oid)? {
+        Some(base_commit) => WorkingCopySnapshot::try_from_base_commit(repo, &base_commit)?,
+        None => None,
+    };
+    let snapshot = match snapshot {
+        Some(snapshot) => snapshot,
+        None => {
+            writeln!(
+                effects.get_output_stream(),
+                "Could not find working copy snapshot {snapshot_oid}, not restoring the index."
+            )?;
+            return Ok(Ok(()));
+        }
+    };
+
+    let tree_arg = match snapshot.commit_stage0.get_tree_oid() {
+        MaybeZeroOid::NonZero(tree_oid) => tree_oid.to_string(),
+        MaybeZeroOid::Zero => "--empty".to_string(),
+    };
+    let GitRunResult {
+        exit_code,
+        stdout: _,
+        stderr: _,
+    } = git_run_info
+        .run_silent(
+            repo,
+            Some(event_tx_id),
+            &["read-tree", &tree_arg],
+            Default::default(),
+        )
+        .wrap_err("Restoring index")?;
+    if !exit_code.is_success() {
+        writeln!(
+            effects.get_output_stream(),
+            "Could not restore the index from working copy snapshot {snapshot_oid}."
+        )?;
+        return Ok(Err(exit_code));
+    }
+    Ok(Ok(()))
+}
+
+/// Determine whether `later_event` updates any of the same references or
+/// commits as `event`, in which case undoing `event` by itself would clobber
+/// the later change.
+fn events_conflict(event: &Event, later_event: &Event) -> bool {
+    match later_event {
+        // These don't change any references or the visibility of any commits.
+        Event::CommitObservedEvent { .. } | Event::WorkingCopySnapshot { .. } => false,
+
+        later_event => {
+            let ref_names = event.get_ref_names();
+            let commit_oids = event.get_commit_oids();
+            later_event
+                .get_ref_names()
+                .iter()
+                .any(|ref_name| ref_names.contains(ref_name))
+                || later_event
+                    .get_commit_oids()
+                    .iter()
+                    .any(|commit_oid| commit_oids.contains(commit_oid))
+        }
+    }
+}
+
+#[instrument(skip(in_))]
+fn undo_single_event(
+    in_: &mut impl Read,
+    effects: &Effects,
+    repo: &Repo,
+    git_run_info: &GitRunInfo,
+    event_log_db: &mut EventLogDb,
+    event_replayer: &EventReplayer,
+    event_id: isize,
+    skip_confirmation: bool,
+) -> EyreExitOr<()> {
+    let event_cursor = event_replayer.make_cursor(event_id);
+    let event = match event_replayer.get_event_before_cursor(event_cursor) {
+        Some((cursor_event_id, event)) if cursor_event_id == event_id => event,
+        Some(_) | None => {
+            writeln!(
+                effects.get_output_stream(),
+                "There is no event with ID {event_id}."
+            )?;
+            return Ok(Err(ExitCode(1)));
+        }
+    };
 
+    match event {
+        Event::CommitObservedEvent { .. } | Event::WorkingCopySnapshot { .. } => {
+            writeln!(
+                effects.get_output_stream(),
+                "Event {event_id} can't be undone by itself."
+            )?;
+            return Ok(Err(ExitCode(1)));
+        }
+        Event::RefUpdateEvent {
+            ref_name,
+            old_oid: MaybeZeroOid::Zero,
+            ..
+        } if ref_name.as_str() == "HEAD" => {
+            writeln!(
+                effects.get_output_stream(),
+                "Event {event_id} can't be undone by itself."
+            )?;
+            return Ok(Err(ExitCode(1)));
+        }
+        _ => {}
+    }
+
+    let conflicting_events: Vec<Event> = event_replayer
+        .get_events_since_cursor(event_cursor)
+        .iter()
+        .filter(|later_event| events_conflict(event, later_event))
+        .cloned()
+        .collect();
+    if !conflicting_events.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "Not undoing event {event_id}, because these later events affect the same references or commits:"
+        )?;
+        for line in describe_events_numbered(effects.get_glyphs(), repo, &conflicting_events)? {
+            writeln!(
+                effects.get_output_stream(),
+                "{}",
+                effects.get_glyphs().render(line)?
+            )?;
+        }
+        return Ok(Err(ExitCode(1)));
+    }
+
+    let now = SystemTime::now();
+    let event_tx_id = event_log_db.make_transaction_id(now, "undo")?;
+    let inverse_events = vec![inverse_event(event.clone(), now, event_tx_id)?];
+    apply_inverse_events(
+        in_,
+        effects,
+        repo,
+        git_run_info,
+        event_log_db,
+        event_tx_id,
+        &inverse_events,
+        skip_confirmation,
+    )
+}
+
+/// Describe the given inverse events, confirm them with the user (unless
+/// `skip_confirmation` is set), and then apply them as part of the transaction
+/// `event_tx_id`.
+#[instrument(skip(in_))]
+fn apply_inverse_events(
+    in_: &mut impl Read,
+    effects: &Effects,
+    repo: &Repo,
+    git_run_info: &GitRunInfo,
+    event_log_db: &mut EventLogDb,
+    event_tx_id: EventTransactionId,
+    inverse_events: &[Event],
+    skip_confirmation: bool,
+) -> EyreExitOr<()> {
+    let head_info = repo.get_head_info()?;
     if inverse_events.is_empty() {
         writeln!(
             effects.get_output_stream(),
@@ -820,8 +1575,41 @@ fn undo_events(
         )?;
         return Ok(Ok(()));
     }
+
+    // Check up front that the objects which references will be restored to
+    // still exist, rather than failing partway through.
+    let mut recoverable_events = Vec::new();
+    let mut unrecoverable_events = Vec::new();
+    for event in inverse_events {
+        match get_required_object_oid(event) {
+            Some(oid) if !repo.contains_object(oid)? => unrecoverable_events.push(event.clone()),
+            Some(_) | None => recoverable_events.push(event.clone()),
+        }
+    }
+    if !unrecoverable_events.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "These actions can't be applied, because the commits they refer to no longer exist:"
+        )?;
+        for line in describe_events_numbered(effects.get_glyphs(), repo, &unrecoverable_events)? {
+            writeln!(
+                effects.get_output_stream(),
+                "{}",
+                effects.get_glyphs().render(line)?
+            )?;
+        }
+        if recoverable_events.is_empty() {
+            writeln!(
+                effects.get_output_stream(),
+                "No undo actions can be applied, exiting."
+            )?;
+            return Ok(Err(ExitCode(1)));
+        }
+    }
+    let inverse_events = recoverable_events.as_slice();
+
     writeln!(effects.get_output_stream(), "Will apply these actions:")?;
-    let events = describe_events_numbered(effects.get_glyphs(), repo, &inverse_events)?;
+    let events = describe_events_numbered(effects.get_glyphs(), repo, inverse_events)?;
     for line in events {
         writeln!(
             effects.get_output_stream(),
@@ -856,7 +1644,17 @@ fn undo_events(
     }
     .to_string();
 
-    let (checkout_target, filtered_events) = extract_checkout_target(&inverse_events)?;
+    let (checkout_target, filtered_events) = extract_checkout_target(inverse_events)?;
+    let stashed_changes = match checkout_target {
+        Some(_) => try_exit_code!(stash_working_copy_changes(
+            effects,
+            git_run_info,
+            repo,
+            event_log_db,
+            event_tx_id
+        )?),
+        None => None,
+    };
     if checkout_target.is_some() {
         repo.detach_head(&head_info)?;
     }
@@ -882,6 +1680,7 @@ fn undo_events(
             } => match repo.find_reference(ref_name)? {
                 Some(mut reference) => {
                     reference.delete().wrap_err("Applying `RefUpdateEvent`")?;
+                    event_log_db.add_events(vec![event.clone()])?;
                 }
                 None => {
                     writeln!(
@@ -907,24 +1706,89 @@ fn undo_events(
                 new_oid: MaybeZeroOid::NonZero(new_oid),
                 message: _,
             } => {
-                // Create or update the given reference.
+                // Create or update the given reference. Since this doesn't
+                // go through Git, the reference-transaction hook won't record
+                // the update, so record it here so that it can be redone.
                 repo.create_reference(ref_name, *new_oid, true, "branchless undo")?;
+                event_log_db.add_events(vec![event.clone()])?;
+            }
+
+            Event::RefRenameEvent {
+                timestamp: _,
+                event_tx_id: _,
+                old_ref_name,
+                new_ref_name,
+                oid,
+            } => {
+                match repo.find_reference(old_ref_name)? {
+                    Some(mut reference) => {
+                        reference.delete().wrap_err("Applying `RefRenameEvent`")?;
+                    }
+                    None => {
+                        writeln!(
+                            effects.get_output_stream(),
+                            "Reference {} did not exist, not deleting it.",
+                            old_ref_name.as_str()
+                        )?;
+                    }
+                }
+                repo.create_reference(new_ref_name, *oid, true, "branchless undo")?;
+                event_log_db.add_events(vec![event.clone()])?;
             }
 
+            Event::RemoteRefUpdateEvent {
+                timestamp: _,
+                event_tx_id: _,
+                ref_name,
+                old_oid: _,
+                new_oid,
+            } => match new_oid {
+                MaybeZeroOid::NonZero(new_oid) => {
+                    repo.create_reference(ref_name, *new_oid, true, "branchless undo")?;
+                    event_log_db.add_events(vec![event.clone()])?;
+                }
+                MaybeZeroOid::Zero => match repo.find_reference(ref_name)? {
+                    Some(mut reference) => {
+                        reference
+                            .delete()
+                            .wrap_err("Applying `RemoteRefUpdateEvent`")?;
+                        event_log_db.add_events(vec![event.clone()])?;
+                    }
+                    None => {
+                        writeln!(
+                            effects.get_output_stream(),
+                            "Reference {} did not exist, not deleting it.",
+                            ref_name.as_str()
+                        )?;
+                    }
+                },
+            },
+
             Event::WorkingCopySnapshot { .. } => {
                 // Should be handled as the checkout target already.
             }
 
+            Event::CommitObservedEvent { .. } => {
+                // Do nothing.
+            }
+
             Event::CommitEvent { .. }
             | Event::ObsoleteEvent { .. }
             | Event::UnobsoleteEvent { .. }
+            | Event::PinEvent { .. }
+            | Event::UnpinEvent { .. }
             | Event::RewriteEvent { .. } => {
                 event_log_db.add_events(vec![event.clone()])?;
             }
         }
     }
 
-    if let Some(UndoCheckoutTarget { target, options }) = checkout_target {
+    if let Some(UndoCheckoutTarget {
+        target,
+        options,
+        index_snapshot_oid,
+    }) = checkout_target
+    {
         try_exit_code!(check_out_commit(
             effects,
             git_run_info,
@@ -935,18 +1799,57 @@ fn undo_events(
             &options,
         )
         .wrap_err("Updating to previous HEAD location")?);
+
+        if let Some(index_snapshot_oid) = index_snapshot_oid {
+            try_exit_code!(restore_index(
+                effects,
+                git_run_info,
+                repo,
+                event_tx_id,
+                index_snapshot_oid
+            )?);
+        }
+    }
+    if let Some(stashed_changes) = stashed_changes {
+        try_exit_code!(unstash_working_copy_changes(
+            effects,
+            git_run_info,
+            repo,
+            event_tx_id,
+            &stashed_changes
+        )?);
     }
 
     writeln!(effects.get_output_stream(), "Applied {num_inverse_events}.")?;
     Ok(Ok(()))
 }
 
+/// Parse a time given either as an absolute date, such as `2023-01-01 12:00`,
+/// or relative to now, such as `2 hours ago`.
+fn parse_time(time: &str) -> eyre::Result<SystemTime> {
+    if let Ok(date) = parse_date_string(time, Local::now(), Dialect::Us) {
+        return Ok(date.into());
+    }
+    if let Ok(interval) = parse_duration(time) {
+        let delta = match interval {
+            Interval::Seconds(seconds) => RelativeDuration::seconds(seconds.into()),
+            Interval::Days(days) => RelativeDuration::days(days.into()),
+            Interval::Months(months) => RelativeDuration::months(months),
+        };
+        return Ok((Local::now() + delta).into());
+    }
+    eyre::bail!("Cannot parse time: {time}")
+}
+
 /// Restore the repository to a previous state interactively.
 #[instrument]
 pub fn undo(
     effects: &Effects,
     git_run_info: &GitRunInfo,
     interactive: bool,
+    event_id: Option<isize>,
+    num_steps: usize,
+    time: Option<&str>,
     skip_confirmation: bool,
 ) -> EyreExitOr<()> {
     let repo = Repo::from_current_dir()?;
@@ -968,8 +1871,35 @@ pub fn undo(
         )?
     };
 
+    if let Some(event_id) = event_id {
+        return undo_single_event(
+            &mut stdin(),
+            effects,
+            &repo,
+            git_run_info,
+            &mut event_log_db,
+            &event_replayer,
+            event_id,
+            skip_confirmation,
+        );
+    }
+
     let event_cursor = {
-        if interactive {
+        if let Some(time) = time {
+            let time = parse_time(time)?;
+            let event_cursor = event_replayer.make_cursor_at_time(time);
+            // Don't stop partway through a transaction which was still in
+            // progress at that time.
+            match event_replayer.get_events_since_cursor(event_cursor).first() {
+                Some(next_event)
+                    if event_replayer.get_event_tx_id_before_cursor(event_cursor)
+                        == Some(next_event.get_event_tx_id()) =>
+                {
+                    event_replayer.advance_cursor_by_transaction(event_cursor, -1)
+                }
+                Some(_) | None => event_cursor,
+            }
+        } else if interactive {
             let result = with_siv(effects, |effects, siv| {
                 select_past_event(siv, &effects, &repo, &dag, &mut event_replayer)
             })?;
@@ -978,7 +1908,74 @@ pub fn undo(
                 None => return Ok(Ok(())),
             }
         } else {
-            event_replayer.advance_cursor_by_transaction(event_replayer.make_default_cursor(), -1)
+            let num_steps = isize::try_from(num_steps)?;
+            event_replayer
+                .advance_cursor_by_transaction(event_replayer.make_default_cursor(), -num_steps)
+        }
+    };
+
+    let result = undo_events(
+        &mut stdin(),
+        effects,
+        &repo,
+        git_run_info,
+        &mut event_log_db,
+        &event_replayer,
+        event_cursor,
+        "undo",
+        skip_confirmation,
+    )?;
+    Ok(result)
+}
+
+/// Find the position in the event log to return to in order to redo the most
+/// recent undo which hasn't already been redone. Returns `None` if there is
+/// nothing to redo, such as when other changes have been made since the last
+/// undo.
+#[instrument]
+fn find_redo_cursor(
+    event_log_db: &EventLogDb,
+    event_replayer: &EventReplayer,
+) -> eyre::Result<Option<EventCursor>> {
+    let mut cursor = event_replayer.make_default_cursor();
+    let mut num_redos = 0;
+    loop {
+        let event_tx_id = match event_replayer.get_event_tx_id_before_cursor(cursor) {
+            Some(event_tx_id @ EventTransactionId::Id(_)) => event_tx_id,
+            Some(EventTransactionId::Suppressed) | None => return Ok(None),
+        };
+        let prev_cursor = event_replayer.advance_cursor_by_transaction(cursor, -1);
+        if prev_cursor == cursor {
+            return Ok(None);
+        }
+
+        match event_log_db.get_transaction_message(event_tx_id)?.as_str() {
+            "redo" => num_redos += 1,
+            "undo" if num_redos > 0 => num_redos -= 1,
+            "undo" => return Ok(Some(prev_cursor)),
+            _ => return Ok(None),
+        }
+        cursor = prev_cursor;
+    }
+}
+
+/// Reapply the changes reverted by the most recent `git undo`.
+#[instrument]
+pub fn redo(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    skip_confirmation: bool,
+) -> EyreExitOr<()> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let mut event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+
+    let event_cursor = match find_redo_cursor(&event_log_db, &event_replayer)? {
+        Some(event_cursor) => event_cursor,
+        None => {
+            writeln!(effects.get_output_stream(), "No undo to redo, exiting.")?;
+            return Ok(Err(ExitCode(1)));
         }
     };
 
@@ -990,6 +1987,7 @@ pub fn undo(
         &mut event_log_db,
         &event_replayer,
         event_cursor,
+        "redo",
         skip_confirmation,
     )?;
     Ok(result)
@@ -1034,6 +2032,7 @@ pub mod testing {
             event_log_db,
             event_replayer,
             event_cursor,
+            "undo",
             false,
         )
     }
@@ -1080,6 +2079,7 @@ mod tests {
                         reset: false,
                         render_smartlog: true,
                     },
+                    index_snapshot_oid: None,
                 },
             ),
             [],
@@ -1087,4 +2087,18 @@ mod tests {
         "###);
         Ok(())
     }
+
+    #[test]
+    fn test_parse_time() -> eyre::Result<()> {
+        let now = SystemTime::now();
+        assert!(parse_time("tomorrow")? > now);
+
+        let two_hours_ago = parse_time("2 hours ago")?;
+        let elapsed = now.duration_since(two_hours_ago)?;
+        assert!(elapsed.as_secs() >= 2 * 60 * 60 - 60, "{elapsed:?}");
+        assert!(elapsed.as_secs() <= 2 * 60 * 60 + 60, "{elapsed:?}");
+
+        assert!(parse_time("not a time").is_err());
+        Ok(())
+    }
 }
@@ -41,6 +41,11 @@ fn redact_event(redactor: &Redactor, event: &Event) -> String {
             event_tx_id: _,
             commit_oid: _,
         }
+        | Event::CommitObservedEvent {
+            timestamp: _,
+            event_tx_id: _,
+            commit_oid: _,
+        }
         | Event::ObsoleteEvent {
             timestamp: _,
             event_tx_id: _,
@@ -50,6 +55,16 @@ fn redact_event(redactor: &Redactor, event: &Event) -> String {
             timestamp: _,
             event_tx_id: _,
             commit_oid: _,
+        }
+        | Event::PinEvent {
+            timestamp: _,
+            event_tx_id: _,
+            commit_oid: _,
+        }
+        | Event::UnpinEvent {
+            timestamp: _,
+            event_tx_id: _,
+            commit_oid: _,
         }) => event,
 
         Event::RefUpdateEvent {
@@ -71,6 +86,41 @@ fn redact_event(redactor: &Redactor, event: &Event) -> String {
             }
         }
 
+        Event::RefRenameEvent {
+            timestamp,
+            event_tx_id,
+            old_ref_name,
+            new_ref_name,
+            oid,
+        } => {
+            let old_ref_name = redactor.redact_ref_name(old_ref_name);
+            let new_ref_name = redactor.redact_ref_name(new_ref_name);
+            Event::RefRenameEvent {
+                timestamp,
+                event_tx_id,
+                old_ref_name,
+                new_ref_name,
+                oid,
+            }
+        }
+
+        Event::RemoteRefUpdateEvent {
+            timestamp,
+            event_tx_id,
+            ref_name,
+            old_oid,
+            new_oid,
+        } => {
+            let ref_name = redactor.redact_ref_name(ref_name);
+            Event::RemoteRefUpdateEvent {
+                timestamp,
+                event_tx_id,
+                ref_name,
+                old_oid,
+                new_oid,
+            }
+        }
+
         Event::WorkingCopySnapshot {
             timestamp,
             event_tx_id,
@@ -141,6 +191,7 @@ fn describe_event_cursor(
         event_cursor,
         &commits,
         false,
+        false,
     )?;
     let graph_lines = render_graph(
         &effects,
@@ -0,0 +1,103 @@
+//! Check the internal database for corruption and other problems.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use itertools::Itertools;
+use lib::core::effects::Effects;
+use lib::core::eventlog::EventLogDb;
+use lib::core::formatting::Pluralize;
+use lib::git::Repo;
+use lib::util::{ExitCode, EyreExitOr};
+use tracing::instrument;
+
+/// Run integrity checks on the event log database, report any problems, and
+/// vacuum it if it's intact.
+#[instrument]
+pub fn check_db(effects: &Effects) -> EyreExitOr<()> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let mut is_healthy = true;
+
+    let integrity_problems = event_log_db.check_integrity()?;
+    if integrity_problems.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "Database integrity check passed."
+        )?;
+    } else {
+        is_healthy = false;
+        writeln!(
+            effects.get_output_stream(),
+            "Database integrity check found {}:",
+            Pluralize {
+                determiner: None,
+                amount: integrity_problems.len(),
+                unit: ("problem", "problems"),
+            },
+        )?;
+        for problem in integrity_problems.iter() {
+            writeln!(effects.get_output_stream(), "  {problem}")?;
+        }
+    }
+
+    let (events, invalid_rowids) = event_log_db.get_events_and_invalid_rowids()?;
+    writeln!(
+        effects.get_output_stream(),
+        "Checked {}.",
+        Pluralize {
+            determiner: None,
+            amount: events.len() + invalid_rowids.len(),
+            unit: ("event", "events"),
+        },
+    )?;
+    if !invalid_rowids.is_empty() {
+        is_healthy = false;
+        writeln!(
+            effects.get_output_stream(),
+            "Found {} which could not be read, with row IDs: {}",
+            Pluralize {
+                determiner: None,
+                amount: invalid_rowids.len(),
+                unit: ("invalid event", "invalid events"),
+            },
+            invalid_rowids.iter().join(", "),
+        )?;
+    }
+
+    let mut missing_commit_oids = HashSet::new();
+    for oid in events.iter().flat_map(|event| event.get_commit_oids()) {
+        if !missing_commit_oids.contains(&oid) && repo.find_commit(oid)?.is_none() {
+            missing_commit_oids.insert(oid);
+        }
+    }
+    if !missing_commit_oids.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "Found {} referred to by events but missing from the object database: {}",
+            Pluralize {
+                determiner: None,
+                amount: missing_commit_oids.len(),
+                unit: ("commit", "commits"),
+            },
+            missing_commit_oids.into_iter().sorted().join(", "),
+        )?;
+        writeln!(
+            effects.get_output_stream(),
+            "This is expected for commits which were garbage-collected. If any of them are still visible, run: git branchless repair"
+        )?;
+    }
+
+    if !is_healthy {
+        writeln!(
+            effects.get_output_stream(),
+            "Not vacuuming the database, since problems were found."
+        )?;
+        return Ok(Err(ExitCode(1)));
+    }
+
+    event_log_db.vacuum()?;
+    writeln!(effects.get_output_stream(), "Vacuumed database.")?;
+    Ok(Ok(()))
+}
@@ -0,0 +1,93 @@
+//! Print internal diagnostic information.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use eyre::Context;
+use lib::core::dag::Dag;
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::formatting::Pluralize;
+use lib::core::repo_ext::RepoExt;
+use lib::git::{CommitGraph, Repo};
+use lib::util::EyreExitOr;
+use tracing::instrument;
+
+fn get_dir_size(path: &Path) -> eyre::Result<u64> {
+    let mut result = 0;
+    for entry in std::fs::read_dir(path).wrap_err_with(|| format!("Reading {path:?}"))? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        result += if metadata.is_dir() {
+            get_dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(result)
+}
+
+/// Print statistics about the caches used to speed up commit graph queries.
+#[instrument]
+pub fn cache_stats(effects: &Effects) -> EyreExitOr<()> {
+    let repo = Repo::from_current_dir()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let dag_dir = repo.get_dag_dir()?;
+    writeln!(
+        effects.get_output_stream(),
+        "Commit graph cache: {:?}",
+        dag_dir
+    )?;
+    writeln!(
+        effects.get_output_stream(),
+        "  Commits: {} ({} public)",
+        dag.set_count(&dag.query_all()?)?,
+        dag.set_count(dag.query_public_commits_slow()?)?,
+    )?;
+    writeln!(
+        effects.get_output_stream(),
+        "  Size on disk: {}",
+        Pluralize {
+            determiner: None,
+            amount: usize::try_from(get_dir_size(&dag_dir)?)?,
+            unit: ("byte", "bytes"),
+        },
+    )?;
+
+    match CommitGraph::open_for_repo(&repo)? {
+        Some(commit_graph) => writeln!(
+            effects.get_output_stream(),
+            "Git commit-graph: {}",
+            Pluralize {
+                determiner: None,
+                amount: commit_graph.len(),
+                unit: ("commit", "commits"),
+            },
+        )?,
+        None => writeln!(effects.get_output_stream(), "Git commit-graph: not used")?,
+    }
+    writeln!(
+        effects.get_output_stream(),
+        "Shallow clone: {}",
+        if repo.is_shallow() { "yes" } else { "no" },
+    )?;
+    writeln!(
+        effects.get_output_stream(),
+        "Cached diff stats: {}",
+        event_log_db.count_cached_diff_stats()?,
+    )?;
+
+    Ok(Ok(()))
+}
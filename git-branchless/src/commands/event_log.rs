@@ -0,0 +1,232 @@
+//! Show, query, export, import, and backfill the event log, such as to back it
+//! up, move it to another machine, or attach it to a bug report.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+use eyre::Context;
+use lib::core::effects::Effects;
+use lib::core::eventlog::{Event, EventFilter, EventLogDb, EventLogExport, ExportedEvent};
+use lib::core::formatting::Pluralize;
+use lib::git::{MaybeZeroOid, NonZeroOid, ReferenceName, ReflogEntry, Repo, Time};
+use lib::util::EyreExitOr;
+
+/// How often to check for new events when following the event log.
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+fn print_event(effects: &Effects, event: &Event) -> eyre::Result<()> {
+    if let Ok(event) = ExportedEvent::try_from(event.clone()) {
+        let json = serde_json::to_string(&event).wrap_err("Serializing event")?;
+        writeln!(effects.get_output_stream(), "{json}")?;
+    }
+    Ok(())
+}
+
+/// Print the events in the event log, one JSON object per line. If `follow` is
+/// set, keep printing new events as they're added until interrupted.
+pub fn show(effects: &Effects, follow: bool) -> EyreExitOr<()> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    if follow {
+        event_log_db.watch_events(0, FOLLOW_POLL_INTERVAL, |event| {
+            print_event(effects, event)?;
+            Ok(ControlFlow::Continue(()))
+        })?;
+    } else {
+        for event in event_log_db.get_events()? {
+            print_event(effects, &event)?;
+        }
+    }
+    Ok(Ok(()))
+}
+
+/// Print the events matching `filter`, one JSON object per line.
+pub fn query(effects: &Effects, filter: &EventFilter) -> EyreExitOr<()> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    for event in event_log_db.query(filter)? {
+        print_event(effects, &event)?;
+    }
+    Ok(Ok(()))
+}
+
+/// Print the contents of the event log as JSON.
+pub fn export(effects: &Effects) -> EyreExitOr<()> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let export = event_log_db.export()?;
+    let json = serde_json::to_string_pretty(&export).wrap_err("Serializing event log")?;
+    writeln!(effects.get_output_stream(), "{json}")?;
+    Ok(Ok(()))
+}
+
+/// Add the events from a JSON file produced by `export` to the event log.
+pub fn import(effects: &Effects, path: &Path) -> EyreExitOr<()> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let json = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Reading event log export from {path:?}"))?;
+    let export: EventLogExport =
+        serde_json::from_str(&json).wrap_err("Deserializing event log export")?;
+    let num_events = event_log_db.import(&export)?;
+    writeln!(
+        effects.get_output_stream(),
+        "Imported {}.",
+        Pluralize {
+            determiner: None,
+            amount: num_events,
+            unit: ("event", "events"),
+        }
+    )?;
+    Ok(Ok(()))
+}
+
+/// Add events for the updates recorded in the reflogs of `HEAD` and the local
+/// branches which aren't already in the event log, such as ones made before
+/// `git-branchless` was initialized or while its hooks were bypassed. The
+/// events are ordered among the existing events according to when the updates
+/// happened.
+pub fn backfill(effects: &Effects) -> EyreExitOr<()> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+
+    let existing_events = event_log_db.get_events()?;
+    let mut seen_ref_updates: HashSet<(ReferenceName, MaybeZeroOid, MaybeZeroOid)> =
+        existing_events
+            .iter()
+            .filter_map(|event| match event {
+                Event::RefUpdateEvent {
+                    ref_name,
+                    old_oid,
+                    new_oid,
+                    ..
+                } => Some((ref_name.clone(), *old_oid, *new_oid)),
+                _ => None,
+            })
+            .collect();
+    let mut seen_commit_oids: HashSet<NonZeroOid> = existing_events
+        .iter()
+        .flat_map(Event::get_commit_oids)
+        .collect();
+
+    let head_reference_name = ReferenceName::from("HEAD");
+    let mut reference_names = vec![head_reference_name.clone()];
+    for branch in repo.get_all_local_branches()? {
+        reference_names.push(branch.get_reference_name()?);
+    }
+
+    // A single operation may update several references at once, such as both
+    // `HEAD` and the checked-out branch, so group the updates with the same
+    // time and message into the same transaction.
+    let mut operations: BTreeMap<(Time, String), Vec<(ReferenceName, ReflogEntry)>> =
+        BTreeMap::new();
+    for reference_name in reference_names {
+        for entry in repo.get_reflog_entries(&reference_name)? {
+            if !seen_ref_updates.insert((reference_name.clone(), entry.old_oid, entry.new_oid)) {
+                continue;
+            }
+            operations
+                .entry((
+                    entry.time.clone(),
+                    entry.message.clone().unwrap_or_default(),
+                ))
+                .or_default()
+                .push((reference_name.clone(), entry));
+        }
+    }
+
+    let operations = operations
+        .into_iter()
+        .map(|((time, message), entries)| {
+            let time = time.to_system_time()?;
+            let timestamp = time.duration_since(UNIX_EPOCH)?.as_secs_f64();
+            Ok((
+                time,
+                format!("reflog: {message}"),
+                (timestamp, message, entries),
+            ))
+        })
+        .collect::<eyre::Result<Vec<_>>>()?;
+    let num_events = event_log_db.add_backfilled_events(
+        operations,
+        |event_tx_id, (timestamp, message, entries)| {
+            // The reflog action is the part of the message before the first
+            // colon, such as `commit (amend)`. It's matched exactly, since
+            // other actions which create commits, like `commit (merge)` or
+            // `commit (initial)`, share the same prefix.
+            let action = match message.split_once(": ") {
+                Some((action, _)) => action,
+                None => message.as_str(),
+            };
+
+            let mut events = Vec::new();
+            for (ref_name, entry) in entries {
+                let ReflogEntry {
+                    old_oid,
+                    new_oid,
+                    time: _,
+                    message: _,
+                } = entry;
+
+                // Only `HEAD` entries are considered for commits, since the
+                // checked-out branch's reflog records the same commits.
+                if ref_name == head_reference_name {
+                    match (action, old_oid, new_oid) {
+                        (
+                            "commit (amend)",
+                            MaybeZeroOid::NonZero(old_commit_oid),
+                            MaybeZeroOid::NonZero(new_commit_oid),
+                        ) => {
+                            if seen_commit_oids.insert(new_commit_oid) {
+                                events.push(Event::RewriteEvent {
+                                    timestamp,
+                                    event_tx_id,
+                                    old_commit_oid: MaybeZeroOid::NonZero(old_commit_oid),
+                                    new_commit_oid: MaybeZeroOid::NonZero(new_commit_oid),
+                                });
+                            }
+                        }
+                        ("commit", _, MaybeZeroOid::NonZero(commit_oid)) => {
+                            if seen_commit_oids.insert(commit_oid) {
+                                events.push(Event::CommitEvent {
+                                    timestamp,
+                                    event_tx_id,
+                                    commit_oid,
+                                });
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+
+                events.push(Event::RefUpdateEvent {
+                    timestamp,
+                    event_tx_id,
+                    ref_name,
+                    old_oid,
+                    new_oid,
+                    message: None,
+                });
+            }
+            events
+        },
+    )?;
+    writeln!(
+        effects.get_output_stream(),
+        "Backfilled {} from the reflog.",
+        Pluralize {
+            determiner: None,
+            amount: num_events,
+            unit: ("event", "events"),
+        }
+    )?;
+    Ok(Ok(()))
+}
@@ -1,24 +1,71 @@
 //! Handle obsoleting commits when explicitly requested by the user (as opposed to
 //! automatically as the result of a rewrite operation).
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 use std::time::SystemTime;
 
 use git_branchless_opts::{ResolveRevsetOptions, Revset};
-use lib::core::dag::{union_all, Dag};
+use lib::core::dag::{union_all, CommitSet, Dag};
 use lib::core::effects::Effects;
 use lib::core::eventlog::{CommitActivityStatus, Event};
 use lib::core::eventlog::{EventLogDb, EventReplayer};
 use lib::core::formatting::{Glyphs, Pluralize};
+use lib::core::gc::mark_commits_reachable;
 use lib::core::repo_ext::RepoExt;
 use lib::core::rewrite::move_branches;
-use lib::git::{CategorizedReferenceName, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
+use lib::git::{CategorizedReferenceName, GitRunInfo, MaybeZeroOid, NonZeroOid, PatchId, Repo};
 use lib::util::{ExitCode, EyreExitOr};
 use tracing::instrument;
 
 use git_branchless_revset::resolve_commits;
 
+/// Find the draft commits whose changes have already been applied to the main
+/// branch, such as when they were landed upstream via cherry-pick or rebase.
+#[instrument]
+fn find_merged_commits(effects: &Effects, repo: &Repo, dag: &Dag) -> eyre::Result<CommitSet> {
+    let draft_commits = dag.query_draft_commits()?.clone();
+    let merge_base_oids = dag.query_parents(dag.query_roots(draft_commits.clone())?)?;
+    let upstream_commits = dag.query_only(dag.main_branch_commit.clone(), merge_base_oids)?;
+
+    let mut upstream_patch_ids: HashSet<PatchId> = HashSet::new();
+    for commit_oid in dag.commit_set_to_vec(&upstream_commits)? {
+        let commit = repo.find_commit_or_fail(commit_oid)?;
+        if let Some(patch_id) = repo.get_patch_id(effects, &commit)? {
+            upstream_patch_ids.insert(patch_id);
+        }
+    }
+
+    let mut merged_commit_oids = Vec::new();
+    for commit_oid in dag.commit_set_to_vec(&draft_commits)? {
+        let commit = repo.find_commit_or_fail(commit_oid)?;
+        if let Some(patch_id) = repo.get_patch_id(effects, &commit)? {
+            if upstream_patch_ids.contains(&patch_id) {
+                merged_commit_oids.push(commit_oid);
+            }
+        }
+    }
+    Ok(merged_commit_oids.into_iter().collect())
+}
+
+/// Options for hiding commits. See `hide`.
+#[derive(Clone, Debug, Default)]
+pub struct HideOptions {
+    /// Don't delete branches that point to commits that would be hidden.
+    pub no_delete_branches: bool,
+
+    /// Also recursively hide all visible children commits of the provided
+    /// commits.
+    pub recursive: bool,
+
+    /// Also hide any draft commits whose changes have already been applied to
+    /// the main branch.
+    pub merged: bool,
+
+    /// The reason for hiding the commits, to be recorded in the event log.
+    pub reason: Option<String>,
+}
+
 /// Hide the hashes provided on the command-line.
 #[instrument]
 pub fn hide(
@@ -26,9 +73,15 @@ pub fn hide(
     git_run_info: &GitRunInfo,
     revsets: Vec<Revset>,
     resolve_revset_options: &ResolveRevsetOptions,
-    no_delete_branches: bool,
-    recursive: bool,
+    options: &HideOptions,
 ) -> EyreExitOr<()> {
+    let HideOptions {
+        no_delete_branches,
+        recursive,
+        merged,
+        reason,
+    } = options;
+
     let now = SystemTime::now();
     let glyphs = Glyphs::detect();
     let repo = Repo::from_current_dir()?;
@@ -44,7 +97,7 @@ pub fn hide(
         event_cursor,
         &references_snapshot,
     )?;
-    let delete_branches = !no_delete_branches;
+    let delete_branches = !*no_delete_branches;
 
     let commit_sets =
         match resolve_commits(effects, &repo, &mut dag, &revsets, resolve_revset_options) {
@@ -56,7 +109,12 @@ pub fn hide(
         };
 
     let commits = union_all(&commit_sets);
-    let commits = if recursive {
+    let commits = if *merged {
+        commits.union(&find_merged_commits(effects, &repo, &dag)?)
+    } else {
+        commits
+    };
+    let commits = if *recursive {
         dag.filter_visible_commits(dag.query_descendants(commits)?)?
     } else {
         commits
@@ -69,6 +127,9 @@ pub fn hide(
 
     let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
     let event_tx_id = event_log_db.make_transaction_id(now, "hide")?;
+    if let Some(reason) = reason {
+        event_log_db.set_transaction_reason(event_tx_id, reason)?;
+    }
     let events = commits
         .iter()
         .map(|commit| Event::ObsoleteEvent {
@@ -189,6 +250,7 @@ pub fn unhide(
     revsets: Vec<Revset>,
     resolve_revset_options: &ResolveRevsetOptions,
     recursive: bool,
+    ancestors: bool,
 ) -> EyreExitOr<()> {
     let now = SystemTime::now();
     let glyphs = Glyphs::detect();
@@ -222,6 +284,15 @@ pub fn unhide(
     } else {
         commits
     };
+    let commits = if ancestors {
+        let hidden_ancestors = dag
+            .query_ancestors(commits.clone())?
+            .difference(dag.query_public_commits_slow()?)
+            .intersection(&dag.query_obsolete_commits());
+        commits.union(&hidden_ancestors)
+    } else {
+        commits
+    };
     let commits = dag.sort(&commits)?;
     let commits = commits
         .into_iter()
@@ -240,6 +311,14 @@ pub fn unhide(
         .collect();
     event_log_db.add_events(events)?;
 
+    // The commits may have been released by a previous `git branchless gc`,
+    // so protect them from Git's garbage collection again.
+    let commit_oids = commits
+        .iter()
+        .map(|commit| commit.get_oid())
+        .collect::<Vec<_>>();
+    mark_commits_reachable(&repo, &commit_oids)?;
+
     let cursor = event_replayer.make_default_cursor();
     let num_commits = commits.len();
     for commit in commits {
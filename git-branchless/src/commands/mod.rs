@@ -2,23 +2,33 @@
 
 mod amend;
 mod bug_report;
+mod check_db;
+mod debug;
+mod event_log;
 mod hide;
+mod pin;
 mod repair;
 mod restack;
+pub mod smartlog;
 mod snapshot;
 mod sync;
+mod warm_cache;
 mod wrap;
 
+use std::time::{Duration, UNIX_EPOCH};
+
 use git_branchless_invoke::CommandContext;
+use lib::core::eventlog::{EventFilter, EventTransactionId};
 use lib::core::rewrite::MergeConflictRemediation;
 
 use lib::util::ExitCode;
 use lib::{core::gc, util::EyreExitOr};
 
 use git_branchless_opts::{
-    rewrite_args, Command, Opts, ResolveRevsetOptions, SnapshotSubcommand, WrappedCommand,
+    rewrite_args, CheckpointSubcommand, Command, DebugSubcommand, EventLogSubcommand, Opts,
+    ResolveRevsetOptions, SnapshotSubcommand, WrappedCommand,
 };
-use lib::git::GitRunInfo;
+use lib::git::{GitRunInfo, ReferenceName};
 
 fn command_main(ctx: CommandContext, opts: Opts) -> EyreExitOr<()> {
     let CommandContext {
@@ -44,6 +54,21 @@ fn command_main(ctx: CommandContext, opts: Opts) -> EyreExitOr<()> {
 
         Command::BugReport => bug_report::bug_report(&effects, &git_run_info)?,
 
+        Command::CheckDb => check_db::check_db(&effects)?,
+
+        Command::Checkpoint { subcommand } => match subcommand {
+            CheckpointSubcommand::Save { name } => {
+                git_branchless_undo::checkpoint::save(&effects, &git_run_info, &name)?
+            }
+            CheckpointSubcommand::Restore { name, yes } => {
+                git_branchless_undo::checkpoint::restore(&effects, &git_run_info, &name, yes)?
+            }
+        },
+
+        Command::Debug { subcommand } => match subcommand {
+            DebugSubcommand::CacheStats => debug::cache_stats(&effects)?,
+        },
+
         Command::Difftool(opts) => {
             let result = scm_record::scm_diff_editor::scm_diff_editor_main(opts);
             match result {
@@ -55,14 +80,55 @@ fn command_main(ctx: CommandContext, opts: Opts) -> EyreExitOr<()> {
             }
         }
 
+        Command::EventLog { subcommand } => match subcommand {
+            EventLogSubcommand::Show { follow } => event_log::show(&effects, follow)?,
+            EventLogSubcommand::Export => event_log::export(&effects)?,
+            EventLogSubcommand::Import { path } => event_log::import(&effects, &path)?,
+            EventLogSubcommand::Backfill => event_log::backfill(&effects)?,
+        },
+
+        Command::Events {
+            event_types,
+            ref_name,
+            commit_oid,
+            event_tx_id,
+            since,
+            until,
+        } => event_log::query(
+            &effects,
+            &EventFilter {
+                event_types,
+                ref_name: ref_name.map(ReferenceName::from),
+                commit_oid,
+                event_tx_id: event_tx_id.map(EventTransactionId::Id),
+                start_time: since.map(|since| UNIX_EPOCH + Duration::from_secs_f64(since)),
+                end_time: until.map(|until| UNIX_EPOCH + Duration::from_secs_f64(until)),
+            },
+        )?,
+
         Command::Switch { switch_options } => {
             git_branchless_navigation::switch(&effects, &git_run_info, &switch_options)?
         }
 
-        Command::Gc => {
-            gc::gc(&effects)?;
-            Ok(())
-        }
+        Command::Gc {
+            dry_run,
+            full,
+            git_gc,
+            full_scan,
+            aggressive,
+            refresh_cache,
+        } => gc::gc(
+            &effects,
+            &git_run_info,
+            &gc::GcOptions {
+                dry_run,
+                full,
+                git_gc,
+                full_scan,
+                aggressive,
+                refresh_cache,
+            },
+        )?,
 
         Command::Hook(args) => git_branchless_hook::command_main(ctx, args)?,
 
@@ -71,13 +137,19 @@ fn command_main(ctx: CommandContext, opts: Opts) -> EyreExitOr<()> {
             resolve_revset_options,
             no_delete_branches,
             recursive,
+            merged,
+            message,
         } => hide::hide(
             &effects,
             &git_run_info,
             revsets,
             &resolve_revset_options,
-            no_delete_branches,
-            recursive,
+            &hide::HideOptions {
+                no_delete_branches,
+                recursive,
+                merged,
+                reason: message,
+            },
         )?,
 
         Command::Init(args) => git_branchless_init::command_main(ctx, args)?,
@@ -117,6 +189,11 @@ fn command_main(ctx: CommandContext, opts: Opts) -> EyreExitOr<()> {
             &traverse_commits_options,
         )?,
 
+        Command::Pin {
+            revsets,
+            resolve_revset_options,
+        } => pin::pin(&effects, revsets, &resolve_revset_options, false)?,
+
         Command::Prev {
             traverse_commits_options,
         } => git_branchless_navigation::traverse_commits(
@@ -145,6 +222,8 @@ fn command_main(ctx: CommandContext, opts: Opts) -> EyreExitOr<()> {
 
         Command::Record(args) => git_branchless_record::command_main(ctx, args)?,
 
+        Command::Redo { yes } => git_branchless_undo::redo(&effects, &git_run_info, yes)?,
+
         Command::Reword {
             revsets,
             resolve_revset_options,
@@ -170,6 +249,10 @@ fn command_main(ctx: CommandContext, opts: Opts) -> EyreExitOr<()> {
             )?
         }
 
+        Command::Smartlog(args) if args.interactive => {
+            smartlog::smartlog_interactive(&effects, &git_run_info, args)?
+        }
+
         Command::Smartlog(args) => git_branchless_smartlog::command_main(ctx, args)?,
 
         Command::Snapshot { subcommand } => match subcommand {
@@ -197,15 +280,41 @@ fn command_main(ctx: CommandContext, opts: Opts) -> EyreExitOr<()> {
 
         Command::Test(args) => git_branchless_test::command_main(ctx, args)?,
 
-        Command::Undo { interactive, yes } => {
-            git_branchless_undo::undo(&effects, &git_run_info, interactive, yes)?
-        }
+        Command::Undo {
+            interactive,
+            event_id,
+            steps,
+            to,
+            yes,
+        } => git_branchless_undo::undo(
+            &effects,
+            &git_run_info,
+            interactive,
+            event_id,
+            steps,
+            to.as_deref(),
+            yes,
+        )?,
 
         Command::Unhide {
             revsets,
             resolve_revset_options,
             recursive,
-        } => hide::unhide(&effects, revsets, &resolve_revset_options, recursive)?,
+            ancestors,
+        } => hide::unhide(
+            &effects,
+            revsets,
+            &resolve_revset_options,
+            recursive,
+            ancestors,
+        )?,
+
+        Command::Unpin {
+            revsets,
+            resolve_revset_options,
+        } => pin::pin(&effects, revsets, &resolve_revset_options, true)?,
+
+        Command::WarmCache => warm_cache::warm_cache(&effects)?,
 
         Command::Wrap {
             git_executable: explicit_git_executable,
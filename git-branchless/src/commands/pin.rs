@@ -0,0 +1,126 @@
+//! Handle pinning commits, so that they're never garbage-collected by
+//! branchless, even if they're hidden.
+
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use git_branchless_opts::{ResolveRevsetOptions, Revset};
+use lib::core::dag::{union_all, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{Event, EventLogDb, EventReplayer};
+use lib::core::formatting::{Glyphs, Pluralize};
+use lib::core::gc::mark_commit_reachable;
+use lib::core::repo_ext::RepoExt;
+use lib::git::Repo;
+use lib::util::{ExitCode, EyreExitOr};
+use tracing::instrument;
+
+use git_branchless_revset::resolve_commits;
+
+/// Pin or unpin the commits provided on the command-line.
+#[instrument]
+pub fn pin(
+    effects: &Effects,
+    revsets: Vec<Revset>,
+    resolve_revset_options: &ResolveRevsetOptions,
+    unpin: bool,
+) -> EyreExitOr<()> {
+    let now = SystemTime::now();
+    let glyphs = Glyphs::detect();
+    let repo = Repo::from_current_dir()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let mut dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let commit_sets =
+        match resolve_commits(effects, &repo, &mut dag, &revsets, resolve_revset_options) {
+            Ok(commit_sets) => commit_sets,
+            Err(err) => {
+                err.describe(effects)?;
+                return Ok(Err(ExitCode(1)));
+            }
+        };
+    let commits = dag.sort(&union_all(&commit_sets))?;
+    let commits = commits
+        .into_iter()
+        .map(|commit_oid| repo.find_commit_or_fail(commit_oid))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
+    let event_tx_id = event_log_db.make_transaction_id(now, if unpin { "unpin" } else { "pin" })?;
+    let events = commits
+        .iter()
+        .map(|commit| {
+            let commit_oid = commit.get_oid();
+            if unpin {
+                Event::UnpinEvent {
+                    timestamp,
+                    event_tx_id,
+                    commit_oid,
+                }
+            } else {
+                Event::PinEvent {
+                    timestamp,
+                    event_tx_id,
+                    commit_oid,
+                }
+            }
+        })
+        .collect();
+    event_log_db.add_events(events)?;
+
+    let num_commits = commits.len();
+    for commit in commits.iter() {
+        let was_pinned = event_replayer.get_cursor_commit_is_pinned(event_cursor, commit.get_oid());
+        if unpin {
+            writeln!(
+                effects.get_output_stream(),
+                "Unpinned commit: {}",
+                glyphs.render(commit.friendly_describe(&glyphs)?)?,
+            )?;
+            if !was_pinned {
+                writeln!(
+                    effects.get_output_stream(),
+                    "(It was not pinned, so this operation had no effect.)"
+                )?;
+            }
+        } else {
+            // The commit may have already been released if it was hidden and
+            // garbage-collected before it was pinned.
+            mark_commit_reachable(&repo, commit.get_oid())?;
+            writeln!(
+                effects.get_output_stream(),
+                "Pinned commit: {}",
+                glyphs.render(commit.friendly_describe(&glyphs)?)?,
+            )?;
+            if was_pinned {
+                writeln!(
+                    effects.get_output_stream(),
+                    "(It was already pinned, so this operation had no effect.)"
+                )?;
+            }
+        }
+    }
+
+    writeln!(
+        effects.get_output_stream(),
+        "To {} {}, run: git undo",
+        if unpin { "pin" } else { "unpin" },
+        Pluralize {
+            determiner: Some(("this", "these")),
+            amount: num_commits,
+            unit: ("commit", "commits"),
+        },
+    )?;
+
+    Ok(Ok(()))
+}
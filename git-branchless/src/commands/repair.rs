@@ -7,8 +7,9 @@ use lib::git::{CategorizedReferenceName, MaybeZeroOid};
 use lib::util::EyreExitOr;
 use lib::{
     core::{
+        config::get_ref_namespace,
         effects::{Effects, OperationType},
-        eventlog::{Event, EventLogDb, EventReplayer},
+        eventlog::{is_gc_ref, Event, EventLogDb, EventReplayer},
         formatting::Pluralize,
     },
     git::Repo,
@@ -21,6 +22,21 @@ pub fn repair(effects: &Effects, dry_run: bool) -> EyreExitOr<()> {
     let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
     let event_cursor = event_replayer.make_default_cursor();
 
+    // References used internally to keep commits alive may point to commits
+    // which were garbage-collected by Git anyways (such as if the references
+    // were created by an older version of branchless).
+    let broken_internal_references = {
+        let ref_namespace = get_ref_namespace(&repo)?;
+        let mut result = Vec::new();
+        for reference in repo.get_all_references()? {
+            let reference_name = reference.get_name()?;
+            if is_gc_ref(&reference_name, &ref_namespace) && reference.peel_to_commit()?.is_none() {
+                result.push(reference);
+            }
+        }
+        result
+    };
+
     let broken_commits = {
         let (effects, progress) = effects.start_operation(OperationType::RepairCommits);
         let _effects = effects;
@@ -82,9 +98,18 @@ pub fn repair(effects: &Effects, dry_run: bool) -> EyreExitOr<()> {
                 message: None,
             });
 
+    let num_broken_internal_references = broken_internal_references.len();
+    let broken_internal_reference_names = broken_internal_references
+        .iter()
+        .map(|reference| reference.get_name())
+        .collect::<Result<Vec<_>, _>>()?;
+
     if !dry_run {
         let events = commit_events.into_iter().chain(branch_events).collect_vec();
         event_log_db.add_events(events)?;
+        for mut reference in broken_internal_references {
+            reference.delete()?;
+        }
     }
 
     if num_broken_commits > 0 {
@@ -119,6 +144,23 @@ pub fn repair(effects: &Effects, dry_run: bool) -> EyreExitOr<()> {
         )?;
     }
 
+    if num_broken_internal_references > 0 {
+        writeln!(
+            effects.get_output_stream(),
+            "Found and repaired {}: {}",
+            Pluralize {
+                determiner: None,
+                amount: num_broken_internal_references,
+                unit: ("broken internal reference", "broken internal references")
+            },
+            broken_internal_reference_names
+                .iter()
+                .map(|reference_name| reference_name.as_str())
+                .sorted()
+                .join(", "),
+        )?;
+    }
+
     if dry_run {
         writeln!(
             effects.get_output_stream(),
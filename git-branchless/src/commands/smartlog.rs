@@ -0,0 +1,374 @@
+//! Browse the smartlog interactively and act on the selected commit.
+
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::time::SystemTime;
+
+use cursive_core::event::{Event, Key};
+use cursive_core::theme::Effect;
+use cursive_core::traits::Resizable;
+use cursive_core::views::{Dialog, LinearLayout, Panel, TextView};
+use cursive_core::{Cursive, CursiveRunner};
+use git_branchless_opts::{MoveOptions, ResolveRevsetOptions, Revset, SmartlogArgs};
+use git_branchless_revset::resolve_commits;
+use git_branchless_smartlog::{make_smartlog_graph, render_graph_with_oids, GraphLine};
+use git_branchless_undo::declare_views;
+use git_branchless_undo::tui::{with_siv, SingletonView};
+use lib::core::check_out::{check_out_commit, CheckOutCommitOptions, CheckoutTarget};
+use lib::core::config::get_smartlog_default_revset;
+use lib::core::dag::Dag;
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::formatting::{set_effect, StyledStringBuilder};
+use lib::core::node_descriptors::{
+    BranchesDescriptor, CommitMessageDescriptor, CommitOidDescriptor,
+    DifferentialRevisionDescriptor, ObsolescenceExplanationDescriptor, Redactor,
+    RelativeTimeDescriptor,
+};
+use lib::core::repo_ext::RepoExt;
+use lib::core::rewrite::MergeConflictRemediation;
+use lib::git::{GitRunInfo, NonZeroOid, Repo};
+use lib::util::{ExitCode, EyreExitOr};
+use tracing::instrument;
+
+use super::{hide, restack};
+
+/// An action to take on the commit selected in the interactive smartlog.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SmartlogAction {
+    /// Check out the commit.
+    CheckOut,
+
+    /// Hide the commit.
+    Hide,
+
+    /// Restack the stack containing the commit.
+    Restack,
+}
+
+/// Render the smartlog for the provided revset, along with the OID of the
+/// commit on each line, if any.
+fn render_smartlog_lines(
+    effects: &Effects,
+    repo: &Repo,
+    revset: Option<Revset>,
+    resolve_revset_options: &ResolveRevsetOptions,
+) -> EyreExitOr<Vec<GraphLine>> {
+    let head_info = repo.get_head_info()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let mut dag = Dag::open_and_sync(
+        effects,
+        repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let revset = match revset {
+        Some(revset) => revset,
+        None => Revset(get_smartlog_default_revset(repo)?),
+    };
+    let commits = match resolve_commits(effects, repo, &mut dag, &[revset], resolve_revset_options)
+    {
+        Ok(result) => match result.as_slice() {
+            [commit_set] => commit_set.clone(),
+            other => panic!("Expected exactly 1 result from resolve commits, got: {other:?}"),
+        },
+        Err(err) => {
+            err.describe(effects)?;
+            return Ok(Err(ExitCode(1)));
+        }
+    };
+
+    let graph = make_smartlog_graph(
+        effects,
+        repo,
+        &dag,
+        &event_replayer,
+        event_cursor,
+        &commits,
+        false,
+        resolve_revset_options.show_hidden_commits,
+    )?;
+    let lines = render_graph_with_oids(
+        effects,
+        repo,
+        &dag,
+        &graph,
+        references_snapshot.head_oid,
+        &mut [
+            &mut CommitOidDescriptor::new(true)?,
+            &mut RelativeTimeDescriptor::new(repo, SystemTime::now())?,
+            &mut ObsolescenceExplanationDescriptor::new(&event_replayer, event_cursor)?,
+            &mut BranchesDescriptor::new(
+                repo,
+                &head_info,
+                &references_snapshot,
+                &Redactor::Disabled,
+            )?,
+            &mut DifferentialRevisionDescriptor::new(repo, &Redactor::Disabled)?,
+            &mut CommitMessageDescriptor::new(&Redactor::Disabled)?,
+        ],
+    )?;
+    Ok(Ok(lines))
+}
+
+/// Let the user select a commit from the rendered smartlog `lines`, along with
+/// the action to take on it. Returns `None` if the user quit without selecting
+/// a commit.
+#[instrument(skip(siv, lines))]
+fn select_commit(
+    mut siv: CursiveRunner<Cursive>,
+    lines: &[GraphLine],
+    initial_oid: Option<NonZeroOid>,
+) -> eyre::Result<Option<(SmartlogAction, NonZeroOid)>> {
+    #[derive(Clone, Copy, Debug)]
+    enum Message {
+        Init,
+        Next,
+        Previous,
+        Help,
+        Quit,
+        Select(SmartlogAction),
+    }
+    let (main_tx, main_rx): (Sender<Message>, Receiver<Message>) = channel();
+
+    [
+        (Key::Down.into(), Message::Next),
+        ('j'.into(), Message::Next),
+        (Key::Up.into(), Message::Previous),
+        ('k'.into(), Message::Previous),
+        (Key::Enter.into(), Message::Select(SmartlogAction::CheckOut)),
+        ('h'.into(), Message::Select(SmartlogAction::Hide)),
+        ('r'.into(), Message::Select(SmartlogAction::Restack)),
+        ('?'.into(), Message::Help),
+        ('q'.into(), Message::Quit),
+        ('Q'.into(), Message::Quit),
+    ]
+    .iter()
+    .cloned()
+    .for_each(|(event, message): (Event, Message)| {
+        // Register these before the views handle the events, so that the
+        // arrow keys aren't consumed by any of them.
+        siv.set_on_pre_event(event, {
+            let main_tx = main_tx.clone();
+            move |_siv| main_tx.send(message).unwrap()
+        });
+    });
+
+    let commit_line_idxs: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter_map(|(line_idx, (_line, oid))| oid.map(|_| line_idx))
+        .collect();
+    let mut selected_idx = commit_line_idxs
+        .iter()
+        .position(|line_idx| lines[*line_idx].1 == initial_oid && initial_oid.is_some())
+        .unwrap_or_else(|| commit_line_idxs.len().saturating_sub(1));
+
+    main_tx.send(Message::Init)?;
+    while siv.is_running() {
+        let message = main_rx.try_recv();
+        if message.is_err() {
+            // For tests: only pump the Cursive event loop if we have no events
+            // of our own to process.
+            siv.step();
+        }
+
+        declare_views! {
+            SmartlogView => TextView,
+        }
+
+        let redraw = |siv: &mut Cursive, selected_idx: usize| {
+            // Only render the lines which fit on the screen, scrolled so that
+            // the selected commit is visible. The panel border and the help
+            // line take up four rows.
+            let height = siv.screen_size().y.saturating_sub(4).max(1);
+            let selected_line_idx = commit_line_idxs.get(selected_idx).copied();
+            let start = match selected_line_idx {
+                Some(line_idx) if line_idx >= height => line_idx + 1 - height,
+                Some(_) | None => 0,
+            };
+            let visible_lines = lines.iter().enumerate().skip(start).take(height).map(
+                |(line_idx, (line, _oid))| {
+                    if Some(line_idx) == selected_line_idx {
+                        set_effect(line.clone(), Effect::Reverse)
+                    } else {
+                        line.clone()
+                    }
+                },
+            );
+            SmartlogView::find(siv)
+                .set_content(StyledStringBuilder::from_lines(visible_lines.collect()));
+        };
+
+        match message {
+            Err(TryRecvError::Disconnected) => break,
+
+            Err(TryRecvError::Empty) => {
+                // If we haven't received a message yet, defer to `siv.step`
+                // to process the next user input.
+                continue;
+            }
+
+            Ok(Message::Init) => {
+                let smartlog_view: SmartlogView = TextView::new("").into();
+                siv.add_fullscreen_layer(
+                    LinearLayout::vertical()
+                        .child(
+                            Panel::new(smartlog_view)
+                                .title("Commit graph")
+                                .full_height(),
+                        )
+                        .child(TextView::new(
+                            "<up>/<down>: select, <enter>: check out, h: hide, r: restack, ?: help, q: quit",
+                        ))
+                        .full_width(),
+                );
+                redraw(&mut siv, selected_idx);
+            }
+
+            Ok(Message::Next) => {
+                if selected_idx + 1 < commit_line_idxs.len() {
+                    selected_idx += 1;
+                }
+                redraw(&mut siv, selected_idx);
+            }
+
+            Ok(Message::Previous) => {
+                selected_idx = selected_idx.saturating_sub(1);
+                redraw(&mut siv, selected_idx);
+            }
+
+            Ok(Message::Help) => {
+                siv.add_layer(
+                    Dialog::new()
+                        .title("How to use")
+                        .content(TextView::new(
+"Use `git smartlog --interactive` to browse and act on the commits in the smartlog.
+
+?: Show this help.
+q: Quit.
+<up>/<down> or k/j: Select the previous/next commit.
+<enter>: Check out the selected commit.
+h: Hide the selected commit.
+r: Restack the stack containing the selected commit.
+",
+                        ))
+                        .dismiss_button("Close"),
+                );
+            }
+
+            Ok(Message::Quit) => siv.quit(),
+
+            Ok(Message::Select(action)) => {
+                siv.quit();
+                let selected_oid = commit_line_idxs
+                    .get(selected_idx)
+                    .and_then(|line_idx| lines[*line_idx].1);
+                return Ok(selected_oid.map(|oid| (action, oid)));
+            }
+        }
+
+        if message.is_ok() {
+            siv.refresh();
+        }
+    }
+
+    Ok(None)
+}
+
+/// Browse the smartlog interactively, and then carry out the action selected
+/// by the user.
+#[instrument]
+pub fn smartlog_interactive(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    args: SmartlogArgs,
+) -> EyreExitOr<()> {
+    let SmartlogArgs {
+        revset,
+        resolve_revset_options,
+        ..
+    } = args;
+    let repo = Repo::from_dir(&git_run_info.working_directory)?;
+    let lines = match render_smartlog_lines(effects, &repo, revset, &resolve_revset_options)? {
+        Ok(lines) => lines,
+        Err(exit_code) => return Ok(Err(exit_code)),
+    };
+    let head_oid = repo.get_head_info()?.oid;
+
+    let selection = with_siv(effects, |_effects, siv| {
+        select_commit(siv, &lines, head_oid)
+    })?;
+    let (action, oid) = match selection {
+        Some(selection) => selection,
+        None => return Ok(Ok(())),
+    };
+
+    match action {
+        SmartlogAction::CheckOut => {
+            let conn = repo.get_db_conn()?;
+            let event_log_db = EventLogDb::new(&conn)?;
+            let event_tx_id =
+                event_log_db.make_transaction_id(SystemTime::now(), "smartlog --interactive")?;
+            check_out_commit(
+                effects,
+                git_run_info,
+                &repo,
+                &event_log_db,
+                event_tx_id,
+                Some(CheckoutTarget::Oid(oid)),
+                &CheckOutCommitOptions::default(),
+            )
+        }
+        SmartlogAction::Hide => hide::hide(
+            effects,
+            git_run_info,
+            vec![Revset(oid.to_string())],
+            &ResolveRevsetOptions::default(),
+            &hide::HideOptions::default(),
+        ),
+        SmartlogAction::Restack => restack::restack(
+            effects,
+            git_run_info,
+            vec![Revset(format!("stack({oid})"))],
+            &ResolveRevsetOptions::default(),
+            &MoveOptions {
+                force_rewrite_public_commits: false,
+                force_in_memory: false,
+                force_on_disk: false,
+                detect_duplicate_commits_via_patch_id: true,
+                resolve_merge_conflicts: false,
+                dump_rebase_constraints: false,
+                dump_rebase_plan: false,
+            },
+            MergeConflictRemediation::Retry,
+        ),
+    }
+}
+
+#[allow(missing_docs)]
+pub mod testing {
+    use cursive_core::{Cursive, CursiveRunner};
+    use git_branchless_opts::ResolveRevsetOptions;
+    use lib::core::effects::Effects;
+    use lib::git::{NonZeroOid, Repo};
+
+    use super::SmartlogAction;
+
+    pub fn select_commit(
+        siv: CursiveRunner<Cursive>,
+        effects: &Effects,
+        repo: &Repo,
+    ) -> eyre::Result<Option<(SmartlogAction, NonZeroOid)>> {
+        let lines =
+            super::render_smartlog_lines(effects, repo, None, &ResolveRevsetOptions::default())?
+                .expect("Rendering the default smartlog should succeed");
+        let head_oid = repo.get_head_info()?.oid;
+        super::select_commit(siv, &lines, head_oid)
+    }
+}
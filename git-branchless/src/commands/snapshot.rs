@@ -8,6 +8,7 @@ use cursive_core::theme::BaseColor;
 use cursive_core::utils::markup::StyledString;
 use eyre::Context;
 use lib::core::check_out::{create_snapshot, restore_snapshot};
+use lib::core::config::get_undo_create_snapshots;
 use lib::core::effects::Effects;
 use lib::core::eventlog::EventLogDb;
 use lib::git::{GitRunInfo, GitRunResult, NonZeroOid, Repo, WorkingCopySnapshot};
@@ -76,5 +77,11 @@ pub fn restore(
         }
     };
 
+    // Restoring discards the current working copy contents, so snapshot them
+    // first in order to be able to undo the restore.
+    if get_undo_create_snapshots(&repo)? {
+        create_snapshot(effects, git_run_info, &repo, &event_log_db, event_tx_id)?;
+    }
+
     restore_snapshot(effects, git_run_info, &repo, event_tx_id, &snapshot)
 }
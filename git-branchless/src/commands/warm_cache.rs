@@ -0,0 +1,64 @@
+//! Precompute the cached commit graph, so that later commands don't have to.
+
+use std::fmt::Write;
+
+use lib::core::dag::{CommitSet, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::formatting::Pluralize;
+use lib::core::repo_ext::RepoExt;
+use lib::git::Repo;
+use lib::util::EyreExitOr;
+use tracing::instrument;
+
+/// Add all visible commits and the upstream of the main branch to the cached
+/// commit graph, and compute the merge-bases of the visible heads with the
+/// main branch.
+///
+/// This can be run after a large fetch so that the next smartlog doesn't have
+/// to walk the newly-fetched commits itself.
+#[instrument]
+pub fn warm_cache(effects: &Effects) -> EyreExitOr<()> {
+    let repo = Repo::from_current_dir()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let mut dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    if let Some(upstream_oid) = repo.get_main_branch()?.get_upstream_branch_target()? {
+        dag.sync_from_oids(
+            effects,
+            &repo,
+            CommitSet::empty(),
+            CommitSet::from(upstream_oid),
+        )?;
+    }
+
+    let visible_heads = dag.query_visible_heads()?;
+    let visible_head_oids = dag.commit_set_to_vec(visible_heads)?;
+    let merge_bases = dag.query_main_branch_merge_bases_many(&visible_head_oids)?;
+
+    writeln!(
+        effects.get_output_stream(),
+        "Cached commit graph contains {}; computed merge-bases for {}.",
+        Pluralize {
+            determiner: None,
+            amount: dag.set_count(&dag.query_all()?)?,
+            unit: ("commit", "commits"),
+        },
+        Pluralize {
+            determiner: None,
+            amount: merge_bases.len(),
+            unit: ("visible head", "visible heads"),
+        },
+    )?;
+    Ok(Ok(()))
+}
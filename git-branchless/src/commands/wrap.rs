@@ -59,7 +59,13 @@ fn make_event_tx_id<S: AsRef<str> + std::fmt::Debug>(
     let conn = repo.get_db_conn()?;
     let event_log_db = EventLogDb::new(&conn)?;
     let event_tx_id = {
-        let message = args.first().map(|s| s.as_ref()).unwrap_or("wrap");
+        // Describe the transaction using the whole command line (e.g. `rebase
+        // main`), so that it's clear which operation the events belong to.
+        let message = if args.is_empty() {
+            "wrap".to_string()
+        } else {
+            args.iter().map(|s| s.as_ref()).join(" ")
+        };
         event_log_db.make_transaction_id(now, message)?
     };
     Ok(event_tx_id)
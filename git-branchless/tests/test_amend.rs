@@ -594,18 +594,14 @@ fn test_amend_undo() -> eyre::Result<()> {
         let stdout = trim_lines(stdout);
         insta::assert_snapshot!(stdout, @r###"
         Will apply these actions:
-        1. Move branch foo from 94b1077 create file1.txt
-                             to 94b1077 create file1.txt
-        2. Check out from 94b1077 create file1.txt
-                       to 94b1077 create file1.txt
-        3. Restore snapshot for branch foo
+        1. Restore snapshot for branch foo
                     pointing to 94b1077 create file1.txt
                 backed up using b4371f8 branchless: automated working copy snapshot
-        4. Move branch foo from 94b1077 create file1.txt
+        2. Move branch foo from 94b1077 create file1.txt
                              to c0bdfb5 create file1.txt
-        5. Rewrite commit 94b1077 create file1.txt
+        3. Rewrite commit 94b1077 create file1.txt
                       as c0bdfb5 create file1.txt
-        6. Restore snapshot for branch foo
+        4. Restore snapshot for branch foo
                     pointing to c0bdfb5 create file1.txt
                 backed up using a293e0b branchless: automated working copy snapshot
         branchless: running command: <git-executable> checkout a293e0b4502882ced673f83b6742539ee06cbc74 -B foo
@@ -620,7 +616,7 @@ fn test_amend_undo() -> eyre::Result<()> {
         O f777ecc (master) create initial.txt
         |
         @ c0bdfb5 (> foo) create file1.txt
-        Applied 6 inverse events.
+        Applied 4 inverse events.
         "###);
     }
 
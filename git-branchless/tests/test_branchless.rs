@@ -136,3 +136,63 @@ fn test_sparse_checkout() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_ascii_glyphs() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &[
+                "branchless",
+                "--color",
+                "always",
+                "--glyphs",
+                "ascii",
+                "smartlog",
+            ],
+            &Default::default(),
+        )?;
+        assert!(stdout.contains('\u{1b}'), "{stdout:?}");
+        assert!(!stdout.contains('◇') && !stdout.contains('●'), "{stdout}");
+    }
+
+    {
+        git.run(&["config", "branchless.glyphs", "unicode"])?;
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "--color", "always", "smartlog"],
+            &Default::default(),
+        )?;
+        assert!(stdout.contains('◇') && stdout.contains('●'), "{stdout}");
+    }
+
+    git.run(&["config", "branchless.glyphs", "auto"])?;
+    for (env, expect_unicode) in [
+        (vec![], true),
+        (vec![("LANG", "en_US.UTF-8")], true),
+        (vec![("LC_ALL", "C"), ("LANG", "en_US.UTF-8")], false),
+        (vec![("LANG", "C")], false),
+        (vec![("TERM", "dumb"), ("LANG", "en_US.UTF-8")], false),
+    ] {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "--color", "always", "smartlog"],
+            &GitRunOptions {
+                env: env
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(
+            stdout.contains('◇') && stdout.contains('●'),
+            expect_unicode,
+            "{env:?}: {stdout}"
+        );
+    }
+
+    Ok(())
+}
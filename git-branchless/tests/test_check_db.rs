@@ -0,0 +1,77 @@
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use lib::core::eventlog::{Event, EventLogDb};
+use lib::git::NonZeroOid;
+use lib::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_check_db_healthy() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("check-db", &[])?;
+        let stdout = stdout
+            .lines()
+            .filter(|line| !line.starts_with("Checked "))
+            .collect::<Vec<_>>()
+            .join("\n");
+        insta::assert_snapshot!(stdout, @r###"
+        Database integrity check passed.
+        Vacuumed database.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_check_db_problems() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    {
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "test")?;
+        event_log_db.add_events(vec![Event::CommitEvent {
+            timestamp: 0.0,
+            event_tx_id,
+            commit_oid: NonZeroOid::from_str("1234567812345678123456781234567812345678")?,
+        }])?;
+        conn.execute(
+            "INSERT INTO event_log (timestamp, type, event_tx_id) VALUES (0.0, 'unknown-type', 1)",
+            rusqlite::params![],
+        )?;
+    }
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "check-db"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert!(
+            stdout.contains("Found 1 invalid event which could not be read"),
+            "{stdout}"
+        );
+        let stdout = stdout
+            .lines()
+            .filter(|line| !line.starts_with("Checked ") && !line.starts_with("Found 1 invalid"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        insta::assert_snapshot!(stdout, @r###"
+        Database integrity check passed.
+        Found 1 commit referred to by events but missing from the object database: 1234567812345678123456781234567812345678
+        This is expected for commits which were garbage-collected. If any of them are still visible, run: git branchless repair
+        Not vacuuming the database, since problems were found.
+        "###);
+    }
+
+    Ok(())
+}
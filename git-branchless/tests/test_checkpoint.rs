@@ -0,0 +1,63 @@
+use lib::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_checkpoint_save_restore() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+
+    {
+        let (stdout, _stderr) = git.branchless("checkpoint", &["save", "before"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Saved checkpoint before.
+        "###);
+    }
+
+    git.run(&["branch", "foo"])?;
+    git.commit_file("test2", 2)?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (foo, master) create test1.txt
+        |
+        @ 96d1c37 create test2.txt
+        "###);
+    }
+
+    git.branchless("checkpoint", &["restore", "before", "--yes"])?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d (master) create test1.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &[
+                "branchless",
+                "checkpoint",
+                "restore",
+                "nonexistent",
+                "--yes",
+            ],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        There is no checkpoint named nonexistent.
+        "###);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,28 @@
+use lib::testing::make_git;
+
+#[test]
+fn test_debug_cache_stats() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("debug", &["cache-stats"])?;
+        let stdout = stdout
+            .lines()
+            .map(str::trim)
+            .filter(|line| {
+                !line.starts_with("Commit graph cache: ") && !line.starts_with("Size on disk: ")
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        insta::assert_snapshot!(stdout, @r###"
+        Commits: 2 (2 public)
+        Git commit-graph: not used
+        Shallow clone: no
+        Cached diff stats: 0
+        "###);
+    }
+
+    Ok(())
+}
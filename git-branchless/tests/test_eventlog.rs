@@ -1,8 +1,8 @@
 use lib::core::effects::Effects;
 use lib::core::eventlog::testing::{get_event_replayer_events, redact_event_timestamp};
-use lib::core::eventlog::{Event, EventLogDb, EventReplayer};
+use lib::core::eventlog::{Event, EventLogDb, EventReplayer, TransactionOrigin};
 use lib::core::formatting::Glyphs;
-use lib::testing::make_git;
+use lib::testing::{make_git, GitInitOptions, GitRunOptions};
 
 #[test]
 fn test_git_v2_31_events() -> eyre::Result<()> {
@@ -43,18 +43,6 @@ fn test_git_v2_31_events() -> eyre::Result<()> {
             new_oid: f777ecc9b0db5ed372b2615695191a8a17f79f24,
             message: None,
         },
-        RefUpdateEvent {
-            timestamp: 0.0,
-            event_tx_id: Id(
-                2,
-            ),
-            ref_name: ReferenceName(
-                "HEAD",
-            ),
-            old_oid: f777ecc9b0db5ed372b2615695191a8a17f79f24,
-            new_oid: f777ecc9b0db5ed372b2615695191a8a17f79f24,
-            message: None,
-        },
         RefUpdateEvent {
             timestamp: 0.0,
             event_tx_id: Id(
@@ -153,3 +141,378 @@ fn test_git_v2_31_events() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_event_log_export_import() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.branchless("hide", &["HEAD"])?;
+
+    let (export_json, _stderr) = git.branchless("event-log", &["export"])?;
+    let export_path = git.repo_path.join("events.json");
+    std::fs::write(&export_path, export_json)?;
+
+    let other_git = make_git()?;
+    other_git.init_repo()?;
+    other_git.detach_head()?;
+    other_git.commit_file("test1", 1)?;
+    other_git.commit_file("test2", 2)?;
+    other_git.run(&["checkout", "HEAD^"])?;
+    {
+        let stdout = other_git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        @ 62fc20d create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) =
+            other_git.branchless("event-log", &["import", export_path.to_str().unwrap()])?;
+        assert!(stdout.starts_with("Imported "), "{stdout}");
+    }
+
+    // The imported events hide `test2`.
+    {
+        let stdout = other_git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        @ 62fc20d create test1.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_event_log_show() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.branchless("hide", &["HEAD"])?;
+
+    let (stdout, _stderr) = git.branchless("event-log", &["show"])?;
+    let events = stdout
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<Vec<serde_json::Value>, _>>()?;
+
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    assert_eq!(events.len(), event_log_db.get_events()?.len());
+
+    let last_event = events.last().unwrap();
+    assert_eq!(last_event["type"], "hide");
+    assert_eq!(
+        last_event["old_ref"],
+        "96d1c37a3d4363611c49f7e52186e189a04c531f"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_events_query() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.branchless("hide", &["HEAD"])?;
+
+    let parse_events = |stdout: String| -> eyre::Result<Vec<serde_json::Value>> {
+        let events = stdout
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<serde_json::Value>, _>>()?;
+        Ok(events)
+    };
+
+    {
+        let (stdout, _stderr) = git.branchless("events", &["--type", "hide"])?;
+        let events = parse_events(stdout)?;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["old_ref"], test2_oid.to_string());
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless(
+            "events",
+            &[
+                "--type",
+                "commit",
+                "--type",
+                "hide",
+                "--commit",
+                &test2_oid.to_string(),
+            ],
+        )?;
+        let events = parse_events(stdout)?;
+        let types: Vec<_> = events.iter().map(|event| event["type"].clone()).collect();
+        assert_eq!(types, vec!["commit", "hide"]);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("events", &["--until", "0"])?;
+        assert_eq!(stdout, "");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_event_transaction_command() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.run_with_options(
+        &["branch", "foo"],
+        &GitRunOptions {
+            env: [("GIT_REFLOG_ACTION".to_string(), "my-script".to_string())]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        },
+    )?;
+
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_tx_id = event_log_db
+        .get_events()?
+        .into_iter()
+        .find_map(|event| match event {
+            Event::RefUpdateEvent {
+                event_tx_id,
+                ref_name,
+                ..
+            } if ref_name.as_str() == "refs/heads/foo" => Some(event_tx_id),
+            _ => None,
+        })
+        .expect("branch creation should have been recorded");
+    assert_eq!(
+        event_log_db.get_transaction_command(event_tx_id)?,
+        Some("my-script".to_string())
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_transaction_origin() -> eyre::Result<()> {
+    let git = make_git()?;
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.run_with_options(
+        &["branch", "foo"],
+        &GitRunOptions {
+            env: [("USER".to_string(), "alice".to_string())]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        },
+    )?;
+
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_tx_id = event_log_db
+        .get_events()?
+        .into_iter()
+        .find_map(|event| match event {
+            Event::RefUpdateEvent {
+                event_tx_id,
+                ref_name,
+                ..
+            } if ref_name.as_str() == "refs/heads/foo" => Some(event_tx_id),
+            _ => None,
+        })
+        .expect("branch creation should have been recorded");
+    let origin = event_log_db
+        .get_transaction_origins()?
+        .remove(&event_tx_id)
+        .expect("transaction origin should have been recorded");
+    assert_eq!(origin.username, Some("alice".to_string()));
+    // The hostname is queried from the system rather than the environment,
+    // which the test harness clears.
+    if cfg!(unix) {
+        assert!(origin.hostname.is_some(), "{origin:?}");
+    }
+
+    let other_origin = TransactionOrigin {
+        hostname: origin.hostname.clone(),
+        username: Some("bob".to_string()),
+    };
+    assert!(origin.differs_from(&other_origin));
+    let unknown_origin = TransactionOrigin {
+        hostname: None,
+        username: None,
+    };
+    assert!(!origin.differs_from(&unknown_origin));
+
+    Ok(())
+}
+
+#[test]
+fn test_shared_event_database() -> eyre::Result<()> {
+    let git1 = make_git()?;
+    let git2 = make_git()?;
+    let git3 = make_git()?;
+
+    if !git1.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    let shared_db_path = git1.repo_path.join(".git").join("shared.sqlite3");
+    let shared_db_path = shared_db_path.to_str().unwrap();
+    for (git, repo_id) in [(&git1, "project"), (&git2, "project"), (&git3, "other")] {
+        git.init_repo()?;
+        git.run(&[
+            "config",
+            "branchless.core.sharedDatabasePath",
+            shared_db_path,
+        ])?;
+        git.run(&["config", "branchless.core.repoId", repo_id])?;
+    }
+
+    git1.run(&["branch", "foo"])?;
+
+    let get_ref_names = |git: &lib::testing::Git| -> eyre::Result<Vec<String>> {
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let ref_names = event_log_db
+            .get_events()?
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::RefUpdateEvent { ref_name, .. } => Some(ref_name.as_str().to_owned()),
+                _ => None,
+            })
+            .collect();
+        Ok(ref_names)
+    };
+    assert_eq!(get_ref_names(&git1)?, vec!["refs/heads/foo".to_string()]);
+    assert_eq!(get_ref_names(&git2)?, vec!["refs/heads/foo".to_string()]);
+    assert_eq!(get_ref_names(&git3)?, Vec::<String>::new());
+
+    // WAL mode doesn't work on network filesystems, so it's not used for shared
+    // databases.
+    let journal_mode: String =
+        git1.get_repo()?
+            .get_db_conn()?
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))?;
+    assert_eq!(journal_mode, "delete");
+
+    Ok(())
+}
+
+#[test]
+fn test_shared_event_database_existing_events() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.run(&["branch", "foo"])?;
+
+    // Share the database which was already in use, by a path relative to the
+    // working copy. The events recorded before it was shared should still be
+    // visible.
+    git.run(&[
+        "config",
+        "branchless.core.sharedDatabasePath",
+        ".git/branchless/db.sqlite3",
+    ])?;
+    git.run(&["config", "branchless.core.repoId", "project"])?;
+    git.run(&["branch", "bar"])?;
+
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let ref_names: Vec<String> = event_log_db
+        .get_events()?
+        .into_iter()
+        .filter_map(|event| match event {
+            Event::RefUpdateEvent { ref_name, .. } => Some(ref_name.as_str().to_owned()),
+            _ => None,
+        })
+        .collect();
+    insta::assert_debug_snapshot!(ref_names, @r###"
+    [
+        "refs/heads/foo",
+        "refs/heads/bar",
+    ]
+    "###);
+
+    Ok(())
+}
+
+#[test]
+fn test_event_log_backfill() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: true,
+        run_branchless_init: false,
+    })?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+    git.branchless("init", &["--main-branch", "master"])?;
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d (> master) create test1.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("event-log", &["backfill"])?;
+        assert!(stdout.starts_with("Backfilled "), "{stdout}");
+    }
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d (> master) create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        "###);
+    }
+
+    // Backfilling again shouldn't add duplicate events.
+    {
+        let (stdout, _stderr) = git.branchless("event-log", &["backfill"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Backfilled 0 events from the reflog.
+        "###);
+    }
+
+    Ok(())
+}
@@ -1,8 +1,16 @@
+use std::collections::HashSet;
+
 use itertools::Itertools;
 use lib::core::eventlog::testing::redact_event_timestamp;
 use lib::core::eventlog::EventLogDb;
-use lib::git::GitVersion;
-use lib::testing::{make_git, GitInitOptions};
+use lib::core::gc::{
+    get_kept_commit_oids, mark_commit_reachable, mark_commits_reachable, unmark_commit_reachable,
+};
+use lib::git::{GitVersion, NonZeroOid, Signature};
+use lib::testing::{
+    make_git, make_git_with_remote_repo, make_git_worktree, GitInitOptions, GitWorktreeWrapper,
+    GitWrapperWithRemoteRepo,
+};
 
 #[test]
 fn test_gc() -> eyre::Result<()> {
@@ -33,7 +41,9 @@ fn test_gc() -> eyre::Result<()> {
         let (stdout, _stderr) = git.branchless("gc", &[])?;
         insta::assert_snapshot!(stdout, @r###"
         branchless: collecting garbage
-        branchless: 1 dangling reference deleted
+        branchless: 1 dangling commit released
+        branchless: 2 kept commits scanned, 1 commit still protected
+        branchless: up to 1 commit newly eligible for Git's garbage collection
         "###);
     }
 
@@ -78,7 +88,9 @@ fn test_gc_reference_transaction() -> eyre::Result<()> {
         let (stdout, _stderr) = git.branchless("gc", &[])?;
         insta::assert_snapshot!(stdout, @r###"
         branchless: collecting garbage
-        branchless: 1 dangling reference deleted
+        branchless: 1 dangling commit released
+        branchless: 2 kept commits scanned, 1 commit still protected
+        branchless: up to 1 commit newly eligible for Git's garbage collection
         "###);
     }
 
@@ -289,3 +301,643 @@ fn test_gc_no_init() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_gc_dry_run() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "HEAD^"])?;
+
+    git.branchless("hide", &["62fc20d2"])?;
+    {
+        let (stdout, _stderr) = git.branchless("gc", &["--dry-run"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: would release 62fc20d create test1.txt
+        branchless: 1 dangling commit would be released (dry run)
+        branchless: 1 kept commit scanned, 0 commits still protected
+        "###);
+    }
+
+    {
+        let repo = git.get_repo()?;
+        let test1_oid: NonZeroOid = "62fc20d2a290daea0d52bdc2ed2ad4be6491010e".parse()?;
+        assert!(get_kept_commit_oids(&repo)?.contains(&test1_oid));
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("gc", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 1 dangling commit released
+        branchless: 1 kept commit scanned, 0 commits still protected
+        branchless: up to 1 commit newly eligible for Git's garbage collection
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_retention_days() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "HEAD^"])?;
+    git.branchless("hide", &["62fc20d2"])?;
+
+    git.run(&["config", "branchless.gc.retentionDays", "1"])?;
+    {
+        let (stdout, _stderr) = git.branchless("gc", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 0 dangling commits released
+        branchless: 1 kept commit scanned, 1 commit still protected
+        branchless: up to 0 commits newly eligible for Git's garbage collection
+        "###);
+    }
+
+    git.run(&["config", "branchless.gc.retentionDays", "0"])?;
+    {
+        let (stdout, _stderr) = git.branchless("gc", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 1 dangling commit released
+        branchless: 1 kept commit scanned, 0 commits still protected
+        branchless: up to 1 commit newly eligible for Git's garbage collection
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_full() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "HEAD^"])?;
+    git.branchless("hide", &["62fc20d2"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("gc", &["--full"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 1 dangling commit released
+        branchless: 1 kept commit scanned, 0 commits still protected
+        branchless: up to 1 commit newly eligible for Git's garbage collection
+        branchless: expired 1 reflog entry for released commits
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["reflog", "show", "HEAD"])?;
+        assert!(
+            !stdout.lines().any(|line| line.starts_with("62fc20d")),
+            "{stdout}"
+        );
+        assert!(
+            stdout.contains("checkout: moving from master to HEAD"),
+            "{stdout}"
+        );
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("gc", &["--full", "--git-gc"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 0 dangling commits released
+        branchless: 0 kept commits scanned, 0 commits still protected
+        branchless: up to 0 commits newly eligible for Git's garbage collection
+        branchless: expired 0 reflog entries for released commits
+        branchless: running command: <git-executable> gc --auto
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_incremental() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "HEAD^"])?;
+    git.branchless("hide", &["62fc20d2"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("gc", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 1 dangling commit released
+        branchless: 1 kept commit scanned, 0 commits still protected
+        branchless: up to 1 commit newly eligible for Git's garbage collection
+        "###);
+    }
+
+    // No events have touched the commit since the last GC, so an incremental
+    // GC won't notice that it's being kept alive again.
+    mark_commit_reachable(
+        &git.get_repo()?,
+        "62fc20d2a290daea0d52bdc2ed2ad4be6491010e".parse()?,
+    )?;
+    {
+        let (stdout, _stderr) = git.branchless("gc", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 0 dangling commits released
+        branchless: 0 kept commits scanned, 0 commits still protected
+        branchless: up to 0 commits newly eligible for Git's garbage collection
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("gc", &["--full-scan"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 1 dangling commit released
+        branchless: 1 kept commit scanned, 0 commits still protected
+        branchless: up to 1 commit newly eligible for Git's garbage collection
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_pinned_commit() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "HEAD^"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("pin", &["62fc20d2"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Pinned commit: 62fc20d create test1.txt
+        To unpin this 1 commit, run: git undo
+        "###);
+    }
+
+    git.branchless("hide", &["62fc20d2"])?;
+    {
+        let (stdout, _stderr) = git.branchless("gc", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 0 dangling commits released
+        branchless: 1 kept commit scanned, 1 commit still protected
+        branchless: up to 0 commits newly eligible for Git's garbage collection
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("unpin", &["62fc20d2"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Unpinned commit: 62fc20d create test1.txt
+        To pin this 1 commit, run: git undo
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("gc", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 1 dangling commit released
+        branchless: 1 kept commit scanned, 0 commits still protected
+        branchless: up to 1 commit newly eligible for Git's garbage collection
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_worktree_head() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["checkout", "HEAD^"])?;
+
+    let GitWorktreeWrapper {
+        temp_dir: _temp_dir,
+        worktree,
+    } = make_git_worktree(&git, "new-worktree")?;
+    worktree.run(&["checkout", &test1_oid.to_string()])?;
+
+    git.branchless("hide", &[&test1_oid.to_string()])?;
+    {
+        let (stdout, _stderr) = git.branchless("gc", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 0 dangling commits released
+        branchless: 1 kept commit scanned, 1 commit still protected
+        branchless: up to 0 commits newly eligible for Git's garbage collection
+        "###);
+    }
+
+    worktree.run(&["checkout", "HEAD^"])?;
+    {
+        let (stdout, _stderr) = git.branchless("gc", &["--full-scan"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 1 dangling commit released
+        branchless: 1 kept commit scanned, 0 commits still protected
+        branchless: up to 1 commit newly eligible for Git's garbage collection
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_auto_events() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "HEAD^"])?;
+    git.branchless("hide", &["62fc20d2"])?;
+
+    {
+        let (_stdout, stderr) = git.run(&["checkout", "HEAD"])?;
+        assert!(!stderr.contains("collecting garbage"), "{stderr}");
+    }
+
+    git.run(&["config", "branchless.gc.autoEvents", "3"])?;
+    {
+        let (_stdout, stderr) = git.run(&["checkout", "HEAD"])?;
+        assert!(stderr.contains("running gc automatically"), "{stderr}");
+        assert!(stderr.contains("1 dangling commit released"), "{stderr}");
+    }
+
+    {
+        let (_stdout, stderr) = git.run(&["checkout", "HEAD"])?;
+        assert!(!stderr.contains("collecting garbage"), "{stderr}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_mark_commits_reachable() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    let initial_oid = git.get_repo()?.get_head_info()?.oid.unwrap();
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+
+    let repo = git.get_repo()?;
+    mark_commits_reachable(&repo, &[initial_oid, test1_oid, test2_oid, initial_oid])?;
+    let kept_commit_oids = get_kept_commit_oids(&repo)?
+        .into_iter()
+        .map(|oid| oid.to_string())
+        .sorted()
+        .join("\n");
+    insta::assert_snapshot!(kept_commit_oids, @r###"
+    62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+    96d1c37a3d4363611c49f7e52186e189a04c531f
+    f777ecc9b0db5ed372b2615695191a8a17f79f24
+    "###);
+
+    Ok(())
+}
+
+#[test]
+fn test_mark_many_commits_reachable() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    let repo = git.get_repo()?;
+    let initial_commit = repo.find_commit_or_fail(repo.get_head_info()?.oid.unwrap())?;
+    let tree = initial_commit.get_tree()?;
+    let signature = Signature::automated()?;
+    let mut commit_oids = Vec::new();
+    for i in 0..200 {
+        commit_oids.push(repo.create_commit(
+            None,
+            &signature,
+            &signature,
+            &format!("commit {i}"),
+            &tree,
+            vec![&initial_commit],
+        )?);
+    }
+
+    mark_commits_reachable(&repo, &commit_oids)?;
+    let expected_oids: HashSet<NonZeroOid> = commit_oids.iter().copied().collect();
+    assert_eq!(get_kept_commit_oids(&repo)?, expected_oids);
+
+    // All of the commits are kept alive by a single reference, with one
+    // keep-alive commit per 64 kept commits (plus the root of the chain).
+    {
+        let (stdout, _stderr) = git.run(&["show-ref"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        d1a1d7733ad31da84975aa172b7927dbfc6efcfb refs/branchless/keep-alive
+        f777ecc9b0db5ed372b2615695191a8a17f79f24 refs/heads/master
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.run(&[
+            "rev-list",
+            "--first-parent",
+            "--count",
+            "refs/branchless/keep-alive",
+        ])?;
+        insta::assert_snapshot!(stdout, @"5");
+    }
+
+    // The kept commits can still be read without the cache.
+    std::fs::remove_file(repo.get_branchless_dir()?.join("keep-alive"))?;
+    assert_eq!(get_kept_commit_oids(&repo)?, expected_oids);
+
+    Ok(())
+}
+
+#[test]
+fn test_pre_auto_gc_marks_active_commits_reachable() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    unmark_commit_reachable(&git.get_repo()?, test1_oid)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("hook", &["pre-auto-gc"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: kept alive 1 commit which had not been marked as reachable
+        branchless: collecting garbage
+        branchless: 0 dangling commits released
+        branchless: 1 kept commit scanned, 1 commit still protected
+        branchless: up to 0 commits newly eligible for Git's garbage collection
+        "###);
+    }
+
+    {
+        let repo = git.get_repo()?;
+        assert!(get_kept_commit_oids(&repo)?.contains(&test1_oid));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_aggressive() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "HEAD^"])?;
+    git.commit_file("test3", 3)?;
+    git.branchless("hide", &["96d1c37"])?;
+    git.run(&["config", "branchless.gc.eventLogRetentionDays", "0"])?;
+
+    let smartlog_before = git.smartlog()?;
+    insta::assert_snapshot!(smartlog_before, @r###"
+    O f777ecc (master) create initial.txt
+    |
+    o 62fc20d create test1.txt
+    |
+    @ 4838e49 create test3.txt
+    "###);
+    let num_events_before = {
+        let conn = git.get_repo()?.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        event_log_db.get_events()?.len()
+    };
+
+    {
+        let (stdout, _stderr) = git.branchless("gc", &["--aggressive"])?;
+        assert!(stdout.contains("branchless: pruned"), "{stdout}");
+    }
+
+    let num_events_after = {
+        let conn = git.get_repo()?.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        event_log_db.get_events()?.len()
+    };
+    assert!(
+        num_events_after < num_events_before,
+        "{num_events_after} < {num_events_before}"
+    );
+
+    // The current state of the repository should not be affected.
+    let smartlog_after = git.smartlog()?;
+    assert_eq!(smartlog_before, smartlog_after);
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_stash() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.write_file_txt("test1", "modified contents")?;
+    git.run(&["stash"])?;
+    git.run(&["checkout", "HEAD^"])?;
+    git.branchless("hide", &["62fc20d2"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("gc", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 0 dangling commits released
+        branchless: 1 kept commit scanned, 1 commit still protected
+        branchless: up to 0 commits newly eligible for Git's garbage collection
+        "###);
+    }
+
+    git.run(&["stash", "drop"])?;
+    {
+        let (stdout, _stderr) = git.branchless("gc", &["--full-scan"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 1 dangling commit released
+        branchless: 1 kept commit scanned, 0 commits still protected
+        branchless: up to 1 commit newly eligible for Git's garbage collection
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_ref_namespace() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&[
+        "config",
+        "branchless.core.refNamespace",
+        "refs/custom-namespace",
+    ])?;
+    git.run(&["update-ref", "refs/branchless/other-tool", "HEAD"])?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "HEAD^"])?;
+
+    {
+        let repo = git.get_repo()?;
+        assert!(repo
+            .find_reference(&"refs/custom-namespace/keep-alive".into())?
+            .is_some());
+        assert!(repo
+            .find_reference(&"refs/branchless/keep-alive".into())?
+            .is_none());
+    }
+
+    git.branchless("hide", &["62fc20d2"])?;
+    {
+        let (stdout, _stderr) = git.branchless("gc", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 1 dangling commit released
+        branchless: 1 kept commit scanned, 0 commits still protected
+        branchless: up to 1 commit newly eligible for Git's garbage collection
+        "###);
+    }
+
+    {
+        let repo = git.get_repo()?;
+        assert!(repo
+            .find_reference(&"refs/branchless/other-tool".into())?
+            .is_some());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_migrate_legacy_references() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    let (legacy_oid, _stderr) = git.run(&["commit-tree", "HEAD^{tree}", "-m", "legacy"])?;
+    let legacy_oid: NonZeroOid = legacy_oid.trim().parse()?;
+    git.run(&[
+        "update-ref",
+        &format!("refs/branchless/{legacy_oid}"),
+        &legacy_oid.to_string(),
+    ])?;
+    git.run(&["update-ref", "refs/branchless/other-tool", "HEAD"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("gc", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 0 dangling commits released
+        branchless: 1 kept commit scanned, 1 commit still protected
+        branchless: up to 0 commits newly eligible for Git's garbage collection
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["show-ref"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        780b1495e4f25c519fe538097bbadb0b51460968 refs/branchless/keep-alive
+        f777ecc9b0db5ed372b2615695191a8a17f79f24 refs/branchless/other-tool
+        f777ecc9b0db5ed372b2615695191a8a17f79f24 refs/heads/master
+        "###);
+    }
+
+    assert!(get_kept_commit_oids(&git.get_repo()?)?.contains(&legacy_oid));
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_refresh_cache() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.smartlog()?;
+    let dag_dir = git.get_repo()?.get_dag_dir()?;
+    assert!(dag_dir.exists());
+
+    // Rewinding the main branch invalidates the commit graph cache.
+    git.run(&["reset", "--hard", "HEAD^"])?;
+    assert!(!dag_dir.exists());
+
+    let smartlog_before = git.smartlog()?;
+    insta::assert_snapshot!(smartlog_before, @r###"
+    :
+    @ 62fc20d (> master) create test1.txt
+    |
+    o 96d1c37 create test2.txt
+    "###);
+
+    {
+        let (stdout, _stderr) = git.branchless("gc", &["--refresh-cache"])?;
+        assert!(
+            stdout.contains("branchless: rebuilt commit graph cache with"),
+            "{stdout}"
+        );
+    }
+    assert!(dag_dir.exists());
+
+    let smartlog_after = git.smartlog()?;
+    assert_eq!(smartlog_before, smartlog_after);
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_refresh_cache_remote_main_branch_rewound() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    if !original_repo.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.commit_file("test2", 2)?;
+    original_repo.clone_repo_into(&cloned_repo, &[])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.smartlog()?;
+    let dag_dir = cloned_repo.get_repo()?.get_dag_dir()?;
+    assert!(dag_dir.exists());
+
+    // Fetching a force-pushed main branch only rewinds the remote-tracking
+    // branch, which also invalidates the commit graph cache.
+    original_repo.run(&["reset", "--hard", "HEAD^"])?;
+    original_repo.commit_file("test3", 3)?;
+    cloned_repo.run(&["fetch"])?;
+    assert!(!dag_dir.exists());
+
+    Ok(())
+}
@@ -137,6 +137,34 @@ fn test_hidden_commit_with_head_as_child() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_hide_with_reason() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    git.branchless(
+        "hide",
+        &["-m", "superseded by new approach", &test1_oid.to_string()],
+    )?;
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        x 62fc20d (manually hidden: superseded by new approach) create test1.txt
+        |
+        @ 96d1c37 create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_hide_master_commit_with_hidden_children() -> eyre::Result<()> {
     let git = make_git()?;
@@ -202,6 +230,46 @@ fn test_branches_always_visible() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_tags_always_visible() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["tag", "wip"])?;
+    git.run(&["checkout", "master"])?;
+
+    git.branchless("hide", &["draft()"])?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc (> master) create initial.txt
+        |
+        x 62fc20d (manually hidden) create test1.txt
+        |
+        x 96d1c37 (manually hidden) create test2.txt
+        "###);
+    }
+
+    git.run(&["tag", "-d", "wip"])?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @"@ f777ecc (> master) create initial.txt
+");
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("events", &["--ref", "refs/tags/wip"])?;
+        let events = stdout.lines().collect::<Vec<_>>();
+        assert_eq!(events.len(), 2, "{stdout}");
+        assert!(events[0].contains(&test2_oid.to_string()), "{stdout}");
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_hide_delete_branches() -> eyre::Result<()> {
     let git = make_git()?;
@@ -447,6 +515,118 @@ fn test_hide_recursive() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_unhide_ancestors() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    let test3_oid = git.commit_file("test3", 3)?;
+    git.run(&["checkout", "master"])?;
+
+    git.branchless("hide", &["-r", &test1_oid.to_string()])?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc (> master) create initial.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("unhide", &["-a", &test3_oid.to_string()])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Unhid commit: 62fc20d create test1.txt
+        Unhid commit: 96d1c37 create test2.txt
+        Unhid commit: 70deb1e create test3.txt
+        To hide these 3 commits, run: git undo
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc (> master) create initial.txt
+        |
+        o 62fc20d create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        |
+        o 70deb1e create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_unhide_after_gc() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    let test3_oid = git.commit_file("test3", 3)?;
+    git.run(&["checkout", "master"])?;
+
+    git.branchless("hide", &["-r", &test1_oid.to_string()])?;
+    {
+        let (stdout, _stderr) = git.branchless("gc", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 3 dangling commits released
+        branchless: 3 kept commits scanned, 0 commits still protected
+        branchless: up to 3 commits newly eligible for Git's garbage collection
+        "###);
+    }
+
+    git.branchless("unhide", &["-a", &test3_oid.to_string()])?;
+    git.run(&["gc", "--prune=now"])?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc (> master) create initial.txt
+        |
+        o 62fc20d create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        |
+        o 70deb1e create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_hide_merged() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&["cherry-pick", &test1_oid.to_string()])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("hide", &["--merged"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Hid commit: 62fc20d create test1.txt
+        To unhide this 1 commit, run: git undo
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        assert!(!stdout.contains("62fc20d"), "{stdout}");
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_smartlog_active_non_head_main_branch_commit() -> eyre::Result<()> {
     let GitWrapperWithRemoteRepo {
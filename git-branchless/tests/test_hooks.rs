@@ -212,7 +212,9 @@ fn test_pre_auto_gc() -> eyre::Result<()> {
     );
     insta::assert_snapshot!(stdout, @r###"
     branchless: collecting garbage
-    branchless: 0 dangling references deleted
+    branchless: 0 dangling commits released
+    branchless: 0 kept commits scanned, 0 commits still protected
+    branchless: up to 0 commits newly eligible for Git's garbage collection
     "###);
 
     Ok(())
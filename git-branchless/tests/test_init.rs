@@ -314,11 +314,11 @@ fn test_main_branch_not_found_error_message() -> eyre::Result<()> {
 
       ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━ SPANTRACE ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
 
-       0: branchless::core::eventlog::from_event_log_db with effects=<Output fancy=false> repo=<Git repository at: "<repo-path>/.git/"> event_log_db=<EventLogDb path=Some("<repo-path>/.git/branchless/db.sqlite3")>
+       0: branchless::core::eventlog::from_event_log_db_with_snapshot with effects=<Output fancy=false> repo=<Git repository at: "<repo-path>/.git/"> event_log_db=<EventLogDb path=Some("<repo-path>/.git/branchless/db.sqlite3")>
           at some/file/path.rs:123
-       1: git_branchless_smartlog::smartlog with effects=<Output fancy=false> git_run_info=<GitRunInfo path_to_git="<git-executable>" working_directory="<repo-path>" env=not shown> options=SmartlogOptions { event_id: None, revset: None, resolve_revset_options: ResolveRevsetOptions { show_hidden_commits: false }, reverse: false, exact: false }
+       1: git_branchless_smartlog::smartlog with effects=<Output fancy=false> git_run_info=<GitRunInfo path_to_git="<git-executable>" working_directory="<repo-path>" env=not shown> options=SmartlogOptions { event_id: None, revset: None, resolve_revset_options: ResolveRevsetOptions { show_hidden_commits: false }, reverse: false, exact: false, format: Text, date_format: None, order: None, filter_options: SmartlogFilterOptions { author: None, path: None, since: None }, expand: false, diff: false, stack: false, limit: None, stat: false }
           at some/file/path.rs:123
-       2: git_branchless_smartlog::command_main with ctx=CommandContext { effects: <Output fancy=false>, git_run_info: <GitRunInfo path_to_git="<git-executable>" working_directory="<repo-path>" env=not shown> } args=SmartlogArgs { event_id: None, revset: None, reverse: false, exact: false, resolve_revset_options: ResolveRevsetOptions { show_hidden_commits: false } }
+       2: git_branchless_smartlog::command_main with ctx=CommandContext { effects: <Output fancy=false>, git_run_info: <GitRunInfo path_to_git="<git-executable>" working_directory="<repo-path>" env=not shown> } args=SmartlogArgs { event_id: None, revset: None, reverse: false, exact: false, json: false, format: None, date_format: None, order: None, expand: false, diff: false, stack: false, stat: false, limit: None, interactive: false, filter_options: SmartlogFilterOptions { author: None, path: None, since: None }, resolve_revset_options: ResolveRevsetOptions { show_hidden_commits: false } }
           at some/file/path.rs:123
 
     Suggestion:
@@ -655,7 +655,7 @@ fn test_install_man_pages() -> eyre::Result<()> {
     .SH NAME
     git\-branchless \- Branchless workflow for Git
     .SH SYNOPSIS
-    \fBgit\-branchless\fR [\fB\-C \fR] [\fB\-\-color\fR] [\fB\-h\fR|\fB\-\-help\fR] [\fB\-V\fR|\fB\-\-version\fR] <\fIsubcommands\fR>
+    \fBgit\-branchless\fR [\fB\-C \fR] [\fB\-\-color\fR] [\fB\-\-glyphs\fR] [\fB\-h\fR|\fB\-\-help\fR] [\fB\-V\fR|\fB\-\-version\fR] <\fIsubcommands\fR>
     .SH DESCRIPTION
     Branchless workflow for Git.
     .PP
@@ -680,6 +680,21 @@ fn test_install_man_pages() -> eyre::Result<()> {
     never: Never display terminal colors
     .RE
     .TP
+    \fB\-\-glyphs\fR=\fIGLYPHS\fR
+    Which characters to use when drawing graphs. If not provided, the `branchless.glyphs` config setting is used
+    .br
+
+    .br
+    \fIPossible values:\fR
+    .RS 14
+    .IP \(bu 2
+    auto: Use Unicode box\-drawing characters, unless the terminal appears not to support them (`TERM=dumb` or a non\-UTF\-8 locale), in which case use plain ASCII. This is the default behavior
+    .IP \(bu 2
+    unicode: Always use Unicode box\-drawing characters when writing to a terminal
+    .IP \(bu 2
+    ascii: Always use plain ASCII characters
+    .RE
+    .TP
     \fB\-h\fR, \fB\-\-help\fR
     Print help (see a summary with \*(Aq\-h\*(Aq)
     .TP
@@ -693,9 +708,24 @@ fn test_install_man_pages() -> eyre::Result<()> {
     git\-branchless\-bug\-report(1)
     Gather information about recent operations to upload as part of a bug report
     .TP
+    git\-branchless\-check\-db(1)
+    Check the internal database for corruption or references to missing commits, and vacuum it if no problems are found
+    .TP
+    git\-branchless\-checkpoint(1)
+    Save the positions of all branches and `HEAD` under a name, or restore them later
+    .TP
+    git\-branchless\-debug(1)
+    Print internal diagnostic information
+    .TP
     git\-branchless\-difftool(1)
     Use the partial commit selector UI as a Git\-compatible difftool; see git\-difftool(1) for more information on Git difftools
     .TP
+    git\-branchless\-event\-log(1)
+    Show, export, or import the event log, which records the operations used to determine which commits are visible and to undo changes
+    .TP
+    git\-branchless\-events(1)
+    Print the events in the event log which match the given filters, one JSON object per line
+    .TP
     git\-branchless\-gc(1)
     Run internal garbage collection
     .TP
@@ -714,6 +744,9 @@ fn test_install_man_pages() -> eyre::Result<()> {
     git\-branchless\-next(1)
     Move to a later commit in the current stack
     .TP
+    git\-branchless\-pin(1)
+    Pin the provided commits, so that they\*(Aqre never garbage\-collected, even if they\*(Aqre hidden
+    .TP
     git\-branchless\-prev(1)
     Move to an earlier commit in the current stack
     .TP
@@ -729,6 +762,9 @@ fn test_install_man_pages() -> eyre::Result<()> {
     git\-branchless\-record(1)
     Create a commit by interactively selecting which changes to include
     .TP
+    git\-branchless\-redo(1)
+    Reapply the changes reverted by the most recent `git undo`
+    .TP
     git\-branchless\-reword(1)
     Reword commits
     .TP
@@ -753,6 +789,12 @@ fn test_install_man_pages() -> eyre::Result<()> {
     git\-branchless\-unhide(1)
     Unhide previously\-hidden commits from the smartlog
     .TP
+    git\-branchless\-unpin(1)
+    Unpin previously\-pinned commits, so that they can be garbage\-collected once hidden
+    .TP
+    git\-branchless\-warm\-cache(1)
+    Precompute the cached commit graph for all visible commits and the upstream of the main branch. This can be run after a large fetch so that the next command doesn\*(Aqt have to do it
+    .TP
     git\-branchless\-wrap(1)
     Wrap a Git command inside a branchless transaction
     .TP
@@ -4511,12 +4511,25 @@ fn test_move_standalone_create_gc_refs() -> eyre::Result<()> {
     {
         let (stdout, _stderr) = git.run(&["show-ref"])?;
         insta::assert_snapshot!(stdout, @r###"
-        62fc20d2a290daea0d52bdc2ed2ad4be6491010e refs/branchless/62fc20d2a290daea0d52bdc2ed2ad4be6491010e
-        96d1c37a3d4363611c49f7e52186e189a04c531f refs/branchless/96d1c37a3d4363611c49f7e52186e189a04c531f
+        658ced3c3bc61392348256339529d89eb288e550 refs/branchless/keep-alive
         62fc20d2a290daea0d52bdc2ed2ad4be6491010e refs/heads/master
         "###);
     }
 
+    {
+        let (stdout, _stderr) = git.run(&[
+            "rev-list",
+            "--parents",
+            "--first-parent",
+            "refs/branchless/keep-alive",
+        ])?;
+        insta::assert_snapshot!(stdout, @r###"
+        658ced3c3bc61392348256339529d89eb288e550 d3ce44bda23a2b9e28c5c4b3c47a4090881651ce 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        d3ce44bda23a2b9e28c5c4b3c47a4090881651ce bd3e401fea9e1f59aad8ee56e0c81207c342a692 96d1c37a3d4363611c49f7e52186e189a04c531f
+        bd3e401fea9e1f59aad8ee56e0c81207c342a692
+        "###);
+    }
+
     Ok(())
 }
 
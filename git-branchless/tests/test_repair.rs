@@ -1,4 +1,5 @@
-use lib::git::{BranchType, ReferenceName};
+use lib::core::gc::unmark_commit_reachable;
+use lib::git::BranchType;
 use lib::testing::make_git;
 
 #[test]
@@ -13,9 +14,7 @@ fn test_repair_broken_commit() -> eyre::Result<()> {
     git.run(&["checkout", "HEAD^"])?;
 
     let repo = git.get_repo()?;
-    repo.find_reference(&ReferenceName::from(format!("refs/branchless/{test3_oid}")))?
-        .unwrap()
-        .delete()?;
+    unmark_commit_reachable(&repo, test3_oid)?;
     git.run(&["gc", "--prune=now"])?;
 
     {
@@ -88,3 +87,34 @@ fn test_repair_broken_branch() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_repair_broken_internal_reference() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    // Simulate a keep-alive reference created by an older version of
+    // branchless, whose commit was then collected by Git.
+    let missing_oid = "1234567890123456789012345678901234567890";
+    let ref_dir = git.repo_path.join(".git").join("refs").join("branchless");
+    std::fs::create_dir_all(&ref_dir)?;
+    std::fs::write(ref_dir.join(missing_oid), format!("{missing_oid}\n"))?;
+
+    {
+        let (stdout, _stderr) = git.branchless("repair", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Found and repaired 1 broken internal reference: refs/branchless/1234567890123456789012345678901234567890
+        (This was a dry-run; run with --no-dry-run to apply changes.)
+        "###);
+    }
+    assert!(ref_dir.join(missing_oid).exists());
+
+    {
+        let (stdout, _stderr) = git.branchless("repair", &["--no-dry-run"])?;
+        insta::assert_snapshot!(stdout, @"Found and repaired 1 broken internal reference: refs/branchless/1234567890123456789012345678901234567890
+");
+    }
+    assert!(!ref_dir.join(missing_oid).exists());
+
+    Ok(())
+}
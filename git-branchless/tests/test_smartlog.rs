@@ -0,0 +1,73 @@
+use git_branchless::commands::smartlog::testing::select_commit;
+use git_branchless::commands::smartlog::SmartlogAction;
+use git_branchless_undo::tui::testing::{CursiveTestingBackend, CursiveTestingEvent};
+use lib::core::effects::Effects;
+use lib::core::formatting::Glyphs;
+use lib::git::{NonZeroOid, Repo};
+use lib::testing::make_git;
+
+use cursive_core::event::Key;
+use cursive_core::{Cursive, CursiveRunner};
+
+fn run_select_commit(
+    repo: &Repo,
+    events: Vec<CursiveTestingEvent>,
+) -> eyre::Result<Option<(SmartlogAction, NonZeroOid)>> {
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let backend = CursiveTestingBackend::init(events);
+    let siv = CursiveRunner::new(Cursive::new(), backend);
+    select_commit(siv, &effects, repo)
+}
+
+#[test]
+fn test_smartlog_interactive_select() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    let test3_oid = git.commit_file("test3", 3)?;
+
+    {
+        let selection = run_select_commit(
+            &git.get_repo()?,
+            vec![CursiveTestingEvent::Event(Key::Enter.into())],
+        )?;
+        assert_eq!(selection, Some((SmartlogAction::CheckOut, test3_oid)));
+    }
+
+    {
+        let selection = run_select_commit(
+            &git.get_repo()?,
+            vec![
+                CursiveTestingEvent::Event(Key::Up.into()),
+                CursiveTestingEvent::Event('h'.into()),
+            ],
+        )?;
+        assert_eq!(selection, Some((SmartlogAction::Hide, test2_oid)));
+    }
+
+    {
+        let selection = run_select_commit(
+            &git.get_repo()?,
+            vec![
+                CursiveTestingEvent::Event('k'.into()),
+                CursiveTestingEvent::Event('j'.into()),
+                CursiveTestingEvent::Event('j'.into()),
+                CursiveTestingEvent::Event('r'.into()),
+            ],
+        )?;
+        assert_eq!(selection, Some((SmartlogAction::Restack, test3_oid)));
+    }
+
+    {
+        let selection = run_select_commit(
+            &git.get_repo()?,
+            vec![CursiveTestingEvent::Event('q'.into())],
+        )?;
+        assert_eq!(selection, None);
+    }
+
+    Ok(())
+}
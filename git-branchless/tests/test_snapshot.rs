@@ -83,6 +83,7 @@ fn test_restore_snapshot_basic() -> eyre::Result<()> {
             git.branchless("snapshot", &["restore", &snapshot_oid.to_string()])?;
         let stdout = trim_lines(stdout);
         insta::assert_snapshot!(stderr, @r###"
+        branchless: creating working copy snapshot
         branchless: restoring from snapshot
         branchless: processing 2 updates: branch master, ref HEAD
         branchless: processing 1 update: ref HEAD
@@ -193,6 +194,7 @@ fn test_restore_snapshot_deleted_files() -> eyre::Result<()> {
             git.branchless("snapshot", &["restore", &snapshot_oid.to_string()])?;
         let stdout = trim_lines(stdout);
         insta::assert_snapshot!(stderr, @r###"
+        branchless: creating working copy snapshot
         branchless: restoring from snapshot
         branchless: processing 2 updates: branch master, ref HEAD
         branchless: processing 1 update: ref HEAD
@@ -266,6 +268,7 @@ fn test_restore_snapshot_delete_file_only_in_index() -> eyre::Result<()> {
             git.branchless("snapshot", &["restore", &snapshot_oid.to_string()])?;
         let stdout = trim_lines(stdout);
         insta::assert_snapshot!(stderr, @r###"
+        branchless: creating working copy snapshot
         branchless: restoring from snapshot
         branchless: processing 2 updates: branch master, ref HEAD
         branchless: processing 1 update: ref HEAD
@@ -336,6 +339,7 @@ fn test_restore_snapshot_respect_untracked_changes() -> eyre::Result<()> {
             },
         )?;
         insta::assert_snapshot!(stderr, @r###"
+        branchless: creating working copy snapshot
         branchless: restoring from snapshot
         branchless: processing 1 update: ref HEAD
         error: The following untracked working tree files would be overwritten by checkout:
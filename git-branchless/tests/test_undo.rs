@@ -1,6 +1,7 @@
 use std::mem::swap;
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 
 use git_branchless_undo::testing::{select_past_event, undo_events};
 use git_branchless_undo::tui::testing::{
@@ -8,10 +9,10 @@ use git_branchless_undo::tui::testing::{
 };
 use lib::core::dag::Dag;
 use lib::core::effects::Effects;
-use lib::core::eventlog::{EventCursor, EventLogDb, EventReplayer};
+use lib::core::eventlog::{Event, EventCursor, EventLogDb, EventReplayer};
 use lib::core::formatting::Glyphs;
 use lib::core::repo_ext::RepoExt;
-use lib::git::{GitRunInfo, GitVersion, Repo};
+use lib::git::{BranchType, GitRunInfo, GitVersion, MaybeZeroOid, Repo};
 use lib::testing::{make_git, trim_lines, Git, GitInitOptions, GitRunOptions};
 
 use cursive_core::event::Key;
@@ -114,6 +115,7 @@ fn test_undo_help() -> eyre::Result<()> {
         │        │ q: Quit.                                                                                           │        │
         │        │ p/n or <left>/<right>: View next/previous state.                                                   │        │
         │        │ g: Go to a provided event ID.                                                                      │        │
+        │        │ c: Compare the commit graph before and after the given state.                                      │        │
         │        │ <enter>: Revert the repository to the given state (requires confirmation).                         │        │
         │        │                                                                                                    │        │
         │        │ You can also copy a commit hash from the past and manually run `git unhide` or `git rebase` on it. │        │
@@ -121,7 +123,6 @@ fn test_undo_help() -> eyre::Result<()> {
         │        │                                                                                            <Close> │        │
         │        └────────────────────────────────────────────────────────────────────────────────────────────────────┘        │
         │                                                                                                                      │
-        │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
         ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
         │There are no previous available events.                                                                               │
@@ -183,7 +184,7 @@ fn test_undo_navigate() -> eyre::Result<()> {
         │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
         ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
-        │Repo after transaction 3 (event 4). Press 'h' for help, 'q' to quit.                                                  │
+        │Repo after transaction 3 (event 4): reference-transaction. Press 'h' for help, 'q' to quit.                           │
         │1. Check out from 62fc20d create test1.txt                                                                            │
         │               to 96d1c37 create test2.txt                                                                            │
         │2. Move branch master from 62fc20d create test1.txt                                                                   │
@@ -211,7 +212,7 @@ fn test_undo_navigate() -> eyre::Result<()> {
         │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
         ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
-        │Repo after transaction 4 (event 6). Press 'h' for help, 'q' to quit.                                                  │
+        │Repo after transaction 4 (event 6): post-commit. Press 'h' for help, 'q' to quit.                                     │
         │1. Commit 96d1c37 create test2.txt                                                                                    │
         │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
@@ -268,7 +269,7 @@ fn test_go_to_event() -> eyre::Result<()> {
     │                                                                                                                      │
     └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
     ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
-    │Repo after transaction 4 (event 6). Press 'h' for help, 'q' to quit.                                                  │
+    │Repo after transaction 4 (event 6): post-commit. Press 'h' for help, 'q' to quit.                                     │
     │1. Commit 96d1c37 create test2.txt                                                                                    │
     │                                                                                                                      │
     └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
@@ -294,7 +295,7 @@ fn test_go_to_event() -> eyre::Result<()> {
     │                                                                                                                      │
     └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
     ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
-    │Repo after transaction 1 (event 1). Press 'h' for help, 'q' to quit.                                                  │
+    │Repo after transaction 1 (event 1): reference-transaction. Press 'h' for help, 'q' to quit.                           │
     │1. Check out from f777ecc create initial.txt                                                                          │
     │               to 62fc20d create test1.txt                                                                            │
     └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
@@ -303,6 +304,42 @@ fn test_go_to_event() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_undo_compare_before_and_after() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? || git.produces_auto_merge_refs()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    let screenshot1 = Default::default();
+    run_select_past_event(
+        &git.get_repo()?,
+        vec![
+            CursiveTestingEvent::Event('p'.into()),
+            CursiveTestingEvent::Event('c'.into()),
+            CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot1)),
+            CursiveTestingEvent::Event('q'.into()),
+        ],
+    )?;
+    let screen = screen_to_string(&screenshot1);
+    let before_index = screen
+        .find("Before this transaction:")
+        .expect("missing graph before transaction");
+    let after_index = screen
+        .find("After this transaction:")
+        .expect("missing graph after transaction");
+    assert!(before_index < after_index, "{screen}");
+    assert!(screen[before_index..after_index].contains("62fc20d (master) create test1.txt"));
+    assert!(screen[after_index..].contains("96d1c37 (master) create test2.txt"));
+
+    Ok(())
+}
+
 #[test]
 fn test_undo_hide() -> eyre::Result<()> {
     let git = make_git()?;
@@ -339,7 +376,7 @@ fn test_undo_hide() -> eyre::Result<()> {
     insta::assert_debug_snapshot!(event_cursor, @r###"
         Some(
             EventCursor {
-                event_id: 9,
+                event_id: 8,
             },
         )
         "###);
@@ -476,7 +513,7 @@ fn test_historical_smartlog_visibility() -> eyre::Result<()> {
         │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
         ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
-        │Repo after transaction 5 (event 5). Press 'h' for help, 'q' to quit.                                                  │
+        │Repo after transaction 5 (event 4): hide. Press 'h' for help, 'q' to quit.                                            │
         │1. Hide commit 62fc20d create test1.txt                                                                               │
         │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
@@ -502,7 +539,7 @@ fn test_historical_smartlog_visibility() -> eyre::Result<()> {
         │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
         ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
-        │Repo after transaction 4 (event 4). Press 'h' for help, 'q' to quit.                                                  │
+        │Repo after transaction 4 (event 3): post-commit. Press 'h' for help, 'q' to quit.                                     │
         │1. Commit 62fc20d create test1.txt                                                                                    │
         │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
@@ -529,7 +566,7 @@ fn test_historical_smartlog_visibility() -> eyre::Result<()> {
         │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
         ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
-        │Repo after transaction 3 (event 3). Press 'h' for help, 'q' to quit.                                                  │
+        │Repo after transaction 3 (event 3): hide. Press 'h' for help, 'q' to quit.                                            │
         │1. Hide commit 62fc20d create test1.txt                                                                               │
         │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
@@ -555,7 +592,7 @@ fn test_historical_smartlog_visibility() -> eyre::Result<()> {
         │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
         ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
-        │Repo after transaction 2 (event 2). Press 'h' for help, 'q' to quit.                                                  │
+        │Repo after transaction 2 (event 2): post-commit. Press 'h' for help, 'q' to quit.                                     │
         │1. Commit 62fc20d create test1.txt                                                                                    │
         │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
@@ -707,7 +744,7 @@ fn test_git_bisect_produces_empty_event() -> eyre::Result<()> {
     │                                                                                                                      │
     └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
     ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
-    │Repo after transaction 3 (event 4). Press 'h' for help, 'q' to quit.                                                  │
+    │Repo after transaction 3 (event 4): reference-transaction. Press 'h' for help, 'q' to quit.                           │
     │1. Empty event for BISECT_HEAD                                                                                        │
     │   This may be an unsupported use-case; see https://github.com/arxanas/git-branchless/issues/57                       │
     └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
@@ -742,7 +779,9 @@ fn test_undo_garbage_collected_commit() -> eyre::Result<()> {
         let (stdout, _stderr) = git.branchless("gc", &[])?;
         insta::assert_snapshot!(stdout, @r###"
         branchless: collecting garbage
-        branchless: 1 dangling reference deleted
+        branchless: 1 dangling commit released
+        branchless: 2 kept commits scanned, 1 commit still protected
+        branchless: up to 1 commit newly eligible for Git's garbage collection
         "###);
     }
     git.run(&["gc", "--prune=now"])?;
@@ -788,7 +827,7 @@ fn test_undo_garbage_collected_commit() -> eyre::Result<()> {
     │                                                                                                                      │
     └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
     ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
-    │Repo after transaction 7 (event 8). Press 'h' for help, 'q' to quit.                                                  │
+    │Repo after transaction 7 (event 8): hide. Press 'h' for help, 'q' to quit.                                            │
     │1. Hide commit <commit not available: 96d1c37a3d4363611c49f7e52186e189a04c531f>                                       │
     │                                                                                                                      │
     └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
@@ -798,17 +837,17 @@ fn test_undo_garbage_collected_commit() -> eyre::Result<()> {
     {
         let (exit_code, stdout) = run_undo_events(&git, event_cursor)?;
         insta::assert_snapshot!(stdout, @r###"
+        These actions can't be applied, because the commits they refer to no longer exist:
+        1. Check out from 62fc20d create test1.txt
+                       to <commit not available: 96d1c37a3d4363611c49f7e52186e189a04c531f>
         Will apply these actions:
         1. Move branch master from 62fc20d create test1.txt
                                 to 62fc20d create test1.txt
         2. Move branch master from 62fc20d create test1.txt
                                 to 62fc20d create test1.txt
-        3. Check out from 62fc20d create test1.txt
-                       to <commit not available: 96d1c37a3d4363611c49f7e52186e189a04c531f>
-        Confirm? [yN] branchless: running command: <git-executable> checkout 96d1c37a3d4363611c49f7e52186e189a04c531f --detach
-        Failed to check out commit: 96d1c37a3d4363611c49f7e52186e189a04c531f
+        Confirm? [yN] Applied 2 inverse events.
         "###);
-        assert!(exit_code > 0);
+        assert_eq!(exit_code, 0);
     }
 
     Ok(())
@@ -839,13 +878,15 @@ fn test_undo_noninteractive() -> eyre::Result<()> {
         let stdout = trim_lines(stdout);
         insta::assert_snapshot!(stdout, @r###"
         Will apply these actions:
-        1. Rewrite commit 9ed8f9a bad message
+        1. Restore staged changes from 9ed8f9a bad message
+                       backed up using 820c94e branchless: automated working copy snapshot
+        2. Rewrite commit 9ed8f9a bad message
                       as 96d1c37 create test2.txt
-        2. Hide commit 9ed8f9a bad message
+        3. Hide commit 9ed8f9a bad message
 
-        3. Move branch master from 9ed8f9a bad message
+        4. Move branch master from 9ed8f9a bad message
                                 to 96d1c37 create test2.txt
-        4. Check out from 9ed8f9a bad message
+        5. Check out from 9ed8f9a bad message
                        to 96d1c37 create test2.txt
         Confirm? [yN] Aborted.
         "###);
@@ -870,18 +911,20 @@ fn test_undo_noninteractive() -> eyre::Result<()> {
         let stdout = trim_lines(stdout);
         insta::assert_snapshot!(stdout, @r###"
         Will apply these actions:
-        1. Rewrite commit 9ed8f9a bad message
+        1. Restore staged changes from 9ed8f9a bad message
+                       backed up using 820c94e branchless: automated working copy snapshot
+        2. Rewrite commit 9ed8f9a bad message
                       as 96d1c37 create test2.txt
-        2. Hide commit 9ed8f9a bad message
+        3. Hide commit 9ed8f9a bad message
 
-        3. Move branch master from 9ed8f9a bad message
+        4. Move branch master from 9ed8f9a bad message
                                 to 96d1c37 create test2.txt
-        4. Check out from 9ed8f9a bad message
+        5. Check out from 9ed8f9a bad message
                        to 96d1c37 create test2.txt
-        Confirm? [yN] branchless: running command: <git-executable> checkout master --detach
+        Confirm? [yN] branchless: running command: <git-executable> reset --soft 96d1c37a3d4363611c49f7e52186e189a04c531f
         :
         @ 96d1c37 (master) create test2.txt
-        Applied 4 inverse events.
+        Applied 5 inverse events.
         "###);
     }
 
@@ -1005,3 +1048,404 @@ fn test_undo_unseen_commit() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_redo() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "redo", "--yes"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        No undo to redo, exiting.
+        "###);
+    }
+
+    git.run(&["branch", "foo"])?;
+    git.branchless("undo", &["--yes"])?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d (master) create test1.txt
+        "###);
+    }
+
+    git.branchless("redo", &["--yes"])?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d (foo, master) create test1.txt
+        "###);
+    }
+
+    // Undoing the redo and redoing again should also work.
+    git.branchless("undo", &["--yes"])?;
+    git.branchless("redo", &["--yes"])?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d (foo, master) create test1.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "redo", "--yes"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        No undo to redo, exiting.
+        "###);
+    }
+
+    Ok(())
+}
+
+/// Find the ID of the event which created the given reference, as would be
+/// passed to `git undo --event`.
+fn find_ref_creation_event_id(git: &Git, ref_name: &str) -> eyre::Result<isize> {
+    let repo = git.get_repo()?;
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+    let mut event_cursor = event_replayer.make_default_cursor();
+    while let Some((event_id, event)) = event_replayer.get_event_before_cursor(event_cursor) {
+        match event {
+            Event::RefUpdateEvent {
+                ref_name: event_ref_name,
+                old_oid: MaybeZeroOid::Zero,
+                ..
+            } if event_ref_name.as_str() == ref_name => return Ok(event_id),
+            _ => event_cursor = event_replayer.make_cursor(event_id - 1),
+        }
+    }
+    eyre::bail!("No event created reference {ref_name}")
+}
+
+#[test]
+fn test_undo_single_event() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.run(&["branch", "foo"])?;
+    git.run(&["branch", "bar"])?;
+    git.run(&["branch", "baz"])?;
+    git.run(&["branch", "-D", "baz"])?;
+
+    let foo_event_id = find_ref_creation_event_id(&git, "refs/heads/foo")?;
+    git.branchless("undo", &["--event", &foo_event_id.to_string(), "--yes"])?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d (bar, master) create test1.txt
+        "###);
+    }
+
+    // `baz` was deleted after it was created, so undoing its creation by
+    // itself would clobber the deletion.
+    let baz_event_id = find_ref_creation_event_id(&git, "refs/heads/baz")?;
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &[
+                "branchless",
+                "undo",
+                "--event",
+                &baz_event_id.to_string(),
+                "--yes",
+            ],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert!(
+            stdout.starts_with(&format!("Not undoing event {baz_event_id}")),
+            "{stdout}"
+        );
+    }
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "undo", "--event", "1000", "--yes"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        There is no event with ID 1000.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_preserves_uncommitted_changes() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file_with_contents("test1", 1, "a\nb\nc\nd\ne\n")?;
+    let test2_oid = git.commit_file_with_contents("test1", 2, "A\nb\nc\nd\ne\n")?;
+    git.run(&["checkout", "HEAD^"])?;
+
+    // Checking out `test2_oid` directly would fail, since this change would be
+    // overwritten.
+    git.write_file_txt("test1", "a\nb\nc\nd\nE\n")?;
+    {
+        let (stdout, _stderr) = git.branchless("undo", &["--yes"])?;
+        assert!(
+            stdout.contains("Reapplied uncommitted changes."),
+            "{stdout}"
+        );
+    }
+
+    let repo = git.get_repo()?;
+    assert_eq!(repo.get_head_info()?.oid, Some(test2_oid));
+    assert_eq!(
+        std::fs::read_to_string(git.repo_path.join("test1.txt"))?,
+        "A\nb\nc\nd\nE\n"
+    );
+    {
+        let (stdout, _stderr) = git.run(&["status", "--porcelain"])?;
+        assert_eq!(stdout, " M test1.txt\n");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_multiple_steps() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.run(&["branch", "foo"])?;
+    git.run(&["branch", "bar"])?;
+    git.run(&["branch", "baz"])?;
+
+    git.branchless("undo", &["-n", "2", "--yes"])?;
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d (foo, master) create test1.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_to_time() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("undo", &["--to", "tomorrow", "--yes"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        No undo actions to apply, exiting.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_amend_restores_index() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+
+    let event_cursor = {
+        let effects = Effects::new_suppress_for_test(Glyphs::text());
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+        event_replayer.make_default_cursor()
+    };
+
+    git.write_file_txt("test1", "amended contents\n")?;
+    git.run(&["add", "test1.txt"])?;
+    git.run(&["commit", "--amend", "--no-edit"])?;
+
+    let (exit_code, _stdout) = run_undo_events(&git, event_cursor)?;
+    assert_eq!(exit_code, 0);
+
+    // The changes which were amended into the commit should be staged again.
+    let repo = git.get_repo()?;
+    assert_eq!(repo.get_head_info()?.oid, Some(test1_oid));
+    {
+        let (stdout, _stderr) = git.run(&["status", "--porcelain"])?;
+        assert_eq!(stdout, "M  test1.txt\n");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_amend_restores_staged_hunks() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.write_file_txt("test1", "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n")?;
+    git.run(&["add", "test1.txt"])?;
+    git.run(&["commit", "-m", "create test1.txt"])?;
+    let original_oid = git.get_repo()?.get_head_info()?.oid;
+
+    let event_cursor = {
+        let effects = Effects::new_suppress_for_test(Glyphs::text());
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+        event_replayer.make_default_cursor()
+    };
+
+    // Stage only the first hunk, then amend it into the commit, leaving the
+    // second hunk unstaged.
+    git.write_file_txt("test1", "A\nb\nc\nd\ne\nf\ng\nh\ni\nj\n")?;
+    git.run(&["add", "test1.txt"])?;
+    git.write_file_txt("test1", "A\nb\nc\nd\ne\nf\ng\nh\ni\nJ\n")?;
+    git.run(&["commit", "--amend", "--no-edit"])?;
+
+    let (exit_code, _stdout) = run_undo_events(&git, event_cursor)?;
+    assert_eq!(exit_code, 0);
+
+    // Only the hunk which was amended into the commit should be staged again.
+    assert_eq!(git.get_repo()?.get_head_info()?.oid, original_oid);
+    {
+        let (stdout, _stderr) = git.run(&["diff", "--cached", "--unified=0"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        diff --git a/test1.txt b/test1.txt
+        index 92dfa21..61e17cd 100644
+        --- a/test1.txt
+        +++ b/test1.txt
+        @@ -1 +1 @@
+        -a
+        +A
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.run(&["diff", "--unified=0"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        diff --git a/test1.txt b/test1.txt
+        index 61e17cd..98bda7f 100644
+        --- a/test1.txt
+        +++ b/test1.txt
+        @@ -10 +10 @@ i
+        -j
+        +J
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_plan_and_apply() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let event_cursor = {
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+        event_replayer.make_default_cursor()
+    };
+
+    git.run(&["branch", "foo"])?;
+
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let mut event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+    let now = SystemTime::now();
+    let event_tx_id = event_log_db.make_transaction_id(now, "undo")?;
+    let plan = git_branchless_undo::plan(&event_replayer, event_cursor, now, event_tx_id)?;
+    assert!(plan.get_checkout_target()?.is_none());
+    assert!(plan.get_visibility_changes().is_empty());
+    match plan.get_ref_updates()?.as_slice() {
+        [Event::RefUpdateEvent {
+            ref_name,
+            new_oid: MaybeZeroOid::Zero,
+            ..
+        }] => assert_eq!(ref_name.as_str(), "refs/heads/foo"),
+        ref_updates => panic!("Unexpected ref updates: {ref_updates:?}"),
+    }
+
+    let result = git_branchless_undo::apply(
+        &effects,
+        &repo,
+        &git.get_git_run_info(),
+        &mut event_log_db,
+        event_tx_id,
+        &plan,
+    )?;
+    assert!(result.is_ok());
+    assert!(repo.find_branch("foo", BranchType::Local)?.is_none());
+
+    Ok(())
+}
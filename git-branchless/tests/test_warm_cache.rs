@@ -0,0 +1,26 @@
+use lib::testing::make_git;
+
+#[test]
+fn test_warm_cache() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    let dag_dir = git.get_repo()?.get_dag_dir()?;
+    if dag_dir.exists() {
+        std::fs::remove_dir_all(&dag_dir)?;
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("warm-cache", &[])?;
+        assert!(
+            stdout.contains("computed merge-bases for 1 visible head."),
+            "{stdout}"
+        );
+    }
+    assert!(dag_dir.exists());
+
+    Ok(())
+}
@@ -49,18 +49,6 @@ fn test_wrap_rebase_in_transaction() -> eyre::Result<()> {
                 new_oid: f777ecc9b0db5ed372b2615695191a8a17f79f24,
                 message: None,
             },
-            RefUpdateEvent {
-                timestamp: 0.0,
-                event_tx_id: Id(
-                    2,
-                ),
-                ref_name: ReferenceName(
-                    "HEAD",
-                ),
-                old_oid: f777ecc9b0db5ed372b2615695191a8a17f79f24,
-                new_oid: f777ecc9b0db5ed372b2615695191a8a17f79f24,
-                message: None,
-            },
             RefUpdateEvent {
                 timestamp: 0.0,
                 event_tx_id: Id(
@@ -147,18 +135,6 @@ fn test_wrap_rebase_in_transaction() -> eyre::Result<()> {
                 new_oid: 96d1c37a3d4363611c49f7e52186e189a04c531f,
                 message: None,
             },
-            RefUpdateEvent {
-                timestamp: 0.0,
-                event_tx_id: Id(
-                    8,
-                ),
-                ref_name: ReferenceName(
-                    "HEAD",
-                ),
-                old_oid: f777ecc9b0db5ed372b2615695191a8a17f79f24,
-                new_oid: 96d1c37a3d4363611c49f7e52186e189a04c531f,
-                message: None,
-            },
             RefUpdateEvent {
                 timestamp: 0.0,
                 event_tx_id: Id(
@@ -314,6 +290,14 @@ fn test_wrap_rebase_in_transaction() -> eyre::Result<()> {
         "###);
     }
 
+    // All of the events produced by the rebase share a transaction, which is
+    // described by the wrapped command line.
+    let last_event_tx_id = events.last().unwrap().get_event_tx_id();
+    insta::assert_snapshot!(
+        event_log_db.get_transaction_message(last_event_tx_id)?,
+        @"rebase foo"
+    );
+
     Ok(())
 }
 